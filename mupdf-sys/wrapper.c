@@ -1,6 +1,8 @@
 #include <stdint.h>
 #include <stdbool.h>
+#include <stdlib.h>
 #include <string.h>
+#include <math.h>
 #include <assert.h>
 #ifdef _WIN32
 #include <windows.h>
@@ -129,6 +131,42 @@ void mupdf_drop_base_context(fz_context *ctx)
     ctx = NULL;
 }
 
+/* Colorspace */
+fz_colorspace *mupdf_new_icc_colorspace(fz_context *ctx, const unsigned char *data, size_t len, mupdf_error_t **errptr)
+{
+    fz_colorspace *cs = NULL;
+    fz_buffer *buf = NULL;
+    fz_var(buf);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer_from_copied_data(ctx, data, len);
+        cs = fz_new_icc_colorspace(ctx, FZ_COLORSPACE_NONE, 0, NULL, buf);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return cs;
+}
+
+fz_default_colorspaces *mupdf_new_default_colorspaces(fz_context *ctx, mupdf_error_t **errptr)
+{
+    fz_default_colorspaces *dcs = NULL;
+    fz_try(ctx)
+    {
+        dcs = fz_new_default_colorspaces(ctx);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return dcs;
+}
+
 /* Pixmap */
 fz_pixmap *mupdf_new_pixmap(fz_context *ctx, fz_colorspace *cs, int x, int y, int w, int h, bool alpha, mupdf_error_t **errptr)
 {
@@ -170,6 +208,18 @@ void mupdf_clear_pixmap_with_value(fz_context *ctx, fz_pixmap *pixmap, int value
     }
 }
 
+void mupdf_clear_pixmap_rect_with_value(fz_context *ctx, fz_pixmap *pixmap, int value, fz_irect rect, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_clear_pixmap_rect_with_value(ctx, pixmap, value, rect);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
 void mupdf_invert_pixmap(fz_context *ctx, fz_pixmap *pixmap, mupdf_error_t **errptr)
 {
     fz_try(ctx)
@@ -303,6 +353,7 @@ fz_buffer *mupdf_pixmap_get_image_data(fz_context *ctx, fz_pixmap *pixmap, int f
             fz_write_pixmap_as_png(ctx, out, pixmap);
             break;
         }
+        fz_close_output(ctx, out);
     }
     fz_always(ctx)
     {
@@ -329,6 +380,130 @@ fz_pixmap *mupdf_scale_pixmap(fz_context *ctx, fz_pixmap *pixmap, float x, float
     return scaled;
 }
 
+fz_pixmap *mupdf_convert_pixmap(fz_context *ctx, fz_pixmap *pixmap, fz_colorspace *cs, fz_color_params cp, bool keep_alpha, mupdf_error_t **errptr)
+{
+    fz_pixmap *converted = NULL;
+    fz_try(ctx)
+    {
+        converted = fz_convert_pixmap(ctx, pixmap, cs, NULL, NULL, cp, keep_alpha);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return converted;
+}
+
+/* Bitmap */
+fz_halftone *mupdf_default_halftone(fz_context *ctx, int num_comps, mupdf_error_t **errptr)
+{
+    fz_halftone *ht = NULL;
+    fz_try(ctx)
+    {
+        ht = fz_default_halftone(ctx, num_comps);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ht;
+}
+
+fz_bitmap *mupdf_new_bitmap_from_pixmap(fz_context *ctx, fz_pixmap *pixmap, fz_halftone *ht, mupdf_error_t **errptr)
+{
+    fz_bitmap *bitmap = NULL;
+    fz_try(ctx)
+    {
+        bitmap = fz_new_bitmap_from_pixmap(ctx, pixmap, ht);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return bitmap;
+}
+
+/* PBM for 1 component bitmaps, PKM for CMYK ones */
+fz_buffer *mupdf_bitmap_get_image_data(fz_context *ctx, fz_bitmap *bitmap, mupdf_error_t **errptr)
+{
+    fz_output *out = NULL;
+    fz_buffer *buf = NULL;
+    fz_var(out);
+    fz_var(buf);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer(ctx, bitmap->stride * bitmap->h + 32);
+        out = fz_new_output_with_buffer(ctx, buf);
+        if (bitmap->n == 4)
+            fz_write_bitmap_as_pkm(ctx, out, bitmap);
+        else
+            fz_write_bitmap_as_pbm(ctx, out, bitmap);
+        fz_close_output(ctx, out);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_output(ctx, out);
+    }
+    fz_catch(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+        buf = NULL;
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+/* Separations */
+fz_separations *mupdf_new_separations(fz_context *ctx, bool controllable, mupdf_error_t **errptr)
+{
+    fz_separations *seps = NULL;
+    fz_try(ctx)
+    {
+        seps = fz_new_separations(ctx, controllable);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return seps;
+}
+
+void mupdf_add_separation(fz_context *ctx, fz_separations *seps, const char *name, fz_colorspace *cs, int colorant, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_add_separation(ctx, seps, name, cs, colorant);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_set_separation_behavior(fz_context *ctx, fz_separations *seps, int separation, int behavior, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_set_separation_behavior(ctx, seps, separation, (fz_separation_behavior)behavior);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_separation_equivalent(fz_context *ctx, fz_separations *seps, int separation, fz_colorspace *cs, float *color, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_separation_equivalent(ctx, seps, separation, cs, color, NULL, fz_default_color_params);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
 /* Font */
 fz_font *mupdf_new_font(fz_context *ctx, const char *name, int index, mupdf_error_t **errptr)
 {
@@ -663,208 +838,3528 @@ fz_rect mupdf_bound_page(fz_context *ctx, fz_page *page, mupdf_error_t **errptr)
     return rect;
 }
 
-fz_pixmap *mupdf_page_to_pixmap(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, float alpha, bool show_extras, mupdf_error_t **errptr)
-{
-    fz_pixmap *pixmap = NULL;
-    fz_try(ctx)
-    {
-        if (show_extras)
-        {
-            pixmap = fz_new_pixmap_from_page(ctx, page, ctm, cs, alpha);
-        }
-        else
-        {
-            pixmap = fz_new_pixmap_from_page_contents(ctx, page, ctm, cs, alpha);
-        }
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
-    return pixmap;
-}
-
-fz_buffer *mupdf_page_to_svg(fz_context *ctx, fz_page *page, fz_matrix ctm, mupdf_error_t **errptr)
+/* Bounds of everything the page draws, annotations included */
+static fz_rect mupdf_content_bbox(fz_context *ctx, fz_page *page)
 {
-    fz_rect mediabox = fz_bound_page(ctx, page);
-    fz_device *dev = NULL;
-    fz_buffer *buf = NULL;
-    fz_output *out = NULL;
-    fz_var(out);
-    fz_var(dev);
-    fz_var(buf);
-    fz_rect tbounds = mediabox;
-    tbounds = fz_transform_rect(tbounds, ctm);
+    fz_rect rect = fz_empty_rect;
+    fz_device *dev = fz_new_bbox_device(ctx, &rect);
     fz_try(ctx)
     {
-        buf = fz_new_buffer(ctx, 1024);
-        out = fz_new_output_with_buffer(ctx, buf);
-        dev = fz_new_svg_device(ctx, out, tbounds.x1 - tbounds.x0, tbounds.y1 - tbounds.y0, FZ_SVG_TEXT_AS_PATH, 1);
-        fz_run_page(ctx, page, dev, ctm, NULL);
+        fz_run_page(ctx, page, dev, fz_identity, NULL);
         fz_close_device(ctx, dev);
     }
     fz_always(ctx)
     {
         fz_drop_device(ctx, dev);
-        fz_drop_output(ctx, out);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return buf;
+    return rect;
 }
 
-fz_stext_page *mupdf_page_to_text_page(fz_context *ctx, fz_page *page, int flags, mupdf_error_t **errptr)
+fz_rect mupdf_page_content_bbox(fz_context *ctx, fz_page *page, mupdf_error_t **errptr)
 {
-    fz_stext_page *text_page = NULL;
-    fz_stext_options opts = {0};
-    opts.flags = flags;
+    fz_rect rect = fz_empty_rect;
     fz_try(ctx)
     {
-        text_page = fz_new_stext_page_from_page(ctx, page, &opts);
+        rect = mupdf_content_bbox(ctx, page);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return text_page;
+    return rect;
 }
 
-fz_display_list *mupdf_page_to_display_list(fz_context *ctx, fz_page *page, bool annots, mupdf_error_t **errptr)
+fz_pixmap *mupdf_page_to_pixmap(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, float alpha, bool show_extras, mupdf_error_t **errptr)
 {
-    fz_display_list *list = NULL;
+    fz_pixmap *pixmap = NULL;
     fz_try(ctx)
     {
-        if (annots)
+        if (show_extras)
         {
-            list = fz_new_display_list_from_page(ctx, page);
+            pixmap = fz_new_pixmap_from_page(ctx, page, ctm, cs, alpha);
         }
         else
         {
-            list = fz_new_display_list_from_page_contents(ctx, page);
+            pixmap = fz_new_pixmap_from_page_contents(ctx, page, ctm, cs, alpha);
         }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return list;
+    return pixmap;
 }
 
-void mupdf_run_page(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+/* Render through `proof_cs`, e.g. a press CMYK profile, before converting to `cs` */
+fz_pixmap *mupdf_page_to_pixmap_with_proof(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, fz_colorspace *proof_cs, bool alpha, bool show_extras, mupdf_error_t **errptr)
 {
+    fz_pixmap *pixmap = NULL;
+    fz_device *dev = NULL;
+    fz_var(pixmap);
+    fz_var(dev);
     fz_try(ctx)
     {
-        fz_run_page(ctx, page, device, ctm, NULL);
+        fz_irect bbox = fz_round_rect(fz_transform_rect(fz_bound_page(ctx, page), ctm));
+        pixmap = fz_new_pixmap_with_bbox(ctx, cs, bbox, NULL, alpha);
+        if (alpha)
+            fz_clear_pixmap(ctx, pixmap);
+        else
+            fz_clear_pixmap_with_value(ctx, pixmap, 0xFF);
+        dev = fz_new_draw_device_with_proof(ctx, ctm, pixmap, proof_cs);
+        if (show_extras)
+            fz_run_page(ctx, page, dev, fz_identity, NULL);
+        else
+            fz_run_page_contents(ctx, page, dev, fz_identity, NULL);
+        fz_close_device(ctx, dev);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
     }
     fz_catch(ctx)
     {
+        fz_drop_pixmap(ctx, pixmap);
+        pixmap = NULL;
         mupdf_save_error(ctx, errptr);
     }
+    return pixmap;
 }
 
-void mupdf_run_page_contents(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+/* Device forwarding everything to `target`, with the color parameters of every call replaced by `params` */
+typedef struct
 {
-    fz_try(ctx)
-    {
-        fz_run_page_contents(ctx, page, device, ctm, NULL);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
+    fz_device super;
+    fz_device *target;
+    fz_color_params params;
+} mupdf_color_params_device;
+
+#define MUPDF_CP_DEV(dev) ((mupdf_color_params_device *)(dev))
+
+static void mupdf_cp_fill_path(fz_context *ctx, fz_device *dev, const fz_path *path, int even_odd, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp)
+{
+    fz_fill_path(ctx, MUPDF_CP_DEV(dev)->target, path, even_odd, ctm, cs, color, alpha, MUPDF_CP_DEV(dev)->params);
 }
 
-void mupdf_run_page_annots(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+static void mupdf_cp_stroke_path(fz_context *ctx, fz_device *dev, const fz_path *path, const fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp)
 {
-    fz_try(ctx)
-    {
-        fz_run_page_annots(ctx, page, device, ctm, NULL);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
+    fz_stroke_path(ctx, MUPDF_CP_DEV(dev)->target, path, stroke, ctm, cs, color, alpha, MUPDF_CP_DEV(dev)->params);
 }
 
-void mupdf_run_page_widgets(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+static void mupdf_cp_clip_path(fz_context *ctx, fz_device *dev, const fz_path *path, int even_odd, fz_matrix ctm, fz_rect scissor)
 {
-    fz_try(ctx)
-    {
-        fz_run_page_widgets(ctx, page, device, ctm, NULL);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
+    fz_clip_path(ctx, MUPDF_CP_DEV(dev)->target, path, even_odd, ctm, scissor);
 }
 
-/* Cookie */
-fz_cookie *mupdf_new_cookie(fz_context *ctx, mupdf_error_t **errptr)
+static void mupdf_cp_clip_stroke_path(fz_context *ctx, fz_device *dev, const fz_path *path, const fz_stroke_state *stroke, fz_matrix ctm, fz_rect scissor)
 {
-    fz_cookie *cookie = NULL;
-    fz_try(ctx)
-    {
-        cookie = fz_malloc_struct(ctx, fz_cookie);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
-    return cookie;
+    fz_clip_stroke_path(ctx, MUPDF_CP_DEV(dev)->target, path, stroke, ctm, scissor);
 }
 
-/* DisplayList */
-fz_display_list *mupdf_new_display_list(fz_context *ctx, fz_rect mediabox, mupdf_error_t **errptr)
+static void mupdf_cp_fill_text(fz_context *ctx, fz_device *dev, const fz_text *text, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp)
 {
-    fz_display_list *list = NULL;
-    fz_try(ctx)
-    {
-        list = fz_new_display_list(ctx, mediabox);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
-    return list;
+    fz_fill_text(ctx, MUPDF_CP_DEV(dev)->target, text, ctm, cs, color, alpha, MUPDF_CP_DEV(dev)->params);
 }
 
-fz_pixmap *mupdf_display_list_to_pixmap(fz_context *ctx, fz_display_list *list, fz_matrix ctm, fz_colorspace *cs, bool alpha, mupdf_error_t **errptr)
+static void mupdf_cp_stroke_text(fz_context *ctx, fz_device *dev, const fz_text *text, const fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp)
+{
+    fz_stroke_text(ctx, MUPDF_CP_DEV(dev)->target, text, stroke, ctm, cs, color, alpha, MUPDF_CP_DEV(dev)->params);
+}
+
+static void mupdf_cp_clip_text(fz_context *ctx, fz_device *dev, const fz_text *text, fz_matrix ctm, fz_rect scissor)
+{
+    fz_clip_text(ctx, MUPDF_CP_DEV(dev)->target, text, ctm, scissor);
+}
+
+static void mupdf_cp_clip_stroke_text(fz_context *ctx, fz_device *dev, const fz_text *text, const fz_stroke_state *stroke, fz_matrix ctm, fz_rect scissor)
+{
+    fz_clip_stroke_text(ctx, MUPDF_CP_DEV(dev)->target, text, stroke, ctm, scissor);
+}
+
+static void mupdf_cp_ignore_text(fz_context *ctx, fz_device *dev, const fz_text *text, fz_matrix ctm)
+{
+    fz_ignore_text(ctx, MUPDF_CP_DEV(dev)->target, text, ctm);
+}
+
+static void mupdf_cp_fill_shade(fz_context *ctx, fz_device *dev, fz_shade *shade, fz_matrix ctm, float alpha, fz_color_params cp)
+{
+    fz_fill_shade(ctx, MUPDF_CP_DEV(dev)->target, shade, ctm, alpha, MUPDF_CP_DEV(dev)->params);
+}
+
+static void mupdf_cp_fill_image(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, float alpha, fz_color_params cp)
+{
+    fz_fill_image(ctx, MUPDF_CP_DEV(dev)->target, image, ctm, alpha, MUPDF_CP_DEV(dev)->params);
+}
+
+static void mupdf_cp_fill_image_mask(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp)
+{
+    fz_fill_image_mask(ctx, MUPDF_CP_DEV(dev)->target, image, ctm, cs, color, alpha, MUPDF_CP_DEV(dev)->params);
+}
+
+static void mupdf_cp_clip_image_mask(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, fz_rect scissor)
+{
+    fz_clip_image_mask(ctx, MUPDF_CP_DEV(dev)->target, image, ctm, scissor);
+}
+
+static void mupdf_cp_pop_clip(fz_context *ctx, fz_device *dev)
+{
+    fz_pop_clip(ctx, MUPDF_CP_DEV(dev)->target);
+}
+
+static void mupdf_cp_begin_mask(fz_context *ctx, fz_device *dev, fz_rect area, int luminosity, fz_colorspace *cs, const float *bc, fz_color_params cp)
+{
+    fz_begin_mask(ctx, MUPDF_CP_DEV(dev)->target, area, luminosity, cs, bc, MUPDF_CP_DEV(dev)->params);
+}
+
+static void mupdf_cp_end_mask(fz_context *ctx, fz_device *dev)
+{
+    fz_end_mask(ctx, MUPDF_CP_DEV(dev)->target);
+}
+
+static void mupdf_cp_begin_group(fz_context *ctx, fz_device *dev, fz_rect area, fz_colorspace *cs, int isolated, int knockout, int blendmode, float alpha)
+{
+    fz_begin_group(ctx, MUPDF_CP_DEV(dev)->target, area, cs, isolated, knockout, blendmode, alpha);
+}
+
+static void mupdf_cp_end_group(fz_context *ctx, fz_device *dev)
+{
+    fz_end_group(ctx, MUPDF_CP_DEV(dev)->target);
+}
+
+static int mupdf_cp_begin_tile(fz_context *ctx, fz_device *dev, fz_rect area, fz_rect view, float xstep, float ystep, fz_matrix ctm, int id)
+{
+    return fz_begin_tile_id(ctx, MUPDF_CP_DEV(dev)->target, area, view, xstep, ystep, ctm, id);
+}
+
+static void mupdf_cp_end_tile(fz_context *ctx, fz_device *dev)
+{
+    fz_end_tile(ctx, MUPDF_CP_DEV(dev)->target);
+}
+
+static void mupdf_cp_render_flags(fz_context *ctx, fz_device *dev, int set, int clear)
+{
+    fz_render_flags(ctx, MUPDF_CP_DEV(dev)->target, set, clear);
+}
+
+static void mupdf_cp_set_default_colorspaces(fz_context *ctx, fz_device *dev, fz_default_colorspaces *dcs)
+{
+    fz_set_default_colorspaces(ctx, MUPDF_CP_DEV(dev)->target, dcs);
+}
+
+static void mupdf_cp_begin_layer(fz_context *ctx, fz_device *dev, const char *name)
+{
+    fz_begin_layer(ctx, MUPDF_CP_DEV(dev)->target, name);
+}
+
+static void mupdf_cp_end_layer(fz_context *ctx, fz_device *dev)
+{
+    fz_end_layer(ctx, MUPDF_CP_DEV(dev)->target);
+}
+
+/* The caller keeps ownership of `target` and closes it after this device */
+static fz_device *mupdf_new_color_params_device(fz_context *ctx, fz_device *target, fz_color_params params)
+{
+    mupdf_color_params_device *dev = fz_new_derived_device(ctx, mupdf_color_params_device);
+    dev->super.fill_path = mupdf_cp_fill_path;
+    dev->super.stroke_path = mupdf_cp_stroke_path;
+    dev->super.clip_path = mupdf_cp_clip_path;
+    dev->super.clip_stroke_path = mupdf_cp_clip_stroke_path;
+    dev->super.fill_text = mupdf_cp_fill_text;
+    dev->super.stroke_text = mupdf_cp_stroke_text;
+    dev->super.clip_text = mupdf_cp_clip_text;
+    dev->super.clip_stroke_text = mupdf_cp_clip_stroke_text;
+    dev->super.ignore_text = mupdf_cp_ignore_text;
+    dev->super.fill_shade = mupdf_cp_fill_shade;
+    dev->super.fill_image = mupdf_cp_fill_image;
+    dev->super.fill_image_mask = mupdf_cp_fill_image_mask;
+    dev->super.clip_image_mask = mupdf_cp_clip_image_mask;
+    dev->super.pop_clip = mupdf_cp_pop_clip;
+    dev->super.begin_mask = mupdf_cp_begin_mask;
+    dev->super.end_mask = mupdf_cp_end_mask;
+    dev->super.begin_group = mupdf_cp_begin_group;
+    dev->super.end_group = mupdf_cp_end_group;
+    dev->super.begin_tile = mupdf_cp_begin_tile;
+    dev->super.end_tile = mupdf_cp_end_tile;
+    dev->super.render_flags = mupdf_cp_render_flags;
+    dev->super.set_default_colorspaces = mupdf_cp_set_default_colorspaces;
+    dev->super.begin_layer = mupdf_cp_begin_layer;
+    dev->super.end_layer = mupdf_cp_end_layer;
+    dev->target = target;
+    dev->params = params;
+    return (fz_device *)dev;
+}
+
+/* Run the page through `device` with `params` used for every color conversion, overriding those of the page content */
+void mupdf_run_page_with_color_params(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, fz_color_params params, mupdf_error_t **errptr)
+{
+    fz_device *dev = NULL;
+    fz_var(dev);
+    fz_try(ctx)
+    {
+        dev = mupdf_new_color_params_device(ctx, device, params);
+        fz_run_page(ctx, page, dev, ctm, NULL);
+        fz_close_device(ctx, dev);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+fz_pixmap *mupdf_page_to_pixmap_with_color_params(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, bool alpha, bool show_extras, fz_color_params params, mupdf_error_t **errptr)
 {
     fz_pixmap *pixmap = NULL;
+    fz_device *draw = NULL;
+    fz_device *dev = NULL;
+    fz_var(pixmap);
+    fz_var(draw);
+    fz_var(dev);
     fz_try(ctx)
     {
-        pixmap = fz_new_pixmap_from_display_list(ctx, list, ctm, cs, alpha);
+        fz_irect bbox = fz_round_rect(fz_transform_rect(fz_bound_page(ctx, page), ctm));
+        pixmap = fz_new_pixmap_with_bbox(ctx, cs, bbox, NULL, alpha);
+        if (alpha)
+            fz_clear_pixmap(ctx, pixmap);
+        else
+            fz_clear_pixmap_with_value(ctx, pixmap, 0xFF);
+        draw = fz_new_draw_device(ctx, ctm, pixmap);
+        dev = mupdf_new_color_params_device(ctx, draw, params);
+        if (show_extras)
+            fz_run_page(ctx, page, dev, fz_identity, NULL);
+        else
+            fz_run_page_contents(ctx, page, dev, fz_identity, NULL);
+        fz_close_device(ctx, dev);
+        fz_close_device(ctx, draw);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_device(ctx, draw);
+    }
+    fz_catch(ctx)
+    {
+        fz_drop_pixmap(ctx, pixmap);
+        pixmap = NULL;
+        mupdf_save_error(ctx, errptr);
+    }
+    return pixmap;
+}
+
+fz_buffer *mupdf_page_to_svg(fz_context *ctx, fz_page *page, fz_matrix ctm, mupdf_error_t **errptr)
+{
+    fz_rect mediabox = fz_bound_page(ctx, page);
+    fz_device *dev = NULL;
+    fz_buffer *buf = NULL;
+    fz_output *out = NULL;
+    fz_var(out);
+    fz_var(dev);
+    fz_var(buf);
+    fz_rect tbounds = mediabox;
+    tbounds = fz_transform_rect(tbounds, ctm);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer(ctx, 1024);
+        out = fz_new_output_with_buffer(ctx, buf);
+        dev = fz_new_svg_device(ctx, out, tbounds.x1 - tbounds.x0, tbounds.y1 - tbounds.y0, FZ_SVG_TEXT_AS_PATH, 1);
+        fz_run_page(ctx, page, dev, ctm, NULL);
+        fz_close_device(ctx, dev);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_output(ctx, out);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+fz_stext_page *mupdf_page_to_text_page(fz_context *ctx, fz_page *page, int flags, mupdf_error_t **errptr)
+{
+    fz_stext_page *text_page = NULL;
+    fz_stext_options opts = {0};
+    opts.flags = flags;
+    fz_try(ctx)
+    {
+        text_page = fz_new_stext_page_from_page(ctx, page, &opts);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return text_page;
+}
+
+fz_display_list *mupdf_page_to_display_list(fz_context *ctx, fz_page *page, bool annots, mupdf_error_t **errptr)
+{
+    fz_display_list *list = NULL;
+    fz_try(ctx)
+    {
+        if (annots)
+        {
+            list = fz_new_display_list_from_page(ctx, page);
+        }
+        else
+        {
+            list = fz_new_display_list_from_page_contents(ctx, page);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return list;
+}
+
+void mupdf_run_page(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_run_page(ctx, page, device, ctm, NULL);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_run_page_contents(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_run_page_contents(ctx, page, device, ctm, NULL);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_run_page_annots(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_run_page_annots(ctx, page, device, ctm, NULL);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_run_page_widgets(fz_context *ctx, fz_page *page, fz_device *device, fz_matrix ctm, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_run_page_widgets(ctx, page, device, ctm, NULL);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+fz_link *mupdf_load_links(fz_context *ctx, fz_page *page, mupdf_error_t **errptr)
+{
+    fz_link *link = NULL;
+    fz_try(ctx)
+    {
+        link = fz_load_links(ctx, page);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return link;
+}
+
+bool mupdf_page_presentation(fz_context *ctx, fz_page *page, fz_transition *transition, float *duration, mupdf_error_t **errptr)
+{
+    bool found = false;
+    fz_try(ctx)
+    {
+        found = fz_page_presentation(ctx, page, transition, duration) != NULL;
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return found;
+}
+
+fz_separations *mupdf_page_separations(fz_context *ctx, fz_page *page, mupdf_error_t **errptr)
+{
+    fz_separations *seps = NULL;
+    fz_try(ctx)
+    {
+        seps = fz_page_separations(ctx, page);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return seps;
+}
+
+fz_pixmap *mupdf_page_to_pixmap_with_separations(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, fz_separations *seps, bool alpha, mupdf_error_t **errptr)
+{
+    fz_pixmap *pixmap = NULL;
+    fz_try(ctx)
+    {
+        pixmap = fz_new_pixmap_from_page_with_separations(ctx, page, ctm, cs, seps, alpha);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return pixmap;
+}
+
+/* Draw into CMYK plus the page spot colors so overprinting can be simulated, then convert to `cs` */
+fz_pixmap *mupdf_page_to_pixmap_with_overprint(fz_context *ctx, fz_page *page, fz_matrix ctm, fz_colorspace *cs, bool alpha, bool show_extras, mupdf_error_t **errptr)
+{
+    fz_separations *seps = NULL;
+    fz_pixmap *cmyk = NULL;
+    fz_pixmap *pixmap = NULL;
+    fz_device *dev = NULL;
+    fz_var(seps);
+    fz_var(cmyk);
+    fz_var(dev);
+    fz_try(ctx)
+    {
+        fz_irect bbox = fz_round_rect(fz_transform_rect(fz_bound_page(ctx, page), ctm));
+        seps = fz_page_separations(ctx, page);
+        if (!seps)
+            seps = fz_new_separations(ctx, 0);
+        cmyk = fz_new_pixmap_with_bbox(ctx, fz_device_cmyk(ctx), bbox, seps, alpha);
+        if (alpha)
+            fz_clear_pixmap(ctx, cmyk);
+        else
+            fz_clear_pixmap_with_value(ctx, cmyk, 0xFF);
+        dev = fz_new_draw_device(ctx, ctm, cmyk);
+        if (show_extras)
+            fz_run_page(ctx, page, dev, fz_identity, NULL);
+        else
+            fz_run_page_contents(ctx, page, dev, fz_identity, NULL);
+        fz_close_device(ctx, dev);
+        pixmap = fz_clone_pixmap_area_with_different_seps(ctx, cmyk, NULL, cs, NULL, fz_default_color_params, NULL);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_pixmap(ctx, cmyk);
+        fz_drop_separations(ctx, seps);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return pixmap;
+}
+
+/* Device feeding the decoded samples of every image drawn on a page into an MD5 digest */
+typedef struct
+{
+    fz_device super;
+    fz_md5 *md5;
+} mupdf_image_digest_device;
+
+static void mupdf_image_digest(fz_context *ctx, fz_device *dev_, fz_image *image)
+{
+    mupdf_image_digest_device *dev = (mupdf_image_digest_device *)dev_;
+    unsigned char digest[16];
+    /* Decode rather than hash the stream so the filters used to store the image don't matter */
+    fz_pixmap *pix = fz_get_pixmap_from_image(ctx, image, NULL, NULL, NULL, NULL);
+    fz_try(ctx)
+    {
+        fz_md5_pixmap(ctx, pix, digest);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_pixmap(ctx, pix);
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+    fz_md5_update(dev->md5, digest, 16);
+}
+
+static void mupdf_image_digest_fill_image(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, float alpha, fz_color_params color_params)
+{
+    mupdf_image_digest(ctx, dev, image);
+}
+
+static void mupdf_image_digest_fill_image_mask(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params color_params)
+{
+    mupdf_image_digest(ctx, dev, image);
+}
+
+static void mupdf_image_digest_clip_image_mask(fz_context *ctx, fz_device *dev, fz_image *image, fz_matrix ctm, fz_rect scissor)
+{
+    mupdf_image_digest(ctx, dev, image);
+}
+
+/* MD5 of the page operations as traced by the trace device, followed by the image contents */
+void mupdf_page_fingerprint(fz_context *ctx, fz_page *page, unsigned char digest[16], mupdf_error_t **errptr)
+{
+    fz_md5 md5;
+    fz_buffer *buf = NULL;
+    fz_output *out = NULL;
+    fz_device *dev = NULL;
+    fz_var(buf);
+    fz_var(out);
+    fz_var(dev);
+    fz_md5_init(&md5);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer(ctx, 8192);
+        out = fz_new_output_with_buffer(ctx, buf);
+        dev = fz_new_trace_device(ctx, out);
+        fz_run_page(ctx, page, dev, fz_identity, NULL);
+        fz_close_device(ctx, dev);
+        fz_drop_device(ctx, dev);
+        dev = NULL;
+        fz_close_output(ctx, out);
+
+        unsigned char *data = NULL;
+        size_t len = fz_buffer_storage(ctx, buf, &data);
+        fz_md5_update(&md5, data, len);
+
+        mupdf_image_digest_device *idev = fz_new_derived_device(ctx, mupdf_image_digest_device);
+        idev->super.fill_image = mupdf_image_digest_fill_image;
+        idev->super.fill_image_mask = mupdf_image_digest_fill_image_mask;
+        idev->super.clip_image_mask = mupdf_image_digest_clip_image_mask;
+        idev->md5 = &md5;
+        dev = (fz_device *)idev;
+        fz_run_page(ctx, page, dev, fz_identity, NULL);
+        fz_close_device(ctx, dev);
+        fz_md5_final(&md5, digest);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_output(ctx, out);
+        fz_drop_buffer(ctx, buf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Cookie */
+fz_cookie *mupdf_new_cookie(fz_context *ctx, mupdf_error_t **errptr)
+{
+    fz_cookie *cookie = NULL;
+    fz_try(ctx)
+    {
+        cookie = fz_malloc_struct(ctx, fz_cookie);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return cookie;
+}
+
+/* DisplayList */
+fz_display_list *mupdf_new_display_list(fz_context *ctx, fz_rect mediabox, mupdf_error_t **errptr)
+{
+    fz_display_list *list = NULL;
+    fz_try(ctx)
+    {
+        list = fz_new_display_list(ctx, mediabox);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return list;
+}
+
+fz_pixmap *mupdf_display_list_to_pixmap(fz_context *ctx, fz_display_list *list, fz_matrix ctm, fz_colorspace *cs, bool alpha, mupdf_error_t **errptr)
+{
+    fz_pixmap *pixmap = NULL;
+    fz_try(ctx)
+    {
+        pixmap = fz_new_pixmap_from_display_list(ctx, list, ctm, cs, alpha);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return pixmap;
+}
+
+fz_stext_page *mupdf_display_list_to_text_page(fz_context *ctx, fz_display_list *list, int flags, mupdf_error_t **errptr)
+{
+    fz_stext_page *text_page = NULL;
+    fz_stext_options opts = {0};
+    opts.flags = flags;
+    fz_try(ctx)
+    {
+        text_page = fz_new_stext_page_from_display_list(ctx, list, &opts);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return text_page;
+}
+
+void mupdf_display_list_run(fz_context *ctx, fz_display_list *list, fz_device *device, fz_matrix ctm, fz_rect area, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_run_display_list(ctx, list, device, ctm, area, NULL);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* PDFObject */
+bool mupdf_pdf_is_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_indirect(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_null(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_null(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_bool(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_bool(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_int(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_int(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_real(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_real(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_number(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_number(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_string(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_string(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_name(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_name(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_array(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_array(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_dict(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_dict(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+bool mupdf_pdf_is_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int b = 0;
+    fz_try(ctx)
+    {
+        b = pdf_is_stream(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b ? true : false;
+}
+
+pdf_obj *mupdf_pdf_new_null()
+{
+    return PDF_NULL;
+}
+
+pdf_obj *mupdf_pdf_new_bool(bool b)
+{
+    return b ? PDF_TRUE : PDF_FALSE;
+}
+
+pdf_obj *mupdf_pdf_new_int(fz_context *ctx, int i, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_int(ctx, i);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_real(fz_context *ctx, float f, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_real(ctx, f);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_string(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_text_string(ctx, s);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_byte_string(fz_context *ctx, const unsigned char *bytes, size_t len, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_string(ctx, (const char *)bytes, len);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_name(fz_context *ctx, const char *name, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_name(ctx, name);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_indirect(fz_context *ctx, pdf_document *pdf, int num, int gen, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_indirect(ctx, pdf, num, gen);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_array(fz_context *ctx, pdf_document *pdf, int capacity, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_array(ctx, pdf, capacity);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_new_dict(fz_context *ctx, pdf_document *pdf, int capacity, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_new_dict(ctx, pdf, capacity);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_obj_from_str(fz_context *ctx, pdf_document *pdf, const char *src, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    pdf_lexbuf lexbuf;
+    fz_stream *stream = fz_open_memory(ctx, (unsigned char *)src, strlen(src));
+    pdf_lexbuf_init(ctx, &lexbuf, PDF_LEXBUF_SMALL);
+    fz_var(stream);
+    fz_try(ctx)
+    {
+        obj = pdf_parse_stm_obj(ctx, pdf, stream, &lexbuf);
+    }
+    fz_always(ctx)
+    {
+        pdf_lexbuf_fin(ctx, &lexbuf);
+        fz_drop_stream(ctx, stream);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+bool mupdf_pdf_to_bool(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    bool b = false;
+    fz_try(ctx)
+    {
+        b = pdf_to_bool(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return b;
+}
+
+int mupdf_pdf_to_int(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int i = 0;
+    fz_try(ctx)
+    {
+        i = pdf_to_int(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return i;
+}
+
+float mupdf_pdf_to_float(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    float f = 0.0;
+    fz_try(ctx)
+    {
+        f = pdf_to_real(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return f;
+}
+
+int mupdf_pdf_to_gen(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int gen = 0;
+    fz_try(ctx)
+    {
+        gen = pdf_to_gen(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return gen;
+}
+
+int mupdf_pdf_to_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int ind = 0;
+    fz_try(ctx)
+    {
+        ind = pdf_to_num(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+const char *mupdf_pdf_to_string(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    const char *s = NULL;
+    fz_try(ctx)
+    {
+        s = pdf_to_text_string(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return s;
+}
+
+const char *mupdf_pdf_to_name(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    const char *s = NULL;
+    fz_try(ctx)
+    {
+        s = pdf_to_name(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return s;
+}
+
+const unsigned char *mupdf_pdf_to_bytes(fz_context *ctx, pdf_obj *obj, size_t *len, mupdf_error_t **errptr)
+{
+    const char *s = NULL;
+    fz_try(ctx)
+    {
+        s = pdf_to_string(ctx, obj, len);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return (unsigned char *)s;
+}
+
+fz_buffer *mupdf_pdf_obj_to_buffer(fz_context *ctx, pdf_obj *obj, bool tight, bool ascii, mupdf_error_t **errptr)
+{
+    fz_output *out = NULL;
+    fz_buffer *buf = NULL;
+    fz_var(out);
+    fz_var(buf);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer(ctx, 512);
+        out = fz_new_output_with_buffer(ctx, buf);
+        pdf_print_obj(ctx, out, obj, tight, ascii);
+        fz_close_output(ctx, out);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_output(ctx, out);
+    }
+    fz_catch(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+        buf = NULL;
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+pdf_obj *mupdf_pdf_resolve_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_resolve_indirect(ctx, obj);
+        pdf_keep_obj(ctx, ind);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+pdf_obj *mupdf_pdf_array_get(fz_context *ctx, pdf_obj *obj, int index, mupdf_error_t **errptr)
+{
+    pdf_obj *val = NULL;
+    fz_try(ctx)
+    {
+        val = pdf_array_get(ctx, obj, index);
+        pdf_keep_obj(ctx, val);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return val;
+}
+
+pdf_obj *mupdf_pdf_dict_gets(fz_context *ctx, pdf_obj *obj, const char *key, mupdf_error_t **errptr)
+{
+    pdf_obj *val = NULL;
+    fz_try(ctx)
+    {
+        val = pdf_dict_gets(ctx, obj, key);
+        pdf_keep_obj(ctx, val);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return val;
+}
+
+pdf_obj *mupdf_pdf_dict_get_inheritable(fz_context *ctx, pdf_obj *obj, pdf_obj *key, mupdf_error_t **errptr)
+{
+    pdf_obj *val = NULL;
+    fz_try(ctx)
+    {
+        val = pdf_dict_get_inheritable(ctx, obj, key);
+        pdf_keep_obj(ctx, val);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return val;
+}
+
+fz_buffer *mupdf_pdf_read_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    fz_buffer *buf = NULL;
+    fz_try(ctx)
+    {
+        buf = pdf_load_stream(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+fz_buffer *mupdf_pdf_read_raw_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    fz_buffer *buf = NULL;
+    fz_try(ctx)
+    {
+        buf = pdf_load_raw_stream(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+void mupdf_pdf_write_object(fz_context *ctx, pdf_obj *self, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    pdf_document *pdf = pdf_get_bound_document(ctx, self);
+    if (!pdf)
+    {
+        *errptr = mupdf_new_error_from_str("object not bound to document");
+        return;
+    }
+    fz_try(ctx)
+    {
+        pdf_update_object(ctx, pdf, pdf_to_num(ctx, self), obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_write_stream_buffer(fz_context *ctx, pdf_obj *obj, fz_buffer *buf, int compressed, mupdf_error_t **errptr)
+{
+    pdf_document *pdf = pdf_get_bound_document(ctx, obj);
+    if (!pdf)
+    {
+        *errptr = mupdf_new_error_from_str("object not bound to document");
+        return;
+    }
+    fz_try(ctx)
+    {
+        pdf_update_stream(ctx, pdf, obj, buf, compressed);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Replace the stream data with `data`, Flate compressing it when `compress` is set */
+void mupdf_pdf_update_stream(fz_context *ctx, pdf_obj *obj, const unsigned char *data, size_t len, bool compress, mupdf_error_t **errptr)
+{
+    pdf_document *pdf = pdf_get_bound_document(ctx, obj);
+    fz_buffer *buf = NULL;
+    if (!pdf)
+    {
+        *errptr = mupdf_new_error_from_str("object not bound to document");
+        return;
+    }
+    fz_var(buf);
+    fz_try(ctx)
+    {
+        if (compress)
+        {
+            size_t size = fz_deflate_bound(ctx, len);
+            buf = fz_new_buffer(ctx, size);
+            fz_deflate(ctx, buf->data, &size, data, len, FZ_DEFLATE_DEFAULT);
+            buf->len = size;
+            pdf_update_stream(ctx, pdf, obj, buf, 1);
+            pdf_dict_put(ctx, obj, PDF_NAME(Filter), PDF_NAME(FlateDecode));
+            pdf_dict_del(ctx, obj, PDF_NAME(DecodeParms));
+        }
+        else
+        {
+            buf = fz_new_buffer_from_copied_data(ctx, data, len);
+            pdf_update_stream(ctx, pdf, obj, buf, 0);
+        }
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+int mupdf_pdf_array_len(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int len = 0;
+    fz_try(ctx)
+    {
+        len = pdf_array_len(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return len;
+}
+
+int mupdf_pdf_dict_len(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    int len = 0;
+    fz_try(ctx)
+    {
+        len = pdf_dict_len(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return len;
+}
+
+pdf_obj *mupdf_pdf_dict_get_key(fz_context *ctx, pdf_obj *obj, int index, mupdf_error_t **errptr)
+{
+    pdf_obj *key = NULL;
+    fz_try(ctx)
+    {
+        key = pdf_dict_get_key(ctx, obj, index);
+        pdf_keep_obj(ctx, key);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return key;
+}
+
+pdf_obj *mupdf_pdf_dict_get_val(fz_context *ctx, pdf_obj *obj, int index, mupdf_error_t **errptr)
+{
+    pdf_obj *val = NULL;
+    fz_try(ctx)
+    {
+        val = pdf_dict_get_val(ctx, obj, index);
+        pdf_keep_obj(ctx, val);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return val;
+}
+
+void mupdf_pdf_array_put(fz_context *ctx, pdf_obj *self, int i, pdf_obj *item, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_array_put(ctx, self, i, item);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_array_push(fz_context *ctx, pdf_obj *self, pdf_obj *item, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_array_push(ctx, self, item);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_array_delete(fz_context *ctx, pdf_obj *self, int i, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_array_delete(ctx, self, i);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_dict_put(fz_context *ctx, pdf_obj *self, pdf_obj *key, pdf_obj *value, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_dict_put(ctx, self, key, value);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_dict_delete(fz_context *ctx, pdf_obj *self, pdf_obj *key, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_dict_del(ctx, self, key);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Buffer */
+size_t mupdf_buffer_read_bytes(fz_context *ctx, fz_buffer *buf, size_t at, unsigned char *output, size_t buf_len, mupdf_error_t **errptr)
+{
+    size_t remaining_input = 0;
+    unsigned char *data;
+    size_t len = fz_buffer_storage(ctx, buf, &data);
+    if (at == len)
+    {
+        // EOF
+        return 0;
+    }
+    else if (at > len)
+    {
+        *errptr = mupdf_new_error_from_str("invalid offset, offset > buffer length");
+        return 0;
+    }
+    remaining_input = len - at;
+    len = fz_minz(buf_len, remaining_input);
+    memcpy(output, &data[at], len);
+    return len;
+}
+
+void mupdf_buffer_write_bytes(fz_context *ctx, fz_buffer *buf, const unsigned char *bytes, size_t len, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_append_data(ctx, buf, bytes, len);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+fz_buffer *mupdf_buffer_from_str(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+{
+    fz_buffer *buf = NULL;
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer_from_copied_data(ctx, (const unsigned char *)s, strlen(s));
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+fz_buffer *mupdf_buffer_from_base64(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+{
+    fz_buffer *buf = NULL;
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer_from_base64(ctx, s, strlen(s));
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+/* Document */
+fz_document *mupdf_open_document(fz_context *ctx, const char *filename, mupdf_error_t **errptr)
+{
+    fz_document *doc = NULL;
+    fz_try(ctx)
+    {
+        doc = fz_open_document(ctx, filename);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return doc;
+}
+
+fz_document *mupdf_open_document_from_bytes(fz_context *ctx, fz_buffer *bytes, const char *magic, mupdf_error_t **errptr)
+{
+    if (!magic)
+    {
+        return NULL;
+    }
+    fz_document *doc = NULL;
+    fz_stream *stream = NULL;
+    fz_var(stream);
+    fz_try(ctx)
+    {
+        stream = fz_open_buffer(ctx, bytes);
+        doc = fz_open_document_with_stream(ctx, magic, stream);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_stream(ctx, stream);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return doc;
+}
+
+/* A stream over a buffer that is still being filled, for documents opened before they are fully downloaded */
+typedef struct
+{
+    fz_buffer *buffer;
+    size_t length;
+    unsigned char scratch[4096];
+} mupdf_progressive_state;
+
+static int mupdf_progressive_next(fz_context *ctx, fz_stream *stm, size_t max)
+{
+    mupdf_progressive_state *state = stm->state;
+    unsigned char *data;
+    size_t available = fz_buffer_storage(ctx, state->buffer, &data);
+    size_t pos = (size_t)stm->pos;
+    size_t n;
+    if (pos >= state->length)
+    {
+        return EOF;
+    }
+    if (pos >= available)
+    {
+        fz_throw(ctx, FZ_ERROR_TRYLATER, "waiting for data at offset %zu", pos);
+    }
+    /* Copy out, the buffer data moves when the caller appends to it */
+    n = fz_minz(fz_minz(available, state->length) - pos, fz_minz(max, sizeof state->scratch));
+    memcpy(state->scratch, data + pos, n);
+    stm->rp = state->scratch;
+    stm->wp = state->scratch + n;
+    stm->pos += n;
+    return *stm->rp++;
+}
+
+static void mupdf_progressive_seek(fz_context *ctx, fz_stream *stm, int64_t offset, int whence)
+{
+    mupdf_progressive_state *state = stm->state;
+    /* fz_seek turns SEEK_CUR into SEEK_SET before calling us */
+    if (whence == SEEK_END)
+    {
+        offset += state->length;
+    }
+    if (offset < 0)
+    {
+        offset = 0;
+    }
+    if ((size_t)offset > state->length)
+    {
+        offset = state->length;
+    }
+    stm->pos = offset;
+    stm->rp = stm->wp = state->scratch;
+}
+
+static void mupdf_progressive_drop(fz_context *ctx, void *state_)
+{
+    mupdf_progressive_state *state = state_;
+    fz_drop_buffer(ctx, state->buffer);
+    fz_free(ctx, state);
+}
+
+fz_document *mupdf_open_document_progressive(fz_context *ctx, const char *magic, fz_buffer *buffer, size_t length, mupdf_error_t **errptr)
+{
+    fz_document *doc = NULL;
+    fz_stream *stream = NULL;
+    fz_var(stream);
+    fz_try(ctx)
+    {
+        mupdf_progressive_state *state = fz_malloc_struct(ctx, mupdf_progressive_state);
+        state->buffer = fz_keep_buffer(ctx, buffer);
+        state->length = length;
+        /* fz_new_stream drops the state itself if it throws */
+        stream = fz_new_stream(ctx, state, mupdf_progressive_next, mupdf_progressive_drop);
+        stream->seek = mupdf_progressive_seek;
+        // Lets linearized PDFs load the first page from the hint tables
+        // instead of waiting for the whole xref
+        stream->progressive = 1;
+        doc = fz_open_document_with_stream(ctx, magic, stream);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_stream(ctx, stream);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return doc;
+}
+
+bool mupdf_recognize_document(fz_context *ctx, const char *magic, mupdf_error_t **errptr)
+{
+    if (!magic)
+    {
+        return false;
+    }
+    bool recognized = false;
+    fz_try(ctx)
+    {
+        recognized = fz_recognize_document(ctx, magic) != NULL;
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return recognized;
+}
+
+bool mupdf_needs_password(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+{
+    bool needs = false;
+    fz_try(ctx)
+    {
+        needs = fz_needs_password(ctx, doc);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return needs;
+}
+
+bool mupdf_authenticate_password(fz_context *ctx, fz_document *doc, const char *password, mupdf_error_t **errptr)
+{
+    bool ok = false;
+    fz_try(ctx)
+    {
+        ok = fz_authenticate_password(ctx, doc, password);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ok;
+}
+
+int mupdf_document_page_count(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+{
+    int count = 0;
+    fz_try(ctx)
+    {
+        count = fz_count_pages(ctx, doc);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return count;
+}
+
+char *mupdf_lookup_metadata(fz_context *ctx, fz_document *doc, const char *key, mupdf_error_t **errptr)
+{
+    int len;
+    char *value = NULL;
+    fz_try(ctx)
+    {
+        len = fz_lookup_metadata(ctx, doc, key, NULL, 0) + 1;
+        if (len > 1)
+        {
+            value = calloc(len, sizeof(char));
+            fz_lookup_metadata(ctx, doc, key, value, len);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return value;
+}
+
+bool mupdf_is_document_reflowable(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+{
+    bool is_reflowable = false;
+    fz_try(ctx)
+    {
+        is_reflowable = fz_is_document_reflowable(ctx, doc);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return is_reflowable;
+}
+
+void mupdf_layout_document(fz_context *ctx, fz_document *doc, float w, float h, float em, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        if (!fz_is_document_reflowable(ctx, doc))
+        {
+            return;
+        }
+        if (w <= 0.0f || h <= 0.0f)
+        {
+            *errptr = mupdf_new_error_from_str("invalid width or height");
+            return;
+        }
+        fz_layout_document(ctx, doc, w, h, em);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+fz_bookmark mupdf_make_bookmark(fz_context *ctx, fz_document *doc, int page_no, mupdf_error_t **errptr)
+{
+    fz_bookmark mark = 0;
+    fz_try(ctx)
+    {
+        mark = fz_make_bookmark(ctx, doc, page_no);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return mark;
+}
+
+int mupdf_lookup_bookmark(fz_context *ctx, fz_document *doc, fz_bookmark mark, mupdf_error_t **errptr)
+{
+    int page_no = -1;
+    fz_try(ctx)
+    {
+        page_no = fz_lookup_bookmark(ctx, doc, mark);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page_no;
+}
+
+fz_page *mupdf_load_page(fz_context *ctx, fz_document *doc, int page_no, mupdf_error_t **errptr)
+{
+    fz_page *page = NULL;
+    fz_try(ctx)
+    {
+        page = fz_load_page(ctx, doc, page_no);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page;
+}
+
+/* Whether the page object has arrived, PDF page trees are looked up without loading the page */
+bool mupdf_page_ready(fz_context *ctx, fz_document *doc, int page_no, mupdf_error_t **errptr)
+{
+    bool ready = false;
+    fz_page *page = NULL;
+    fz_var(page);
+    fz_try(ctx)
+    {
+        pdf_document *pdf = pdf_specifics(ctx, doc);
+        if (pdf)
+            pdf_lookup_page_obj(ctx, pdf, page_no);
+        else
+            page = fz_load_page(ctx, doc, page_no);
+        ready = true;
+    }
+    fz_always(ctx)
+    {
+        fz_drop_page(ctx, page);
+    }
+    fz_catch(ctx)
+    {
+        if (fz_caught(ctx) != FZ_ERROR_TRYLATER)
+        {
+            mupdf_save_error(ctx, errptr);
+        }
+    }
+    return ready;
+}
+
+static pdf_document *mupdf_convert_to_pdf_internal(fz_context *ctx, fz_document *doc, int fp, int tp, int rotate)
+{
+    pdf_document *pdfout = pdf_create_document(ctx);
+    int i, incr = 1, s = fp, e = tp;
+    if (fp > tp) // revert page sequence
+    {
+        incr = -1;
+        s = tp;
+        e = fp;
+    }
+    fz_rect mediabox;
+    fz_device *dev = NULL;
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    fz_page *page;
+    fz_var(dev);
+    fz_var(contents);
+    fz_var(resources);
+    fz_var(page);
+    for (i = fp; i >= s && i <= e; i += incr)
+    {
+        fz_try(ctx)
+        {
+            page = fz_load_page(ctx, doc, i);
+            mediabox = fz_bound_page(ctx, page);
+            dev = pdf_page_write(ctx, pdfout, mediabox, &resources, &contents);
+            fz_run_page(ctx, page, dev, fz_identity, NULL);
+            fz_close_device(ctx, dev);
+            fz_drop_device(ctx, dev);
+            dev = NULL;
+            pdf_obj *page_obj = pdf_add_page(ctx, pdfout, mediabox, rotate, resources, contents);
+            pdf_insert_page(ctx, pdfout, -1, page_obj);
+            pdf_drop_obj(ctx, page_obj);
+        }
+        fz_always(ctx)
+        {
+            pdf_drop_obj(ctx, resources);
+            fz_drop_buffer(ctx, contents);
+            fz_drop_device(ctx, dev);
+        }
+        fz_catch(ctx)
+        {
+            fz_drop_page(ctx, page);
+            fz_rethrow(ctx);
+        }
+    }
+    return pdfout;
+}
+
+pdf_document *mupdf_convert_to_pdf(fz_context *ctx, fz_document *doc, int fp, int tp, int rotate, mupdf_error_t **errptr)
+{
+    if (rotate % 90)
+    {
+        *errptr = mupdf_new_error_from_str("rotation not multiple of 90");
+        return NULL;
+    }
+    pdf_document *pdf = NULL;
+    fz_try(ctx)
+    {
+        pdf = mupdf_convert_to_pdf_internal(ctx, doc, fp, tp, rotate);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return pdf;
+}
+
+int mupdf_resolve_link(fz_context *ctx, fz_document *doc, const char *uri, mupdf_error_t **errptr)
+{
+    int page_no = -1;
+    float xp = 0.0f, yp = 0.0f;
+    fz_try(ctx)
+    {
+        page_no = fz_resolve_link(ctx, doc, uri, &xp, &yp);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page_no;
+}
+
+int mupdf_resolve_link_dest(fz_context *ctx, fz_document *doc, const char *uri, float *xp, float *yp, mupdf_error_t **errptr)
+{
+    int page_no = -1;
+    fz_try(ctx)
+    {
+        page_no = fz_resolve_link(ctx, doc, uri, xp, yp);
+        /* fz_resolve_link zeroes the point of links without one, internal links carry
+           their target point as "#page,x,y" */
+        if (uri[0] != '#' || !strchr(uri, ','))
+        {
+            *xp = NAN;
+            *yp = NAN;
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page_no;
+}
+
+fz_outline *mupdf_load_outline(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+{
+    fz_outline *outline = NULL;
+    fz_try(ctx)
+    {
+        outline = fz_load_outline(ctx, doc);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return outline;
+}
+
+/* PdfDocument */
+pdf_document *mupdf_pdf_open_document_from_bytes(fz_context *ctx, fz_buffer *bytes, mupdf_error_t **errptr)
+{
+    pdf_document *pdf = NULL;
+    fz_stream *stream = NULL;
+    fz_var(stream);
+    fz_try(ctx)
+    {
+        stream = fz_open_buffer(ctx, bytes);
+        pdf = pdf_open_document_with_stream(ctx, stream);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_stream(ctx, stream);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return pdf;
+}
+
+pdf_obj *mupdf_pdf_add_object(fz_context *ctx, pdf_document *pdf, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_add_object(ctx, pdf, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+pdf_obj *mupdf_pdf_create_object(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_new_indirect(ctx, pdf, pdf_create_object(ctx, pdf), 0);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+void mupdf_pdf_update_object(fz_context *ctx, pdf_document *pdf, int num, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_update_object(ctx, pdf, num, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_delete_object(fz_context *ctx, pdf_document *pdf, int num, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_delete_object(ctx, pdf, num);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+pdf_obj *mupdf_pdf_add_image(fz_context *ctx, pdf_document *pdf, fz_image *image, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_add_image(ctx, pdf, image);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+pdf_obj *mupdf_pdf_add_font(fz_context *ctx, pdf_document *pdf, fz_font *font, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_add_cid_font(ctx, pdf, font);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+pdf_obj *mupdf_pdf_add_cjk_font(fz_context *ctx, pdf_document *pdf, fz_font *font, int ordering, int wmode, bool serif, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_add_cjk_font(ctx, pdf, font, ordering, wmode, serif);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+pdf_obj *mupdf_pdf_add_simple_font(fz_context *ctx, pdf_document *pdf, fz_font *font, int encoding, mupdf_error_t **errptr)
+{
+    pdf_obj *ind = NULL;
+    fz_try(ctx)
+    {
+        ind = pdf_add_simple_font(ctx, pdf, font, encoding);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ind;
+}
+
+void mupdf_pdf_save_document(fz_context *ctx, pdf_document *pdf, const char *filename, pdf_write_options pwo, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_save_document(ctx, pdf, filename, &pwo);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Placeholders for the two /ID strings, all bytes below 32 so they are written as hex strings */
+static const unsigned char mupdf_id_placeholder[2][16] = {
+    {0x1f, 0x01, 0x1e, 0x02, 0x1d, 0x03, 0x1c, 0x04, 0x1b, 0x05, 0x1a, 0x06, 0x19, 0x07, 0x18, 0x08},
+    {0x08, 0x18, 0x07, 0x19, 0x06, 0x1a, 0x05, 0x1b, 0x04, 0x1c, 0x03, 0x1d, 0x02, 0x1e, 0x01, 0x1f},
+};
+
+static void mupdf_set_id(fz_context *ctx, pdf_document *pdf, const unsigned char *first, const unsigned char *second)
+{
+    pdf_obj *id = pdf_new_array(ctx, pdf, 2);
+    fz_try(ctx)
+    {
+        pdf_array_push_drop(ctx, id, pdf_new_string(ctx, (const char *)first, 16));
+        pdf_array_push_drop(ctx, id, pdf_new_string(ctx, (const char *)second, 16));
+        pdf_dict_put(ctx, pdf_trailer(ctx, pdf), PDF_NAME(ID), id);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, id);
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+}
+
+static fz_buffer *mupdf_write_buffer(fz_context *ctx, pdf_document *pdf, pdf_write_options *pwo)
+{
+    fz_output *out = NULL;
+    fz_buffer *buf = fz_new_buffer(ctx, 8192);
+    fz_var(out);
+    fz_try(ctx)
+    {
+        out = fz_new_output_with_buffer(ctx, buf);
+        pdf_write_document(ctx, pdf, out, pwo);
+        fz_close_output(ctx, out);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_output(ctx, out);
+    }
+    fz_catch(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+        fz_rethrow(ctx);
+    }
+    return buf;
+}
+
+static void mupdf_md5_buffer(fz_context *ctx, fz_buffer *buf, unsigned char digest[16])
+{
+    fz_md5 md5;
+    unsigned char *data = NULL;
+    size_t len = fz_buffer_storage(ctx, buf, &data);
+    fz_md5_init(&md5);
+    fz_md5_update(&md5, data, len);
+    fz_md5_final(&md5, digest);
+}
+
+/* Overwrite every hex string `<from>` in the buffer with `to`, returns the number replaced */
+static int mupdf_patch_hex_string(fz_context *ctx, fz_buffer *buf, const unsigned char *from, const unsigned char *to)
+{
+    static const char *digits[2] = {"0123456789ABCDEF", "0123456789abcdef"};
+    char hex[2][32], out[32];
+    unsigned char *data = NULL;
+    size_t len = fz_buffer_storage(ctx, buf, &data);
+    size_t pos;
+    int i, k, found = 0;
+    for (i = 0; i < 16; i++)
+    {
+        for (k = 0; k < 2; k++)
+        {
+            hex[k][2 * i] = digits[k][from[i] >> 4];
+            hex[k][2 * i + 1] = digits[k][from[i] & 15];
+        }
+        out[2 * i] = digits[0][to[i] >> 4];
+        out[2 * i + 1] = digits[0][to[i] & 15];
+    }
+    for (pos = 0; pos + 34 <= len; pos++)
+    {
+        if (data[pos] != '<' || data[pos + 33] != '>')
+            continue;
+        for (k = 0; k < 2; k++)
+        {
+            if (memcmp(data + pos + 1, hex[k], 32) == 0)
+            {
+                memcpy(data + pos + 1, out, 32);
+                found++;
+                pos += 33;
+                break;
+            }
+        }
+    }
+    return found;
+}
+
+static int mupdf_writes_encrypted(pdf_document *pdf, const pdf_write_options *pwo)
+{
+    if (pwo->do_encrypt == PDF_ENCRYPT_KEEP)
+        return pdf->crypt != NULL;
+    return pwo->do_encrypt != PDF_ENCRYPT_NONE;
+}
+
+fz_buffer *mupdf_pdf_write_document(fz_context *ctx, pdf_document *pdf, pdf_write_options pwo, mupdf_error_t **errptr)
+{
+    fz_buffer *buf = NULL;
+    fz_try(ctx)
+    {
+        buf = mupdf_write_buffer(ctx, pdf, &pwo);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+/* Write the document with the changing /ID string set to the MD5 digest of the output. The
+   permanent string is kept from the document, or also taken from the digest when there is none.
+   The trailer and the unsaved changes flag are left as they were. */
+fz_buffer *mupdf_pdf_write_document_deterministic(fz_context *ctx, pdf_document *pdf, pdf_write_options pwo, mupdf_error_t **errptr)
+{
+    pdf_obj *trailer = pdf_trailer(ctx, pdf);
+    pdf_obj *old_id = pdf_keep_obj(ctx, pdf_dict_get(ctx, trailer, PDF_NAME(ID)));
+    int dirty = pdf->dirty;
+    fz_buffer *buf = NULL;
+    unsigned char first[16], digest[16];
+    int have_first = 0;
+    fz_var(buf);
+    fz_var(have_first);
+    fz_try(ctx)
+    {
+        pdf_obj *old_first = pdf_array_get(ctx, old_id, 0);
+        if (pdf_is_string(ctx, old_first) && pdf_to_str_len(ctx, old_first) == 16)
+        {
+            memcpy(first, pdf_to_str_buf(ctx, old_first), 16);
+            have_first = 1;
+        }
+        else if (mupdf_writes_encrypted(pdf, &pwo))
+        {
+            /* The permanent string is part of the encryption key, so it can't be patched after writing */
+            mupdf_set_id(ctx, pdf, mupdf_id_placeholder[0], mupdf_id_placeholder[1]);
+            buf = mupdf_write_buffer(ctx, pdf, &pwo);
+            mupdf_md5_buffer(ctx, buf, first);
+            fz_drop_buffer(ctx, buf);
+            buf = NULL;
+            have_first = 1;
+        }
+        mupdf_set_id(ctx, pdf, have_first ? first : mupdf_id_placeholder[0], mupdf_id_placeholder[1]);
+        buf = mupdf_write_buffer(ctx, pdf, &pwo);
+        mupdf_md5_buffer(ctx, buf, digest);
+        if (!have_first && mupdf_patch_hex_string(ctx, buf, mupdf_id_placeholder[0], digest) == 0)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "cannot find the document ID in the output");
+        if (mupdf_patch_hex_string(ctx, buf, mupdf_id_placeholder[1], digest) == 0)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "cannot find the document ID in the output");
+    }
+    fz_always(ctx)
+    {
+        fz_try(ctx)
+        {
+            if (old_id)
+                pdf_dict_put(ctx, trailer, PDF_NAME(ID), old_id);
+            else
+                pdf_dict_del(ctx, trailer, PDF_NAME(ID));
+        }
+        fz_catch(ctx)
+        {
+            fz_warn(ctx, "cannot restore the document ID");
+        }
+        pdf_drop_obj(ctx, old_id);
+        pdf->dirty = dirty;
+    }
+    fz_catch(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
+        buf = NULL;
+        mupdf_save_error(ctx, errptr);
+    }
+    return buf;
+}
+
+void mupdf_pdf_enable_js(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_enable_js(ctx, pdf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_disable_js(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        pdf_disable_js(ctx, pdf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+bool mupdf_pdf_js_supported(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    bool supported = false;
+    fz_try(ctx)
+    {
+        supported = pdf_js_supported(ctx, pdf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return supported;
+}
+
+void mupdf_pdf_calculate_form(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        if (pdf->recalculate)
+        {
+            pdf_calculate_form(ctx, pdf);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+pdf_obj *mupdf_pdf_trailer(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_trailer(ctx, pdf);
+        pdf_keep_obj(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+pdf_obj *mupdf_pdf_catalog(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_dict_get(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root));
+        pdf_keep_obj(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+int mupdf_pdf_count_objects(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    int count = 0;
+    fz_try(ctx)
+    {
+        count = pdf_xref_len(ctx, pdf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return count;
+}
+
+/* Xref entry type of object `num`: 'n' in use, 'o' in an object stream, 'f' free, 0 unset */
+/* Type of the cross-reference entry `num`, with the generation of the object it holds in `gen` */
+char mupdf_pdf_xref_entry_type(fz_context *ctx, pdf_document *pdf, int num, int *gen, mupdf_error_t **errptr)
+{
+    char type = 0;
+    fz_try(ctx)
+    {
+        pdf_xref_entry *entry = pdf_get_xref_entry(ctx, pdf, num);
+        type = entry->type;
+        /* Objects in object streams keep their index in the stream there, their generation is 0 */
+        *gen = type == 'o' ? 0 : entry->gen;
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return type;
+}
+
+pdf_graft_map *mupdf_pdf_new_graft_map(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+{
+    pdf_graft_map *map = NULL;
+    fz_try(ctx)
+    {
+        map = pdf_new_graft_map(ctx, pdf);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return map;
+}
+
+pdf_obj *mupdf_pdf_graft_object(fz_context *ctx, pdf_document *doc, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    pdf_obj *graft_obj = NULL;
+    fz_try(ctx)
+    {
+        graft_obj = pdf_graft_object(ctx, doc, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return graft_obj;
+}
+
+pdf_obj *mupdf_pdf_graft_mapped_object(fz_context *ctx, pdf_graft_map *map, pdf_obj *obj, mupdf_error_t **errptr)
+{
+    pdf_obj *graft_obj = NULL;
+    fz_try(ctx)
+    {
+        graft_obj = pdf_graft_mapped_object(ctx, map, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return graft_obj;
+}
+
+void mupdf_pdf_graft_mapped_page(fz_context *ctx, pdf_graft_map *map, pdf_document *dst, int page_to, pdf_document *src, int page_from, mupdf_error_t **errptr)
+{
+    static pdf_obj *const known_page_objs[] = {
+        PDF_NAME(Contents),
+        PDF_NAME(Resources),
+        PDF_NAME(MediaBox),
+        PDF_NAME(CropBox),
+        PDF_NAME(BleedBox),
+        PDF_NAME(TrimBox),
+        PDF_NAME(ArtBox),
+        PDF_NAME(Rotate),
+        PDF_NAME(UserUnit)};
+    pdf_obj *page_ref = NULL;
+    pdf_obj *page_dict = NULL;
+    pdf_obj *ref = NULL;
+    int i;
+    fz_var(page_dict);
+    fz_var(ref);
+    fz_try(ctx)
+    {
+        page_ref = pdf_lookup_page_obj(ctx, src, page_from);
+        pdf_flatten_inheritable_page_items(ctx, page_ref);
+
+        page_dict = pdf_new_dict(ctx, dst, 4);
+        pdf_dict_put(ctx, page_dict, PDF_NAME(Type), PDF_NAME(Page));
+        for (i = 0; i < (int)nelem(known_page_objs); i++)
+        {
+            pdf_obj *obj = pdf_dict_get(ctx, page_ref, known_page_objs[i]);
+            if (obj != NULL)
+                pdf_dict_put_drop(ctx, page_dict, known_page_objs[i], pdf_graft_mapped_object(ctx, map, obj));
+        }
+
+        ref = pdf_add_object(ctx, dst, page_dict);
+        pdf_insert_page(ctx, dst, page_to, ref);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, page_dict);
+        pdf_drop_obj(ctx, ref);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Copy page `page_from` of `src` as a form XObject drawing it, rotated and cropped, in (0, 0, w, h) */
+pdf_obj *mupdf_pdf_graft_page_as_xobject(fz_context *ctx, pdf_graft_map *map, pdf_document *dst, pdf_document *src, int page_from, fz_rect *bounds, mupdf_error_t **errptr)
+{
+    pdf_page *page = NULL;
+    fz_buffer *contents = NULL;
+    fz_buffer *buf = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *xobj = NULL;
+    fz_var(page);
+    fz_var(contents);
+    fz_var(buf);
+    fz_var(resources);
+    fz_try(ctx)
+    {
+        fz_rect mediabox, box;
+        fz_matrix ctm;
+        pdf_obj *obj;
+        page = pdf_load_page(ctx, src, page_from);
+        pdf_page_transform(ctx, page, &mediabox, &ctm);
+        box = fz_transform_rect(mediabox, ctm);
+        *bounds = fz_make_rect(0, 0, box.x1 - box.x0, box.y1 - box.y0);
+        /* The page transform has y going down, flip it back for PDF space */
+        ctm = fz_concat(ctm, fz_make_matrix(1, 0, 0, -1, 0, bounds->y1));
+
+        contents = fz_new_buffer(ctx, 1024);
+        obj = pdf_dict_get(ctx, page->obj, PDF_NAME(Contents));
+        if (pdf_is_array(ctx, obj))
+        {
+            int i, n = pdf_array_len(ctx, obj);
+            for (i = 0; i < n; i++)
+            {
+                buf = pdf_load_stream(ctx, pdf_array_get(ctx, obj, i));
+                fz_append_buffer(ctx, contents, buf);
+                fz_append_byte(ctx, contents, '\n');
+                fz_drop_buffer(ctx, buf);
+                buf = NULL;
+            }
+        }
+        else if (pdf_is_stream(ctx, obj))
+        {
+            buf = pdf_load_stream(ctx, obj);
+            fz_append_buffer(ctx, contents, buf);
+        }
+
+        obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(Resources));
+        resources = obj ? pdf_graft_mapped_object(ctx, map, obj) : pdf_new_dict(ctx, dst, 0);
+        xobj = pdf_new_xobject(ctx, dst, mediabox, ctm, resources, contents);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, resources);
+        fz_drop_buffer(ctx, buf);
+        fz_drop_buffer(ctx, contents);
+        fz_drop_page(ctx, (fz_page *)page);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return xobj;
+}
+
+/* Insert a page object for `mediabox` with `resources` and `contents` before `page_no` and load it */
+static pdf_page *mupdf_pdf_insert_new_page(fz_context *ctx, pdf_document *pdf, int page_no, fz_rect mediabox, pdf_obj *resources, fz_buffer *contents)
+{
+    pdf_obj *page_obj = pdf_add_page(ctx, pdf, mediabox, 0, resources, contents);
+    fz_try(ctx)
+        pdf_insert_page(ctx, pdf, page_no, page_obj);
+    fz_always(ctx)
+        pdf_drop_obj(ctx, page_obj);
+    fz_catch(ctx)
+        fz_rethrow(ctx);
+    pdf->dirty = 1;
+    int n = page_no;
+    int page_count = pdf_count_pages(ctx, pdf);
+    while (n < 0)
+    {
+        n += page_count;
+    }
+    fz_page *fz_page = fz_load_page(ctx, &pdf->super, n);
+    return pdf_page_from_fz_page(ctx, fz_page);
+}
+
+pdf_page *mupdf_pdf_new_page(fz_context *ctx, pdf_document *pdf, int page_no, float width, float height, mupdf_error_t **errptr)
+{
+    fz_rect mediabox = fz_unit_rect;
+    mediabox.x1 = width;
+    mediabox.y1 = height;
+    pdf_obj *resources = NULL;
+    pdf_page *page = NULL;
+    fz_var(resources);
+    fz_try(ctx)
+    {
+        // create /Resources and /Contents objects
+        resources = pdf_add_object_drop(ctx, pdf, pdf_new_dict(ctx, pdf, 1));
+        page = mupdf_pdf_insert_new_page(ctx, pdf, page_no, mediabox, resources, NULL);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, resources);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page;
+}
+
+/* Device drawing into new page `resources` and `contents` for `mediabox`, with y going down */
+fz_device *mupdf_pdf_page_write(fz_context *ctx, pdf_document *pdf, fz_rect mediabox, pdf_obj **resources, fz_buffer **contents, mupdf_error_t **errptr)
+{
+    fz_device *dev = NULL;
+    *resources = NULL;
+    *contents = NULL;
+    fz_try(ctx)
+    {
+        dev = pdf_page_write(ctx, pdf, mediabox, resources, contents);
+    }
+    fz_catch(ctx)
+    {
+        pdf_drop_obj(ctx, *resources);
+        fz_drop_buffer(ctx, *contents);
+        *resources = NULL;
+        *contents = NULL;
+        mupdf_save_error(ctx, errptr);
+    }
+    return dev;
+}
+
+pdf_page *mupdf_pdf_add_page(fz_context *ctx, pdf_document *pdf, int page_no, fz_rect mediabox, pdf_obj *resources, fz_buffer *contents, mupdf_error_t **errptr)
+{
+    pdf_page *page = NULL;
+    fz_try(ctx)
+    {
+        page = mupdf_pdf_insert_new_page(ctx, pdf, page_no, mediabox, resources, contents);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page;
+}
+
+pdf_obj *mupdf_pdf_lookup_page_obj(fz_context *ctx, pdf_document *pdf, int page_no, mupdf_error_t **errptr)
+{
+    pdf_obj *obj = NULL;
+    fz_try(ctx)
+    {
+        obj = pdf_lookup_page_obj(ctx, pdf, page_no);
+        pdf_keep_obj(ctx, obj);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return obj;
+}
+
+int mupdf_pdf_lookup_page_number(fz_context *ctx, pdf_document *pdf, pdf_obj *page, mupdf_error_t **errptr)
+{
+    int page_no = -1;
+    fz_try(ctx)
+    {
+        page_no = pdf_lookup_page_number(ctx, pdf, page);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return page_no;
+}
+
+/* Destination array for `page_no`, a /XYZ at the page space point (x, y) or /Fit when it is NAN */
+static pdf_obj *mupdf_pdf_new_page_dest(fz_context *ctx, pdf_document *pdf, int page_no, float x, float y)
+{
+    pdf_obj *target = pdf_lookup_page_obj(ctx, pdf, page_no);
+    pdf_obj *dest = pdf_new_array(ctx, pdf, 5);
+    fz_try(ctx)
+    {
+        pdf_array_push(ctx, dest, target);
+        if (isnan(x) || isnan(y))
+        {
+            pdf_array_push(ctx, dest, PDF_NAME(Fit));
+        }
+        else
+        {
+            fz_rect mediabox;
+            fz_matrix target_ctm;
+            pdf_page_obj_transform(ctx, target, &mediabox, &target_ctm);
+            fz_point p = fz_transform_point(fz_make_point(x, y), fz_invert_matrix(target_ctm));
+            pdf_array_push(ctx, dest, PDF_NAME(XYZ));
+            pdf_array_push_real(ctx, dest, p.x);
+            pdf_array_push_real(ctx, dest, p.y);
+            pdf_array_push(ctx, dest, PDF_NULL);
+        }
+    }
+    fz_catch(ctx)
+    {
+        pdf_drop_obj(ctx, dest);
+        fz_rethrow(ctx);
+    }
+    return dest;
+}
+
+pdf_obj *mupdf_pdf_new_dest(fz_context *ctx, pdf_document *pdf, int page_no, float x, float y, mupdf_error_t **errptr)
+{
+    pdf_obj *dest = NULL;
+    fz_try(ctx)
+    {
+        dest = mupdf_pdf_new_page_dest(ctx, pdf, page_no, x, y);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return dest;
+}
+
+fz_matrix mupdf_pdf_page_obj_transform(fz_context *ctx, pdf_obj *page, fz_rect *mediabox, mupdf_error_t **errptr)
+{
+    fz_matrix ctm = fz_identity;
+    fz_try(ctx)
+    {
+        pdf_page_obj_transform(ctx, page, mediabox, &ctm);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return ctm;
+}
+
+void mupdf_pdf_insert_page(fz_context *ctx, pdf_document *pdf, int page_no, pdf_obj *page, mupdf_error_t **errptr)
+{
+    int count = pdf_count_pages(ctx, pdf);
+    if (page_no < -1 || page_no > count)
+    {
+        *errptr = mupdf_new_error_from_str("page_no is not a valid page");
+        return;
+    }
+    fz_try(ctx)
+    {
+        pdf_insert_page(ctx, pdf, page_no == -1 ? count : page_no, page);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_delete_page(fz_context *ctx, pdf_document *pdf, int page_no, mupdf_error_t **errptr)
+{
+    if (page_no < 0 || page_no >= pdf_count_pages(ctx, pdf))
+    {
+        *errptr = mupdf_new_error_from_str("page_no is not a valid page");
+        return;
+    }
+    fz_try(ctx)
+    {
+        pdf_delete_page(ctx, pdf, page_no);
+        if (pdf->rev_page_map)
+        {
+            pdf_drop_page_tree(ctx, pdf);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_pdf_delete_page_range(fz_context *ctx, pdf_document *pdf, int start, int end, mupdf_error_t **errptr)
+{
+    if (start < 0 || start > end || end > pdf_count_pages(ctx, pdf))
+    {
+        *errptr = mupdf_new_error_from_str("page range is not valid");
+        return;
+    }
+    fz_try(ctx)
+    {
+        pdf_delete_page_range(ctx, pdf, start, end);
+        if (pdf->rev_page_map)
+        {
+            pdf_drop_page_tree(ctx, pdf);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Give the page copy its own annotations, sharing them would leave their /P on the original page.
+   Popups are dropped, the copied markup annotations open without them. */
+static void mupdf_copy_page_annots(fz_context *ctx, pdf_document *pdf, pdf_obj *page, pdf_obj *copy)
+{
+    pdf_obj *annots = pdf_dict_get(ctx, page, PDF_NAME(Annots));
+    pdf_obj *copies = NULL;
+    pdf_obj *annot = NULL;
+    int i, n = pdf_array_len(ctx, annots);
+    if (!pdf_is_array(ctx, annots))
+    {
+        return;
+    }
+    fz_var(copies);
+    fz_var(annot);
+    fz_try(ctx)
+    {
+        copies = pdf_new_array(ctx, pdf, n);
+        for (i = 0; i < n; i++)
+        {
+            pdf_obj *src = pdf_array_get(ctx, annots, i);
+            if (!pdf_is_dict(ctx, src) || pdf_name_eq(ctx, pdf_dict_get(ctx, src, PDF_NAME(Subtype)), PDF_NAME(Popup)))
+            {
+                continue;
+            }
+            annot = pdf_copy_dict(ctx, src);
+            pdf_dict_del(ctx, annot, PDF_NAME(Popup));
+            pdf_dict_put(ctx, annot, PDF_NAME(P), copy);
+            pdf_array_push_drop(ctx, copies, pdf_add_object(ctx, pdf, annot));
+            pdf_drop_obj(ctx, annot);
+            annot = NULL;
+        }
+        pdf_dict_put(ctx, copy, PDF_NAME(Annots), copies);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, annot);
+        pdf_drop_obj(ctx, copies);
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+}
+
+/* Replace the page tree with a flat one holding the pages at `order`, repeated pages are copied */
+void mupdf_pdf_rearrange_pages(fz_context *ctx, pdf_document *pdf, const int *order, int len, mupdf_error_t **errptr)
+{
+    pdf_obj *kids = NULL;
+    pdf_obj *used = NULL;
+    pdf_obj *copy = NULL;
+    int i;
+    if (len <= 0)
+    {
+        *errptr = mupdf_new_error_from_str("order must keep at least one page");
+        return;
+    }
+    fz_var(kids);
+    fz_var(used);
+    fz_var(copy);
+    fz_try(ctx)
+    {
+        int count = pdf_count_pages(ctx, pdf);
+        pdf_obj *root;
+        for (i = 0; i < len; i++)
+        {
+            if (order[i] < 0 || order[i] >= count)
+            {
+                fz_throw(ctx, FZ_ERROR_GENERIC, "page_no is not a valid page");
+            }
+        }
+        root = pdf_dict_get(ctx, pdf_dict_get(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root)), PDF_NAME(Pages));
+        kids = pdf_new_array(ctx, pdf, len);
+        used = pdf_new_dict(ctx, pdf, len);
+        for (i = 0; i < len; i++)
+        {
+            pdf_obj *page = pdf_lookup_page_obj(ctx, pdf, order[i]);
+            char num[32];
+            pdf_flatten_inheritable_page_items(ctx, page);
+            fz_snprintf(num, sizeof num, "%d", pdf_to_num(ctx, page));
+            if (pdf_dict_gets(ctx, used, num))
+            {
+                pdf_obj *ref;
+                copy = pdf_copy_dict(ctx, page);
+                ref = pdf_add_object(ctx, pdf, copy);
+                pdf_drop_obj(ctx, copy);
+                copy = ref;
+                mupdf_copy_page_annots(ctx, pdf, page, copy);
+                pdf_array_push(ctx, kids, copy);
+                pdf_drop_obj(ctx, copy);
+                copy = NULL;
+            }
+            else
+            {
+                pdf_array_push(ctx, kids, page);
+                pdf_dict_puts(ctx, used, num, PDF_TRUE);
+            }
+        }
+        for (i = 0; i < len; i++)
+            pdf_dict_put(ctx, pdf_array_get(ctx, kids, i), PDF_NAME(Parent), root);
+        pdf_dict_put(ctx, root, PDF_NAME(Kids), kids);
+        pdf_dict_put_int(ctx, root, PDF_NAME(Count), len);
+        if (pdf->rev_page_map)
+        {
+            pdf_drop_page_tree(ctx, pdf);
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, copy);
+        pdf_drop_obj(ctx, used);
+        pdf_drop_obj(ctx, kids);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Recolor: rewrite device colors of page contents and images to one target colorspace */
+#define MUPDF_RECOLOR_DEPTH 64
+
+#ifdef _WIN32
+#define MUPDF_THREAD_LOCAL __declspec(thread)
+#else
+#define MUPDF_THREAD_LOCAL __thread
+#endif
+
+typedef struct
+{
+    fz_colorspace *target;
+    /* Source colorspace of the current fill and stroke colors per q level, NULL when they pass through */
+    fz_colorspace *fill[MUPDF_RECOLOR_DEPTH];
+    fz_colorspace *stroke[MUPDF_RECOLOR_DEPTH];
+    int top;
+    int overflow;
+    /* The buffer processor operators we override */
+    void (*op_q)(fz_context *, pdf_processor *);
+    void (*op_Q)(fz_context *, pdf_processor *);
+    void (*op_CS)(fz_context *, pdf_processor *, const char *, fz_colorspace *);
+    void (*op_cs)(fz_context *, pdf_processor *, const char *, fz_colorspace *);
+    void (*op_SC_color)(fz_context *, pdf_processor *, int, float *);
+    void (*op_sc_color)(fz_context *, pdf_processor *, int, float *);
+    void (*op_G)(fz_context *, pdf_processor *, float);
+    void (*op_g)(fz_context *, pdf_processor *, float);
+    void (*op_RG)(fz_context *, pdf_processor *, float, float, float);
+    void (*op_rg)(fz_context *, pdf_processor *, float, float, float);
+    void (*op_K)(fz_context *, pdf_processor *, float, float, float, float);
+    void (*op_k)(fz_context *, pdf_processor *, float, float, float, float);
+} mupdf_recolor_state;
+
+/* The buffer processor has no room for extra state, so the operators find it here */
+static MUPDF_THREAD_LOCAL mupdf_recolor_state *recolor_state;
+
+static bool mupdf_recolor_convertible(fz_context *ctx, fz_colorspace *cs)
+{
+    if (!cs || fz_colorspace_is_indexed(ctx, cs))
+        return false;
+    return fz_colorspace_is_gray(ctx, cs) || fz_colorspace_is_rgb(ctx, cs) ||
+           fz_colorspace_is_cmyk(ctx, cs) || fz_colorspace_is_lab(ctx, cs);
+}
+
+static void mupdf_recolor_set(fz_context *ctx, int stroke, fz_colorspace *cs)
+{
+    mupdf_recolor_state *st = recolor_state;
+    fz_colorspace **slot = stroke ? &st->stroke[st->top] : &st->fill[st->top];
+    fz_colorspace *old = *slot;
+    *slot = fz_keep_colorspace(ctx, cs);
+    fz_drop_colorspace(ctx, old);
+}
+
+static void mupdf_recolor_emit(fz_context *ctx, pdf_processor *proc, int stroke, fz_colorspace *ss, const float *sv)
+{
+    mupdf_recolor_state *st = recolor_state;
+    float dv[FZ_MAX_COLORS];
+    fz_convert_color(ctx, ss, sv, st->target, dv, NULL, fz_default_color_params);
+    switch (fz_colorspace_n(ctx, st->target))
+    {
+    case 1:
+        (stroke ? st->op_G : st->op_g)(ctx, proc, dv[0]);
+        break;
+    case 3:
+        (stroke ? st->op_RG : st->op_rg)(ctx, proc, dv[0], dv[1], dv[2]);
+        break;
+    default:
+        (stroke ? st->op_K : st->op_k)(ctx, proc, dv[0], dv[1], dv[2], dv[3]);
+        break;
+    }
+}
+
+static void mupdf_recolor_device(fz_context *ctx, pdf_processor *proc, int stroke, fz_colorspace *ss, const float *sv)
+{
+    mupdf_recolor_set(ctx, stroke, ss);
+    mupdf_recolor_emit(ctx, proc, stroke, ss, sv);
+}
+
+static void mupdf_recolor_q(fz_context *ctx, pdf_processor *proc)
+{
+    mupdf_recolor_state *st = recolor_state;
+    if (st->top + 1 < MUPDF_RECOLOR_DEPTH)
+    {
+        st->top++;
+        st->fill[st->top] = fz_keep_colorspace(ctx, st->fill[st->top - 1]);
+        st->stroke[st->top] = fz_keep_colorspace(ctx, st->stroke[st->top - 1]);
+    }
+    else
+    {
+        st->overflow++;
+    }
+    st->op_q(ctx, proc);
+}
+
+static void mupdf_recolor_Q(fz_context *ctx, pdf_processor *proc)
+{
+    mupdf_recolor_state *st = recolor_state;
+    if (st->overflow > 0)
+    {
+        st->overflow--;
+    }
+    else if (st->top > 0)
+    {
+        fz_drop_colorspace(ctx, st->fill[st->top]);
+        fz_drop_colorspace(ctx, st->stroke[st->top]);
+        st->fill[st->top] = st->stroke[st->top] = NULL;
+        st->top--;
+    }
+    st->op_Q(ctx, proc);
+}
+
+static void mupdf_recolor_colorspace(fz_context *ctx, pdf_processor *proc, int stroke, const char *name, fz_colorspace *cs)
+{
+    mupdf_recolor_state *st = recolor_state;
+    if (mupdf_recolor_convertible(ctx, cs))
+    {
+        /* Setting a colorspace resets the color to black */
+        float black[FZ_MAX_COLORS] = {0};
+        if (fz_colorspace_is_subtractive(ctx, cs))
+            black[fz_colorspace_n(ctx, cs) - 1] = 1;
+        mupdf_recolor_device(ctx, proc, stroke, cs, black);
+    }
+    else
+    {
+        mupdf_recolor_set(ctx, stroke, NULL);
+        (stroke ? st->op_CS : st->op_cs)(ctx, proc, name, cs);
+    }
+}
+
+static void mupdf_recolor_CS(fz_context *ctx, pdf_processor *proc, const char *name, fz_colorspace *cs)
+{
+    mupdf_recolor_colorspace(ctx, proc, 1, name, cs);
+}
+
+static void mupdf_recolor_cs(fz_context *ctx, pdf_processor *proc, const char *name, fz_colorspace *cs)
+{
+    mupdf_recolor_colorspace(ctx, proc, 0, name, cs);
+}
+
+static void mupdf_recolor_SC_color(fz_context *ctx, pdf_processor *proc, int n, float *color)
+{
+    mupdf_recolor_state *st = recolor_state;
+    if (st->stroke[st->top])
+        mupdf_recolor_emit(ctx, proc, 1, st->stroke[st->top], color);
+    else
+        st->op_SC_color(ctx, proc, n, color);
+}
+
+static void mupdf_recolor_sc_color(fz_context *ctx, pdf_processor *proc, int n, float *color)
+{
+    mupdf_recolor_state *st = recolor_state;
+    if (st->fill[st->top])
+        mupdf_recolor_emit(ctx, proc, 0, st->fill[st->top], color);
+    else
+        st->op_sc_color(ctx, proc, n, color);
+}
+
+static void mupdf_recolor_G(fz_context *ctx, pdf_processor *proc, float g)
+{
+    mupdf_recolor_device(ctx, proc, 1, fz_device_gray(ctx), &g);
+}
+
+static void mupdf_recolor_g(fz_context *ctx, pdf_processor *proc, float g)
+{
+    mupdf_recolor_device(ctx, proc, 0, fz_device_gray(ctx), &g);
+}
+
+static void mupdf_recolor_RG(fz_context *ctx, pdf_processor *proc, float r, float g, float b)
+{
+    float rgb[3] = {r, g, b};
+    mupdf_recolor_device(ctx, proc, 1, fz_device_rgb(ctx), rgb);
+}
+
+static void mupdf_recolor_rg(fz_context *ctx, pdf_processor *proc, float r, float g, float b)
+{
+    float rgb[3] = {r, g, b};
+    mupdf_recolor_device(ctx, proc, 0, fz_device_rgb(ctx), rgb);
+}
+
+static void mupdf_recolor_K(fz_context *ctx, pdf_processor *proc, float c, float m, float y, float k)
+{
+    float cmyk[4] = {c, m, y, k};
+    mupdf_recolor_device(ctx, proc, 1, fz_device_cmyk(ctx), cmyk);
+}
+
+static void mupdf_recolor_k(fz_context *ctx, pdf_processor *proc, float c, float m, float y, float k)
+{
+    float cmyk[4] = {c, m, y, k};
+    mupdf_recolor_device(ctx, proc, 0, fz_device_cmyk(ctx), cmyk);
+}
+
+static void mupdf_recolor_install(pdf_processor *proc, mupdf_recolor_state *st)
+{
+#define MUPDF_RECOLOR_HOOK(op) \
+    st->op = proc->op;         \
+    proc->op = mupdf_recolor_##op;
+    MUPDF_RECOLOR_HOOK(op_q)
+    MUPDF_RECOLOR_HOOK(op_Q)
+    MUPDF_RECOLOR_HOOK(op_CS)
+    MUPDF_RECOLOR_HOOK(op_cs)
+    MUPDF_RECOLOR_HOOK(op_SC_color)
+    MUPDF_RECOLOR_HOOK(op_sc_color)
+    MUPDF_RECOLOR_HOOK(op_G)
+    MUPDF_RECOLOR_HOOK(op_g)
+    MUPDF_RECOLOR_HOOK(op_RG)
+    MUPDF_RECOLOR_HOOK(op_rg)
+    MUPDF_RECOLOR_HOOK(op_K)
+    MUPDF_RECOLOR_HOOK(op_k)
+#undef MUPDF_RECOLOR_HOOK
+}
+
+static void mupdf_recolor_contents(fz_context *ctx, pdf_document *pdf, pdf_obj *page, fz_colorspace *target)
+{
+    mupdf_recolor_state st;
+    pdf_processor *proc = NULL;
+    fz_buffer *buf = NULL;
+    pdf_obj *new_ref = NULL;
+    pdf_obj *contents = pdf_dict_get(ctx, page, PDF_NAME(Contents));
+    pdf_obj *resources = pdf_dict_get_inheritable(ctx, page, PDF_NAME(Resources));
+    int i;
+    if (!contents)
+        return;
+
+    memset(&st, 0, sizeof st);
+    st.target = target;
+    st.fill[0] = fz_keep_colorspace(ctx, fz_device_gray(ctx));
+    st.stroke[0] = fz_keep_colorspace(ctx, fz_device_gray(ctx));
+    fz_var(proc);
+    fz_var(buf);
+    fz_var(new_ref);
+    fz_try(ctx)
+    {
+        buf = fz_new_buffer(ctx, 1024);
+        proc = pdf_new_buffer_processor(ctx, buf, 0);
+        mupdf_recolor_install(proc, &st);
+        recolor_state = &st;
+        pdf_process_contents(ctx, proc, pdf, resources, contents, NULL);
+        pdf_close_processor(ctx, proc);
+
+        new_ref = pdf_add_new_dict(ctx, pdf, 1);
+        pdf_update_stream(ctx, pdf, new_ref, buf, 0);
+        pdf_dict_put(ctx, page, PDF_NAME(Contents), new_ref);
+    }
+    fz_always(ctx)
+    {
+        recolor_state = NULL;
+        for (i = 0; i <= st.top; i++)
+        {
+            fz_drop_colorspace(ctx, st.fill[i]);
+            fz_drop_colorspace(ctx, st.stroke[i]);
+        }
+        pdf_drop_obj(ctx, new_ref);
+        pdf_drop_processor(ctx, proc);
+        fz_drop_buffer(ctx, buf);
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+}
+
+/* Replace the images of the page resources, `done` maps already converted object numbers to the new images */
+static void mupdf_recolor_images(fz_context *ctx, pdf_document *pdf, pdf_obj *page, fz_colorspace *target, pdf_obj *done)
+{
+    pdf_obj *resources = pdf_dict_get_inheritable(ctx, page, PDF_NAME(Resources));
+    pdf_obj *xobjs = pdf_dict_get(ctx, resources, PDF_NAME(XObject));
+    int i, n = pdf_dict_len(ctx, xobjs);
+    for (i = 0; i < n; i++)
+    {
+        pdf_obj *key = pdf_dict_get_key(ctx, xobjs, i);
+        pdf_obj *val = pdf_dict_get_val(ctx, xobjs, i);
+        fz_image *image = NULL;
+        fz_image *recolored = NULL;
+        fz_pixmap *pix = NULL;
+        fz_pixmap *converted = NULL;
+        pdf_obj *ref = NULL;
+        char num[32];
+        if (!pdf_name_eq(ctx, pdf_dict_get(ctx, val, PDF_NAME(Subtype)), PDF_NAME(Image)))
+            continue;
+        fz_snprintf(num, sizeof num, "%d", pdf_to_num(ctx, val));
+        ref = pdf_dict_gets(ctx, done, num);
+        if (ref)
+        {
+            pdf_dict_put(ctx, xobjs, key, ref);
+            continue;
+        }
+
+        fz_var(image);
+        fz_var(recolored);
+        fz_var(pix);
+        fz_var(converted);
+        fz_var(ref);
+        fz_try(ctx)
+        {
+            image = pdf_load_image(ctx, pdf, val);
+            if (!image->imagemask && image->colorspace && fz_colorspace_type(ctx, image->colorspace) != fz_colorspace_type(ctx, target))
+            {
+                pix = fz_get_pixmap_from_image(ctx, image, NULL, NULL, NULL, NULL);
+                converted = fz_convert_pixmap(ctx, pix, target, NULL, NULL, fz_default_color_params, 0);
+                recolored = fz_new_image_from_pixmap(ctx, converted, image->mask);
+                ref = pdf_add_image(ctx, pdf, recolored);
+                pdf_dict_put(ctx, xobjs, key, ref);
+                pdf_dict_puts(ctx, done, num, ref);
+            }
+        }
+        fz_always(ctx)
+        {
+            pdf_drop_obj(ctx, ref);
+            fz_drop_image(ctx, recolored);
+            fz_drop_pixmap(ctx, converted);
+            fz_drop_pixmap(ctx, pix);
+            fz_drop_image(ctx, image);
+        }
+        fz_catch(ctx)
+        {
+            fz_rethrow(ctx);
+        }
+    }
+}
+
+/* Rewrite the device colors of every page content stream and page image to `target` */
+void mupdf_pdf_recolor(fz_context *ctx, pdf_document *pdf, fz_colorspace *target, mupdf_error_t **errptr)
+{
+    pdf_obj *done = NULL;
+    fz_var(done);
+    fz_try(ctx)
+    {
+        int i, n = pdf_count_pages(ctx, pdf);
+        if (!fz_colorspace_is_gray(ctx, target) && !fz_colorspace_is_rgb(ctx, target) && !fz_colorspace_is_cmyk(ctx, target))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "can only recolor to gray, RGB or CMYK");
+        done = pdf_new_dict(ctx, pdf, 4);
+        for (i = 0; i < n; i++)
+        {
+            pdf_obj *page = pdf_lookup_page_obj(ctx, pdf, i);
+            mupdf_recolor_contents(ctx, pdf, page, target);
+            mupdf_recolor_images(ctx, pdf, page, target, done);
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, done);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+/* Image downsampling */
+#define MUPDF_DPI_GSTATE_MAX 64
+#define MUPDF_DPI_FORM_MAX 16
+
+/* Content stream processor recording the smallest resolution each image XObject is drawn at */
+typedef struct
+{
+    pdf_processor super;
+    pdf_document *pdf;
+    pdf_obj *resources;
+    pdf_obj *dpi;
+    fz_matrix ctm[MUPDF_DPI_GSTATE_MAX];
+    int depth;
+    int forms;
+} mupdf_dpi_processor;
+
+static fz_matrix *mupdf_dpi_ctm(mupdf_dpi_processor *p)
+{
+    return &p->ctm[fz_mini(p->depth, MUPDF_DPI_GSTATE_MAX - 1)];
+}
+
+static void mupdf_dpi_q(fz_context *ctx, pdf_processor *proc)
+{
+    mupdf_dpi_processor *p = (mupdf_dpi_processor *)proc;
+    fz_matrix ctm = *mupdf_dpi_ctm(p);
+    p->depth++;
+    *mupdf_dpi_ctm(p) = ctm;
+}
+
+static void mupdf_dpi_Q(fz_context *ctx, pdf_processor *proc)
+{
+    mupdf_dpi_processor *p = (mupdf_dpi_processor *)proc;
+    if (p->depth > 0)
+        p->depth--;
+}
+
+static void mupdf_dpi_cm(fz_context *ctx, pdf_processor *proc, float a, float b, float c, float d, float e, float f)
+{
+    mupdf_dpi_processor *p = (mupdf_dpi_processor *)proc;
+    fz_matrix *ctm = mupdf_dpi_ctm(p);
+    *ctm = fz_concat(fz_make_matrix(a, b, c, d, e, f), *ctm);
+}
+
+static void mupdf_dpi_Do_image(fz_context *ctx, pdf_processor *proc, const char *name, fz_image *image)
+{
+    mupdf_dpi_processor *p = (mupdf_dpi_processor *)proc;
+    fz_matrix *ctm = mupdf_dpi_ctm(p);
+    pdf_obj *xobj = pdf_dict_gets(ctx, pdf_dict_get(ctx, p->resources, PDF_NAME(XObject)), name);
+    float w = sqrtf(ctm->a * ctm->a + ctm->b * ctm->b);
+    float h = sqrtf(ctm->c * ctm->c + ctm->d * ctm->d);
+    float dpi;
+    pdf_obj *seen;
+    char num[32];
+    if (pdf_to_num(ctx, xobj) <= 0 || w <= 0 || h <= 0)
+        return;
+
+    dpi = fz_min(image->w * 72 / w, image->h * 72 / h);
+    fz_snprintf(num, sizeof num, "%d", pdf_to_num(ctx, xobj));
+    seen = pdf_dict_gets(ctx, p->dpi, num);
+    if (!seen || dpi < pdf_to_real(ctx, seen))
+        pdf_dict_puts_drop(ctx, p->dpi, num, pdf_new_real(ctx, dpi));
+}
+
+static void mupdf_dpi_Do_form(fz_context *ctx, pdf_processor *proc, const char *name, pdf_obj *xobj, pdf_obj *page_resources)
+{
+    mupdf_dpi_processor *p = (mupdf_dpi_processor *)proc;
+    pdf_obj *resources = p->resources;
+    pdf_obj *form_resources = pdf_dict_get(ctx, xobj, PDF_NAME(Resources));
+    int depth = p->depth;
+    if (p->forms >= MUPDF_DPI_FORM_MAX)
+        return;
+
+    mupdf_dpi_q(ctx, proc);
+    *mupdf_dpi_ctm(p) = fz_concat(pdf_to_matrix(ctx, pdf_dict_get(ctx, xobj, PDF_NAME(Matrix))), *mupdf_dpi_ctm(p));
+    p->resources = form_resources ? form_resources : page_resources;
+    p->forms++;
+    fz_try(ctx)
+    {
+        pdf_process_contents(ctx, proc, p->pdf, p->resources, xobj, NULL);
+    }
+    fz_always(ctx)
+    {
+        p->forms--;
+        p->resources = resources;
+        p->depth = depth;
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+}
+
+/* Rewrite image object `num` in place, subsampled by 2^factor and Flate compressed */
+static bool mupdf_downsample_image(fz_context *ctx, pdf_document *pdf, int num, int factor)
+{
+    pdf_obj *ref = NULL;
+    fz_image *image = NULL;
+    fz_pixmap *pix = NULL;
+    fz_buffer *raw = NULL;
+    fz_buffer *buf = NULL;
+    pdf_obj *base = NULL;
+    bool rewritten = false;
+    fz_var(ref);
+    fz_var(image);
+    fz_var(pix);
+    fz_var(raw);
+    fz_var(buf);
+    fz_var(base);
+    fz_try(ctx)
+    {
+        ref = pdf_new_indirect(ctx, pdf, num, 0);
+        if (pdf_dict_get(ctx, ref, PDF_NAME(ColorSpace)) &&
+            !pdf_dict_get_bool(ctx, ref, PDF_NAME(ImageMask)) &&
+            !pdf_is_array(ctx, pdf_dict_get(ctx, ref, PDF_NAME(Mask))))
+        {
+            image = pdf_load_image(ctx, pdf, ref);
+            pix = fz_get_pixmap_from_image(ctx, image, NULL, NULL, NULL, NULL);
+            if (!pix->alpha && (pix->w >> factor) > 0 && (pix->h >> factor) > 0)
+            {
+                pdf_obj *cs = pdf_dict_get(ctx, ref, PDF_NAME(ColorSpace));
+                size_t row, size;
+                int y;
+                fz_subsample_pixmap(ctx, pix, factor);
+                row = (size_t)pix->w * pix->n;
+                raw = fz_new_buffer(ctx, row * pix->h);
+                for (y = 0; y < pix->h; y++)
+                    fz_append_data(ctx, raw, pix->samples + y * pix->stride, row);
+                size = fz_deflate_bound(ctx, raw->len);
+                buf = fz_new_buffer(ctx, size);
+                fz_deflate(ctx, buf->data, &size, raw->data, raw->len, FZ_DEFLATE_DEFAULT);
+                buf->len = size;
+
+                /* Indexed images come out expanded to their base colorspace */
+                if (pdf_is_array(ctx, cs) && pdf_name_eq(ctx, pdf_array_get(ctx, cs, 0), PDF_NAME(Indexed)))
+                {
+                    base = pdf_keep_obj(ctx, pdf_array_get(ctx, cs, 1));
+                    pdf_dict_put(ctx, ref, PDF_NAME(ColorSpace), base);
+                }
+                pdf_dict_put_int(ctx, ref, PDF_NAME(Width), pix->w);
+                pdf_dict_put_int(ctx, ref, PDF_NAME(Height), pix->h);
+                pdf_dict_put_int(ctx, ref, PDF_NAME(BitsPerComponent), 8);
+                pdf_dict_del(ctx, ref, PDF_NAME(Decode));
+                pdf_dict_del(ctx, ref, PDF_NAME(DecodeParms));
+                pdf_dict_del(ctx, ref, PDF_NAME(SMaskInData));
+                pdf_dict_put(ctx, ref, PDF_NAME(Filter), PDF_NAME(FlateDecode));
+                pdf_update_stream(ctx, pdf, ref, buf, 1);
+                rewritten = true;
+            }
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, base);
+        fz_drop_buffer(ctx, buf);
+        fz_drop_buffer(ctx, raw);
+        fz_drop_pixmap(ctx, pix);
+        fz_drop_image(ctx, image);
+        pdf_drop_obj(ctx, ref);
+    }
+    fz_catch(ctx)
+    {
+        fz_rethrow(ctx);
+    }
+    return rewritten;
+}
+
+/* Downsample the images drawn above `max_dpi` by powers of two, keeping them at or above it */
+int mupdf_pdf_downsample_images(fz_context *ctx, pdf_document *pdf, float max_dpi, mupdf_error_t **errptr)
+{
+    mupdf_dpi_processor *proc = NULL;
+    pdf_obj *dpi = NULL;
+    int count = 0;
+    fz_var(proc);
+    fz_var(dpi);
+    fz_try(ctx)
+    {
+        int i, n;
+        if (!(max_dpi > 0))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "resolution must be positive");
+        dpi = pdf_new_dict(ctx, pdf, 8);
+        proc = pdf_new_processor(ctx, sizeof *proc);
+        proc->super.op_q = mupdf_dpi_q;
+        proc->super.op_Q = mupdf_dpi_Q;
+        proc->super.op_cm = mupdf_dpi_cm;
+        proc->super.op_Do_image = mupdf_dpi_Do_image;
+        proc->super.op_Do_form = mupdf_dpi_Do_form;
+        proc->pdf = pdf;
+        proc->dpi = dpi;
+
+        n = pdf_count_pages(ctx, pdf);
+        for (i = 0; i < n; i++)
+        {
+            pdf_obj *page = pdf_lookup_page_obj(ctx, pdf, i);
+            pdf_obj *contents = pdf_dict_get(ctx, page, PDF_NAME(Contents));
+            if (!contents)
+                continue;
+            proc->resources = pdf_dict_get_inheritable(ctx, page, PDF_NAME(Resources));
+            proc->ctm[0] = fz_identity;
+            proc->depth = 0;
+            pdf_process_contents(ctx, &proc->super, pdf, proc->resources, contents, NULL);
+        }
+        pdf_close_processor(ctx, &proc->super);
+
+        n = pdf_dict_len(ctx, dpi);
+        for (i = 0; i < n; i++)
+        {
+            int num = atoi(pdf_to_name(ctx, pdf_dict_get_key(ctx, dpi, i)));
+            float res = pdf_to_real(ctx, pdf_dict_get_val(ctx, dpi, i));
+            int factor = 0;
+            while (factor < 8 && res / (1 << (factor + 1)) >= max_dpi)
+                factor++;
+            if (factor > 0 && mupdf_downsample_image(ctx, pdf, num, factor))
+                count++;
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_processor(ctx, (pdf_processor *)proc);
+        pdf_drop_obj(ctx, dpi);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return count;
+}
+
+/* Device */
+fz_device *mupdf_new_draw_device(fz_context *ctx, fz_pixmap *pixmap, fz_irect clip, mupdf_error_t **errptr)
+{
+    fz_device *device = NULL;
+    fz_try(ctx)
+    {
+        if (fz_is_infinite_irect(clip))
+        {
+            device = fz_new_draw_device(ctx, fz_identity, pixmap);
+        }
+        else
+        {
+            device = fz_new_draw_device_with_bbox(ctx, fz_identity, pixmap, &clip);
+        }
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return device;
+}
+
+fz_device *mupdf_new_draw_device_with_proof(fz_context *ctx, fz_pixmap *pixmap, fz_colorspace *proof_cs, mupdf_error_t **errptr)
+{
+    fz_device *device = NULL;
+    fz_try(ctx)
+    {
+        device = fz_new_draw_device_with_proof(ctx, fz_identity, pixmap, proof_cs);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return device;
+}
+
+fz_device *mupdf_new_display_list_device(fz_context *ctx, fz_display_list *list, mupdf_error_t **errptr)
+{
+    fz_device *device = NULL;
+    fz_try(ctx)
+    {
+        device = fz_new_list_device(ctx, list);
+        fz_keep_display_list(ctx, list);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return device;
+}
+
+fz_device *mupdf_new_stext_device(fz_context *ctx, fz_stext_page *tp, int flags, mupdf_error_t **errptr)
+{
+    fz_device *device = NULL;
+    fz_try(ctx)
+    {
+        fz_stext_options opts = {0};
+        opts.flags = flags;
+        device = fz_new_stext_device(ctx, tp, &opts);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+    return device;
+}
+
+void mupdf_fill_path(fz_context *ctx, fz_device *device, fz_path *path, bool even_odd, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_fill_path(ctx, device, path, even_odd, ctm, cs, color, alpha, cp);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
+}
+
+void mupdf_stroke_path(fz_context *ctx, fz_device *device, fz_path *path, fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+{
+    fz_try(ctx)
+    {
+        fz_stroke_path(ctx, device, path, stroke, ctm, cs, color, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return pixmap;
 }
 
-fz_stext_page *mupdf_display_list_to_text_page(fz_context *ctx, fz_display_list *list, int flags, mupdf_error_t **errptr)
+void mupdf_clip_path(fz_context *ctx, fz_device *device, fz_path *path, bool even_odd, fz_matrix ctm, mupdf_error_t **errptr)
 {
-    fz_stext_page *text_page = NULL;
-    fz_stext_options opts = {0};
-    opts.flags = flags;
     fz_try(ctx)
     {
-        text_page = fz_new_stext_page_from_display_list(ctx, list, &opts);
+        fz_clip_path(ctx, device, path, even_odd, ctm, fz_infinite_rect);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return text_page;
 }
 
-void mupdf_display_list_run(fz_context *ctx, fz_display_list *list, fz_device *device, fz_matrix ctm, fz_rect area, mupdf_error_t **errptr)
+void mupdf_clip_stroke_path(fz_context *ctx, fz_device *device, fz_path *path, fz_stroke_state *stroke, fz_matrix ctm, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_run_display_list(ctx, list, device, ctm, area, NULL);
+        fz_clip_stroke_path(ctx, device, path, stroke, ctm, fz_infinite_rect);
     }
     fz_catch(ctx)
     {
@@ -872,489 +4367,596 @@ void mupdf_display_list_run(fz_context *ctx, fz_display_list *list, fz_device *d
     }
 }
 
-/* PDFObject */
-bool mupdf_pdf_is_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_fill_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_indirect(ctx, obj);
+        fz_fill_text(ctx, device, text, ctm, cs, color, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_null(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_null(ctx, obj);
+        fz_stroke_text(ctx, device, text, stroke, ctm, cs, color, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_bool(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_clip_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_bool(ctx, obj);
+        fz_clip_text(ctx, device, text, ctm, fz_infinite_rect);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_int(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_clip_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, fz_stroke_state *stroke, fz_matrix ctm, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_int(ctx, obj);
+        fz_clip_stroke_text(ctx, device, text, stroke, ctm, fz_infinite_rect);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_real(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_ignore_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_real(ctx, obj);
+        fz_ignore_text(ctx, device, text, ctm);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_number(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_fill_shade(fz_context *ctx, fz_device *device, fz_shade *shade, fz_matrix ctm, float alpha, fz_color_params cp, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_number(ctx, obj);
+        fz_fill_shade(ctx, device, shade, ctm, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_string(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_fill_image(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, float alpha, fz_color_params cp, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_string(ctx, obj);
+        fz_fill_image(ctx, device, image, ctm, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_name(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_fill_image_mask(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_name(ctx, obj);
+        fz_fill_image_mask(ctx, device, image, ctm, cs, color, alpha, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_array(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_clip_image_mask(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_array(ctx, obj);
+        fz_clip_image_mask(ctx, device, image, ctm, fz_infinite_rect);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_dict(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_pop_clip(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_dict(ctx, obj);
+        fz_pop_clip(ctx, device);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-bool mupdf_pdf_is_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_begin_layer(fz_context *ctx, fz_device *device, const char *name, mupdf_error_t **errptr)
 {
-    int b = 0;
     fz_try(ctx)
     {
-        b = pdf_is_stream(ctx, obj);
+        fz_begin_layer(ctx, device, name);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return b ? true : false;
 }
 
-pdf_obj *mupdf_pdf_new_null()
-{
-    return PDF_NULL;
-}
-
-pdf_obj *mupdf_pdf_new_bool(bool b)
+void mupdf_end_layer(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
 {
-    return b ? PDF_TRUE : PDF_FALSE;
+    fz_try(ctx)
+    {
+        fz_end_layer(ctx, device);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
+    }
 }
 
-pdf_obj *mupdf_pdf_new_int(fz_context *ctx, int i, mupdf_error_t **errptr)
+void mupdf_begin_mask(fz_context *ctx, fz_device *device, fz_rect area, bool luminosity, fz_colorspace *cs, const float *color, fz_color_params cp, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_int(ctx, i);
+        fz_begin_mask(ctx, device, area, luminosity, cs, color, cp);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_new_real(fz_context *ctx, float f, mupdf_error_t **errptr)
+void mupdf_end_mask(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_real(ctx, f);
+        fz_end_mask(ctx, device);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_new_string(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+void mupdf_begin_group(fz_context *ctx, fz_device *device, fz_rect area, fz_colorspace *cs, bool isolated, bool knockout, int blendmode, float alpha, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_text_string(ctx, s);
+        fz_begin_group(ctx, device, area, cs, isolated, knockout, blendmode, alpha);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_new_name(fz_context *ctx, const char *name, mupdf_error_t **errptr)
+void mupdf_end_group(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_name(ctx, name);
+        fz_end_group(ctx, device);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_new_indirect(fz_context *ctx, pdf_document *pdf, int num, int gen, mupdf_error_t **errptr)
+void mupdf_set_default_colorspaces(fz_context *ctx, fz_device *device, fz_default_colorspaces *dcs, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_indirect(ctx, pdf, num, gen);
+        fz_set_default_colorspaces(ctx, device, dcs);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_new_array(fz_context *ctx, pdf_document *pdf, int capacity, mupdf_error_t **errptr)
+int mupdf_begin_tile(fz_context *ctx, fz_device *device, fz_rect area, fz_rect view, float xstep, float ystep, fz_matrix ctm, int id, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
+    int i = 0;
     fz_try(ctx)
     {
-        obj = pdf_new_array(ctx, pdf, capacity);
+        i = fz_begin_tile_id(ctx, device, area, view, xstep, ystep, ctm, id);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
+    return i;
 }
 
-pdf_obj *mupdf_pdf_new_dict(fz_context *ctx, pdf_document *pdf, int capacity, mupdf_error_t **errptr)
+void mupdf_end_tile(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_new_dict(ctx, pdf, capacity);
+        fz_end_tile(ctx, device);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-pdf_obj *mupdf_pdf_obj_from_str(fz_context *ctx, pdf_document *pdf, const char *src, mupdf_error_t **errptr)
+/* PdfPage */
+pdf_annot *mupdf_pdf_create_annot(fz_context *ctx, pdf_page *page, int subtype, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
-    pdf_lexbuf lexbuf;
-    fz_stream *stream = fz_open_memory(ctx, (unsigned char *)src, strlen(src));
-    pdf_lexbuf_init(ctx, &lexbuf, PDF_LEXBUF_SMALL);
-    fz_var(stream);
+    pdf_annot *annot = NULL;
     fz_try(ctx)
     {
-        obj = pdf_parse_stm_obj(ctx, pdf, stream, &lexbuf);
-    }
-    fz_always(ctx)
-    {
-        pdf_lexbuf_fin(ctx, &lexbuf);
-        fz_drop_stream(ctx, stream);
+        annot = pdf_create_annot(ctx, page, subtype);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
+    return annot;
 }
 
-bool mupdf_pdf_to_bool(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* Highlight, underline, strike-out or squiggly annotation covering `quads`, in page space */
+pdf_annot *mupdf_pdf_create_markup_annot(fz_context *ctx, pdf_page *page, int subtype, const fz_quad *quads, int n, const float *color, int ncolor, float opacity, mupdf_error_t **errptr)
 {
-    bool b = false;
+    pdf_annot *annot = NULL;
+    fz_var(annot);
     fz_try(ctx)
     {
-        b = pdf_to_bool(ctx, obj);
+        annot = pdf_create_annot(ctx, page, subtype);
+        pdf_set_annot_quad_points(ctx, annot, n, quads);
+        pdf_set_annot_color(ctx, annot, ncolor, color);
+        if (opacity < 1)
+            pdf_set_annot_opacity(ctx, annot, opacity);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return b;
+    return annot;
 }
 
-int mupdf_pdf_to_int(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* Ink annotation with `n` strokes of count[i] points each, `points` are in page space */
+pdf_annot *mupdf_pdf_create_ink_annot(fz_context *ctx, pdf_page *page, int n, const int *count, const fz_point *points, float width, const float *color, int ncolor, mupdf_error_t **errptr)
 {
-    int i = 0;
+    pdf_annot *annot = NULL;
+    fz_var(annot);
     fz_try(ctx)
     {
-        i = pdf_to_int(ctx, obj);
+        annot = pdf_create_annot(ctx, page, PDF_ANNOT_INK);
+        pdf_set_annot_ink_list(ctx, annot, n, count, points);
+        pdf_set_annot_border(ctx, annot, width);
+        pdf_set_annot_color(ctx, annot, ncolor, color);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return i;
+    return annot;
 }
 
-float mupdf_pdf_to_float(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* FreeText annotation showing `text` in `rect`, in page space, with the RGB `color` */
+pdf_annot *mupdf_pdf_create_free_text_annot(fz_context *ctx, pdf_page *page, fz_rect rect, const char *text, const char *font, float size, const float *color, float border, int align, mupdf_error_t **errptr)
 {
-    float f = 0.0;
+    pdf_annot *annot = NULL;
+    fz_var(annot);
     fz_try(ctx)
     {
-        f = pdf_to_real(ctx, obj);
+        annot = pdf_create_annot(ctx, page, PDF_ANNOT_FREE_TEXT);
+        pdf_set_annot_rect(ctx, annot, rect);
+        pdf_set_annot_contents(ctx, annot, text);
+        pdf_set_annot_default_appearance(ctx, annot, font, size, color);
+        pdf_set_annot_quadding(ctx, annot, align);
+        pdf_set_annot_border(ctx, annot, border);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return f;
+    return annot;
 }
 
-int mupdf_pdf_to_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* Make `ap` the normal appearance of `annot` and keep MuPDF from synthesizing one over it */
+static void mupdf_pdf_set_annot_ap(fz_context *ctx, pdf_annot *annot, pdf_obj *ap)
 {
-    int ind = 0;
+    pdf_dict_put(ctx, pdf_dict_put_dict(ctx, annot->obj, PDF_NAME(AP), 1), PDF_NAME(N), ap);
+    pdf_drop_obj(ctx, annot->ap);
+    annot->ap = pdf_keep_obj(ctx, ap);
+    annot->needs_new_ap = 0;
+    annot->has_new_ap = 1;
+}
+
+/* Bounding box of an appearance stream covering the whole annotation */
+static fz_rect mupdf_pdf_annot_ap_bbox(fz_context *ctx, pdf_annot *annot)
+{
+    fz_rect rect = pdf_dict_get_rect(ctx, annot->obj, PDF_NAME(Rect));
+    return fz_make_rect(0, 0, rect.x1 - rect.x0, rect.y1 - rect.y0);
+}
+
+/* Appearance drawing the form XObject `xobj` scaled to the size of `annot` */
+static void mupdf_pdf_set_annot_xobject_ap(fz_context *ctx, pdf_annot *annot, pdf_obj *xobj)
+{
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *ap = NULL;
+    fz_var(contents);
+    fz_var(resources);
+    fz_var(ap);
     fz_try(ctx)
     {
-        ind = pdf_to_num(ctx, obj);
+        pdf_document *pdf = annot->page->doc;
+        fz_rect bbox = pdf_dict_get_rect(ctx, xobj, PDF_NAME(BBox));
+        fz_rect ap_bbox = mupdf_pdf_annot_ap_bbox(ctx, annot);
+        float sx, sy;
+        if (fz_is_empty_rect(bbox))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "appearance has an empty bounding box");
+        sx = ap_bbox.x1 / (bbox.x1 - bbox.x0);
+        sy = ap_bbox.y1 / (bbox.y1 - bbox.y0);
+
+        resources = pdf_new_dict(ctx, pdf, 1);
+        pdf_dict_puts(ctx, pdf_dict_put_dict(ctx, resources, PDF_NAME(XObject), 1), "Fm0", xobj);
+        contents = fz_new_buffer(ctx, 64);
+        fz_append_printf(ctx, contents, "q %g 0 0 %g %g %g cm /Fm0 Do Q\n", sx, sy, -bbox.x0 * sx, -bbox.y0 * sy);
+        ap = pdf_new_xobject(ctx, pdf, ap_bbox, fz_identity, resources, contents);
+        mupdf_pdf_set_annot_ap(ctx, annot, ap);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, resources);
+        pdf_drop_obj(ctx, ap);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return ind;
 }
 
-const char *mupdf_pdf_to_string(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* Stamp annotation in `rect`, in page space, whose appearance is the form XObject `xobj` scaled to fit */
+pdf_annot *mupdf_pdf_create_stamp_annot(fz_context *ctx, pdf_page *page, fz_rect rect, pdf_obj *xobj, mupdf_error_t **errptr)
 {
-    const char *s = NULL;
+    pdf_annot *annot = NULL;
+    fz_var(annot);
     fz_try(ctx)
     {
-        s = pdf_to_text_string(ctx, obj);
+        annot = pdf_create_annot(ctx, page, PDF_ANNOT_STAMP);
+        pdf_set_annot_rect(ctx, annot, rect);
+        pdf_update_annot(ctx, annot);
+        mupdf_pdf_set_annot_xobject_ap(ctx, annot, xobj);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return s;
+    return annot;
 }
 
-const char *mupdf_pdf_to_name(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+/* File attachment annotation in `rect`, in page space, embedding `data` as `filename` */
+pdf_annot *mupdf_pdf_create_file_attachment_annot(fz_context *ctx, pdf_page *page, fz_rect rect, const char *filename, const unsigned char *data, size_t len, const char *mime_type, const char *desc, mupdf_error_t **errptr)
 {
-    const char *s = NULL;
-    fz_try(ctx)
+    pdf_annot *annot = NULL;
+    fz_buffer *buf = NULL;
+    pdf_obj *stream_dict = NULL;
+    pdf_obj *stream = NULL;
+    pdf_obj *fs = NULL;
+    fz_var(annot);
+    fz_var(buf);
+    fz_var(stream_dict);
+    fz_var(stream);
+    fz_var(fs);
+    fz_try(ctx)
+    {
+        pdf_document *pdf = page->doc;
+        pdf_obj *params;
+
+        buf = fz_new_buffer_from_copied_data(ctx, data, len);
+        stream_dict = pdf_new_dict(ctx, pdf, 3);
+        pdf_dict_put(ctx, stream_dict, PDF_NAME(Type), PDF_NAME(EmbeddedFile));
+        if (mime_type)
+            pdf_dict_put_name(ctx, stream_dict, PDF_NAME(Subtype), mime_type);
+        params = pdf_dict_put_dict(ctx, stream_dict, PDF_NAME(Params), 1);
+        pdf_dict_put_int(ctx, params, PDF_NAME(Size), (int64_t)len);
+        stream = pdf_add_stream(ctx, pdf, buf, stream_dict, 0);
+
+        fs = pdf_new_dict(ctx, pdf, 5);
+        pdf_dict_put(ctx, fs, PDF_NAME(Type), PDF_NAME(Filespec));
+        pdf_dict_put_text_string(ctx, fs, PDF_NAME(F), filename);
+        pdf_dict_put_text_string(ctx, fs, PDF_NAME(UF), filename);
+        if (desc)
+            pdf_dict_put_text_string(ctx, fs, PDF_NAME(Desc), desc);
+        pdf_dict_put(ctx, pdf_dict_put_dict(ctx, fs, PDF_NAME(EF), 1), PDF_NAME(F), stream);
+
+        annot = pdf_create_annot(ctx, page, PDF_ANNOT_FILE_ATTACHMENT);
+        pdf_set_annot_rect(ctx, annot, rect);
+        pdf_dict_put(ctx, annot->obj, PDF_NAME(FS), fs);
+        pdf_dict_put_name(ctx, annot->obj, PDF_NAME(Name), "Paperclip");
+        if (desc)
+            pdf_set_annot_contents(ctx, annot, desc);
+        pdf_update_annot(ctx, annot);
+    }
+    fz_always(ctx)
     {
-        s = pdf_to_name(ctx, obj);
+        fz_drop_buffer(ctx, buf);
+        pdf_drop_obj(ctx, stream_dict);
+        pdf_drop_obj(ctx, stream);
+        pdf_drop_obj(ctx, fs);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return s;
+    return annot;
 }
 
-const unsigned char *mupdf_pdf_to_bytes(fz_context *ctx, pdf_obj *obj, size_t *len, mupdf_error_t **errptr)
+/* Text annotation replying (/IRT) to `parent`, placed at its top left corner */
+pdf_annot *mupdf_pdf_create_reply_annot(fz_context *ctx, pdf_page *page, pdf_annot *parent, const char *text, const char *author, mupdf_error_t **errptr)
 {
-    const char *s = NULL;
+    pdf_annot *annot = NULL;
+    fz_var(annot);
     fz_try(ctx)
     {
-        s = pdf_to_string(ctx, obj, len);
+        fz_rect rect = pdf_bound_annot(ctx, parent);
+        annot = pdf_create_annot(ctx, page, PDF_ANNOT_TEXT);
+        pdf_set_annot_rect(ctx, annot, fz_make_rect(rect.x0, rect.y0, rect.x0 + 20, rect.y0 + 20));
+        pdf_dict_put(ctx, annot->obj, PDF_NAME(IRT), parent->obj);
+        pdf_set_annot_contents(ctx, annot, text);
+        pdf_set_annot_author(ctx, annot, author);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
+        pdf_drop_annot(ctx, annot);
+        annot = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return (unsigned char *)s;
+    return annot;
 }
 
-pdf_obj *mupdf_pdf_resolve_indirect(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_pdf_delete_annot(fz_context *ctx, pdf_page *page, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
     fz_try(ctx)
     {
-        ind = pdf_resolve_indirect(ctx, obj);
-        pdf_keep_obj(ctx, ind);
+        pdf_delete_annot(ctx, page, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
 }
 
-pdf_obj *mupdf_pdf_array_get(fz_context *ctx, pdf_obj *obj, int index, mupdf_error_t **errptr)
+bool mupdf_pdf_update_page(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
 {
-    pdf_obj *val = NULL;
+    bool updated = false;
     fz_try(ctx)
     {
-        val = pdf_array_get(ctx, obj, index);
-        pdf_keep_obj(ctx, val);
+        updated = pdf_update_page(ctx, page);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return val;
+    return updated;
 }
 
-pdf_obj *mupdf_pdf_dict_gets(fz_context *ctx, pdf_obj *obj, const char *key, mupdf_error_t **errptr)
+bool mupdf_pdf_redact_page(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
 {
-    pdf_obj *val = NULL;
+    bool redacted = false;
     fz_try(ctx)
     {
-        val = pdf_dict_gets(ctx, obj, key);
-        pdf_keep_obj(ctx, val);
+        redacted = pdf_redact_page(ctx, page->doc, page, NULL);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return val;
+    return redacted;
 }
 
-pdf_obj *mupdf_pdf_dict_get_inheritable(fz_context *ctx, pdf_obj *obj, pdf_obj *key, mupdf_error_t **errptr)
+void mupdf_pdf_clean_page_contents(fz_context *ctx, pdf_page *page, bool sanitize, mupdf_error_t **errptr)
 {
-    pdf_obj *val = NULL;
     fz_try(ctx)
     {
-        val = pdf_dict_get_inheritable(ctx, obj, key);
-        pdf_keep_obj(ctx, val);
+        pdf_clean_page_contents(ctx, page->doc, page, NULL, NULL, NULL, sanitize, 0);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return val;
 }
 
-fz_buffer *mupdf_pdf_read_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_pdf_page_set_rotation(fz_context *ctx, pdf_page *page, int rotation, mupdf_error_t **errptr)
 {
-    fz_buffer *buf = NULL;
+    if (rotation % 90)
+    {
+        *errptr = mupdf_new_error_from_str("rotation not multiple of 90");
+        return;
+    }
     fz_try(ctx)
     {
-        buf = pdf_load_stream(ctx, obj);
+        pdf_dict_put_int(ctx, page->obj, PDF_NAME(Rotate), (int64_t)(((rotation % 360) + 360) % 360));
+        page->doc->dirty = 1;
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return buf;
 }
 
-fz_buffer *mupdf_pdf_read_raw_stream(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+static fz_rect mupdf_pdf_page_media_rect(fz_context *ctx, pdf_page *page)
 {
-    fz_buffer *buf = NULL;
+    pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(MediaBox));
+    if (obj)
+        return pdf_to_rect(ctx, obj);
+    return pdf_bound_page(ctx, page);
+}
+
+/* Page box `name` with y going down from the top of the MediaBox, falling back the way PDF defines */
+fz_rect mupdf_pdf_page_box(fz_context *ctx, pdf_page *page, const char *name, mupdf_error_t **errptr)
+{
+    fz_rect r = fz_empty_rect;
     fz_try(ctx)
     {
-        buf = pdf_load_raw_stream(ctx, obj);
+        fz_rect mediabox = mupdf_pdf_page_media_rect(ctx, page);
+        fz_rect box = mediabox;
+        pdf_obj *obj = pdf_dict_gets(ctx, page->obj, name);
+        if (!obj && strcmp(name, "MediaBox") != 0)
+            obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(CropBox));
+        if (obj)
+            box = fz_intersect_rect(pdf_to_rect(ctx, obj), mediabox);
+        r.x0 = box.x0;
+        r.y0 = mediabox.y1 - box.y1;
+        r.x1 = box.x1;
+        r.y1 = mediabox.y1 - box.y0;
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return buf;
+    return r;
 }
 
-void mupdf_pdf_write_object(fz_context *ctx, pdf_obj *self, pdf_obj *obj, mupdf_error_t **errptr)
+/* Set page box `name` from a rect with y going down from the top of the MediaBox */
+void mupdf_pdf_page_set_box(fz_context *ctx, pdf_page *page, const char *name, fz_rect rect, mupdf_error_t **errptr)
 {
-    pdf_document *pdf = pdf_get_bound_document(ctx, self);
-    if (!pdf)
-    {
-        *errptr = mupdf_new_error_from_str("object not bound to document");
-        return;
-    }
     fz_try(ctx)
     {
-        pdf_update_object(ctx, pdf, pdf_to_num(ctx, self), obj);
+        fz_rect mediabox = mupdf_pdf_page_media_rect(ctx, page);
+        fz_rect box = fz_empty_rect;
+        box.x0 = rect.x0;
+        box.y0 = mediabox.y1 - rect.y1;
+        box.x1 = rect.x1;
+        box.y1 = mediabox.y1 - rect.y0;
+        pdf_dict_puts_drop(ctx, page->obj, name, pdf_new_rect(ctx, page->doc, box));
+        page->doc->dirty = 1;
     }
     fz_catch(ctx)
     {
@@ -1362,67 +4964,147 @@ void mupdf_pdf_write_object(fz_context *ctx, pdf_obj *self, pdf_obj *obj, mupdf_
     }
 }
 
-void mupdf_pdf_write_stream_buffer(fz_context *ctx, pdf_obj *obj, fz_buffer *buf, int compressed, mupdf_error_t **errptr)
+void mupdf_pdf_page_set_crop_box(fz_context *ctx, pdf_page *page, fz_rect rect, mupdf_error_t **errptr)
 {
-    pdf_document *pdf = pdf_get_bound_document(ctx, obj);
-    if (!pdf)
-    {
-        *errptr = mupdf_new_error_from_str("object not bound to document");
-        return;
-    }
+    mupdf_pdf_page_set_box(ctx, page, "CropBox", rect, errptr);
+}
+
+/* Set the CropBox to the content bounds grown by `margin`, returns false for blank pages */
+bool mupdf_pdf_page_crop_to_content(fz_context *ctx, pdf_page *page, float margin, mupdf_error_t **errptr)
+{
+    bool cropped = false;
     fz_try(ctx)
     {
-        pdf_update_stream(ctx, pdf, obj, buf, compressed);
+        fz_rect content = mupdf_content_bbox(ctx, &page->super);
+        if (!fz_is_empty_rect(content))
+        {
+            fz_matrix ctm, inv;
+            fz_rect box;
+            pdf_page_transform(ctx, page, NULL, &ctm);
+            inv = fz_invert_matrix(ctm);
+            box = fz_intersect_rect(fz_expand_rect(content, margin), pdf_bound_page(ctx, page));
+            box = fz_transform_rect(box, inv);
+            pdf_dict_put_drop(ctx, page->obj, PDF_NAME(CropBox), pdf_new_rect(ctx, page->doc, box));
+            page->doc->dirty = 1;
+            cropped = true;
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return cropped;
 }
 
-int mupdf_pdf_array_len(fz_context *ctx, pdf_obj *obj, mupdf_error_t **errptr)
+void mupdf_pdf_page_set_media_box(fz_context *ctx, pdf_page *page, fz_rect rect, mupdf_error_t **errptr)
 {
-    int len = 0;
     fz_try(ctx)
     {
-        len = pdf_array_len(ctx, obj);
+        pdf_dict_put_drop(ctx, page->obj, PDF_NAME(MediaBox), pdf_new_rect(ctx, page->doc, rect));
+        page->doc->dirty = 1;
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return len;
 }
 
-void mupdf_pdf_array_put(fz_context *ctx, pdf_obj *self, int i, pdf_obj *item, mupdf_error_t **errptr)
+static pdf_obj *mupdf_pdf_add_content_stream(fz_context *ctx, pdf_document *pdf, fz_buffer *buf)
 {
+    pdf_obj *ref = pdf_add_new_dict(ctx, pdf, 1);
     fz_try(ctx)
-    {
-        pdf_array_put(ctx, self, i, item);
-    }
+        pdf_update_stream(ctx, pdf, ref, buf, 0);
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        pdf_drop_obj(ctx, ref);
+        fz_rethrow(ctx);
     }
+    return ref;
 }
 
-void mupdf_pdf_array_push(fz_context *ctx, pdf_obj *self, pdf_obj *item, mupdf_error_t **errptr)
+/* Add content streams `before` and `after` around the existing page contents, either may be NULL */
+static void mupdf_pdf_page_surround_contents(fz_context *ctx, pdf_page *page, fz_buffer *before, fz_buffer *after)
 {
+    pdf_document *pdf = page->doc;
+    pdf_obj *old = pdf_dict_get(ctx, page->obj, PDF_NAME(Contents));
+    pdf_obj *contents = pdf_new_array(ctx, pdf, 3);
+    pdf_obj *ref = NULL;
+    fz_var(ref);
     fz_try(ctx)
     {
-        pdf_array_push(ctx, self, item);
+        int i, n;
+        if (before)
+        {
+            ref = mupdf_pdf_add_content_stream(ctx, pdf, before);
+            pdf_array_push(ctx, contents, ref);
+            pdf_drop_obj(ctx, ref);
+            ref = NULL;
+        }
+        if (pdf_is_array(ctx, old))
+        {
+            n = pdf_array_len(ctx, old);
+            for (i = 0; i < n; i++)
+                pdf_array_push(ctx, contents, pdf_array_get(ctx, old, i));
+        }
+        else if (old)
+        {
+            pdf_array_push(ctx, contents, old);
+        }
+        if (after)
+        {
+            ref = mupdf_pdf_add_content_stream(ctx, pdf, after);
+            pdf_array_push(ctx, contents, ref);
+        }
+        pdf_dict_put(ctx, page->obj, PDF_NAME(Contents), contents);
+        pdf->dirty = 1;
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, ref);
+        pdf_drop_obj(ctx, contents);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
 }
 
-void mupdf_pdf_array_delete(fz_context *ctx, pdf_obj *self, int i, mupdf_error_t **errptr)
+static void mupdf_pdf_page_overlay(fz_context *ctx, pdf_page *page, fz_buffer *ops);
+
+/* Wrap the page contents in `q m cm ... Q` and move the boxes within the MediaBox along with them */
+void mupdf_pdf_page_transform_content(fz_context *ctx, pdf_page *page, fz_matrix m, mupdf_error_t **errptr)
 {
+    static const char *boxes[] = {"CropBox", "BleedBox", "TrimBox", "ArtBox"};
+    pdf_document *pdf = page->doc;
+    fz_buffer *before = NULL;
+    fz_buffer *after = NULL;
+    fz_var(before);
+    fz_var(after);
     fz_try(ctx)
     {
-        pdf_array_delete(ctx, self, i);
+        fz_rect mediabox = mupdf_pdf_page_media_rect(ctx, page);
+        int i;
+
+        before = fz_new_buffer(ctx, 64);
+        fz_append_printf(ctx, before, "q %g %g %g %g %g %g cm\n", m.a, m.b, m.c, m.d, m.e, m.f);
+        after = fz_new_buffer_from_copied_data(ctx, (const unsigned char *)"\nQ\n", 3);
+        mupdf_pdf_page_surround_contents(ctx, page, before, after);
+
+        for (i = 0; i < (int)nelem(boxes); i++)
+        {
+            pdf_obj *obj = pdf_dict_gets(ctx, page->obj, boxes[i]);
+            if (!obj && i == 0)
+                obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(CropBox));
+            if (!obj)
+                continue;
+            fz_rect box = fz_intersect_rect(fz_transform_rect(pdf_to_rect(ctx, obj), m), mediabox);
+            pdf_dict_puts_drop(ctx, page->obj, boxes[i], pdf_new_rect(ctx, pdf, box));
+        }
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, before);
+        fz_drop_buffer(ctx, after);
     }
     fz_catch(ctx)
     {
@@ -1430,11 +5112,62 @@ void mupdf_pdf_array_delete(fz_context *ctx, pdf_obj *self, int i, mupdf_error_t
     }
 }
 
-void mupdf_pdf_dict_put(fz_context *ctx, pdf_obj *self, pdf_obj *key, pdf_obj *value, mupdf_error_t **errptr)
+/* Draw `image` within `rect`, in page coordinates, above or below the existing page contents */
+void mupdf_pdf_page_insert_image(fz_context *ctx, pdf_page *page, fz_rect rect, fz_image *image, bool keep_proportion, bool overlay, mupdf_error_t **errptr)
 {
+    pdf_document *pdf = page->doc;
+    pdf_obj *ref = NULL;
+    fz_buffer *ops = NULL;
+    fz_var(ref);
+    fz_var(ops);
     fz_try(ctx)
     {
-        pdf_dict_put(ctx, self, key, value);
+        fz_matrix page_ctm, m;
+        float w = rect.x1 - rect.x0, h = rect.y1 - rect.y0;
+        float x = rect.x0, y = rect.y0;
+        pdf_obj *resources, *xobjs;
+        char name[32];
+        int i;
+
+        if (keep_proportion && image->w > 0 && image->h > 0)
+        {
+            float scale = fz_min(w / image->w, h / image->h);
+            x += (w - image->w * scale) / 2;
+            y += (h - image->h * scale) / 2;
+            w = image->w * scale;
+            h = image->h * scale;
+        }
+        /* PDF draws images into the unit square with y going up, page coordinates go down */
+        pdf_page_transform(ctx, page, NULL, &page_ctm);
+        m = fz_concat(fz_make_matrix(1, 0, 0, -1, 0, 1), fz_make_matrix(w, 0, 0, h, x, y));
+        m = fz_concat(m, fz_invert_matrix(page_ctm));
+
+        resources = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(Resources));
+        if (!resources)
+            resources = pdf_dict_put_dict(ctx, page->obj, PDF_NAME(Resources), 1);
+        xobjs = pdf_dict_get(ctx, resources, PDF_NAME(XObject));
+        if (!xobjs)
+            xobjs = pdf_dict_put_dict(ctx, resources, PDF_NAME(XObject), 1);
+        for (i = 0;; i++)
+        {
+            fz_snprintf(name, sizeof name, "Im%d", i);
+            if (!pdf_dict_gets(ctx, xobjs, name))
+                break;
+        }
+        ref = pdf_add_image(ctx, pdf, image);
+        pdf_dict_puts(ctx, xobjs, name, ref);
+
+        ops = fz_new_buffer(ctx, 64);
+        fz_append_printf(ctx, ops, "q %g %g %g %g %g %g cm /%s Do Q\n", m.a, m.b, m.c, m.d, m.e, m.f, name);
+        if (overlay)
+            mupdf_pdf_page_overlay(ctx, page, ops);
+        else
+            mupdf_pdf_page_surround_contents(ctx, page, ops, NULL);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, ref);
+        fz_drop_buffer(ctx, ops);
     }
     fz_catch(ctx)
     {
@@ -1442,11 +5175,43 @@ void mupdf_pdf_dict_put(fz_context *ctx, pdf_obj *self, pdf_obj *key, pdf_obj *v
     }
 }
 
-void mupdf_pdf_dict_delete(fz_context *ctx, pdf_obj *self, pdf_obj *key, mupdf_error_t **errptr)
+/* Write the `n` lines `strs`, each starting at the baseline point `pos[i]` in page coordinates, on top of the page contents in a single content stream */
+void mupdf_pdf_page_insert_text(fz_context *ctx, pdf_page *page, int n, const fz_point *pos, const char *const *strs, fz_font *font, float size, fz_colorspace *cs, const float *color, mupdf_error_t **errptr)
 {
+    pdf_document *pdf = page->doc;
+    fz_text *text = NULL;
+    fz_device *dev = NULL;
+    fz_buffer *ops = NULL;
+    fz_var(text);
+    fz_var(dev);
+    fz_var(ops);
     fz_try(ctx)
     {
-        pdf_dict_del(ctx, self, key);
+        fz_matrix page_ctm;
+        fz_rect mediabox;
+        pdf_obj *resources;
+        int i;
+
+        text = fz_new_text(ctx);
+        for (i = 0; i < n; i++)
+            fz_show_string(ctx, text, font, fz_make_matrix(size, 0, 0, -size, pos[i].x, pos[i].y), strs[i], 0, 0, FZ_BIDI_LTR, FZ_LANG_UNSET);
+
+        pdf_page_transform(ctx, page, &mediabox, &page_ctm);
+        resources = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(Resources));
+        if (!resources)
+            resources = pdf_dict_put_dict(ctx, page->obj, PDF_NAME(Resources), 1);
+        ops = fz_new_buffer(ctx, 256);
+        /* The device adds the font to the resources and writes the text operators */
+        dev = pdf_new_pdf_device(ctx, pdf, fz_invert_matrix(page_ctm), mediabox, resources, ops);
+        fz_fill_text(ctx, dev, text, fz_identity, cs, color, 1, fz_default_color_params);
+        fz_close_device(ctx, dev);
+        mupdf_pdf_page_overlay(ctx, page, ops);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_text(ctx, text);
+        fz_drop_buffer(ctx, ops);
     }
     fz_catch(ctx)
     {
@@ -1454,216 +5219,561 @@ void mupdf_pdf_dict_delete(fz_context *ctx, pdf_obj *self, pdf_obj *key, mupdf_e
     }
 }
 
-/* Buffer */
-size_t mupdf_buffer_read_bytes(fz_context *ctx, fz_buffer *buf, size_t at, unsigned char *output, size_t buf_len, mupdf_error_t **errptr)
+/* Add an ExtGState `name` with the fill and stroke alpha to the resources of the page or form `obj`, unless there is one already */
+void mupdf_pdf_add_alpha_gstate(fz_context *ctx, pdf_obj *obj, const char *name, float fill, float stroke, mupdf_error_t **errptr)
 {
-    size_t remaining_input = 0;
-    unsigned char *data;
-    size_t len = fz_buffer_storage(ctx, buf, &data);
-    if (at == len)
+    fz_try(ctx)
     {
-        // EOF
-        return 0;
+        pdf_obj *resources, *gstates, *gs;
+        resources = pdf_dict_get_inheritable(ctx, obj, PDF_NAME(Resources));
+        if (!resources)
+            resources = pdf_dict_put_dict(ctx, obj, PDF_NAME(Resources), 1);
+        gstates = pdf_dict_get(ctx, resources, PDF_NAME(ExtGState));
+        if (!gstates)
+            gstates = pdf_dict_put_dict(ctx, resources, PDF_NAME(ExtGState), 1);
+        if (!pdf_dict_gets(ctx, gstates, name))
+        {
+            gs = pdf_new_dict(ctx, pdf_get_bound_document(ctx, obj), 3);
+            pdf_dict_puts_drop(ctx, gstates, name, gs);
+            pdf_dict_put(ctx, gs, PDF_NAME(Type), PDF_NAME(ExtGState));
+            pdf_dict_put_real(ctx, gs, PDF_NAME(ca), fill);
+            pdf_dict_put_real(ctx, gs, PDF_NAME(CA), stroke);
+        }
     }
-    else if (at > len)
+    fz_catch(ctx)
     {
-        *errptr = mupdf_new_error_from_str("invalid offset, offset > buffer length");
-        return 0;
+        mupdf_save_error(ctx, errptr);
     }
-    remaining_input = len - at;
-    len = fz_minz(buf_len, remaining_input);
-    memcpy(output, &data[at], len);
-    return len;
 }
 
-void mupdf_buffer_write_bytes(fz_context *ctx, fz_buffer *buf, const unsigned char *bytes, size_t len, mupdf_error_t **errptr)
+/* Form XObject with the content stream operators `ops` and empty resources */
+pdf_obj *mupdf_pdf_new_form_xobject(fz_context *ctx, pdf_document *pdf, fz_rect bbox, const char *ops, mupdf_error_t **errptr)
 {
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *xobj = NULL;
+    fz_var(contents);
+    fz_var(resources);
     fz_try(ctx)
     {
-        fz_append_data(ctx, buf, bytes, len);
+        resources = pdf_new_dict(ctx, pdf, 1);
+        contents = fz_new_buffer_from_copied_data(ctx, (const unsigned char *)ops, strlen(ops));
+        xobj = pdf_new_xobject(ctx, pdf, bbox, fz_identity, resources, contents);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, resources);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return xobj;
 }
 
-fz_buffer *mupdf_buffer_from_str(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+/* Append content stream operators `ops`, in page coordinates, on top of the page contents */
+void mupdf_pdf_page_append_content(fz_context *ctx, pdf_page *page, const char *ops, mupdf_error_t **errptr)
 {
     fz_buffer *buf = NULL;
+    fz_var(buf);
     fz_try(ctx)
     {
-        buf = fz_new_buffer_from_copied_data(ctx, (const unsigned char *)s, strlen(s));
+        fz_matrix m;
+        pdf_page_transform(ctx, page, NULL, &m);
+        m = fz_invert_matrix(m);
+        buf = fz_new_buffer(ctx, strlen(ops) + 64);
+        fz_append_printf(ctx, buf, "q %g %g %g %g %g %g cm\n", m.a, m.b, m.c, m.d, m.e, m.f);
+        fz_append_string(ctx, buf, ops);
+        fz_append_string(ctx, buf, "\nQ\n");
+        mupdf_pdf_page_overlay(ctx, page, buf);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return buf;
 }
 
-fz_buffer *mupdf_buffer_from_base64(fz_context *ctx, const char *s, mupdf_error_t **errptr)
+/* Form XObject showing `text` on one line from the origin, `bbox` is set to its bounds */
+pdf_obj *mupdf_pdf_new_text_xobject(fz_context *ctx, pdf_document *pdf, const char *str, fz_font *font, float size, fz_colorspace *cs, const float *color, fz_rect *bbox, mupdf_error_t **errptr)
 {
-    fz_buffer *buf = NULL;
+    fz_text *text = NULL;
+    fz_device *dev = NULL;
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *xobj = NULL;
+    fz_var(text);
+    fz_var(dev);
+    fz_var(contents);
+    fz_var(resources);
     fz_try(ctx)
     {
-        buf = fz_new_buffer_from_base64(ctx, s, strlen(s));
+        fz_matrix end;
+        text = fz_new_text(ctx);
+        end = fz_show_string(ctx, text, font, fz_scale(size, size), str, 0, 0, FZ_BIDI_LTR, FZ_LANG_UNSET);
+        *bbox = fz_make_rect(0, fz_font_descender(ctx, font) * size, end.e, fz_font_ascender(ctx, font) * size);
+
+        resources = pdf_new_dict(ctx, pdf, 1);
+        contents = fz_new_buffer(ctx, 256);
+        dev = pdf_new_pdf_device(ctx, pdf, fz_identity, *bbox, resources, contents);
+        fz_fill_text(ctx, dev, text, fz_identity, cs, color, 1, fz_default_color_params);
+        fz_close_device(ctx, dev);
+        xobj = pdf_new_xobject(ctx, pdf, *bbox, fz_identity, resources, contents);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_device(ctx, dev);
+        fz_drop_text(ctx, text);
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, resources);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return buf;
+    return xobj;
 }
 
-/* Document */
-fz_document *mupdf_open_document(fz_context *ctx, const char *filename, mupdf_error_t **errptr)
+/* Form XObject drawing `image` at its own resolution from the origin, `bbox` is set to its bounds */
+pdf_obj *mupdf_pdf_new_image_xobject(fz_context *ctx, pdf_document *pdf, fz_image *image, fz_rect *bbox, mupdf_error_t **errptr)
 {
-    fz_document *doc = NULL;
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *ref = NULL;
+    pdf_obj *xobj = NULL;
+    fz_var(contents);
+    fz_var(resources);
+    fz_var(ref);
     fz_try(ctx)
     {
-        doc = fz_open_document(ctx, filename);
+        int xres, yres;
+        float w, h;
+        fz_image_resolution(image, &xres, &yres);
+        w = image->w * 72.0f / xres;
+        h = image->h * 72.0f / yres;
+        *bbox = fz_make_rect(0, 0, w, h);
+
+        ref = pdf_add_image(ctx, pdf, image);
+        resources = pdf_new_dict(ctx, pdf, 1);
+        pdf_dict_puts(ctx, pdf_dict_put_dict(ctx, resources, PDF_NAME(XObject), 1), "Im0", ref);
+        contents = fz_new_buffer(ctx, 64);
+        fz_append_printf(ctx, contents, "q %g 0 0 %g 0 0 cm /Im0 Do Q\n", w, h);
+        xobj = pdf_new_xobject(ctx, pdf, *bbox, fz_identity, resources, contents);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, resources);
+        pdf_drop_obj(ctx, ref);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return doc;
+    return xobj;
 }
 
-fz_document *mupdf_open_document_from_bytes(fz_context *ctx, fz_buffer *bytes, const char *magic, mupdf_error_t **errptr)
+/* Draw the form XObject `xobj` through `m`, in page coordinates, with the ExtGState `gstate` if not NULL */
+void mupdf_pdf_page_insert_xobject(fz_context *ctx, pdf_page *page, pdf_obj *xobj, fz_matrix m, const char *gstate, bool overlay, mupdf_error_t **errptr)
 {
-    if (!magic)
-    {
-        return NULL;
-    }
-    fz_document *doc = NULL;
-    fz_stream *stream = NULL;
-    fz_var(stream);
+    fz_buffer *ops = NULL;
+    fz_var(ops);
     fz_try(ctx)
     {
-        stream = fz_open_buffer(ctx, bytes);
-        doc = fz_open_document_with_stream(ctx, magic, stream);
+        fz_matrix page_ctm;
+        pdf_obj *resources, *xobjs;
+        char name[32];
+        int i;
+
+        resources = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(Resources));
+        if (!resources)
+            resources = pdf_dict_put_dict(ctx, page->obj, PDF_NAME(Resources), 1);
+        xobjs = pdf_dict_get(ctx, resources, PDF_NAME(XObject));
+        if (!xobjs)
+            xobjs = pdf_dict_put_dict(ctx, resources, PDF_NAME(XObject), 1);
+        /* Reuse the name when the form is already in the resources, stamping twice shares it */
+        for (i = 0;; i++)
+        {
+            pdf_obj *val;
+            fz_snprintf(name, sizeof name, "Fm%d", i);
+            val = pdf_dict_gets(ctx, xobjs, name);
+            if (!val)
+            {
+                pdf_dict_puts(ctx, xobjs, name, xobj);
+                break;
+            }
+            if (!pdf_objcmp(ctx, val, xobj))
+                break;
+        }
+
+        pdf_page_transform(ctx, page, NULL, &page_ctm);
+        m = fz_concat(m, fz_invert_matrix(page_ctm));
+        ops = fz_new_buffer(ctx, 128);
+        fz_append_string(ctx, ops, "q ");
+        if (gstate)
+            fz_append_printf(ctx, ops, "/%s gs ", gstate);
+        fz_append_printf(ctx, ops, "%g %g %g %g %g %g cm /%s Do Q\n", m.a, m.b, m.c, m.d, m.e, m.f, name);
+
+        if (overlay)
+            mupdf_pdf_page_overlay(ctx, page, ops);
+        else
+            mupdf_pdf_page_surround_contents(ctx, page, ops, NULL);
     }
     fz_always(ctx)
     {
-        fz_drop_stream(ctx, stream);
+        fz_drop_buffer(ctx, ops);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return doc;
 }
 
-bool mupdf_recognize_document(fz_context *ctx, const char *magic, mupdf_error_t **errptr)
+/* Content stream operations */
+
+static int mupdf_is_white(int c)
 {
-    if (!magic)
+    return c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\f' || c == 0;
+}
+
+static pdf_obj *mupdf_lex_operand(fz_context *ctx, pdf_document *pdf, fz_stream *stm, pdf_lexbuf *buf, pdf_token tok)
+{
+    switch (tok)
     {
-        return false;
+    case PDF_TOK_INT:
+        return pdf_new_int(ctx, buf->i);
+    case PDF_TOK_REAL:
+        return pdf_new_real(ctx, buf->f);
+    case PDF_TOK_STRING:
+        return pdf_new_string(ctx, buf->scratch, buf->len);
+    case PDF_TOK_NAME:
+        return pdf_new_name(ctx, buf->scratch);
+    case PDF_TOK_TRUE:
+        return PDF_TRUE;
+    case PDF_TOK_FALSE:
+        return PDF_FALSE;
+    case PDF_TOK_NULL:
+        return PDF_NULL;
+    case PDF_TOK_OPEN_ARRAY:
+        return pdf_parse_array(ctx, pdf, stm, buf);
+    case PDF_TOK_OPEN_DICT:
+        return pdf_parse_dict(ctx, pdf, stm, buf);
+    default:
+        fz_throw(ctx, FZ_ERROR_SYNTAX, "unexpected token in content stream");
     }
-    bool recognized = false;
+}
+
+/* Inline image dictionary up to ID and its data up to EI, pushed to `op` */
+static void mupdf_lex_inline_image(fz_context *ctx, pdf_document *pdf, fz_stream *stm, pdf_lexbuf *buf, pdf_obj *op)
+{
+    pdf_obj *dict = pdf_new_dict(ctx, pdf, 8);
+    fz_buffer *data = NULL;
+    pdf_obj *key = NULL;
+    fz_var(data);
+    fz_var(key);
     fz_try(ctx)
     {
-        recognized = fz_recognize_document(ctx, magic) != NULL;
+        pdf_token tok;
+        int c;
+        pdf_array_push(ctx, op, dict);
+        for (;;)
+        {
+            tok = pdf_lex(ctx, stm, buf);
+            if (tok == PDF_TOK_KEYWORD && !strcmp(buf->scratch, "ID"))
+                break;
+            if (tok != PDF_TOK_NAME)
+                fz_throw(ctx, FZ_ERROR_SYNTAX, "expected name in inline image dictionary");
+            key = pdf_new_name(ctx, buf->scratch);
+            pdf_dict_put_drop(ctx, dict, key, mupdf_lex_operand(ctx, pdf, stm, buf, pdf_lex(ctx, stm, buf)));
+            pdf_drop_obj(ctx, key);
+            key = NULL;
+        }
+        /* A single white-space separates ID from the data, which ends at white-space EI.
+           Writers also put CRLF there, MuPDF skips it as one the same way. */
+        c = fz_read_byte(ctx, stm);
+        if (c == '\r' && fz_peek_byte(ctx, stm) == '\n')
+            fz_read_byte(ctx, stm);
+        data = fz_new_buffer(ctx, 1024);
+        while ((c = fz_read_byte(ctx, stm)) != EOF)
+        {
+            fz_append_byte(ctx, data, c);
+            if (data->len >= 3 && data->data[data->len - 1] == 'I' && data->data[data->len - 2] == 'E' &&
+                mupdf_is_white(data->data[data->len - 3]))
+            {
+                c = fz_peek_byte(ctx, stm);
+                if (c == EOF || mupdf_is_white(c) || strchr("()<>[]{}/%", c))
+                {
+                    data->len -= 3;
+                    break;
+                }
+            }
+        }
+        pdf_array_push_drop(ctx, op, pdf_new_string(ctx, (char *)data->data, data->len));
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, key);
+        pdf_drop_obj(ctx, dict);
+        fz_drop_buffer(ctx, data);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return recognized;
 }
 
-bool mupdf_needs_password(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+/* Count the q and Q operators of `stm`, `extra` Q restoring a state saved before it and `open` q never restored */
+static void mupdf_count_gstate_nesting(fz_context *ctx, pdf_document *pdf, fz_stream *stm, int *extra, int *open)
 {
-    bool needs = false;
+    pdf_lexbuf buf;
+    pdf_obj *image = NULL;
+    fz_var(image);
+    pdf_lexbuf_init(ctx, &buf, PDF_LEXBUF_SMALL);
     fz_try(ctx)
     {
-        needs = fz_needs_password(ctx, doc);
+        pdf_token tok;
+        while ((tok = pdf_lex(ctx, stm, &buf)) != PDF_TOK_EOF)
+        {
+            if (tok != PDF_TOK_KEYWORD)
+                continue;
+            if (!strcmp(buf.scratch, "q"))
+                (*open)++;
+            else if (!strcmp(buf.scratch, "Q") && *open > 0)
+                (*open)--;
+            else if (!strcmp(buf.scratch, "Q"))
+                (*extra)++;
+            else if (!strcmp(buf.scratch, "BI"))
+            {
+                /* Skip the image data, it could hold anything */
+                image = pdf_new_array(ctx, pdf, 2);
+                mupdf_lex_inline_image(ctx, pdf, stm, &buf, image);
+                pdf_drop_obj(ctx, image);
+                image = NULL;
+            }
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, image);
+        pdf_lexbuf_fin(ctx, &buf);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return needs;
 }
 
-bool mupdf_authenticate_password(fz_context *ctx, fz_document *doc, const char *password, mupdf_error_t **errptr)
+/* Balanced q and Q to put around content with the given nesting */
+static void mupdf_append_gstate_balance(fz_context *ctx, fz_buffer *before, fz_buffer *after, int extra, int open)
 {
-    bool ok = false;
+    int i;
+    for (i = 0; i <= extra; i++)
+        fz_append_string(ctx, before, "q\n");
+    fz_append_byte(ctx, after, '\n');
+    /* The `extra` Q of the content already restore as many of the q added before it */
+    for (i = 0; i <= open; i++)
+        fz_append_string(ctx, after, "Q\n");
+}
+
+/* Enclose the page contents in enough q and Q to balance them, followed by `ops` if not NULL */
+static void mupdf_pdf_page_overlay(fz_context *ctx, pdf_page *page, fz_buffer *ops)
+{
+    pdf_document *pdf = page->doc;
+    fz_stream *stm = NULL;
+    fz_buffer *before = NULL;
+    fz_buffer *after = NULL;
+    fz_var(stm);
+    fz_var(before);
+    fz_var(after);
     fz_try(ctx)
     {
-        ok = fz_authenticate_password(ctx, doc, password);
+        int extra = 0, open = 0;
+        stm = pdf_open_contents_stream(ctx, pdf, pdf_page_contents(ctx, page));
+        mupdf_count_gstate_nesting(ctx, pdf, stm, &extra, &open);
+        before = fz_new_buffer(ctx, 16);
+        after = fz_new_buffer(ctx, 64);
+        mupdf_append_gstate_balance(ctx, before, after, extra, open);
+        if (ops)
+            fz_append_buffer(ctx, after, ops);
+        mupdf_pdf_page_surround_contents(ctx, page, before, after);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_stream(ctx, stm);
+        fz_drop_buffer(ctx, before);
+        fz_drop_buffer(ctx, after);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return ok;
 }
 
-int mupdf_document_page_count(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+void mupdf_pdf_page_wrap_contents(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
 {
-    int count = 0;
     fz_try(ctx)
     {
-        count = fz_count_pages(ctx, doc);
+        mupdf_pdf_page_overlay(ctx, page, NULL);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return count;
 }
 
-char *mupdf_lookup_metadata(fz_context *ctx, fz_document *doc, const char *key, mupdf_error_t **errptr)
+/* Append `len` bytes of content stream operators in PDF user space, balanced on their own, on top of the page contents */
+void mupdf_pdf_page_append_raw_content(fz_context *ctx, pdf_page *page, const unsigned char *data, size_t len, mupdf_error_t **errptr)
 {
-    int len;
-    char *value = NULL;
+    fz_stream *stm = NULL;
+    fz_buffer *ops = NULL;
+    fz_buffer *after = NULL;
+    fz_var(stm);
+    fz_var(ops);
+    fz_var(after);
     fz_try(ctx)
     {
-        len = fz_lookup_metadata(ctx, doc, key, NULL, 0) + 1;
-        if (len > 1)
-        {
-            value = calloc(len, sizeof(char));
-            fz_lookup_metadata(ctx, doc, key, value, len);
-        }
+        int extra = 0, open = 0;
+        stm = fz_open_memory(ctx, data, len);
+        mupdf_count_gstate_nesting(ctx, page->doc, stm, &extra, &open);
+        ops = fz_new_buffer(ctx, len + 32);
+        after = fz_new_buffer(ctx, 16);
+        mupdf_append_gstate_balance(ctx, ops, after, extra, open);
+        fz_append_data(ctx, ops, data, len);
+        fz_append_buffer(ctx, ops, after);
+        mupdf_pdf_page_overlay(ctx, page, ops);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_stream(ctx, stm);
+        fz_drop_buffer(ctx, ops);
+        fz_drop_buffer(ctx, after);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return value;
 }
 
-bool mupdf_is_document_reflowable(fz_context *ctx, fz_document *doc, mupdf_error_t **errptr)
+/* The operations of the page contents, an array of arrays of the operands followed by the operator name */
+pdf_obj *mupdf_pdf_page_content_operations(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
 {
-    bool is_reflowable = false;
+    pdf_document *pdf = page->doc;
+    pdf_lexbuf buf;
+    fz_stream *stm = NULL;
+    pdf_obj *ops = NULL;
+    pdf_obj *op = NULL;
+    fz_var(stm);
+    fz_var(ops);
+    fz_var(op);
+    pdf_lexbuf_init(ctx, &buf, PDF_LEXBUF_LARGE);
     fz_try(ctx)
     {
-        is_reflowable = fz_is_document_reflowable(ctx, doc);
+        pdf_token tok;
+        ops = pdf_new_array(ctx, pdf, 64);
+        stm = pdf_open_contents_stream(ctx, pdf, pdf_page_contents(ctx, page));
+        op = pdf_new_array(ctx, pdf, 4);
+        while ((tok = pdf_lex(ctx, stm, &buf)) != PDF_TOK_EOF)
+        {
+            if (tok == PDF_TOK_KEYWORD)
+            {
+                if (!strcmp(buf.scratch, "BI"))
+                {
+                    mupdf_lex_inline_image(ctx, pdf, stm, &buf, op);
+                    pdf_array_push_drop(ctx, op, pdf_new_name(ctx, "BI"));
+                }
+                else
+                {
+                    pdf_array_push_drop(ctx, op, pdf_new_name(ctx, buf.scratch));
+                }
+                pdf_array_push(ctx, ops, op);
+                pdf_drop_obj(ctx, op);
+                op = NULL;
+                op = pdf_new_array(ctx, pdf, 4);
+            }
+            else
+            {
+                pdf_array_push_drop(ctx, op, mupdf_lex_operand(ctx, pdf, stm, &buf, tok));
+            }
+        }
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, op);
+        fz_drop_stream(ctx, stm);
+        pdf_lexbuf_fin(ctx, &buf);
     }
     fz_catch(ctx)
     {
+        pdf_drop_obj(ctx, ops);
+        ops = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return is_reflowable;
+    return ops;
 }
 
-void mupdf_layout_document(fz_context *ctx, fz_document *doc, float w, float h, float em, mupdf_error_t **errptr)
+/* Replace the page contents with a single stream writing `ops`, in the form returned by mupdf_pdf_page_content_operations */
+void mupdf_pdf_page_set_content_operations(fz_context *ctx, pdf_page *page, pdf_obj *ops, mupdf_error_t **errptr)
 {
+    pdf_document *pdf = page->doc;
+    fz_buffer *buf = NULL;
+    fz_output *out = NULL;
+    pdf_obj *ref = NULL;
+    fz_var(buf);
+    fz_var(out);
+    fz_var(ref);
     fz_try(ctx)
     {
-        if (!fz_is_document_reflowable(ctx, doc))
-        {
-            return;
-        }
-        if (w <= 0.0f || h <= 0.0f)
+        int i, j, n = pdf_array_len(ctx, ops);
+        buf = fz_new_buffer(ctx, 1024);
+        out = fz_new_output_with_buffer(ctx, buf);
+        for (i = 0; i < n; i++)
         {
-            *errptr = mupdf_new_error_from_str("invalid width or height");
-            return;
+            pdf_obj *op = pdf_array_get(ctx, ops, i);
+            int len = pdf_array_len(ctx, op);
+            const char *name = pdf_to_name(ctx, pdf_array_get(ctx, op, len - 1));
+            if (len < 1 || !*name)
+                fz_throw(ctx, FZ_ERROR_GENERIC, "content operation without operator");
+            if (!strcmp(name, "BI"))
+            {
+                pdf_obj *dict = pdf_array_get(ctx, op, 0);
+                pdf_obj *data = pdf_array_get(ctx, op, 1);
+                int k, m = pdf_dict_len(ctx, dict);
+                fz_write_string(ctx, out, "BI\n");
+                for (k = 0; k < m; k++)
+                {
+                    pdf_print_obj(ctx, out, pdf_dict_get_key(ctx, dict, k), 1, 1);
+                    fz_write_byte(ctx, out, ' ');
+                    pdf_print_obj(ctx, out, pdf_dict_get_val(ctx, dict, k), 1, 1);
+                    fz_write_byte(ctx, out, '\n');
+                }
+                fz_write_string(ctx, out, "ID\n");
+                fz_write_data(ctx, out, pdf_to_str_buf(ctx, data), pdf_to_str_len(ctx, data));
+                fz_write_string(ctx, out, "\nEI\n");
+                continue;
+            }
+            for (j = 0; j < len - 1; j++)
+            {
+                pdf_print_obj(ctx, out, pdf_array_get(ctx, op, j), 1, 1);
+                fz_write_byte(ctx, out, ' ');
+            }
+            fz_write_string(ctx, out, name);
+            fz_write_byte(ctx, out, '\n');
         }
-        fz_layout_document(ctx, doc, w, h, em);
+        fz_close_output(ctx, out);
+
+        ref = mupdf_pdf_add_content_stream(ctx, pdf, buf);
+        pdf_dict_put(ctx, page->obj, PDF_NAME(Contents), ref);
+        pdf->dirty = 1;
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, ref);
+        fz_drop_output(ctx, out);
+        fz_drop_buffer(ctx, buf);
     }
     fz_catch(ctx)
     {
@@ -1671,226 +5781,306 @@ void mupdf_layout_document(fz_context *ctx, fz_document *doc, float w, float h,
     }
 }
 
-fz_page *mupdf_load_page(fz_context *ctx, fz_document *doc, int page_no, mupdf_error_t **errptr)
+fz_point mupdf_pdf_page_crop_box_position(fz_context *ctx, pdf_page *page)
 {
-    fz_page *page = NULL;
-    fz_try(ctx)
+    fz_point pos = fz_make_point(0, 0);
+    pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(CropBox));
+    if (!obj)
     {
-        page = fz_load_page(ctx, doc, page_no);
+        return pos;
     }
-    fz_catch(ctx)
+    fz_rect cbox = pdf_to_rect(ctx, obj);
+    pos = fz_make_point(cbox.x0, cbox.y0);
+    return pos;
+}
+
+fz_rect mupdf_pdf_page_media_box(fz_context *ctx, pdf_page *page)
+{
+    fz_rect r = fz_empty_rect;
+    pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(MediaBox));
+    if (!obj)
     {
-        mupdf_save_error(ctx, errptr);
+        return r;
     }
-    return page;
+    r = pdf_to_rect(ctx, obj);
+    return r;
 }
 
-static pdf_document *mupdf_convert_to_pdf_internal(fz_context *ctx, fz_document *doc, int fp, int tp, int rotate)
+fz_matrix mupdf_pdf_page_transform(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
 {
-    pdf_document *pdfout = pdf_create_document(ctx);
-    int i, incr = 1, s = fp, e = tp;
-    if (fp > tp) // revert page sequence
+    fz_matrix ctm = fz_identity;
+    fz_try(ctx)
     {
-        incr = -1;
-        s = tp;
-        e = fp;
+        pdf_page_transform(ctx, page, NULL, &ctm);
     }
-    fz_rect mediabox;
-    fz_device *dev = NULL;
-    fz_buffer *contents = NULL;
-    pdf_obj *resources = NULL;
-    fz_page *page;
-    fz_var(dev);
-    fz_var(contents);
-    fz_var(resources);
-    fz_var(page);
-    for (i = fp; i >= s && i <= e; i += incr)
+    fz_catch(ctx)
     {
-        fz_try(ctx)
-        {
-            page = fz_load_page(ctx, doc, i);
-            mediabox = fz_bound_page(ctx, page);
-            dev = pdf_page_write(ctx, pdfout, mediabox, &resources, &contents);
-            fz_run_page(ctx, page, dev, fz_identity, NULL);
-            fz_close_device(ctx, dev);
-            fz_drop_device(ctx, dev);
-            dev = NULL;
-            pdf_obj *page_obj = pdf_add_page(ctx, pdfout, mediabox, rotate, resources, contents);
-            pdf_insert_page(ctx, pdfout, -1, page_obj);
-            pdf_drop_obj(ctx, page_obj);
-        }
-        fz_always(ctx)
-        {
-            pdf_drop_obj(ctx, resources);
-            fz_drop_buffer(ctx, contents);
-            fz_drop_device(ctx, dev);
-        }
-        fz_catch(ctx)
-        {
-            fz_drop_page(ctx, page);
-            fz_rethrow(ctx);
-        }
+        mupdf_save_error(ctx, errptr);
     }
-    return pdfout;
+    return ctm;
 }
 
-pdf_document *mupdf_convert_to_pdf(fz_context *ctx, fz_document *doc, int fp, int tp, int rotate, mupdf_error_t **errptr)
+static void mupdf_pdf_reload_links(fz_context *ctx, pdf_page *page)
 {
-    if (rotate % 90)
+    fz_matrix page_ctm;
+    pdf_page_transform(ctx, page, NULL, &page_ctm);
+    fz_drop_link(ctx, page->links);
+    page->links = NULL;
+    pdf_obj *annots = pdf_dict_get(ctx, page->obj, PDF_NAME(Annots));
+    if (annots)
     {
-        *errptr = mupdf_new_error_from_str("rotation not multiple of 90");
-        return NULL;
+        page->links = pdf_load_link_annots(ctx, page->doc, annots, page->super.number, page_ctm);
     }
-    pdf_document *pdf = NULL;
+}
+
+fz_default_colorspaces *mupdf_pdf_load_default_colorspaces(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
+{
+    fz_default_colorspaces *dcs = NULL;
     fz_try(ctx)
     {
-        pdf = mupdf_convert_to_pdf_internal(ctx, doc, fp, tp, rotate);
+        dcs = pdf_load_default_colorspaces(ctx, page->doc, page);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return pdf;
+    return dcs;
 }
 
-int mupdf_resolve_link(fz_context *ctx, fz_document *doc, const char *uri, mupdf_error_t **errptr)
+/* Add a /Link annotation, `rect` is in page space, `key` is /A or /Dest */
+static void mupdf_pdf_add_link(fz_context *ctx, pdf_page *page, fz_rect rect, pdf_obj *key, pdf_obj *val)
 {
-    int page_no = -1;
-    float xp = 0.0f, yp = 0.0f;
+    pdf_document *doc = page->doc;
+    pdf_obj *annot = NULL, *ind = NULL;
+    fz_var(annot);
+    fz_var(ind);
     fz_try(ctx)
     {
-        page_no = fz_resolve_link(ctx, doc, uri, &xp, &yp);
+        fz_matrix page_ctm;
+        pdf_page_transform(ctx, page, NULL, &page_ctm);
+        rect = fz_transform_rect(rect, fz_invert_matrix(page_ctm));
+
+        annot = pdf_new_dict(ctx, doc, 5);
+        pdf_dict_put(ctx, annot, PDF_NAME(Type), PDF_NAME(Annot));
+        pdf_dict_put(ctx, annot, PDF_NAME(Subtype), PDF_NAME(Link));
+        pdf_dict_put_rect(ctx, annot, PDF_NAME(Rect), rect);
+        pdf_obj *border = pdf_dict_put_array(ctx, annot, PDF_NAME(Border), 3);
+        pdf_array_push_int(ctx, border, 0);
+        pdf_array_push_int(ctx, border, 0);
+        pdf_array_push_int(ctx, border, 0);
+        pdf_dict_put(ctx, annot, key, val);
+        ind = pdf_add_object(ctx, doc, annot);
+
+        pdf_obj *annots = pdf_dict_get(ctx, page->obj, PDF_NAME(Annots));
+        if (!pdf_is_array(ctx, annots))
+        {
+            annots = pdf_dict_put_array(ctx, page->obj, PDF_NAME(Annots), 1);
+        }
+        pdf_array_push(ctx, annots, ind);
+        doc->dirty = 1;
+        mupdf_pdf_reload_links(ctx, page);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, ind);
+        pdf_drop_obj(ctx, annot);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return page_no;
 }
 
-/* PdfDocument */
-pdf_document *mupdf_pdf_open_document_from_bytes(fz_context *ctx, fz_buffer *bytes, mupdf_error_t **errptr)
+void mupdf_pdf_create_link_uri(fz_context *ctx, pdf_page *page, fz_rect rect, const char *uri, mupdf_error_t **errptr)
 {
-    pdf_document *pdf = NULL;
-    fz_stream *stream = NULL;
-    fz_var(stream);
+    pdf_obj *action = NULL;
+    fz_var(action);
     fz_try(ctx)
     {
-        stream = fz_open_buffer(ctx, bytes);
-        pdf = pdf_open_document_with_stream(ctx, stream);
+        action = pdf_new_dict(ctx, page->doc, 2);
+        pdf_dict_put(ctx, action, PDF_NAME(S), PDF_NAME(URI));
+        pdf_dict_put_text_string(ctx, action, PDF_NAME(URI), uri);
+        mupdf_pdf_add_link(ctx, page, rect, PDF_NAME(A), action);
     }
     fz_always(ctx)
     {
-        fz_drop_stream(ctx, stream);
+        pdf_drop_obj(ctx, action);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return pdf;
 }
 
-pdf_obj *mupdf_pdf_add_object(fz_context *ctx, pdf_document *pdf, pdf_obj *obj, mupdf_error_t **errptr)
+/* Link to `page_no`, scrolling to the page space point (x, y) unless it is NAN */
+void mupdf_pdf_create_link_dest(fz_context *ctx, pdf_page *page, fz_rect rect, int page_no, float x, float y, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    pdf_obj *dest = NULL;
+    fz_var(dest);
     fz_try(ctx)
     {
-        ind = pdf_add_object(ctx, pdf, obj);
+        dest = mupdf_pdf_new_page_dest(ctx, page->doc, page_no, x, y);
+        mupdf_pdf_add_link(ctx, page, rect, PDF_NAME(Dest), dest);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, dest);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
 }
 
-pdf_obj *mupdf_pdf_create_object(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* Delete the first /Link annotation whose page space bounds match `rect` */
+bool mupdf_pdf_delete_link(fz_context *ctx, pdf_page *page, fz_rect rect, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    bool deleted = false;
     fz_try(ctx)
     {
-        ind = pdf_new_indirect(ctx, pdf, pdf_create_object(ctx, pdf), 0);
+        fz_matrix page_ctm;
+        pdf_page_transform(ctx, page, NULL, &page_ctm);
+        pdf_obj *annots = pdf_dict_get(ctx, page->obj, PDF_NAME(Annots));
+        int n = pdf_array_len(ctx, annots);
+        for (int i = 0; i < n; i++)
+        {
+            pdf_obj *annot = pdf_array_get(ctx, annots, i);
+            if (!pdf_name_eq(ctx, pdf_dict_get(ctx, annot, PDF_NAME(Subtype)), PDF_NAME(Link)))
+            {
+                continue;
+            }
+            fz_rect bounds = fz_transform_rect(pdf_dict_get_rect(ctx, annot, PDF_NAME(Rect)), page_ctm);
+            if (fabsf(bounds.x0 - rect.x0) < 0.01f && fabsf(bounds.y0 - rect.y0) < 0.01f &&
+                fabsf(bounds.x1 - rect.x1) < 0.01f && fabsf(bounds.y1 - rect.y1) < 0.01f)
+            {
+                pdf_array_delete(ctx, annots, i);
+                page->doc->dirty = 1;
+                deleted = true;
+                break;
+            }
+        }
+        if (deleted)
+        {
+            mupdf_pdf_reload_links(ctx, page);
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
+    return deleted;
 }
 
-void mupdf_pdf_delete_object(fz_context *ctx, pdf_document *pdf, int num, mupdf_error_t **errptr)
+/* PDFAnnotation */
+int mupdf_pdf_annot_type(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    int subtype = 0;
     fz_try(ctx)
     {
-        pdf_delete_object(ctx, pdf, num);
+        subtype = pdf_annot_type(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return subtype;
 }
 
-pdf_obj *mupdf_pdf_add_image(fz_context *ctx, pdf_document *pdf, fz_image *image, mupdf_error_t **errptr)
+/* The annotation on the same page `annot` replies to (/IRT), NULL if none */
+pdf_annot *mupdf_pdf_annot_in_reply_to(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    pdf_annot *parent = NULL;
     fz_try(ctx)
     {
-        ind = pdf_add_image(ctx, pdf, image);
+        int num = pdf_to_num(ctx, pdf_dict_get(ctx, annot->obj, PDF_NAME(IRT)));
+        pdf_annot *other;
+        for (other = pdf_first_annot(ctx, annot->page); num > 0 && other; other = pdf_next_annot(ctx, other))
+        {
+            if (pdf_to_num(ctx, other->obj) == num)
+            {
+                parent = pdf_keep_annot(ctx, other);
+                break;
+            }
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
+    return parent;
 }
 
-pdf_obj *mupdf_pdf_add_font(fz_context *ctx, pdf_document *pdf, fz_font *font, mupdf_error_t **errptr)
+/* Page space rect of the popup of `annot`, empty if it has none */
+fz_rect mupdf_pdf_annot_popup(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    fz_rect rect = fz_empty_rect;
     fz_try(ctx)
     {
-        ind = pdf_add_cid_font(ctx, pdf, font);
+        pdf_obj *popup = pdf_dict_get(ctx, annot->obj, PDF_NAME(Popup));
+        if (popup)
+        {
+            fz_matrix page_ctm;
+            pdf_page_transform(ctx, annot->page, NULL, &page_ctm);
+            rect = fz_transform_rect(pdf_dict_get_rect(ctx, popup, PDF_NAME(Rect)), page_ctm);
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
+    return rect;
 }
 
-pdf_obj *mupdf_pdf_add_cjk_font(fz_context *ctx, pdf_document *pdf, fz_font *font, int ordering, int wmode, bool serif, mupdf_error_t **errptr)
+/* Move the popup of `annot` to `rect`, in page space, creating the popup annotation if needed */
+void mupdf_pdf_set_annot_popup(fz_context *ctx, pdf_annot *annot, fz_rect rect, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    pdf_annot *created = NULL;
+    fz_var(created);
     fz_try(ctx)
     {
-        ind = pdf_add_cjk_font(ctx, pdf, font, ordering, wmode, serif);
+        pdf_obj *popup = pdf_dict_get(ctx, annot->obj, PDF_NAME(Popup));
+        fz_matrix page_ctm;
+        pdf_page_transform(ctx, annot->page, NULL, &page_ctm);
+        if (!popup)
+        {
+            created = pdf_create_annot(ctx, annot->page, PDF_ANNOT_POPUP);
+            popup = created->obj;
+            pdf_dict_put(ctx, popup, PDF_NAME(Parent), annot->obj);
+            pdf_dict_put(ctx, annot->obj, PDF_NAME(Popup), popup);
+        }
+        pdf_dict_put_rect(ctx, popup, PDF_NAME(Rect), fz_transform_rect(rect, fz_invert_matrix(page_ctm)));
+        pdf_dirty_annot(ctx, annot);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_annot(ctx, created);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
 }
 
-pdf_obj *mupdf_pdf_add_simple_font(fz_context *ctx, pdf_document *pdf, fz_font *font, int encoding, mupdf_error_t **errptr)
+/* Synthesize the appearance of `annot` if it changed, returns whether it did */
+bool mupdf_pdf_update_annot(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    pdf_obj *ind = NULL;
+    bool changed = false;
     fz_try(ctx)
     {
-        ind = pdf_add_simple_font(ctx, pdf, font, encoding);
+        changed = pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return ind;
+    return changed;
 }
 
-void mupdf_pdf_save_document(fz_context *ctx, pdf_document *pdf, const char *filename, pdf_write_options pwo, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_appearance_xobject(fz_context *ctx, pdf_annot *annot, pdf_obj *xobj, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        pdf_save_document(ctx, pdf, filename, &pwo);
+        mupdf_pdf_set_annot_xobject_ap(ctx, annot, xobj);
     }
     fz_catch(ctx)
     {
@@ -1898,35 +6088,74 @@ void mupdf_pdf_save_document(fz_context *ctx, pdf_document *pdf, const char *fil
     }
 }
 
-fz_buffer *mupdf_pdf_write_document(fz_context *ctx, pdf_document *pdf, pdf_write_options pwo, mupdf_error_t **errptr)
+/* Appearance from the display list `list`, its bounds scaled to the annotation rect */
+void mupdf_pdf_set_annot_appearance_display_list(fz_context *ctx, pdf_annot *annot, fz_display_list *list, mupdf_error_t **errptr)
 {
-    fz_output *out = NULL;
-    fz_buffer *buf = NULL;
-    fz_var(out);
-    fz_var(buf);
+    fz_buffer *contents = NULL;
+    pdf_obj *resources = NULL;
+    pdf_obj *ap = NULL;
+    fz_device *dev = NULL;
+    fz_var(contents);
+    fz_var(resources);
+    fz_var(ap);
+    fz_var(dev);
     fz_try(ctx)
     {
-        buf = fz_new_buffer(ctx, 8192);
-        out = fz_new_output_with_buffer(ctx, buf);
-        pdf_write_document(ctx, pdf, out, &pwo);
-        fz_close_output(ctx, out);
+        pdf_document *pdf = annot->page->doc;
+        fz_rect bounds = fz_bound_display_list(ctx, list);
+        fz_rect bbox = mupdf_pdf_annot_ap_bbox(ctx, annot);
+        float sx, sy;
+        fz_matrix ctm;
+        if (fz_is_empty_rect(bounds))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "appearance has an empty bounding box");
+        /* Display lists have y going down, appearance streams y going up */
+        sx = bbox.x1 / (bounds.x1 - bounds.x0);
+        sy = bbox.y1 / (bounds.y1 - bounds.y0);
+        ctm = fz_make_matrix(sx, 0, 0, -sy, -bounds.x0 * sx, bounds.y1 * sy);
+
+        resources = pdf_new_dict(ctx, pdf, 1);
+        contents = fz_new_buffer(ctx, 1024);
+        dev = pdf_new_pdf_device(ctx, pdf, fz_identity, bbox, resources, contents);
+        fz_run_display_list(ctx, list, dev, ctm, fz_infinite_rect, NULL);
+        fz_close_device(ctx, dev);
+        ap = pdf_new_xobject(ctx, pdf, bbox, fz_identity, resources, contents);
+        mupdf_pdf_set_annot_ap(ctx, annot, ap);
     }
     fz_always(ctx)
     {
-        fz_drop_output(ctx, out);
+        fz_drop_device(ctx, dev);
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, resources);
+        pdf_drop_obj(ctx, ap);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return buf;
 }
 
-void mupdf_pdf_enable_js(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* Appearance from raw content stream bytes in the coordinates of the annotation rect, origin at its lower left */
+void mupdf_pdf_set_annot_appearance_content(fz_context *ctx, pdf_annot *annot, const unsigned char *data, size_t len, pdf_obj *resources, mupdf_error_t **errptr)
 {
+    fz_buffer *contents = NULL;
+    pdf_obj *res = NULL;
+    pdf_obj *ap = NULL;
+    fz_var(contents);
+    fz_var(res);
+    fz_var(ap);
     fz_try(ctx)
     {
-        pdf_enable_js(ctx, pdf);
+        pdf_document *pdf = annot->page->doc;
+        res = resources ? pdf_keep_obj(ctx, resources) : pdf_new_dict(ctx, pdf, 0);
+        contents = fz_new_buffer_from_copied_data(ctx, data, len);
+        ap = pdf_new_xobject(ctx, pdf, mupdf_pdf_annot_ap_bbox(ctx, annot), fz_identity, res, contents);
+        mupdf_pdf_set_annot_ap(ctx, annot, ap);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, contents);
+        pdf_drop_obj(ctx, res);
+        pdf_drop_obj(ctx, ap);
     }
     fz_catch(ctx)
     {
@@ -1934,183 +6163,302 @@ void mupdf_pdf_enable_js(fz_context *ctx, pdf_document *pdf, mupdf_error_t **err
     }
 }
 
-void mupdf_pdf_disable_js(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* First annotation of `page` or NULL, the returned reference must be dropped */
+pdf_annot *mupdf_pdf_first_annot(fz_context *ctx, pdf_page *page)
 {
-    fz_try(ctx)
-    {
-        pdf_disable_js(ctx, pdf);
-    }
-    fz_catch(ctx)
-    {
-        mupdf_save_error(ctx, errptr);
-    }
+    return pdf_keep_annot(ctx, pdf_first_annot(ctx, page));
 }
 
-bool mupdf_pdf_js_supported(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+pdf_annot *mupdf_pdf_next_annot(fz_context *ctx, pdf_annot *annot)
 {
-    bool supported = false;
+    return pdf_keep_annot(ctx, pdf_next_annot(ctx, annot));
+}
+
+/* First form widget of `page` or NULL, widgets are not part of the annotation list */
+pdf_widget *mupdf_pdf_first_widget(fz_context *ctx, pdf_page *page)
+{
+    return pdf_keep_annot(ctx, pdf_first_widget(ctx, page));
+}
+
+pdf_widget *mupdf_pdf_next_widget(fz_context *ctx, pdf_widget *widget)
+{
+    return pdf_keep_annot(ctx, pdf_next_widget(ctx, widget));
+}
+
+int mupdf_pdf_widget_type(fz_context *ctx, pdf_widget *widget, mupdf_error_t **errptr)
+{
+    int type = PDF_WIDGET_TYPE_UNKNOWN;
     fz_try(ctx)
     {
-        supported = pdf_js_supported(ctx, pdf);
+        type = pdf_widget_type(ctx, widget);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return supported;
+    return type;
 }
 
-void mupdf_pdf_calculate_form(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+int mupdf_pdf_field_type(fz_context *ctx, pdf_obj *field, mupdf_error_t **errptr)
 {
+    int type = PDF_WIDGET_TYPE_UNKNOWN;
     fz_try(ctx)
     {
-        if (pdf->recalculate)
-        {
-            pdf_calculate_form(ctx, pdf);
-        }
+        type = pdf_field_type(ctx, field);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return type;
 }
 
-pdf_obj *mupdf_pdf_trailer(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* 0-based number of the page showing `widget`, found from /P or else the page /Annots, -1 if none does */
+static int mupdf_widget_page_number(fz_context *ctx, pdf_document *pdf, pdf_obj *widget)
 {
-    pdf_obj *obj = NULL;
-    fz_try(ctx)
-    {
-        obj = pdf_trailer(ctx, pdf);
-        pdf_keep_obj(ctx, obj);
-    }
-    fz_catch(ctx)
+    pdf_obj *page = pdf_dict_get(ctx, widget, PDF_NAME(P));
+    int i, k, n;
+    if (page)
+        return pdf_lookup_page_number(ctx, pdf, page);
+    n = pdf_count_pages(ctx, pdf);
+    for (i = 0; i < n; i++)
     {
-        mupdf_save_error(ctx, errptr);
+        pdf_obj *annots = pdf_dict_get(ctx, pdf_lookup_page_obj(ctx, pdf, i), PDF_NAME(Annots));
+        for (k = 0; k < pdf_array_len(ctx, annots); k++)
+            if (!pdf_objcmp(ctx, pdf_array_get(ctx, annots, k), widget))
+                return i;
     }
-    return obj;
+    return -1;
 }
 
-pdf_obj *mupdf_pdf_catalog(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* Regenerate the appearances of every widget of the terminal field `field` */
+static void mupdf_pdf_update_field_widgets(fz_context *ctx, pdf_document *pdf, pdf_obj *field)
 {
-    pdf_obj *obj = NULL;
+    pdf_obj *kids = pdf_dict_get(ctx, field, PDF_NAME(Kids));
+    int i, n = kids ? pdf_array_len(ctx, kids) : 1;
+    fz_page *page = NULL;
+    fz_var(page);
     fz_try(ctx)
     {
-        obj = pdf_dict_get(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root));
-        pdf_keep_obj(ctx, obj);
+        for (i = 0; i < n; i++)
+        {
+            pdf_obj *widget = kids ? pdf_array_get(ctx, kids, i) : field;
+            int page_no = mupdf_widget_page_number(ctx, pdf, widget);
+            if (page_no < 0)
+                continue;
+            /* Pages already loaded are shared, so their widgets are updated too */
+            page = fz_load_page(ctx, &pdf->super, page_no);
+            pdf_update_page(ctx, (pdf_page *)page);
+            fz_drop_page(ctx, page);
+            page = NULL;
+        }
+    }
+    fz_always(ctx)
+    {
+        fz_drop_page(ctx, page);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
-    return obj;
 }
 
-int mupdf_pdf_count_objects(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* Set the value of a text field, returns false if it was rejected by the field's checks.
+   The appearances of all its widgets are regenerated, and viewers asked to do the same */
+bool mupdf_pdf_set_text_field_value(fz_context *ctx, pdf_obj *field, const char *value, mupdf_error_t **errptr)
 {
-    int count = 0;
+    bool accepted = false;
     fz_try(ctx)
     {
-        count = pdf_xref_len(ctx, pdf);
+        pdf_document *pdf = pdf_get_bound_document(ctx, field);
+        if (!pdf)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "field is not bound to a document");
+        if (pdf_field_type(ctx, field) != PDF_WIDGET_TYPE_TEXT)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "not a text field");
+        accepted = pdf_set_field_value(ctx, pdf, field, value, 1);
+        if (accepted)
+        {
+            pdf_obj *form = pdf_dict_getp(ctx, pdf_trailer(ctx, pdf), "Root/AcroForm");
+            if (form)
+                pdf_dict_put_bool(ctx, form, PDF_NAME(NeedAppearances), 1);
+            mupdf_pdf_update_field_widgets(ctx, pdf, field);
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return count;
+    return accepted;
 }
 
-pdf_graft_map *mupdf_pdf_new_graft_map(fz_context *ctx, pdf_document *pdf, mupdf_error_t **errptr)
+/* Select `state` on the widget if it has an appearance for it, "Off" otherwise.
+   Returns whether the state was found */
+static bool mupdf_pdf_set_widget_state(fz_context *ctx, pdf_obj *widget, pdf_obj *state)
 {
-    pdf_graft_map *map = NULL;
+    pdf_obj *normal = pdf_dict_getp(ctx, widget, "AP/N");
+    bool found = pdf_is_dict(ctx, normal) && pdf_dict_get(ctx, normal, state);
+    pdf_dict_put(ctx, widget, PDF_NAME(AS), found ? state : PDF_NAME(Off));
+    return found;
+}
+
+/* Turn a check box or radio button field to the appearance state `name`, "Off" to clear it */
+void mupdf_pdf_set_button_field_state(fz_context *ctx, pdf_obj *field, const char *name, mupdf_error_t **errptr)
+{
+    pdf_obj *state = NULL;
+    fz_var(state);
     fz_try(ctx)
     {
-        map = pdf_new_graft_map(ctx, pdf);
+        int type = pdf_field_type(ctx, field);
+        if (type != PDF_WIDGET_TYPE_CHECKBOX && type != PDF_WIDGET_TYPE_RADIOBUTTON)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "not a check box or radio button");
+        state = pdf_new_name(ctx, name);
+        bool found = false;
+        pdf_obj *kids = pdf_dict_get(ctx, field, PDF_NAME(Kids));
+        if (kids)
+        {
+            int i, n = pdf_array_len(ctx, kids);
+            for (i = 0; i < n; i++)
+                found |= mupdf_pdf_set_widget_state(ctx, pdf_array_get(ctx, kids, i), state);
+        }
+        else
+        {
+            found = mupdf_pdf_set_widget_state(ctx, field, state);
+        }
+        if (!found && !pdf_name_eq(ctx, state, PDF_NAME(Off)))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "no widget has the state %s", name);
+        pdf_dict_put(ctx, field, PDF_NAME(V), state);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, state);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return map;
 }
 
-pdf_obj *mupdf_pdf_graft_object(fz_context *ctx, pdf_document *doc, pdf_obj *obj, mupdf_error_t **errptr)
+/* Index of the option of a choice field whose export value is `value`, -1 if there is none */
+static int mupdf_pdf_choice_option_index(fz_context *ctx, pdf_obj *opts, const char *value)
 {
-    pdf_obj *graft_obj = NULL;
-    fz_try(ctx)
-    {
-        graft_obj = pdf_graft_object(ctx, doc, obj);
-    }
-    fz_catch(ctx)
+    int i, n = pdf_array_len(ctx, opts);
+    for (i = 0; i < n; i++)
     {
-        mupdf_save_error(ctx, errptr);
+        pdf_obj *opt = pdf_array_get(ctx, opts, i);
+        if (pdf_is_array(ctx, opt))
+            opt = pdf_array_get(ctx, opt, 0);
+        if (!strcmp(pdf_to_text_string(ctx, opt), value))
+            return i;
     }
-    return graft_obj;
+    return -1;
 }
 
-pdf_page *mupdf_pdf_new_page(fz_context *ctx, pdf_document *pdf, int page_no, float width, float height, mupdf_error_t **errptr)
+/* Select `count` options of a combo or list box by export value, none clears the selection.
+   Values outside the options are accepted by editable combo boxes only */
+void mupdf_pdf_set_choice_field_value(fz_context *ctx, pdf_obj *field, const char *const *values, int count, mupdf_error_t **errptr)
 {
-    fz_rect mediabox = fz_unit_rect;
-    mediabox.x1 = width;
-    mediabox.y1 = height;
-    pdf_obj *resources = NULL, *page_obj = NULL;
-    fz_buffer *contents = NULL;
-    pdf_page *page = NULL;
-    fz_var(resources);
-    fz_var(page_obj);
-    fz_var(contents);
+    pdf_obj *value = NULL;
+    pdf_obj *indices = NULL;
+    fz_var(value);
+    fz_var(indices);
     fz_try(ctx)
     {
-        // create /Resources and /Contents objects
-        resources = pdf_add_object_drop(ctx, pdf, pdf_new_dict(ctx, pdf, 1));
-        page_obj = pdf_add_page(ctx, pdf, mediabox, 0, resources, contents);
-        pdf_insert_page(ctx, pdf, page_no, page_obj);
-        pdf->dirty = 1;
-        int n = page_no;
-        int page_count = pdf_count_pages(ctx, pdf);
-        while (n < 0)
+        pdf_document *pdf = pdf_get_bound_document(ctx, field);
+        int type = pdf_field_type(ctx, field);
+        int flags = pdf_to_int(ctx, pdf_dict_get_inheritable(ctx, field, PDF_NAME(Ff)));
+        pdf_obj *opts = pdf_dict_get_inheritable(ctx, field, PDF_NAME(Opt));
+        int i, k;
+        if (!pdf)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "field is not bound to a document");
+        if (type != PDF_WIDGET_TYPE_COMBOBOX && type != PDF_WIDGET_TYPE_LISTBOX)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "not a choice field");
+        if (count > 1 && !(flags & PDF_CH_FIELD_IS_MULTI_SELECT))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "field does not allow multiple selection");
+        indices = pdf_new_array(ctx, pdf, count);
+        for (k = 0; k < count; k++)
+        {
+            i = mupdf_pdf_choice_option_index(ctx, opts, values[k]);
+            if (i >= 0)
+                pdf_array_push_int(ctx, indices, i);
+            else if (type != PDF_WIDGET_TYPE_COMBOBOX || !(flags & PDF_CH_FIELD_IS_EDIT))
+                fz_throw(ctx, FZ_ERROR_GENERIC, "no option %s", values[k]);
+        }
+        if (count == 1)
+        {
+            value = pdf_new_text_string(ctx, values[0]);
+        }
+        else if (count > 1)
         {
-            n += page_count;
+            value = pdf_new_array(ctx, pdf, count);
+            for (k = 0; k < count; k++)
+                pdf_array_push_drop(ctx, value, pdf_new_text_string(ctx, values[k]));
         }
-        fz_page *fz_page = fz_load_page(ctx, &pdf->super, n);
-        page = pdf_page_from_fz_page(ctx, fz_page);
+        if (value)
+            pdf_dict_put(ctx, field, PDF_NAME(V), value);
+        else
+            pdf_dict_del(ctx, field, PDF_NAME(V));
+        /* Viewers use the indices to tell apart options sharing an export value */
+        if (count > 1)
+            pdf_dict_put(ctx, field, PDF_NAME(I), indices);
+        else
+            pdf_dict_del(ctx, field, PDF_NAME(I));
+        pdf_obj *form = pdf_dict_getp(ctx, pdf_trailer(ctx, pdf), "Root/AcroForm");
+        if (form)
+            pdf_dict_put_bool(ctx, form, PDF_NAME(NeedAppearances), 1);
     }
     fz_always(ctx)
     {
-        fz_drop_buffer(ctx, contents);
-        pdf_drop_obj(ctx, page_obj);
-        pdf_drop_obj(ctx, resources);
+        pdf_drop_obj(ctx, value);
+        pdf_drop_obj(ctx, indices);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return page;
 }
 
-pdf_obj *mupdf_pdf_lookup_page_obj(fz_context *ctx, pdf_document *pdf, int page_no, mupdf_error_t **errptr)
+/* Append an option to a choice field, with a display text different from its export value
+   when `display` is not NULL */
+void mupdf_pdf_add_choice_field_option(fz_context *ctx, pdf_obj *field, const char *export_value, const char *display, mupdf_error_t **errptr)
 {
-    pdf_obj *obj = NULL;
     fz_try(ctx)
     {
-        obj = pdf_lookup_page_obj(ctx, pdf, page_no);
+        pdf_document *pdf = pdf_get_bound_document(ctx, field);
+        int type = pdf_field_type(ctx, field);
+        pdf_obj *opts = pdf_dict_get_inheritable(ctx, field, PDF_NAME(Opt));
+        if (!pdf)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "field is not bound to a document");
+        if (type != PDF_WIDGET_TYPE_COMBOBOX && type != PDF_WIDGET_TYPE_LISTBOX)
+            fz_throw(ctx, FZ_ERROR_GENERIC, "not a choice field");
+        if (!pdf_is_array(ctx, opts))
+            opts = pdf_dict_put_array(ctx, field, PDF_NAME(Opt), 1);
+        if (display)
+        {
+            pdf_obj *opt = pdf_array_push_array(ctx, opts, 2);
+            pdf_array_push_drop(ctx, opt, pdf_new_text_string(ctx, export_value));
+            pdf_array_push_drop(ctx, opt, pdf_new_text_string(ctx, display));
+        }
+        else
+        {
+            pdf_array_push_drop(ctx, opts, pdf_new_text_string(ctx, export_value));
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return obj;
 }
 
-void mupdf_pdf_insert_page(fz_context *ctx, pdf_document *pdf, int page_no, pdf_obj *page, mupdf_error_t **errptr)
+/* Ask viewers, and MuPDF when loading a page, to regenerate the widget appearances of the
+   form `field` belongs to */
+void mupdf_pdf_field_need_appearances(fz_context *ctx, pdf_obj *field, mupdf_error_t **errptr)
 {
-    if (page_no < 0 || page_no >= pdf_count_pages(ctx, pdf))
-    {
-        *errptr = mupdf_new_error_from_str("page_no is not a valid page");
-        return;
-    }
     fz_try(ctx)
     {
-        pdf_insert_page(ctx, pdf, page_no, page);
+        pdf_document *pdf = pdf_get_bound_document(ctx, field);
+        pdf_obj *form = pdf ? pdf_dict_getp(ctx, pdf_trailer(ctx, pdf), "Root/AcroForm") : NULL;
+        if (form)
+            pdf_dict_put_bool(ctx, form, PDF_NAME(NeedAppearances), 1);
     }
     fz_catch(ctx)
     {
@@ -2118,133 +6466,304 @@ void mupdf_pdf_insert_page(fz_context *ctx, pdf_document *pdf, int page_no, pdf_
     }
 }
 
-void mupdf_pdf_delete_page(fz_context *ctx, pdf_document *pdf, int page_no, mupdf_error_t **errptr)
+/* Draw the normal appearance of the widget `obj` into `ops` the way viewers place it in its rect,
+   returns false if it has none */
+static bool mupdf_pdf_flatten_widget(fz_context *ctx, pdf_obj *xobjs, pdf_obj *obj, fz_buffer *ops)
 {
-    if (page_no < 0 || page_no >= pdf_count_pages(ctx, pdf))
+    pdf_obj *ap = pdf_dict_getp(ctx, obj, "AP/N");
+    fz_rect rect, bbox;
+    float sx, sy;
+    char name[32];
+    int i;
+
+    if (!pdf_is_stream(ctx, ap))
+        ap = pdf_dict_get(ctx, ap, pdf_dict_get(ctx, obj, PDF_NAME(AS)));
+    if (!pdf_is_stream(ctx, ap))
+        return false;
+    rect = pdf_dict_get_rect(ctx, obj, PDF_NAME(Rect));
+    bbox = fz_transform_rect(pdf_xobject_bbox(ctx, ap), pdf_xobject_matrix(ctx, ap));
+    if (fz_is_empty_rect(rect) || fz_is_empty_rect(bbox))
+        return false;
+
+    for (i = 0;; i++)
     {
-        *errptr = mupdf_new_error_from_str("page_no is not a valid page");
-        return;
+        fz_snprintf(name, sizeof name, "Widget%d", i);
+        if (!pdf_dict_gets(ctx, xobjs, name))
+            break;
     }
+    pdf_dict_puts(ctx, xobjs, name, ap);
+    /* Map the transformed bounding box of the appearance onto the rect */
+    sx = (rect.x1 - rect.x0) / (bbox.x1 - bbox.x0);
+    sy = (rect.y1 - rect.y0) / (bbox.y1 - bbox.y0);
+    fz_append_printf(ctx, ops, "q %g 0 0 %g %g %g cm /%s Do Q\n", sx, sy, rect.x0 - bbox.x0 * sx, rect.y0 - bbox.y0 * sy, name);
+    return true;
+}
+
+/* The XObject resources of `page`, copied onto it first so that inherited or shared resources stay untouched */
+static pdf_obj *mupdf_page_own_xobjects(fz_context *ctx, pdf_document *pdf, pdf_obj *page)
+{
+    pdf_obj *resources = pdf_dict_get_inheritable(ctx, page, PDF_NAME(Resources));
+    pdf_obj *xobjs = pdf_dict_get(ctx, resources, PDF_NAME(XObject));
+    /* The copy keeps the old XObject dictionary alive */
+    resources = pdf_is_dict(ctx, resources) ? pdf_copy_dict(ctx, resources) : pdf_new_dict(ctx, pdf, 1);
+    pdf_dict_put_drop(ctx, page, PDF_NAME(Resources), resources);
+    xobjs = pdf_is_dict(ctx, xobjs) ? pdf_copy_dict(ctx, xobjs) : pdf_new_dict(ctx, pdf, 1);
+    pdf_dict_put_drop(ctx, resources, PDF_NAME(XObject), xobjs);
+    return xobjs;
+}
+
+/* Drop the fields under `field` that are not signatures, returns whether `field` holds any */
+static bool mupdf_prune_to_signature_fields(fz_context *ctx, pdf_document *pdf, pdf_obj *field)
+{
+    pdf_obj *kids = pdf_dict_get(ctx, field, PDF_NAME(Kids));
+    pdf_obj *kept = NULL;
+    bool any = false;
+    int i, n = pdf_array_len(ctx, kids);
+    /* Widgets have no name of their own, a field with only widget kids is terminal */
+    for (i = 0; i < n; i++)
+        if (pdf_dict_get(ctx, pdf_array_get(ctx, kids, i), PDF_NAME(T)))
+            break;
+    if (i == n)
+        return pdf_field_type(ctx, field) == PDF_WIDGET_TYPE_SIGNATURE;
+    /* Kids cycle */
+    if (pdf_mark_obj(ctx, field))
+        return false;
+    fz_var(kept);
+    fz_var(any);
     fz_try(ctx)
     {
-        pdf_delete_page(ctx, pdf, page_no);
-        if (pdf->rev_page_map)
+        kept = pdf_new_array(ctx, pdf, n);
+        for (i = 0; i < n; i++)
         {
-            pdf_drop_page_tree(ctx, pdf);
+            pdf_obj *kid = pdf_array_get(ctx, kids, i);
+            if (mupdf_prune_to_signature_fields(ctx, pdf, kid))
+                pdf_array_push(ctx, kept, kid);
         }
+        any = pdf_array_len(ctx, kept) > 0;
+        if (any)
+            pdf_dict_put(ctx, field, PDF_NAME(Kids), kept);
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, kept);
+        pdf_unmark_obj(ctx, field);
     }
     fz_catch(ctx)
     {
-        mupdf_save_error(ctx, errptr);
+        fz_rethrow(ctx);
     }
+    return any;
 }
 
-/* Device */
-fz_device *mupdf_new_draw_device(fz_context *ctx, fz_pixmap *pixmap, fz_irect clip, mupdf_error_t **errptr)
+/* Draw the visible form widgets of every page into its contents and remove them with the
+   AcroForm, keeping signature fields when `keep_signatures` is set. Returns the number of
+   widgets drawn */
+int mupdf_pdf_flatten_form(fz_context *ctx, pdf_document *pdf, bool print_only, bool keep_signatures, mupdf_error_t **errptr)
 {
-    fz_device *device = NULL;
+    fz_page *page = NULL;
+    fz_buffer *ops = NULL;
+    pdf_obj *kept = NULL;
+    int count = 0;
+    fz_var(page);
+    fz_var(ops);
+    fz_var(kept);
     fz_try(ctx)
     {
-        if (fz_is_infinite_irect(clip))
+        pdf_obj *form, *fields;
+        int i, k, n = pdf_count_pages(ctx, pdf);
+        for (i = 0; i < n; i++)
         {
-            device = fz_new_draw_device(ctx, fz_identity, pixmap);
+            pdf_page *ppage;
+            pdf_obj *annots, *xobjs = NULL;
+            page = fz_load_page(ctx, &pdf->super, i);
+            ppage = (pdf_page *)page;
+            /* Regenerate the appearances of widgets that need it before drawing them */
+            pdf_update_page(ctx, ppage);
+            annots = pdf_dict_get(ctx, ppage->obj, PDF_NAME(Annots));
+            if (!pdf_is_array(ctx, annots))
+            {
+                fz_drop_page(ctx, page);
+                page = NULL;
+                continue;
+            }
+            ops = fz_new_buffer(ctx, 256);
+            kept = pdf_new_array(ctx, pdf, pdf_array_len(ctx, annots));
+            for (k = 0; k < pdf_array_len(ctx, annots); k++)
+            {
+                pdf_obj *obj = pdf_array_get(ctx, annots, k);
+                int flags = pdf_dict_get_int(ctx, obj, PDF_NAME(F));
+                if (!pdf_name_eq(ctx, pdf_dict_get(ctx, obj, PDF_NAME(Subtype)), PDF_NAME(Widget)) ||
+                    (keep_signatures && pdf_field_type(ctx, obj) == PDF_WIDGET_TYPE_SIGNATURE))
+                {
+                    pdf_array_push(ctx, kept, obj);
+                    continue;
+                }
+                if (flags & (PDF_ANNOT_IS_HIDDEN | PDF_ANNOT_IS_NO_VIEW | PDF_ANNOT_IS_INVISIBLE))
+                    continue;
+                if (print_only && !(flags & PDF_ANNOT_IS_PRINT))
+                    continue;
+                if (!xobjs)
+                    xobjs = mupdf_page_own_xobjects(ctx, pdf, ppage->obj);
+                if (mupdf_pdf_flatten_widget(ctx, xobjs, obj, ops))
+                    count++;
+            }
+            if (fz_buffer_storage(ctx, ops, NULL) > 0)
+                mupdf_pdf_page_overlay(ctx, ppage, ops);
+            pdf_dict_put(ctx, ppage->obj, PDF_NAME(Annots), kept);
+            pdf_drop_obj(ctx, kept);
+            kept = NULL;
+            fz_drop_buffer(ctx, ops);
+            ops = NULL;
+            fz_drop_page(ctx, page);
+            page = NULL;
         }
-        else
+
+        form = pdf_dict_getp(ctx, pdf_trailer(ctx, pdf), "Root/AcroForm");
+        fields = pdf_dict_get(ctx, form, PDF_NAME(Fields));
+        if (keep_signatures && pdf_is_array(ctx, fields))
         {
-            device = fz_new_draw_device_with_bbox(ctx, fz_identity, pixmap, &clip);
+            kept = pdf_new_array(ctx, pdf, 1);
+            for (k = 0; k < pdf_array_len(ctx, fields); k++)
+            {
+                pdf_obj *field = pdf_array_get(ctx, fields, k);
+                if (mupdf_prune_to_signature_fields(ctx, pdf, field))
+                    pdf_array_push(ctx, kept, field);
+            }
+        }
+        if (form && kept && pdf_array_len(ctx, kept) > 0)
+        {
+            pdf_dict_put(ctx, form, PDF_NAME(Fields), kept);
+            pdf_dict_del(ctx, form, PDF_NAME(NeedAppearances));
+        }
+        else if (form)
+        {
+            pdf_dict_del(ctx, pdf_dict_get(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root)), PDF_NAME(AcroForm));
         }
+        pdf->dirty = 1;
+    }
+    fz_always(ctx)
+    {
+        pdf_drop_obj(ctx, kept);
+        fz_drop_buffer(ctx, ops);
+        fz_drop_page(ctx, page);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return device;
+    return count;
 }
 
-fz_device *mupdf_new_display_list_device(fz_context *ctx, fz_display_list *list, mupdf_error_t **errptr)
+/* Set a terminal field back to its default value (`/DV`), clearing it when it has none.
+   pdf_field_reset only resets a value stored on the field itself, so an inherited value is
+   reset first on the ancestor holding it */
+void mupdf_pdf_reset_field(fz_context *ctx, pdf_obj *field, mupdf_error_t **errptr)
 {
-    fz_device *device = NULL;
     fz_try(ctx)
     {
-        device = fz_new_list_device(ctx, list);
-        fz_keep_display_list(ctx, list);
+        pdf_obj *holder = field;
+        int depth = 0;
+        /* The depth limit ends /Parent cycles */
+        while (holder && !pdf_dict_get(ctx, holder, PDF_NAME(V)) && depth++ < 32)
+            holder = pdf_dict_get(ctx, holder, PDF_NAME(Parent));
+        if (holder && holder != field && pdf_dict_get(ctx, holder, PDF_NAME(V)))
+        {
+            pdf_obj *dv = pdf_dict_get(ctx, holder, PDF_NAME(DV));
+            if (dv)
+                pdf_dict_put(ctx, holder, PDF_NAME(V), dv);
+            else
+                pdf_dict_del(ctx, holder, PDF_NAME(V));
+        }
+        pdf_field_reset(ctx, pdf_get_bound_document(ctx, field), field);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return device;
 }
 
-fz_device *mupdf_new_stext_device(fz_context *ctx, fz_stext_page *tp, int flags, mupdf_error_t **errptr)
+fz_rect mupdf_pdf_bound_annot(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    fz_device *device = NULL;
+    fz_rect rect = fz_empty_rect;
     fz_try(ctx)
     {
-        fz_stext_options opts = {0};
-        opts.flags = flags;
-        device = fz_new_stext_device(ctx, tp, &opts);
+        rect = pdf_bound_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return device;
+    return rect;
 }
 
-void mupdf_fill_path(fz_context *ctx, fz_device *device, fz_path *path, bool even_odd, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+const char *mupdf_pdf_annot_contents(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    const char *s = NULL;
     fz_try(ctx)
     {
-        fz_fill_path(ctx, device, path, even_odd, ctm, cs, color, alpha, cp);
+        s = pdf_annot_contents(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return s;
 }
 
-void mupdf_stroke_path(fz_context *ctx, fz_device *device, fz_path *path, fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+/* Author (/T) of the annotation, NULL when it has none */
+const char *mupdf_pdf_annot_author(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    const char *s = NULL;
     fz_try(ctx)
     {
-        fz_stroke_path(ctx, device, path, stroke, ctm, cs, color, alpha, cp);
+        if (pdf_dict_get(ctx, annot->obj, PDF_NAME(T)))
+            s = pdf_annot_author(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return s;
 }
 
-void mupdf_clip_path(fz_context *ctx, fz_device *device, fz_path *path, bool even_odd, fz_matrix ctm, mupdf_error_t **errptr)
+/* Raw PDF date string stored under `key` in the annotation dictionary, NULL when missing */
+const char *mupdf_pdf_annot_date(fz_context *ctx, pdf_annot *annot, const char *key, mupdf_error_t **errptr)
 {
+    const char *s = NULL;
     fz_try(ctx)
     {
-        fz_clip_path(ctx, device, path, even_odd, ctm, fz_infinite_rect);
+        pdf_obj *date = pdf_dict_gets(ctx, annot->obj, key);
+        if (pdf_is_string(ctx, date))
+            s = pdf_to_str_buf(ctx, date);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return s;
 }
 
-void mupdf_clip_stroke_path(fz_context *ctx, fz_device *device, fz_path *path, fz_stroke_state *stroke, fz_matrix ctm, mupdf_error_t **errptr)
+/* Color (/C) of the annotation, returns the number of components written to `color` */
+int mupdf_pdf_annot_color(fz_context *ctx, pdf_annot *annot, float *color, mupdf_error_t **errptr)
 {
+    int n = 0;
     fz_try(ctx)
     {
-        fz_clip_stroke_path(ctx, device, path, stroke, ctm, fz_infinite_rect);
+        pdf_annot_color(ctx, annot, &n, color);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return n;
 }
 
-void mupdf_fill_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_rect(fz_context *ctx, pdf_annot *annot, fz_rect rect, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_fill_text(ctx, device, text, ctm, cs, color, alpha, cp);
+        pdf_set_annot_rect(ctx, annot, rect);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2252,11 +6771,12 @@ void mupdf_fill_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matri
     }
 }
 
-void mupdf_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, fz_stroke_state *stroke, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_color(fz_context *ctx, pdf_annot *annot, int n, const float *color, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_stroke_text(ctx, device, text, stroke, ctm, cs, color, alpha, cp);
+        pdf_set_annot_color(ctx, annot, n, color);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2264,23 +6784,26 @@ void mupdf_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, fz_str
     }
 }
 
-void mupdf_clip_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, mupdf_error_t **errptr)
+float mupdf_pdf_annot_opacity(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    float opacity = 1;
     fz_try(ctx)
     {
-        fz_clip_text(ctx, device, text, ctm, fz_infinite_rect);
+        opacity = pdf_annot_opacity(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return opacity;
 }
 
-void mupdf_clip_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, fz_stroke_state *stroke, fz_matrix ctm, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_opacity(fz_context *ctx, pdf_annot *annot, float opacity, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_clip_stroke_text(ctx, device, text, stroke, ctm, fz_infinite_rect);
+        pdf_set_annot_opacity(ctx, annot, opacity);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2288,11 +6811,12 @@ void mupdf_clip_stroke_text(fz_context *ctx, fz_device *device, fz_text *text, f
     }
 }
 
-void mupdf_ignore_text(fz_context *ctx, fz_device *device, fz_text *text, fz_matrix ctm, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_contents(fz_context *ctx, pdf_annot *annot, const char *text, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_ignore_text(ctx, device, text, ctm);
+        pdf_set_annot_contents(ctx, annot, text);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2300,11 +6824,11 @@ void mupdf_ignore_text(fz_context *ctx, fz_device *device, fz_text *text, fz_mat
     }
 }
 
-void mupdf_fill_shade(fz_context *ctx, fz_device *device, fz_shade *shade, fz_matrix ctm, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_author(fz_context *ctx, pdf_annot *annot, const char *author, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_fill_shade(ctx, device, shade, ctm, alpha, cp);
+        pdf_set_annot_author(ctx, annot, author);
     }
     fz_catch(ctx)
     {
@@ -2312,11 +6836,12 @@ void mupdf_fill_shade(fz_context *ctx, fz_device *device, fz_shade *shade, fz_ma
     }
 }
 
-void mupdf_fill_image(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+/* Set /M to `secs` seconds since the Unix epoch */
+void mupdf_pdf_set_annot_modification_date(fz_context *ctx, pdf_annot *annot, int64_t secs, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_fill_image(ctx, device, image, ctm, alpha, cp);
+        pdf_set_annot_modification_date(ctx, annot, secs);
     }
     fz_catch(ctx)
     {
@@ -2324,23 +6849,26 @@ void mupdf_fill_image(fz_context *ctx, fz_device *device, fz_image *image, fz_ma
     }
 }
 
-void mupdf_fill_image_mask(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, fz_colorspace *cs, const float *color, float alpha, fz_color_params cp, mupdf_error_t **errptr)
+int mupdf_pdf_annot_flags(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    int flags = 0;
     fz_try(ctx)
     {
-        fz_fill_image_mask(ctx, device, image, ctm, cs, color, alpha, cp);
+        flags = pdf_annot_flags(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return flags;
 }
 
-void mupdf_clip_image_mask(fz_context *ctx, fz_device *device, fz_image *image, fz_matrix ctm, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_flags(fz_context *ctx, pdf_annot *annot, int flags, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_clip_image_mask(ctx, device, image, ctm, fz_infinite_rect);
+        pdf_set_annot_flags(ctx, annot, flags);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2348,23 +6876,26 @@ void mupdf_clip_image_mask(fz_context *ctx, fz_device *device, fz_image *image,
     }
 }
 
-void mupdf_pop_clip(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
+int mupdf_pdf_annot_interior_color(fz_context *ctx, pdf_annot *annot, float *color, mupdf_error_t **errptr)
 {
+    int n = 0;
     fz_try(ctx)
     {
-        fz_pop_clip(ctx, device);
+        pdf_annot_interior_color(ctx, annot, &n, color);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return n;
 }
 
-void mupdf_begin_layer(fz_context *ctx, fz_device *device, const char *name, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_interior_color(fz_context *ctx, pdf_annot *annot, int n, const float *color, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_begin_layer(ctx, device, name);
+        pdf_set_annot_interior_color(ctx, annot, n, color);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2372,23 +6903,26 @@ void mupdf_begin_layer(fz_context *ctx, fz_device *device, const char *name, mup
     }
 }
 
-void mupdf_end_layer(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
+float mupdf_pdf_annot_border(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    float w = 0;
     fz_try(ctx)
     {
-        fz_end_layer(ctx, device);
+        w = pdf_annot_border(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return w;
 }
 
-void mupdf_begin_mask(fz_context *ctx, fz_device *device, fz_rect area, bool luminosity, fz_colorspace *cs, const float *color, fz_color_params cp, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_border(fz_context *ctx, pdf_annot *annot, float w, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_begin_mask(ctx, device, area, luminosity, cs, color, cp);
+        pdf_set_annot_border(ctx, annot, w);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2396,23 +6930,38 @@ void mupdf_begin_mask(fz_context *ctx, fz_device *device, fz_rect area, bool lum
     }
 }
 
-void mupdf_end_mask(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
+static pdf_obj *mupdf_pdf_annot_border_style_dict(fz_context *ctx, pdf_annot *annot)
 {
+    pdf_obj *bs = pdf_dict_get(ctx, annot->obj, PDF_NAME(BS));
+    if (!pdf_is_dict(ctx, bs))
+        bs = pdf_dict_put_dict(ctx, annot->obj, PDF_NAME(BS), 2);
+    return bs;
+}
+
+/* First letter of the border style name (/BS /S), 'S' (solid) when missing */
+char mupdf_pdf_annot_border_style(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
+{
+    char style = 'S';
     fz_try(ctx)
     {
-        fz_end_mask(ctx, device);
+        const char *name = pdf_to_name(ctx, pdf_dict_getp(ctx, annot->obj, "BS/S"));
+        if (*name)
+            style = *name;
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return style;
 }
 
-void mupdf_begin_group(fz_context *ctx, fz_device *device, fz_rect area, fz_colorspace *cs, bool isolated, bool knockout, int blendmode, float alpha, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_border_style(fz_context *ctx, pdf_annot *annot, const char *style, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_begin_group(ctx, device, area, cs, isolated, knockout, blendmode, alpha);
+        pdf_dict_put_name(ctx, mupdf_pdf_annot_border_style_dict(ctx, annot), PDF_NAME(S), style);
+        pdf_dirty_annot(ctx, annot);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
@@ -2420,37 +6969,56 @@ void mupdf_begin_group(fz_context *ctx, fz_device *device, fz_rect area, fz_colo
     }
 }
 
-void mupdf_end_group(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
+/* Copy at most `max` dash lengths (/BS /D) to `dash`, returns the length of the dash array */
+int mupdf_pdf_annot_border_dash(fz_context *ctx, pdf_annot *annot, float *dash, int max, mupdf_error_t **errptr)
 {
+    int n = 0;
     fz_try(ctx)
     {
-        fz_end_group(ctx, device);
+        pdf_obj *d = pdf_dict_getp(ctx, annot->obj, "BS/D");
+        int i;
+        n = pdf_array_len(ctx, d);
+        for (i = 0; i < n && i < max; i++)
+            dash[i] = pdf_array_get_real(ctx, d, i);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return n;
 }
 
-int mupdf_begin_tile(fz_context *ctx, fz_device *device, fz_rect area, fz_rect view, float xstep, float ystep, fz_matrix ctm, int id, mupdf_error_t **errptr)
+/* Set the dash lengths (/BS /D), an empty array removes the dash pattern */
+void mupdf_pdf_set_annot_border_dash(fz_context *ctx, pdf_annot *annot, const float *dash, int n, mupdf_error_t **errptr)
 {
-    int i = 0;
     fz_try(ctx)
     {
-        i = fz_begin_tile_id(ctx, device, area, view, xstep, ystep, ctm, id);
+        pdf_obj *bs = mupdf_pdf_annot_border_style_dict(ctx, annot);
+        if (n > 0)
+        {
+            int i;
+            pdf_obj *d = pdf_dict_put_array(ctx, bs, PDF_NAME(D), n);
+            for (i = 0; i < n; i++)
+                pdf_array_push_real(ctx, d, dash[i]);
+        }
+        else
+        {
+            pdf_dict_del(ctx, bs, PDF_NAME(D));
+        }
+        pdf_dirty_annot(ctx, annot);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return i;
 }
 
-void mupdf_end_tile(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
+void mupdf_pdf_annot_line(fz_context *ctx, pdf_annot *annot, fz_point *a, fz_point *b, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_end_tile(ctx, device);
+        pdf_annot_line(ctx, annot, a, b);
     }
     fz_catch(ctx)
     {
@@ -2458,155 +7026,176 @@ void mupdf_end_tile(fz_context *ctx, fz_device *device, mupdf_error_t **errptr)
     }
 }
 
-/* PdfPage */
-pdf_annot *mupdf_pdf_create_annot(fz_context *ctx, pdf_page *page, int subtype, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_line(fz_context *ctx, pdf_annot *annot, fz_point a, fz_point b, mupdf_error_t **errptr)
 {
-    pdf_annot *annot = NULL;
     fz_try(ctx)
     {
-        annot = pdf_create_annot(ctx, page, subtype);
+        pdf_set_annot_line(ctx, annot, a, b);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return annot;
 }
 
-void mupdf_pdf_delete_annot(fz_context *ctx, pdf_page *page, pdf_annot *annot, mupdf_error_t **errptr)
+int mupdf_pdf_annot_quad_point_count(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
+    int n = 0;
     fz_try(ctx)
     {
-        pdf_delete_annot(ctx, page, annot);
+        n = pdf_annot_quad_point_count(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return n;
 }
 
-bool mupdf_pdf_update_page(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
+fz_quad mupdf_pdf_annot_quad_point(fz_context *ctx, pdf_annot *annot, int i, mupdf_error_t **errptr)
 {
-    bool updated = false;
+    fz_quad q = fz_quad_from_rect(fz_empty_rect);
     fz_try(ctx)
     {
-        updated = pdf_update_page(ctx, page);
+        q = pdf_annot_quad_point(ctx, annot, i);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return updated;
+    return q;
 }
 
-bool mupdf_pdf_redact_page(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_quad_points(fz_context *ctx, pdf_annot *annot, int n, const fz_quad *quads, mupdf_error_t **errptr)
 {
-    bool redacted = false;
     fz_try(ctx)
     {
-        redacted = pdf_redact_page(ctx, page->doc, page, NULL);
+        pdf_set_annot_quad_points(ctx, annot, n, quads);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return redacted;
 }
 
-void mupdf_pdf_page_set_rotation(fz_context *ctx, pdf_page *page, int rotation, mupdf_error_t **errptr)
+int mupdf_pdf_annot_vertex_count(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
 {
-    if (rotation % 90)
+    int n = 0;
+    fz_try(ctx)
     {
-        *errptr = mupdf_new_error_from_str("rotation not multiple of 90");
-        return;
+        n = pdf_annot_vertex_count(ctx, annot);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
     }
+    return n;
+}
+
+fz_point mupdf_pdf_annot_vertex(fz_context *ctx, pdf_annot *annot, int i, mupdf_error_t **errptr)
+{
+    fz_point p = fz_make_point(0, 0);
     fz_try(ctx)
     {
-        pdf_dict_put_int(ctx, page->obj, PDF_NAME(Rotate), (int64_t)rotation);
-        page->doc->dirty = 1;
+        p = pdf_annot_vertex(ctx, annot, i);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
+    return p;
 }
 
-void mupdf_pdf_page_set_crop_box(fz_context *ctx, pdf_page *page, fz_rect rect, mupdf_error_t **errptr)
+void mupdf_pdf_set_annot_vertices(fz_context *ctx, pdf_annot *annot, int n, const fz_point *v, mupdf_error_t **errptr)
 {
     fz_try(ctx)
     {
-        fz_rect mediabox = pdf_bound_page(ctx, page);
-        pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(MediaBox));
-        if (obj)
-        {
-            mediabox = pdf_to_rect(ctx, obj);
-        }
-        fz_rect cropbox = fz_empty_rect;
-        cropbox.x0 = rect.x0;
-        cropbox.y0 = mediabox.y1 - rect.y1;
-        cropbox.x1 = rect.x1;
-        cropbox.y1 = mediabox.y1 - rect.y0;
-        pdf_dict_put_drop(ctx, page->obj, PDF_NAME(CropBox), pdf_new_rect(ctx, page->doc, cropbox));
+        pdf_set_annot_vertices(ctx, annot, n, v);
+        pdf_update_annot(ctx, annot);
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    page->doc->dirty = 1;
 }
 
-fz_point mupdf_pdf_page_crop_box_position(fz_context *ctx, pdf_page *page)
+void mupdf_pdf_annot_line_ending_styles(fz_context *ctx, pdf_annot *annot, int *start, int *end, mupdf_error_t **errptr)
 {
-    fz_point pos = fz_make_point(0, 0);
-    pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(CropBox));
-    if (!obj)
+    fz_try(ctx)
     {
-        return pos;
+        enum pdf_line_ending s, e;
+        pdf_annot_line_ending_styles(ctx, annot, &s, &e);
+        *start = s;
+        *end = e;
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
     }
-    fz_rect cbox = pdf_to_rect(ctx, obj);
-    pos = fz_make_point(cbox.x0, cbox.y0);
-    return pos;
 }
 
-fz_rect mupdf_pdf_page_media_box(fz_context *ctx, pdf_page *page)
+void mupdf_pdf_set_annot_line_ending_styles(fz_context *ctx, pdf_annot *annot, int start, int end, mupdf_error_t **errptr)
 {
-    fz_rect r = fz_empty_rect;
-    pdf_obj *obj = pdf_dict_get_inheritable(ctx, page->obj, PDF_NAME(MediaBox));
-    if (!obj)
+    fz_try(ctx)
     {
-        return r;
+        pdf_set_annot_line_ending_styles(ctx, annot, start, end);
+        pdf_update_annot(ctx, annot);
+    }
+    fz_catch(ctx)
+    {
+        mupdf_save_error(ctx, errptr);
     }
-    r = pdf_to_rect(ctx, obj);
-    return r;
 }
 
-fz_matrix mupdf_pdf_page_transform(fz_context *ctx, pdf_page *page, mupdf_error_t **errptr)
+/* XML */
+/* Parse `data` as XML, throwing unless the root element is `root` */
+fz_xml_doc *mupdf_parse_xml(fz_context *ctx, const unsigned char *data, size_t len, const char *root, mupdf_error_t **errptr)
 {
-    fz_matrix ctm = fz_identity;
+    fz_buffer *buf = NULL;
+    fz_xml_doc *xml = NULL;
+    fz_var(buf);
+    fz_var(xml);
     fz_try(ctx)
     {
-        pdf_page_transform(ctx, page, NULL, &ctm);
+        buf = fz_new_buffer_from_copied_data(ctx, data, len);
+        xml = fz_parse_xml(ctx, buf, 1);
+        if (!fz_xml_is_tag(fz_xml_root(xml), root))
+            fz_throw(ctx, FZ_ERROR_GENERIC, "root element is not <%s>", root);
+    }
+    fz_always(ctx)
+    {
+        fz_drop_buffer(ctx, buf);
     }
     fz_catch(ctx)
     {
+        fz_drop_xml(ctx, xml);
+        xml = NULL;
         mupdf_save_error(ctx, errptr);
     }
-    return ctm;
+    return xml;
 }
 
-/* PDFAnnotation */
-int mupdf_pdf_annot_type(fz_context *ctx, pdf_annot *annot, mupdf_error_t **errptr)
+/* Set the value of the form field with the fully qualified `name`, returns false if there is none */
+bool mupdf_pdf_set_field_value_by_name(fz_context *ctx, pdf_document *pdf, const char *name, const char *value, mupdf_error_t **errptr)
 {
-    int subtype = 0;
+    bool found = false;
     fz_try(ctx)
     {
-        subtype = pdf_annot_type(ctx, annot);
+        pdf_obj *fields = pdf_dict_getp(ctx, pdf_trailer(ctx, pdf), "Root/AcroForm/Fields");
+        pdf_obj *field = pdf_lookup_field(ctx, fields, (char *)name);
+        if (field)
+        {
+            pdf_set_field_value(ctx, pdf, field, value, 1);
+            found = true;
+        }
     }
     fz_catch(ctx)
     {
         mupdf_save_error(ctx, errptr);
     }
-    return subtype;
+    return found;
 }
 
 /* DocumentWriter */
@@ -1,3 +1,7 @@
 #include "mupdf/fitz.h"
 #include "mupdf/ucdn.h"
-#include "mupdf/pdf.h"
\ No newline at end of file
+#include "mupdf/pdf.h"
+/* Older releases have no switch for ICC color management and always build it */
+#ifndef FZ_ENABLE_ICC
+#define FZ_ENABLE_ICC 1
+#endif
@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::slice;
+
+use mupdf_sys::*;
+
+use crate::{context, Buffer, Error, Pixmap};
+
+/// Threshold tiles used to dither a pixmap down to one bit per component.
+#[derive(Debug)]
+pub struct Halftone {
+    pub(crate) inner: *mut fz_halftone,
+}
+
+impl Halftone {
+    /// The default MuPDF halftone for `num_comps` components.
+    pub fn new(num_comps: i32) -> Result<Self, Error> {
+        let inner = unsafe { ffi_try!(mupdf_default_halftone(context(), num_comps)) };
+        Ok(Self { inner })
+    }
+}
+
+impl Drop for Halftone {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_halftone(context(), self.inner) };
+        }
+    }
+}
+
+/// A bitonal image with one bit per component, for fax, thermal printer or e-ink output.
+///
+/// Rows are packed most significant bit first, a set bit means ink.
+#[derive(Debug)]
+pub struct Bitmap {
+    pub(crate) inner: *mut fz_bitmap,
+}
+
+impl Bitmap {
+    /// Halftone a gray or CMYK pixmap without alpha.
+    ///
+    /// Uses the default halftone when `halftone` is `None`.
+    pub fn from_pixmap(pixmap: &Pixmap, halftone: Option<&Halftone>) -> Result<Self, Error> {
+        let ht = halftone.map_or(std::ptr::null_mut(), |ht| ht.inner);
+        let inner = unsafe { ffi_try!(mupdf_new_bitmap_from_pixmap(context(), pixmap.inner, ht)) };
+        Ok(Self { inner })
+    }
+
+    pub fn width(&self) -> u32 {
+        unsafe { (*self.inner).w as u32 }
+    }
+
+    pub fn height(&self) -> u32 {
+        unsafe { (*self.inner).h as u32 }
+    }
+
+    /// Length in bytes of one row of samples.
+    pub fn stride(&self) -> usize {
+        unsafe { (*self.inner).stride as usize }
+    }
+
+    /// Number of components per pixel, 1 for gray and 4 for CMYK.
+    pub fn n(&self) -> u8 {
+        unsafe { (*self.inner).n as u8 }
+    }
+
+    /// Horizontal and vertical resolution in dpi (dots per inch).
+    pub fn resolution(&self) -> (i32, i32) {
+        unsafe { ((*self.inner).xres, (*self.inner).yres) }
+    }
+
+    /// Packed bit data, `height` rows of `stride` bytes each.
+    pub fn samples(&self) -> &[u8] {
+        unsafe {
+            if (*self.inner).samples.is_null() {
+                return &[];
+            }
+            let len = (*self.inner).h as usize * (*self.inner).stride as usize;
+            slice::from_raw_parts((*self.inner).samples, len)
+        }
+    }
+
+    /// Write the bitmap as PBM, or PKM for CMYK bitmaps.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<u64, Error> {
+        let mut buf = unsafe {
+            let inner = ffi_try!(mupdf_bitmap_get_image_data(context(), self.inner));
+            Buffer::from_raw(inner)
+        };
+        Ok(io::copy(&mut buf, w)?)
+    }
+
+    /// Save the bitmap as PBM, or PKM for CMYK bitmaps.
+    pub fn save_as(&self, filename: &str) -> Result<(), Error> {
+        let mut file = File::create(filename)?;
+        self.write_to(&mut file)?;
+        Ok(())
+    }
+}
+
+impl Drop for Bitmap {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_bitmap(context(), self.inner) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bitmap, Halftone};
+    use crate::{Colorspace, Pixmap};
+
+    #[test]
+    fn test_bitmap_from_pixmap() {
+        let cs = Colorspace::device_gray();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 10, 4, false).expect("Pixmap::new_with_w_h");
+        pixmap.clear().unwrap();
+
+        let bitmap = Bitmap::from_pixmap(&pixmap, None).unwrap();
+        assert_eq!((bitmap.width(), bitmap.height(), bitmap.n()), (10, 4, 1));
+        assert_eq!(bitmap.samples().len(), bitmap.stride() * 4);
+
+        let halftone = Halftone::new(1).unwrap();
+        let bitmap = Bitmap::from_pixmap(&pixmap, Some(&halftone)).unwrap();
+        let mut pbm = Vec::new();
+        bitmap.write_to(&mut pbm).unwrap();
+        assert!(pbm.starts_with(b"P4"));
+    }
+}
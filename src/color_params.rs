@@ -38,6 +38,30 @@ impl ColorParams {
         flags & Self::OPM != 0
     }
 
+    /// Parameters MuPDF uses when none are given: relative colorimetric with black point compensation
+    pub fn mupdf_default() -> Self {
+        unsafe { fz_default_color_params }.into()
+    }
+
+    pub fn intent(&self) -> RenderingIntent {
+        Self::rendering_intent(self.0)
+    }
+
+    /// Black point compensation
+    pub fn bp(&self) -> bool {
+        Self::is_bp(self.0)
+    }
+
+    /// Overprint
+    pub fn op(&self) -> bool {
+        Self::is_op(self.0)
+    }
+
+    /// Overprint mode
+    pub fn opm(&self) -> bool {
+        Self::is_opm(self.0)
+    }
+
     pub fn new(ri: RenderingIntent, bp: bool, op: bool, opm: bool) -> Self {
         let mut flags = match ri {
             RenderingIntent::Perceptual => 0,
@@ -64,7 +88,35 @@ impl Into<fz_color_params> for ColorParams {
         let bp = ((flags >> 5) & 1) as u8;
         let op = ((flags >> 6) & 1) as u8;
         let opm = ((flags >> 7) & 1) as u8;
-        let ri = (flags & 32) as u8;
+        let ri = (flags & 3) as u8;
         fz_color_params { ri, bp, op, opm }
     }
 }
+
+impl From<fz_color_params> for ColorParams {
+    fn from(cp: fz_color_params) -> Self {
+        Self(cp.ri as i32 & 3 | (cp.bp as i32) << 5 | (cp.op as i32) << 6 | (cp.opm as i32) << 7)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mupdf_sys::fz_color_params;
+
+    use super::{ColorParams, RenderingIntent};
+
+    #[test]
+    fn test_color_params_round_trip() {
+        let cp = ColorParams::new(RenderingIntent::Saturation, true, false, true);
+        assert_eq!(cp.intent(), RenderingIntent::Saturation);
+        assert!(cp.bp() && !cp.op() && cp.opm());
+
+        let raw: fz_color_params = cp.into();
+        assert_eq!((raw.ri, raw.bp, raw.op, raw.opm), (2, 1, 0, 1));
+        assert_eq!(ColorParams::from(raw), cp);
+
+        let default = ColorParams::mupdf_default();
+        assert_eq!(default.intent(), RenderingIntent::RelativeColorimetric);
+        assert!(default.bp());
+    }
+}
@@ -3,7 +3,7 @@ use std::ffi::CStr;
 
 use mupdf_sys::*;
 
-use crate::context;
+use crate::{context, Error};
 
 #[derive(Debug)]
 pub struct Colorspace {
@@ -12,29 +12,51 @@ pub struct Colorspace {
 
 impl Colorspace {
     pub(crate) unsafe fn from_raw(inner: *mut fz_colorspace) -> Self {
-        Self { inner }
+        Self {
+            inner: fz_keep_colorspace(context(), inner),
+        }
     }
 
     pub fn device_gray() -> Self {
-        let inner = unsafe { fz_device_gray(context()) };
+        let inner = unsafe { fz_keep_colorspace(context(), fz_device_gray(context())) };
         Self { inner }
     }
 
     pub fn device_rgb() -> Self {
-        let inner = unsafe { fz_device_rgb(context()) };
+        let inner = unsafe { fz_keep_colorspace(context(), fz_device_rgb(context())) };
         Self { inner }
     }
 
     pub fn device_bgr() -> Self {
-        let inner = unsafe { fz_device_bgr(context()) };
+        let inner = unsafe { fz_keep_colorspace(context(), fz_device_bgr(context())) };
         Self { inner }
     }
 
     pub fn device_cmyk() -> Self {
-        let inner = unsafe { fz_device_cmyk(context()) };
+        let inner = unsafe { fz_keep_colorspace(context(), fz_device_cmyk(context())) };
+        Self { inner }
+    }
+
+    /// CIE L*a*b* colorspace
+    pub fn device_lab() -> Self {
+        let inner = unsafe { fz_keep_colorspace(context(), fz_device_lab(context())) };
         Self { inner }
     }
 
+    /// Load an ICC profile, e.g. to render for a specific monitor or press.
+    ///
+    /// The colorspace type is taken from the profile. Fails if MuPDF was built without ICC support.
+    pub fn from_icc(profile: &[u8]) -> Result<Self, Error> {
+        let inner = unsafe {
+            ffi_try!(mupdf_new_icc_colorspace(
+                context(),
+                profile.as_ptr(),
+                profile.len()
+            ))
+        };
+        Ok(Self { inner })
+    }
+
     pub fn num_of_components(&self) -> u32 {
         unsafe { fz_colorspace_n(context(), self.inner) as u32 }
     }
@@ -92,9 +114,84 @@ impl PartialEq for Colorspace {
     }
 }
 
+impl Drop for Colorspace {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_colorspace(context(), self.inner) };
+        }
+    }
+}
+
+/// Colorspaces used for untagged `DeviceGray`, `DeviceRGB` and `DeviceCMYK` content,
+/// plus the output intent of the document.
+#[derive(Debug)]
+pub struct DefaultColorspaces {
+    pub(crate) inner: *mut fz_default_colorspaces,
+}
+
+impl DefaultColorspaces {
+    pub(crate) unsafe fn from_raw(inner: *mut fz_default_colorspaces) -> Self {
+        Self { inner }
+    }
+
+    /// The device colorspaces, with no output intent
+    pub fn new() -> Result<Self, Error> {
+        let inner = unsafe { ffi_try!(mupdf_new_default_colorspaces(context())) };
+        Ok(Self { inner })
+    }
+
+    pub fn gray(&self) -> Colorspace {
+        unsafe { Colorspace::from_raw(fz_default_gray(context(), self.inner)) }
+    }
+
+    pub fn rgb(&self) -> Colorspace {
+        unsafe { Colorspace::from_raw(fz_default_rgb(context(), self.inner)) }
+    }
+
+    pub fn cmyk(&self) -> Colorspace {
+        unsafe { Colorspace::from_raw(fz_default_cmyk(context(), self.inner)) }
+    }
+
+    pub fn output_intent(&self) -> Option<Colorspace> {
+        let cs = unsafe { fz_default_output_intent(context(), self.inner) };
+        if cs.is_null() {
+            return None;
+        }
+        Some(unsafe { Colorspace::from_raw(cs) })
+    }
+
+    /// Ignored unless `cs` is a gray colorspace
+    pub fn set_gray(&mut self, cs: &Colorspace) {
+        unsafe { fz_set_default_gray(context(), self.inner, cs.inner) }
+    }
+
+    /// Ignored unless `cs` is an RGB colorspace
+    pub fn set_rgb(&mut self, cs: &Colorspace) {
+        unsafe { fz_set_default_rgb(context(), self.inner, cs.inner) }
+    }
+
+    /// Ignored unless `cs` is a CMYK colorspace
+    pub fn set_cmyk(&mut self, cs: &Colorspace) {
+        unsafe { fz_set_default_cmyk(context(), self.inner, cs.inner) }
+    }
+
+    /// Also replaces the default colorspace of the same kind as `cs`
+    pub fn set_output_intent(&mut self, cs: &Colorspace) {
+        unsafe { fz_set_default_output_intent(context(), self.inner, cs.inner) }
+    }
+}
+
+impl Drop for DefaultColorspaces {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_default_colorspaces(context(), self.inner) };
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Colorspace;
+    use super::{Colorspace, DefaultColorspaces};
 
     #[test]
     fn test_color_space_device_colors() {
@@ -114,5 +211,26 @@ mod test {
         assert!(cmyk.is_device_cmyk());
         assert!(cmyk.is_cmyk());
         assert_eq!(cmyk.name(), "DeviceCMYK");
+
+        let lab = Colorspace::device_lab();
+        assert!(lab.is_lab());
+        assert_eq!(lab.num_of_components(), 3);
+    }
+
+    #[test]
+    fn test_color_space_from_icc() {
+        assert!(Colorspace::from_icc(b"not an icc profile").is_err());
+    }
+
+    #[test]
+    fn test_default_colorspaces() {
+        let mut dcs = DefaultColorspaces::new().unwrap();
+        assert_eq!(dcs.gray(), Colorspace::device_gray());
+        assert_eq!(dcs.rgb(), Colorspace::device_rgb());
+        assert_eq!(dcs.cmyk(), Colorspace::device_cmyk());
+        assert!(dcs.output_intent().is_none());
+
+        dcs.set_gray(&Colorspace::device_rgb());
+        assert_eq!(dcs.gray(), Colorspace::device_gray());
     }
 }
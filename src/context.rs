@@ -69,12 +69,21 @@ impl Context {
         })
     }
 
+    /// Whether the linked MuPDF was built with ICC color management
+    pub fn has_icc() -> bool {
+        FZ_ENABLE_ICC != 0
+    }
+
+    /// Turn ICC color management back on, a no-op unless [`Context::has_icc`]
     pub fn enable_icc(&mut self) {
         unsafe {
             fz_enable_icc(self.inner);
         }
     }
 
+    /// Use fast device color conversions instead of ICC profiles.
+    ///
+    /// The setting is shared by the contexts of all threads.
     pub fn disable_icc(&mut self) {
         unsafe {
             fz_disable_icc(self.inner);
@@ -174,4 +183,10 @@ mod test {
         assert!(ctx.use_document_css());
         assert!(ctx.user_css().is_none());
     }
+
+    #[test]
+    fn test_context_icc() {
+        // The bundled MuPDF is built with lcms2
+        assert!(Context::has_icc());
+    }
 }
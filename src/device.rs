@@ -4,8 +4,8 @@ use std::ptr;
 use mupdf_sys::*;
 
 use crate::{
-    context, ColorParams, Colorspace, DisplayList, Error, IRect, Image, Matrix, Path, Pixmap, Rect,
-    Shade, StrokeState, Text, TextPage, TextPageOptions,
+    context, ColorParams, Colorspace, DefaultColorspaces, DisplayList, Error, IRect, Image, Matrix,
+    Path, Pixmap, Rect, Shade, StrokeState, Text, TextPage, TextPageOptions,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -54,6 +54,21 @@ impl Device {
         Self::from_pixmap_with_clip(pixmap, IRect::INF)
     }
 
+    /// Draw device simulating how `proof` (e.g. a press CMYK profile) would reproduce the colors
+    pub fn from_pixmap_with_proof(pixmap: &Pixmap, proof: &Colorspace) -> Result<Self, Error> {
+        let dev = unsafe {
+            ffi_try!(mupdf_new_draw_device_with_proof(
+                context(),
+                pixmap.inner,
+                proof.inner
+            ))
+        };
+        Ok(Self {
+            dev,
+            list: ptr::null_mut(),
+        })
+    }
+
     pub fn from_display_list(list: &DisplayList) -> Result<Self, Error> {
         let dev = unsafe { ffi_try!(mupdf_new_display_list_device(context(), list.inner)) };
         Ok(Self {
@@ -387,6 +402,23 @@ impl Device {
         Ok(())
     }
 
+    /// Colorspaces to use for untagged device colors drawn from now on
+    ///
+    /// Running a PDF page replaces them with the page's own defaults, the ones returned
+    /// by [`PdfPage::default_colorspaces`](crate::pdf::PdfPage::default_colorspaces), so
+    /// this only affects content drawn directly on the device and pages of other
+    /// document types.
+    pub fn set_default_colorspaces(&self, dcs: &DefaultColorspaces) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_set_default_colorspaces(
+                context(),
+                self.dev,
+                dcs.inner
+            ));
+        }
+        Ok(())
+    }
+
     pub fn begin_tile(
         &self,
         area: Rect,
@@ -3,7 +3,40 @@ use std::io::Write;
 
 use mupdf_sys::*;
 
-use crate::{context, Buffer, Error, Page, PdfDocument};
+use crate::pdf::page_label;
+use crate::{context, Buffer, Error, LinkDestination, Outline, Page, PdfDocument, PdfObject};
+
+/// Supplies passwords for encrypted documents, e.g. by prompting the user or querying a vault.
+pub trait PasswordProvider {
+    /// Password to try for the 1-based `attempt`, `None` gives up.
+    fn password(&mut self, attempt: u32) -> Option<String>;
+}
+
+impl<F> PasswordProvider for F
+where
+    F: FnMut(u32) -> Option<String>,
+{
+    fn password(&mut self, attempt: u32) -> Option<String> {
+        self(attempt)
+    }
+}
+
+/// Reading position that survives [`Document::layout`](struct.Document.html#method.layout),
+/// convert it to and from `i64` to persist it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bookmark(fz_bookmark);
+
+impl From<Bookmark> for i64 {
+    fn from(mark: Bookmark) -> i64 {
+        mark.0 as i64
+    }
+}
+
+impl From<i64> for Bookmark {
+    fn from(mark: i64) -> Bookmark {
+        Bookmark(mark as fz_bookmark)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MetadataName {
@@ -54,6 +87,44 @@ impl Document {
         Ok(Self { inner })
     }
 
+    /// Open a document, asking `provider` for passwords until one is accepted.
+    ///
+    /// Fails with [`Error::InvalidPassword`](enum.Error.html#variant.InvalidPassword)
+    /// when the provider gives up.
+    pub fn open_with_password_provider<P: PasswordProvider>(
+        filename: &str,
+        provider: &mut P,
+    ) -> Result<Self, Error> {
+        let mut doc = Self::open(filename)?;
+        if !doc.authenticate_with(provider)? {
+            return Err(Error::InvalidPassword);
+        }
+        Ok(doc)
+    }
+
+    /// Open a document from `data` while it is still being downloaded.
+    ///
+    /// `length` is the full size of the document, `data` holds the bytes received so far
+    /// and the caller keeps appending to it as more arrive. Full xref and page tree
+    /// validation is deferred so that the first page of a linearized PDF can be loaded
+    /// as early as possible, use [`Document::is_page_ready`](#method.is_page_ready) to
+    /// poll later pages.
+    ///
+    /// Fails with a MuPDF error of code `FZ_ERROR_TRYLATER` when not even the parts
+    /// needed to open the document have arrived yet, try again after appending more data.
+    pub fn open_progressive(data: &Buffer, magic: &str, length: usize) -> Result<Self, Error> {
+        let c_magic = CString::new(magic)?;
+        let inner = unsafe {
+            ffi_try!(mupdf_open_document_progressive(
+                context(),
+                c_magic.as_ptr(),
+                data.inner,
+                length
+            ))
+        };
+        Ok(Self { inner })
+    }
+
     pub fn from_bytes(bytes: &[u8], magic: &str) -> Result<Self, Error> {
         let c_magic = CString::new(magic)?;
         let len = bytes.len();
@@ -92,6 +163,28 @@ impl Document {
         Ok(ret)
     }
 
+    /// Authenticate with passwords from `provider`, retrying until one is accepted
+    /// or the provider gives up.
+    ///
+    /// The provider is not asked at all when the document does not need a password.
+    pub fn authenticate_with<P: PasswordProvider>(
+        &mut self,
+        provider: &mut P,
+    ) -> Result<bool, Error> {
+        if !self.needs_password()? {
+            return Ok(true);
+        }
+        let mut attempt = 1;
+        while let Some(password) = provider.password(attempt) {
+            if self.authenticate(&password)? {
+                return Ok(true);
+            }
+            attempt += 1;
+        }
+        Ok(false)
+    }
+
+    /// Number of pages, valid page indices are `0..page_count`.
     pub fn page_count(&self) -> Result<i32, Error> {
         let count = unsafe { ffi_try!(mupdf_document_page_count(context(), self.inner)) };
         Ok(count)
@@ -112,15 +205,45 @@ impl Document {
         Ok(info)
     }
 
+    /// Resolve an internal link to the 0-based index of its target page.
     pub fn resolve_link(&self, uri: &str) -> Result<Option<i32>, Error> {
+        Ok(self.resolve_link_dest(uri)?.map(|dest| dest.page))
+    }
+
+    /// Resolve an internal link to its target page and the point on it to scroll to.
+    ///
+    /// MuPDF 1.16 does not keep the zoom of PDF destinations, so links are reported
+    /// either as [`XYZ`](enum.LinkDestinationKind.html#variant.XYZ) when they carry a
+    /// target point or as [`Fit`](enum.LinkDestinationKind.html#variant.Fit) otherwise.
+    pub fn resolve_link_dest(&self, uri: &str) -> Result<Option<LinkDestination>, Error> {
         let c_uri = CString::new(uri)?;
-        let n = unsafe { ffi_try!(mupdf_resolve_link(context(), self.inner, c_uri.as_ptr())) };
+        let mut x = 0.0;
+        let mut y = 0.0;
+        let n = unsafe {
+            ffi_try!(mupdf_resolve_link_dest(
+                context(),
+                self.inner,
+                c_uri.as_ptr(),
+                &mut x,
+                &mut y
+            ))
+        };
         if n >= 0 {
-            return Ok(Some(n));
+            return Ok(Some(LinkDestination::new(n, x, y)));
         }
         Ok(None)
     }
 
+    /// Table of contents of the document.
+    pub fn outlines(&self) -> Result<Vec<Outline>, Error> {
+        unsafe {
+            let outline = ffi_try!(mupdf_load_outline(context(), self.inner));
+            let items = Outline::from_list(outline);
+            fz_drop_outline(context(), outline);
+            Ok(items)
+        }
+    }
+
     pub fn is_reflowable(&self) -> Result<bool, Error> {
         let ret = unsafe { ffi_try!(mupdf_is_document_reflowable(context(), self.inner)) };
         Ok(ret)
@@ -134,6 +257,9 @@ impl Document {
         return false;
     }
 
+    /// Convert the 0-based page range `start_page..=end_page` to a new PDF document.
+    ///
+    /// A negative `end_page` converts up to the last page.
     pub fn convert_to_pdf(
         &self,
         start_page: i32,
@@ -176,11 +302,70 @@ impl Document {
         Ok(())
     }
 
+    /// Bookmark the position of the 0-based page `page_no` in the current layout.
+    pub fn make_bookmark(&self, page_no: i32) -> Result<Bookmark, Error> {
+        let mark = unsafe { ffi_try!(mupdf_make_bookmark(context(), self.inner, page_no)) };
+        Ok(Bookmark(mark))
+    }
+
+    /// 0-based index of the page showing `mark` in the current layout.
+    pub fn lookup_bookmark(&self, mark: Bookmark) -> Result<Option<i32>, Error> {
+        let n = unsafe { ffi_try!(mupdf_lookup_bookmark(context(), self.inner, mark.0)) };
+        if n >= 0 {
+            return Ok(Some(n));
+        }
+        Ok(None)
+    }
+
+    /// Load the page at 0-based index `page_no`.
+    ///
+    /// Use [`Document::index_for_label`](#method.index_for_label) to find the index
+    /// of a page from the label printed on it.
     pub fn load_page(&self, page_no: i32) -> Result<Page, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_load_page(context(), self.inner, page_no));
-            Ok(Page::from_raw(inner))
+            Ok(Page::from_raw(inner, self.inner))
+        }
+    }
+
+    /// Check whether the data needed to load the 0-based page `page_no` has arrived.
+    ///
+    /// This is always true for documents that were not opened with
+    /// [`Document::open_progressive`](#method.open_progressive).
+    pub fn is_page_ready(&self, page_no: i32) -> Result<bool, Error> {
+        let ready = unsafe { ffi_try!(mupdf_page_ready(context(), self.inner, page_no)) };
+        Ok(ready)
+    }
+
+    /// Displayed label of the page at 0-based index `page_no`, e.g. `"iv"` or `"A-3"`.
+    ///
+    /// Documents without page labels are labelled with 1-based page numbers.
+    pub fn page_label(&self, page_no: i32) -> Result<String, Error> {
+        let ranges = self.page_label_ranges()?;
+        Ok(page_label::page_label(&ranges, page_no))
+    }
+
+    /// Find the 0-based index of the first page displayed with `label`.
+    pub fn index_for_label(&self, label: &str) -> Result<Option<i32>, Error> {
+        let ranges = self.page_label_ranges()?;
+        for page_no in 0..self.page_count()? {
+            if page_label::page_label(&ranges, page_no) == label {
+                return Ok(Some(page_no));
+            }
         }
+        Ok(None)
+    }
+
+    fn page_label_ranges(&self) -> Result<Vec<page_label::PageLabelRange>, Error> {
+        let pdf = unsafe { pdf_specifics(context(), self.inner) };
+        if pdf.is_null() {
+            return Ok(Vec::new());
+        }
+        let catalog = unsafe {
+            let inner = ffi_try!(mupdf_pdf_catalog(context(), pdf));
+            PdfObject::from_raw(inner, true)
+        };
+        page_label::load_page_labels(&catalog)
     }
 
     pub fn pages(&self) -> Result<PageIter, Error> {
@@ -243,6 +428,8 @@ impl<'a> IntoIterator for &'a mut Document {
 #[cfg(test)]
 mod test {
     use super::{Document, MetadataName, Page};
+    use crate::{Buffer, Error};
+    use mupdf_sys::FZ_ERROR_TRYLATER;
 
     #[test]
     fn test_recognize_document() {
@@ -267,6 +454,42 @@ mod test {
         assert_eq!(bounds.y1, 842.0);
     }
 
+    #[test]
+    fn test_document_password_provider() {
+        let mut attempts = 0;
+        let mut provider = |_attempt: u32| -> Option<String> {
+            attempts += 1;
+            None
+        };
+        let mut doc =
+            Document::open_with_password_provider("tests/files/dummy.pdf", &mut provider).unwrap();
+        assert!(doc.authenticate_with(&mut provider).unwrap());
+        assert_eq!(attempts, 0);
+    }
+
+    #[test]
+    fn test_document_open_progressive() {
+        use std::io::Write;
+
+        let bytes = std::fs::read("tests/files/dummy.pdf").unwrap();
+        let mut data = Buffer::new();
+        data.write_all(&bytes[..16]).unwrap();
+        // The xref at the end of the file has not arrived yet
+        match Document::open_progressive(&data, "application/pdf", bytes.len()) {
+            Err(Error::MuPdf(err)) => assert_eq!(err.code, FZ_ERROR_TRYLATER as i32),
+            _ => panic!("expected a try later error"),
+        }
+
+        data.write_all(&bytes[16..]).unwrap();
+        let doc = Document::open_progressive(&data, "application/pdf", bytes.len()).unwrap();
+        drop(data);
+        assert_eq!(doc.page_count().unwrap(), 1);
+        assert!(doc.is_page_ready(0).unwrap());
+        let page0 = doc.load_page(0).unwrap();
+        let bounds = page0.bounds().unwrap();
+        assert_eq!(bounds.x1, 595.0);
+    }
+
     #[test]
     fn test_document_page_iterator() {
         let doc = Document::open("tests/files/dummy.pdf").unwrap();
@@ -281,6 +504,46 @@ mod test {
         assert_eq!(bounds.y1, 842.0);
     }
 
+    #[test]
+    fn test_document_bookmarks() {
+        use crate::Bookmark;
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let mark = doc.make_bookmark(0).unwrap();
+        assert_eq!(doc.lookup_bookmark(mark).unwrap(), Some(0));
+        let stored: i64 = mark.into();
+        assert_eq!(Bookmark::from(stored), mark);
+    }
+
+    #[test]
+    fn test_document_outlines() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.outlines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_document_page_labels() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        assert_eq!(doc.page_label(0).unwrap(), "1");
+        assert_eq!(doc.index_for_label("1").unwrap(), Some(0));
+        assert_eq!(doc.index_for_label("iv").unwrap(), None);
+    }
+
+    #[test]
+    fn test_document_resolve_link_dest() {
+        use crate::LinkDestinationKind;
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let dest = doc.resolve_link_dest("#1").unwrap().unwrap();
+        assert_eq!(dest.page, 0);
+        assert_eq!(dest.kind, LinkDestinationKind::Fit);
+
+        let dest = doc.resolve_link_dest("#1,100,200").unwrap().unwrap();
+        assert_eq!(dest.page, 0);
+        assert_eq!(dest.kind, LinkDestinationKind::XYZ);
+        assert_eq!(doc.resolve_link("#1,100,200").unwrap(), Some(0));
+    }
+
     #[test]
     fn test_document_metadata() {
         let doc = Document::open("tests/files/dummy.pdf").unwrap();
@@ -57,7 +57,10 @@ macro_rules! ffi_try {
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
+    /// An argument out of the range the call accepts, with what was wrong with it
+    InvalidArgument(String),
     InvalidLanguage(String),
+    InvalidPassword,
     InvalidPdfDocument,
     MuPdf(MuPdfError),
     Nul(NulError),
@@ -67,7 +70,9 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             Error::Io(ref err) => err.fmt(f),
+            Error::InvalidArgument(ref msg) => write!(f, "invalid argument: {}", msg),
             Error::InvalidLanguage(ref lang) => write!(f, "invalid language {}", lang),
+            Error::InvalidPassword => write!(f, "invalid password"),
             Error::InvalidPdfDocument => write!(f, "invalid pdf document"),
             Error::MuPdf(ref err) => err.fmt(f),
             Error::Nul(ref err) => err.fmt(f),
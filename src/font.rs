@@ -82,12 +82,32 @@ impl Font {
         Ok(Self { inner })
     }
 
+    pub(crate) unsafe fn from_raw(ptr: *mut fz_font) -> Self {
+        Self { inner: ptr }
+    }
+
     pub fn name(&self) -> &str {
         let f_name = unsafe { fz_font_name(context(), self.inner) };
         let c_name = unsafe { CStr::from_ptr(f_name) };
         c_name.to_str().unwrap()
     }
 
+    pub fn is_bold(&self) -> bool {
+        unsafe { fz_font_is_bold(context(), self.inner) != 0 }
+    }
+
+    pub fn is_italic(&self) -> bool {
+        unsafe { fz_font_is_italic(context(), self.inner) != 0 }
+    }
+
+    pub fn is_serif(&self) -> bool {
+        unsafe { fz_font_is_serif(context(), self.inner) != 0 }
+    }
+
+    pub fn is_monospaced(&self) -> bool {
+        unsafe { fz_font_is_monospaced(context(), self.inner) != 0 }
+    }
+
     pub fn encode_character(&self, unicode: i32) -> Result<i32, Error> {
         let glyph = unsafe { ffi_try!(mupdf_encode_character(context(), self.inner, unicode)) };
         Ok(glyph)
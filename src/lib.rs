@@ -1,4 +1,5 @@
 #[rustfmt::skip] #[macro_use] mod error;
+mod bitmap;
 mod buffer;
 mod color_params;
 mod colorspace;
@@ -19,26 +20,30 @@ mod pixmap;
 mod point;
 mod quad;
 mod rect;
+mod separations;
 mod shade;
 mod size;
 mod stroke_state;
 mod text;
 mod text_page;
+mod transition;
+mod version;
 
+pub use bitmap::{Bitmap, Halftone};
 pub use buffer::Buffer;
 pub use color_params::{ColorParams, RenderingIntent};
-pub use colorspace::Colorspace;
+pub use colorspace::{Colorspace, DefaultColorspaces};
 pub(crate) use context::context;
 pub use context::Context;
 pub use device::{BlendMode, Device};
 pub use display_list::DisplayList;
-pub use document::{Document, PageIter};
+pub use document::{Bookmark, Document, PageIter, PasswordProvider};
 pub use document_writer::DocumentWriter;
 pub(crate) use error::ffi_error;
 pub use error::Error;
 pub use font::{CjkFontOrdering, Font, SimpleFontEncoding, WriteMode};
 pub use image::Image;
-pub use link::Link;
+pub use link::{Link, LinkDestination, LinkDestinationKind, LinkIter};
 pub use matrix::Matrix;
 pub use outline::Outline;
 pub use page::Page;
@@ -48,8 +53,14 @@ pub use pixmap::{ImageFormat, Pixmap};
 pub use point::Point;
 pub use quad::Quad;
 pub use rect::{IRect, Rect};
+pub use separations::{SeparationBehavior, Separations};
 pub use shade::Shade;
 pub use size::Size;
 pub use stroke_state::{LineCap, LineJoin, StrokeState};
 pub use text::Text;
-pub use text_page::{TextPage, TextPageOptions};
+pub use text_page::{
+    BidiClass, TextBlock, TextBlockIter, TextBlockType, TextChar, TextCharIter, TextDirection,
+    TextHtml, TextLine, TextLineIter, TextPage, TextPageOptions,
+};
+pub use transition::{Presentation, Transition, TransitionType};
+pub use version::{capabilities, version, Capabilities};
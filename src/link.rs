@@ -1,8 +1,134 @@
-use crate::Rect;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+use mupdf_sys::*;
+
+use crate::{context, Error, Page, Rect};
 
 #[derive(Debug, Clone)]
 pub struct Link {
     pub bounds: Rect,
-    pub page: u32,
+    /// 0-based index of the destination page, `None` for external links
+    pub page: Option<i32>,
     pub uri: String,
 }
+
+impl Link {
+    pub fn is_external(&self) -> bool {
+        self.page.is_none()
+    }
+}
+
+/// How a viewer should position the target page of a link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkDestinationKind {
+    /// Fit the whole page in the window
+    Fit,
+    /// Scroll to the target point, keeping the current zoom
+    XYZ,
+}
+
+/// Target of an internal link: the page plus the point to scroll to, in page space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkDestination {
+    /// 0-based index of the target page
+    pub page: i32,
+    pub x: f32,
+    pub y: f32,
+    pub kind: LinkDestinationKind,
+}
+
+impl LinkDestination {
+    pub(crate) fn new(page: i32, x: f32, y: f32) -> Self {
+        if x.is_nan() || y.is_nan() {
+            Self {
+                page,
+                x: 0.0,
+                y: 0.0,
+                kind: LinkDestinationKind::Fit,
+            }
+        } else {
+            Self {
+                page,
+                x,
+                y,
+                kind: LinkDestinationKind::XYZ,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LinkIter<'a> {
+    head: *mut fz_link,
+    next: *mut fz_link,
+    doc: *mut fz_document,
+    _marker: PhantomData<&'a Page>,
+}
+
+impl LinkIter<'_> {
+    pub(crate) unsafe fn from_raw(head: *mut fz_link, doc: *mut fz_document) -> Self {
+        Self {
+            head,
+            next: head,
+            doc,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Iterator for LinkIter<'_> {
+    type Item = Result<Link, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let node = unsafe { &*self.next };
+        self.next = node.next;
+        let uri = if node.uri.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(node.uri) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        let page = if node.uri.is_null() || self.doc.is_null() {
+            None
+        } else {
+            match unsafe { resolve_internal_link(self.doc, node.uri) } {
+                Ok(page) => page,
+                Err(err) => return Some(Err(err)),
+            }
+        };
+        Some(Ok(Link {
+            bounds: node.rect.into(),
+            page,
+            uri,
+        }))
+    }
+}
+
+unsafe fn resolve_internal_link(
+    doc: *mut fz_document,
+    uri: *const std::os::raw::c_char,
+) -> Result<Option<i32>, Error> {
+    if fz_is_external_link(context(), uri) != 0 {
+        return Ok(None);
+    }
+    let n = ffi_try!(mupdf_resolve_link(context(), doc, uri));
+    if n >= 0 {
+        return Ok(Some(n));
+    }
+    Ok(None)
+}
+
+impl Drop for LinkIter<'_> {
+    fn drop(&mut self) {
+        if !self.head.is_null() {
+            unsafe {
+                fz_drop_link(context(), self.head);
+            }
+        }
+    }
+}
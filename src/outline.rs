@@ -1,9 +1,41 @@
-#[derive(Debug)]
+use std::ffi::CStr;
+
+use mupdf_sys::*;
+
+#[derive(Debug, Clone)]
 pub struct Outline {
     pub title: String,
     pub uri: String,
+    /// 0-based index of the target page, `-1` for external links
     pub page: i32,
     pub down: Vec<Outline>,
     pub x: f32,
     pub y: f32,
 }
+
+impl Outline {
+    /// Convert a linked list of outline nodes and their children.
+    pub(crate) unsafe fn from_list(mut node: *mut fz_outline) -> Vec<Self> {
+        let mut items = Vec::new();
+        while !node.is_null() {
+            let n = &*node;
+            items.push(Self {
+                title: string_or_empty(n.title),
+                uri: string_or_empty(n.uri),
+                page: n.page,
+                down: Self::from_list(n.down),
+                x: n.x,
+                y: n.y,
+            });
+            node = n.next;
+        }
+        items
+    }
+}
+
+unsafe fn string_or_empty(ptr: *const std::os::raw::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
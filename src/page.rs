@@ -1,20 +1,25 @@
 use std::io::Read;
+use std::mem;
 
 use mupdf_sys::*;
 
 use crate::{
-    context, Buffer, Colorspace, Device, DisplayList, Error, Matrix, Pixmap, Rect, TextPage,
-    TextPageOptions,
+    context, Buffer, ColorParams, Colorspace, Device, DisplayList, Error, LinkIter, Matrix, Pixmap,
+    Presentation, Rect, Separations, TextPage, TextPageOptions,
 };
 
 #[derive(Debug)]
 pub struct Page {
     pub(crate) inner: *mut fz_page,
+    doc: *mut fz_document,
 }
 
 impl Page {
-    pub(crate) unsafe fn from_raw(raw: *mut fz_page) -> Self {
-        Self { inner: raw }
+    pub(crate) unsafe fn from_raw(raw: *mut fz_page, doc: *mut fz_document) -> Self {
+        Self {
+            inner: raw,
+            doc: fz_keep_document(context(), doc),
+        }
     }
 
     pub fn bounds(&self) -> Result<Rect, Error> {
@@ -22,6 +27,19 @@ impl Page {
         Ok(rect.into())
     }
 
+    /// Bounds of everything drawn on the page, annotations included, in page space.
+    ///
+    /// The rect is empty for blank pages.
+    pub fn content_bbox(&self) -> Result<Rect, Error> {
+        let rect = unsafe { ffi_try!(mupdf_page_content_bbox(context(), self.inner)) };
+        Ok(rect.into())
+    }
+
+    /// Render the page into a pixmap in `cs`.
+    ///
+    /// Pass [`Colorspace::device_cmyk`] (or a CMYK ICC colorspace) to render straight to CMYK
+    /// without going through RGB, and save the result as PSD or PAM. Use
+    /// [`Page::to_pixmap_with_separations`] to keep spot colors as extra channels.
     pub fn to_pixmap(
         &self,
         ctm: &Matrix,
@@ -42,6 +60,117 @@ impl Page {
         }
     }
 
+    /// Soft-proof the page: render it as it would come out in `proof`, e.g. a press CMYK
+    /// profile, then convert the result to `cs` for display.
+    pub fn to_pixmap_with_proof(
+        &self,
+        ctm: &Matrix,
+        cs: &Colorspace,
+        proof: &Colorspace,
+        alpha: bool,
+        show_extras: bool,
+    ) -> Result<Pixmap, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_page_to_pixmap_with_proof(
+                context(),
+                self.inner,
+                ctm.into(),
+                cs.inner,
+                proof.inner,
+                alpha,
+                show_extras
+            ));
+            Ok(Pixmap::from_raw(inner))
+        }
+    }
+
+    /// Render the page like [`Page::to_pixmap`], optionally simulating overprint.
+    ///
+    /// With `simulate_overprint` the page is drawn in CMYK plus its spot colors, so overprinted
+    /// inks combine as on press (Acrobat's "Overprint Preview"), then converted to `cs`.
+    pub fn to_pixmap_with_overprint(
+        &self,
+        ctm: &Matrix,
+        cs: &Colorspace,
+        alpha: bool,
+        show_extras: bool,
+        simulate_overprint: bool,
+    ) -> Result<Pixmap, Error> {
+        if !simulate_overprint {
+            return self.to_pixmap(ctm, cs, if alpha { 1.0 } else { 0.0 }, show_extras);
+        }
+        unsafe {
+            let inner = ffi_try!(mupdf_page_to_pixmap_with_overprint(
+                context(),
+                self.inner,
+                ctm.into(),
+                cs.inner,
+                alpha,
+                show_extras
+            ));
+            Ok(Pixmap::from_raw(inner))
+        }
+    }
+
+    /// Render the page like [`Page::to_pixmap`], converting colors with `params`
+    ///
+    /// `params` replaces the rendering intent, black point compensation and overprint
+    /// settings of every drawing operation, including those set by the page content.
+    pub fn to_pixmap_with_color_params(
+        &self,
+        ctm: &Matrix,
+        cs: &Colorspace,
+        alpha: bool,
+        show_extras: bool,
+        params: ColorParams,
+    ) -> Result<Pixmap, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_page_to_pixmap_with_color_params(
+                context(),
+                self.inner,
+                ctm.into(),
+                cs.inner,
+                alpha,
+                show_extras,
+                params.into()
+            ));
+            Ok(Pixmap::from_raw(inner))
+        }
+    }
+
+    /// Spot colors used by the page, `None` if it only uses process colors
+    pub fn separations(&self) -> Result<Option<Separations>, Error> {
+        let seps = unsafe { ffi_try!(mupdf_page_separations(context(), self.inner)) };
+        if seps.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(unsafe { Separations::from_raw(seps) }))
+    }
+
+    /// Render the page keeping the separations set to [`SeparationBehavior::Spot`] as extra
+    /// channels after the process colors of `cs`.
+    ///
+    /// [`SeparationBehavior::Spot`]: crate::SeparationBehavior::Spot
+    pub fn to_pixmap_with_separations(
+        &self,
+        ctm: &Matrix,
+        cs: &Colorspace,
+        separations: &Separations,
+        alpha: bool,
+    ) -> Result<Pixmap, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_page_to_pixmap_with_separations(
+                context(),
+                self.inner,
+                ctm.into(),
+                cs.inner,
+                separations.inner,
+                alpha
+            ));
+            Ok(Pixmap::from_raw(inner))
+        }
+    }
+
     pub fn to_svg(&self, ctm: &Matrix) -> Result<String, Error> {
         let mut buf = unsafe {
             let inner = ffi_try!(mupdf_page_to_svg(context(), self.inner, ctm.into()));
@@ -86,6 +215,26 @@ impl Page {
         Ok(())
     }
 
+    /// [`run`](Self::run) with `params` replacing the color parameters of every drawing
+    /// operation, see [`to_pixmap_with_color_params`](Self::to_pixmap_with_color_params)
+    pub fn run_with_color_params(
+        &self,
+        device: &Device,
+        ctm: &Matrix,
+        params: ColorParams,
+    ) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_run_page_with_color_params(
+                context(),
+                self.inner,
+                device.dev,
+                ctm.into(),
+                params.into()
+            ))
+        }
+        Ok(())
+    }
+
     pub fn run_contents(&self, device: &Device, ctm: &Matrix) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_run_page_contents(
@@ -121,6 +270,53 @@ impl Page {
         }
         Ok(())
     }
+
+    /// MD5 digest of everything the page draws: path geometry, text, colors and decoded image
+    /// samples.
+    ///
+    /// Unlike a hash of the file, the digest does not depend on object numbers, stream
+    /// compression or metadata, so identical pages from different documents share a
+    /// fingerprint. Images are decoded to be hashed, lossy recompression changes the digest.
+    pub fn fingerprint(&self) -> Result<[u8; 16], Error> {
+        let mut digest = [0; 16];
+        unsafe {
+            ffi_try!(mupdf_page_fingerprint(
+                context(),
+                self.inner,
+                digest.as_mut_ptr()
+            ));
+        }
+        Ok(digest)
+    }
+
+    /// Transition effect and auto-advance timing of the page, `None` when the page has none.
+    pub fn presentation(&self) -> Result<Option<Presentation>, Error> {
+        let mut transition = unsafe { mem::zeroed::<fz_transition>() };
+        let mut duration = 0.0;
+        let found = unsafe {
+            ffi_try!(mupdf_page_presentation(
+                context(),
+                self.inner,
+                &mut transition,
+                &mut duration
+            ))
+        };
+        if !found {
+            return Ok(None);
+        }
+        Ok(Some(Presentation {
+            transition: transition.into(),
+            duration,
+        }))
+    }
+
+    /// Hyperlinks on the page, internal links have their destination page resolved.
+    pub fn links(&self) -> Result<LinkIter, Error> {
+        unsafe {
+            let links = ffi_try!(mupdf_load_links(context(), self.inner));
+            Ok(LinkIter::from_raw(links, self.doc))
+        }
+    }
 }
 
 impl Drop for Page {
@@ -130,6 +326,11 @@ impl Drop for Page {
                 fz_drop_page(context(), self.inner);
             }
         }
+        if !self.doc.is_null() {
+            unsafe {
+                fz_drop_document(context(), self.doc);
+            }
+        }
     }
 }
 
@@ -163,4 +364,157 @@ mod test {
             .to_text_page(TextPageOptions::PRESERVE_IMAGES)
             .unwrap();
     }
+
+    #[test]
+    fn test_page_to_pixmap_with_proof() {
+        use crate::Colorspace;
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let rgb = Colorspace::device_rgb();
+        let cmyk = Colorspace::device_cmyk();
+        let pixmap = page0
+            .to_pixmap_with_proof(&Matrix::IDENTITY, &rgb, &cmyk, false, true)
+            .unwrap();
+        assert_eq!(pixmap.n(), 3);
+        assert_eq!(pixmap.color_space().unwrap(), rgb);
+        assert!(pixmap.width() > 0 && pixmap.height() > 0);
+    }
+
+    #[test]
+    fn test_page_to_cmyk_pixmap() {
+        use crate::{Colorspace, ImageFormat, SeparationBehavior, Separations};
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let cmyk = Colorspace::device_cmyk();
+        let pixmap = page0
+            .to_pixmap(&Matrix::IDENTITY, &cmyk, 0.0, true)
+            .unwrap();
+        assert!(pixmap.color_space().unwrap().is_cmyk());
+        assert_eq!(pixmap.n(), 4);
+        // Blank paper is no ink at all
+        assert_eq!(&pixmap.samples()[..4], &[0, 0, 0, 0]);
+        assert!(pixmap
+            .to_bytes(ImageFormat::PSD)
+            .unwrap()
+            .starts_with(b"8BPS"));
+
+        let mut seps = Separations::new(true).unwrap();
+        seps.add("Spot", &cmyk, 3).unwrap();
+        seps.set_behavior(0, SeparationBehavior::Spot).unwrap();
+        let pixmap = page0
+            .to_pixmap_with_separations(&Matrix::IDENTITY, &cmyk, &seps, false)
+            .unwrap();
+        assert!(pixmap
+            .to_bytes(ImageFormat::PSD)
+            .unwrap()
+            .starts_with(b"8BPS"));
+    }
+
+    #[test]
+    fn test_page_to_pixmap_with_overprint() {
+        use crate::Colorspace;
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let rgb = Colorspace::device_rgb();
+        let plain = page0
+            .to_pixmap_with_overprint(&Matrix::IDENTITY, &rgb, false, true, false)
+            .unwrap();
+        let preview = page0
+            .to_pixmap_with_overprint(&Matrix::IDENTITY, &rgb, false, true, true)
+            .unwrap();
+        assert_eq!(preview.n(), 3);
+        assert_eq!(
+            (preview.width(), preview.height()),
+            (plain.width(), plain.height())
+        );
+        // Nothing on the page overprints, going through CMYK only shifts the colors slightly
+        assert_eq!(&preview.samples()[..3], &[0xFF, 0xFF, 0xFF]);
+        assert!(preview
+            .samples()
+            .iter()
+            .zip(plain.samples())
+            .all(|(a, b)| (*a as i32 - *b as i32).abs() <= 48));
+    }
+
+    #[test]
+    fn test_page_to_pixmap_with_color_params() {
+        use crate::{ColorParams, Colorspace, RenderingIntent};
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let rgb = Colorspace::device_rgb();
+        let plain = page0.to_pixmap(&Matrix::IDENTITY, &rgb, 0.0, true).unwrap();
+        let default = page0
+            .to_pixmap_with_color_params(
+                &Matrix::IDENTITY,
+                &rgb,
+                false,
+                true,
+                ColorParams::mupdf_default(),
+            )
+            .unwrap();
+        assert_eq!(default.samples(), plain.samples());
+
+        let params = ColorParams::new(RenderingIntent::Perceptual, false, false, false);
+        let perceptual = page0
+            .to_pixmap_with_color_params(&Matrix::IDENTITY, &rgb, false, true, params)
+            .unwrap();
+        assert_eq!(
+            (perceptual.width(), perceptual.height()),
+            (plain.width(), plain.height())
+        );
+    }
+
+    #[test]
+    fn test_page_separations() {
+        use crate::{Colorspace, SeparationBehavior, Separations};
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        assert!(page0.separations().unwrap().is_none());
+
+        let cmyk = Colorspace::device_cmyk();
+        let mut seps = Separations::new(true).unwrap();
+        seps.add("Pantone 123 C", &cmyk, 1).unwrap();
+        seps.set_behavior(0, SeparationBehavior::Spot).unwrap();
+        let pixmap = page0
+            .to_pixmap_with_separations(&Matrix::IDENTITY, &cmyk, &seps, false)
+            .unwrap();
+        assert_eq!(pixmap.n(), 5);
+    }
+
+    #[test]
+    fn test_page_fingerprint() {
+        use crate::{PdfDocument, Size};
+
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let fingerprint = doc.load_page(0).unwrap().fingerprint().unwrap();
+        let doc2 = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert_eq!(
+            doc2.load_page(0).unwrap().fingerprint().unwrap(),
+            fingerprint
+        );
+
+        let mut blank = PdfDocument::new();
+        let page = blank.new_page(Size::A4).unwrap();
+        assert_ne!(page.fingerprint().unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn test_page_presentation() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        assert!(page0.presentation().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_page_links() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let links: Vec<_> = page0.links().unwrap().collect::<Result<_, _>>().unwrap();
+        assert!(links.is_empty());
+    }
 }
@@ -1,9 +1,14 @@
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::os::raw::c_char;
+
+use bitflags::bitflags;
 use mupdf_sys::*;
 
 use num_enum::TryFromPrimitive;
-use std::convert::TryFrom;
 
-use crate::{context, Error};
+use crate::{context, DisplayList, Error, PdfObject, PdfPage, Point, Quad, Rect};
 
 #[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
 #[repr(i32)]
@@ -52,6 +57,165 @@ pub enum LineEndingStyle {
     Slash = 9,
 }
 
+bitflags! {
+    /// Annotation flags (`/F`)
+    pub struct PdfAnnotationFlags: i32 {
+        const INVISIBLE = 1;
+        const HIDDEN = 1 << 1;
+        const PRINT = 1 << 2;
+        const NO_ZOOM = 1 << 3;
+        const NO_ROTATE = 1 << 4;
+        const NO_VIEW = 1 << 5;
+        const READ_ONLY = 1 << 6;
+        const LOCKED = 1 << 7;
+        const TOGGLE_NO_VIEW = 1 << 8;
+        const LOCKED_CONTENTS = 1 << 9;
+    }
+}
+
+/// How the border of an annotation is drawn (`/BS /S`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderStyle {
+    Solid,
+    Dashed,
+    Beveled,
+    Inset,
+    Underline,
+}
+
+impl BorderStyle {
+    fn from_letter(c: u8) -> Self {
+        match c {
+            b'D' => Self::Dashed,
+            b'B' => Self::Beveled,
+            b'I' => Self::Inset,
+            b'U' => Self::Underline,
+            _ => Self::Solid,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Solid => "S",
+            Self::Dashed => "D",
+            Self::Beveled => "B",
+            Self::Inset => "I",
+            Self::Underline => "U",
+        }
+    }
+}
+
+/// Reviewer decision recorded by a state annotation replying to another annotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReviewState {
+    /// States of the `Review` model
+    Accepted,
+    Rejected,
+    Cancelled,
+    Completed,
+    None,
+    /// States of the `Marked` model, a reviewer's own check mark
+    Marked,
+    Unmarked,
+}
+
+impl ReviewState {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Accepted => "Accepted",
+            Self::Rejected => "Rejected",
+            Self::Cancelled => "Cancelled",
+            Self::Completed => "Completed",
+            Self::None => "None",
+            Self::Marked => "Marked",
+            Self::Unmarked => "Unmarked",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Accepted" => Some(Self::Accepted),
+            "Rejected" => Some(Self::Rejected),
+            "Cancelled" => Some(Self::Cancelled),
+            "Completed" => Some(Self::Completed),
+            "None" => Some(Self::None),
+            "Marked" => Some(Self::Marked),
+            "Unmarked" => Some(Self::Unmarked),
+            _ => Option::None,
+        }
+    }
+
+    /// Name of the state model (`/StateModel`) the state belongs to
+    pub fn model(self) -> &'static str {
+        match self {
+            Self::Marked | Self::Unmarked => "Marked",
+            _ => "Review",
+        }
+    }
+}
+
+/// Horizontal alignment of the text of a FreeText annotation
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+pub enum TextAlignment {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Standard fonts usable in the default appearance of a FreeText annotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FreeTextFont {
+    Helvetica,
+    TimesRoman,
+    Courier,
+}
+
+impl FreeTextFont {
+    /// Resource name of the font in the default appearance string
+    pub(crate) fn resource_name(self) -> &'static str {
+        match self {
+            Self::Helvetica => "Helv",
+            Self::TimesRoman => "TiRo",
+            Self::Courier => "Cour",
+        }
+    }
+}
+
+/// Look of a FreeText annotation created by [`PdfPage::create_free_text`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreeTextStyle {
+    pub font: FreeTextFont,
+    pub size: f32,
+    /// RGB text color
+    pub color: [f32; 3],
+    /// Width of the border in points, 0 for none
+    pub border_width: f32,
+    pub alignment: TextAlignment,
+}
+
+impl Default for FreeTextStyle {
+    fn default() -> Self {
+        Self {
+            font: FreeTextFont::Helvetica,
+            size: 12.0,
+            color: [0.0, 0.0, 0.0],
+            border_width: 1.0,
+            alignment: TextAlignment::Left,
+        }
+    }
+}
+
+/// A file embedded in a file attachment annotation
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfEmbeddedFile {
+    pub filename: String,
+    /// MIME type, e.g. `text/plain`, stored as the stream `/Subtype`
+    pub mime_type: Option<String>,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub struct PdfAnnotation {
     pub(crate) inner: *mut pdf_annot,
@@ -67,6 +231,468 @@ impl PdfAnnotation {
         let typ = PdfAnnotationType::try_from(subtype).unwrap_or(PdfAnnotationType::Unknown);
         Ok(typ)
     }
+
+    /// The annotation dictionary
+    pub fn object(&self) -> PdfObject {
+        unsafe { PdfObject::from_raw(pdf_keep_obj(context(), (*self.inner).obj), true) }
+    }
+
+    /// The file embedded in a file attachment annotation
+    pub fn attachment(&self) -> Result<Option<PdfEmbeddedFile>, Error> {
+        let fs = match self.object().get_dict("FS")? {
+            Some(fs) => fs,
+            None => return Ok(None),
+        };
+        let stream = match fs.get_dict("EF")?.map(|ef| ef.get_dict("F")).transpose()? {
+            Some(Some(stream)) => stream,
+            _ => return Ok(None),
+        };
+        let filename = match fs.get_dict("UF")? {
+            Some(name) => name.as_string()?.to_string(),
+            None => match fs.get_dict("F")? {
+                Some(name) => name.as_string()?.to_string(),
+                None => String::new(),
+            },
+        };
+        let mime_type = match stream.get_dict("Subtype")? {
+            Some(subtype) => Some(subtype.as_name()?.to_string()),
+            None => None,
+        };
+        let description = match fs.get_dict("Desc")? {
+            Some(desc) => Some(desc.as_string()?.to_string()),
+            None => None,
+        };
+        Ok(Some(PdfEmbeddedFile {
+            filename,
+            mime_type,
+            description,
+            data: stream.read_stream()?,
+        }))
+    }
+
+    /// Bounds of the annotation in page space
+    pub fn rect(&self) -> Result<Rect, Error> {
+        let rect = unsafe { ffi_try!(mupdf_pdf_bound_annot(context(), self.inner)) };
+        Ok(rect.into())
+    }
+
+    /// Text of the annotation (`/Contents`), empty when it has none
+    pub fn contents(&self) -> Result<String, Error> {
+        let ptr = unsafe { ffi_try!(mupdf_pdf_annot_contents(context(), self.inner)) };
+        Ok(unsafe { string_from_ptr(ptr) }.unwrap_or_default())
+    }
+
+    pub fn set_contents(&mut self, text: &str) -> Result<(), Error> {
+        let c_text = CString::new(text)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_contents(
+                context(),
+                self.inner,
+                c_text.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Author of the annotation (`/T`)
+    pub fn author(&self) -> Result<Option<String>, Error> {
+        let ptr = unsafe { ffi_try!(mupdf_pdf_annot_author(context(), self.inner)) };
+        Ok(unsafe { string_from_ptr(ptr) })
+    }
+
+    pub fn set_author(&mut self, author: &str) -> Result<(), Error> {
+        let c_author = CString::new(author)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_author(
+                context(),
+                self.inner,
+                c_author.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creation date as a raw PDF date string, e.g. `D:20200102030405Z`
+    pub fn creation_date(&self) -> Result<Option<String>, Error> {
+        self.date("CreationDate")
+    }
+
+    /// Modification date (`/M`) as a raw PDF date string
+    pub fn modification_date(&self) -> Result<Option<String>, Error> {
+        self.date("M")
+    }
+
+    /// Set the modification date to `secs` seconds since the Unix epoch
+    pub fn set_modification_date(&mut self, secs: i64) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_modification_date(
+                context(),
+                self.inner,
+                secs
+            ));
+        }
+        Ok(())
+    }
+
+    /// Constant opacity (`/CA`), from 0 to 1
+    pub fn opacity(&self) -> Result<f32, Error> {
+        let opacity = unsafe { ffi_try!(mupdf_pdf_annot_opacity(context(), self.inner)) };
+        Ok(opacity)
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_opacity(context(), self.inner, opacity));
+        }
+        Ok(())
+    }
+
+    pub fn flags(&self) -> Result<PdfAnnotationFlags, Error> {
+        let flags = unsafe { ffi_try!(mupdf_pdf_annot_flags(context(), self.inner)) };
+        Ok(PdfAnnotationFlags::from_bits_truncate(flags))
+    }
+
+    pub fn set_flags(&mut self, flags: PdfAnnotationFlags) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_flags(
+                context(),
+                self.inner,
+                flags.bits()
+            ));
+        }
+        Ok(())
+    }
+
+    fn date(&self, key: &str) -> Result<Option<String>, Error> {
+        let c_key = CString::new(key)?;
+        let ptr = unsafe { ffi_try!(mupdf_pdf_annot_date(context(), self.inner, c_key.as_ptr())) };
+        Ok(unsafe { string_from_ptr(ptr) })
+    }
+
+    /// Color components (`/C`), 0 (transparent), 1 (gray), 3 (RGB) or 4 (CMYK) of them
+    pub fn color(&self) -> Result<Vec<f32>, Error> {
+        let mut color = [0.0; 4];
+        let n = unsafe {
+            ffi_try!(mupdf_pdf_annot_color(
+                context(),
+                self.inner,
+                color.as_mut_ptr()
+            ))
+        };
+        Ok(color[..n as usize].to_vec())
+    }
+
+    /// Move or resize the annotation, `rect` is in page space
+    pub fn set_rect(&mut self, rect: Rect) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_rect(context(), self.inner, rect.into()));
+        }
+        Ok(())
+    }
+
+    /// The annotation on the same page this one is a reply to (`/IRT`)
+    pub fn in_reply_to(&self) -> Result<Option<PdfAnnotation>, Error> {
+        unsafe {
+            let parent = ffi_try!(mupdf_pdf_annot_in_reply_to(context(), self.inner));
+            if parent.is_null() {
+                return Ok(None);
+            }
+            Ok(Some(PdfAnnotation::from_raw(parent)))
+        }
+    }
+
+    /// The state set by this annotation if it is a state annotation (`/State`)
+    pub fn review_state(&self) -> Result<Option<ReviewState>, Error> {
+        match self.object().get_dict("State")? {
+            Some(state) => Ok(ReviewState::from_name(state.as_string()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Bounds of the popup window showing the contents, in page space
+    pub fn popup(&self) -> Result<Option<Rect>, Error> {
+        let rect: Rect = unsafe { ffi_try!(mupdf_pdf_annot_popup(context(), self.inner)) }.into();
+        if rect.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(rect))
+    }
+
+    /// Move the popup window to `rect`, adding a popup annotation if there is none yet
+    pub fn set_popup(&mut self, rect: Rect) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_popup(
+                context(),
+                self.inner,
+                rect.into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Have the next [`update`](Self::update) rebuild the appearance stream
+    pub(crate) fn dirty(&mut self) {
+        unsafe { pdf_dirty_annot(context(), self.inner) };
+    }
+
+    /// Synthesize the appearance stream after changes, returns whether it was rebuilt
+    ///
+    /// The setters of this type already do so, this is for edits made through the
+    /// underlying objects.
+    pub fn update(&mut self) -> Result<bool, Error> {
+        let changed = unsafe { ffi_try!(mupdf_pdf_update_annot(context(), self.inner)) };
+        Ok(changed)
+    }
+
+    /// Replace the appearance with the form XObject `xobj` scaled to the annotation rect
+    ///
+    /// MuPDF no longer synthesizes the appearance until the annotation is edited again.
+    pub fn set_appearance(&mut self, xobj: &PdfObject) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_appearance_xobject(
+                context(),
+                self.inner,
+                xobj.inner
+            ));
+        }
+        Ok(())
+    }
+
+    /// Replace the appearance with `list`, its bounds scaled to the annotation rect
+    pub fn set_appearance_from_display_list(&mut self, list: &DisplayList) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_appearance_display_list(
+                context(),
+                self.inner,
+                list.inner
+            ));
+        }
+        Ok(())
+    }
+
+    /// Replace the appearance with the content stream `content`, drawn with the origin at the
+    /// lower left corner of the annotation and y going up
+    pub fn set_appearance_content(
+        &mut self,
+        content: &[u8],
+        resources: Option<&PdfObject>,
+    ) -> Result<(), Error> {
+        let res = resources.map_or(std::ptr::null_mut(), |res| res.inner);
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_appearance_content(
+                context(),
+                self.inner,
+                content.as_ptr(),
+                content.len(),
+                res
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set the stroke color (`/C`), an empty slice makes it transparent
+    pub fn set_color(&mut self, color: &[f32]) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_color(
+                context(),
+                self.inner,
+                color.len() as i32,
+                color.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fill color (`/IC`) of square, circle, line, polygon and polyline annotations
+    pub fn interior_color(&self) -> Result<Vec<f32>, Error> {
+        let mut color = [0.0; 4];
+        let n = unsafe {
+            ffi_try!(mupdf_pdf_annot_interior_color(
+                context(),
+                self.inner,
+                color.as_mut_ptr()
+            ))
+        };
+        Ok(color[..n as usize].to_vec())
+    }
+
+    pub fn set_interior_color(&mut self, color: &[f32]) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_interior_color(
+                context(),
+                self.inner,
+                color.len() as i32,
+                color.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Width of the border or line in points
+    pub fn border_width(&self) -> Result<f32, Error> {
+        let width = unsafe { ffi_try!(mupdf_pdf_annot_border(context(), self.inner)) };
+        Ok(width)
+    }
+
+    pub fn set_border_width(&mut self, width: f32) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_border(context(), self.inner, width));
+        }
+        Ok(())
+    }
+
+    pub fn border_style(&self) -> Result<BorderStyle, Error> {
+        let style = unsafe { ffi_try!(mupdf_pdf_annot_border_style(context(), self.inner)) };
+        Ok(BorderStyle::from_letter(style as u8))
+    }
+
+    /// Set the border style, MuPDF only draws solid borders in the appearances it synthesizes
+    pub fn set_border_style(&mut self, style: BorderStyle) -> Result<(), Error> {
+        let c_style = CString::new(style.name())?;
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_border_style(
+                context(),
+                self.inner,
+                c_style.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Alternating dash and gap lengths of a dashed border
+    pub fn border_dash(&self) -> Result<Vec<f32>, Error> {
+        let mut dash = [0.0; 16];
+        let n = unsafe {
+            ffi_try!(mupdf_pdf_annot_border_dash(
+                context(),
+                self.inner,
+                dash.as_mut_ptr(),
+                dash.len() as i32
+            ))
+        };
+        Ok(dash[..(n as usize).min(dash.len())].to_vec())
+    }
+
+    pub fn set_border_dash(&mut self, dash: &[f32]) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_border_dash(
+                context(),
+                self.inner,
+                dash.as_ptr(),
+                dash.len() as i32
+            ));
+        }
+        Ok(())
+    }
+
+    /// End points of a line annotation, in page space
+    pub fn line(&self) -> Result<(Point, Point), Error> {
+        let mut a = fz_point { x: 0.0, y: 0.0 };
+        let mut b = fz_point { x: 0.0, y: 0.0 };
+        unsafe {
+            ffi_try!(mupdf_pdf_annot_line(context(), self.inner, &mut a, &mut b));
+        }
+        Ok((a.into(), b.into()))
+    }
+
+    pub fn set_line(&mut self, a: Point, b: Point) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_line(
+                context(),
+                self.inner,
+                a.into(),
+                b.into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Areas covered by a text markup annotation, in page space
+    pub fn quad_points(&self) -> Result<Vec<Quad>, Error> {
+        let n = unsafe { ffi_try!(mupdf_pdf_annot_quad_point_count(context(), self.inner)) };
+        let mut quads = Vec::with_capacity(n as usize);
+        for i in 0..n {
+            let q = unsafe { ffi_try!(mupdf_pdf_annot_quad_point(context(), self.inner, i)) };
+            quads.push(q.into());
+        }
+        Ok(quads)
+    }
+
+    pub fn set_quad_points(&mut self, quads: &[Quad]) -> Result<(), Error> {
+        let quads: Vec<fz_quad> = quads.iter().cloned().map(Into::into).collect();
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_quad_points(
+                context(),
+                self.inner,
+                quads.len() as i32,
+                quads.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Vertices of a polygon or polyline annotation, in page space
+    pub fn vertices(&self) -> Result<Vec<Point>, Error> {
+        let n = unsafe { ffi_try!(mupdf_pdf_annot_vertex_count(context(), self.inner)) };
+        let mut vertices = Vec::with_capacity(n as usize);
+        for i in 0..n {
+            let p = unsafe { ffi_try!(mupdf_pdf_annot_vertex(context(), self.inner, i)) };
+            vertices.push(p.into());
+        }
+        Ok(vertices)
+    }
+
+    pub fn set_vertices(&mut self, vertices: &[Point]) -> Result<(), Error> {
+        let points: Vec<fz_point> = vertices.iter().map(|&p| p.into()).collect();
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_vertices(
+                context(),
+                self.inner,
+                points.len() as i32,
+                points.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Styles of the start and end of a line or polyline annotation
+    pub fn line_ending_styles(&self) -> Result<(LineEndingStyle, LineEndingStyle), Error> {
+        let mut start = 0;
+        let mut end = 0;
+        unsafe {
+            ffi_try!(mupdf_pdf_annot_line_ending_styles(
+                context(),
+                self.inner,
+                &mut start,
+                &mut end
+            ));
+        }
+        let style = |s| LineEndingStyle::try_from(s).unwrap_or(LineEndingStyle::None);
+        Ok((style(start), style(end)))
+    }
+
+    pub fn set_line_ending_styles(
+        &mut self,
+        start: LineEndingStyle,
+        end: LineEndingStyle,
+    ) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_set_annot_line_ending_styles(
+                context(),
+                self.inner,
+                start as i32,
+                end as i32
+            ));
+        }
+        Ok(())
+    }
+}
+
+unsafe fn string_from_ptr(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
 }
 
 impl Drop for PdfAnnotation {
@@ -78,3 +704,61 @@ impl Drop for PdfAnnotation {
         }
     }
 }
+
+/// Iterator over the annotations of a [`PdfPage`], in `/Annots` order
+#[derive(Debug)]
+pub struct PdfAnnotationIter<'a> {
+    next: *mut pdf_annot,
+    widgets: bool,
+    _page: PhantomData<&'a PdfPage>,
+}
+
+impl<'a> PdfAnnotationIter<'a> {
+    pub(crate) fn new(page: &'a PdfPage) -> Self {
+        let next = unsafe { mupdf_pdf_first_annot(context(), page.inner) };
+        Self {
+            next,
+            widgets: false,
+            _page: PhantomData,
+        }
+    }
+
+    /// Iterate over the form widgets instead, which MuPDF keeps apart from the annotations
+    pub(crate) fn new_widgets(page: &'a PdfPage) -> Self {
+        let next = unsafe { mupdf_pdf_first_widget(context(), page.inner) };
+        Self {
+            next,
+            widgets: true,
+            _page: PhantomData,
+        }
+    }
+}
+
+impl Iterator for PdfAnnotationIter<'_> {
+    type Item = PdfAnnotation;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let annot = unsafe { PdfAnnotation::from_raw(self.next) };
+        self.next = unsafe {
+            if self.widgets {
+                mupdf_pdf_next_widget(context(), annot.inner)
+            } else {
+                mupdf_pdf_next_annot(context(), annot.inner)
+            }
+        };
+        Some(annot)
+    }
+}
+
+impl Drop for PdfAnnotationIter<'_> {
+    fn drop(&mut self) {
+        if !self.next.is_null() {
+            unsafe {
+                pdf_drop_annot(context(), self.next);
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::pdf::xfdf::{floats, real_array, ANNOTATION_TYPES};
+use crate::pdf::{PdfAnnotation, PdfDocument, PdfObject, PdfPage};
+use crate::Error;
+
+/// One element of [`PdfDocument::annotations_to_json`], members that are `None` are left
+/// out of the JSON object
+///
+/// Coordinates are in PDF space, colors have 1 (gray), 3 (RGB) or 4 (CMYK) components.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonAnnotation {
+    /// 0-based page number
+    #[serde(default)]
+    pub page: i32,
+    /// Lower case subtype, `"highlight"`, `"ink"`, ...
+    #[serde(rename = "type")]
+    pub subtype: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rect: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interior_color: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quad_points: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vertices: Option<Vec<f64>>,
+    /// Start and end points of a line annotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<Vec<f64>>,
+    /// Points of each stroke of an ink annotation, as x, y pairs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ink_list: Option<Vec<Vec<f64>>>,
+    /// Annotation flags, see [`PdfAnnotationFlags`](crate::PdfAnnotationFlags)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<i32>,
+    /// 0 for left, 1 for centered and 2 for right aligned text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub justification: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub border_width: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+    /// PDF date string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// PDF date string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_appearance: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_endings: Option<Vec<String>>,
+}
+
+/// Widen through the shortest decimal form so `0.1` stays `0.1` in JSON
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+fn widen_all(values: Vec<f32>) -> Vec<f64> {
+    values.into_iter().map(widen).collect()
+}
+
+fn narrow_all(values: &[f64]) -> Vec<f32> {
+    values.iter().map(|v| *v as f32).collect()
+}
+
+fn numbers(obj: &PdfObject, key: &str) -> Result<Option<Vec<f64>>, Error> {
+    match obj.get_dict(key)? {
+        Some(array) => Ok(Some(widen_all(floats(&array)?))),
+        None => Ok(None),
+    }
+}
+
+fn int(obj: &PdfObject, key: &str) -> Result<Option<i32>, Error> {
+    match obj.get_dict(key)? {
+        Some(int) => Ok(Some(int.as_int()?)),
+        None => Ok(None),
+    }
+}
+
+fn string(obj: &PdfObject, key: &str) -> Result<Option<String>, Error> {
+    match obj.get_dict(key)? {
+        Some(string) => Ok(Some(string.as_string()?.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn annotation_to_json(
+    page_no: i32,
+    annot: &PdfAnnotation,
+) -> Result<Option<JsonAnnotation>, Error> {
+    let subtype = annot.r#type()?;
+    let name = match ANNOTATION_TYPES.iter().find(|(_, t)| *t == subtype) {
+        Some((name, _)) => *name,
+        None => return Ok(None),
+    };
+    let obj = annot.object();
+    let mut json = JsonAnnotation {
+        page: page_no,
+        subtype: name.to_string(),
+        rect: numbers(&obj, "Rect")?,
+        color: numbers(&obj, "C")?,
+        interior_color: numbers(&obj, "IC")?,
+        quad_points: numbers(&obj, "QuadPoints")?,
+        vertices: numbers(&obj, "Vertices")?,
+        line: numbers(&obj, "L")?,
+        flags: int(&obj, "F")?,
+        justification: int(&obj, "Q")?,
+        author: string(&obj, "T")?,
+        contents: string(&obj, "Contents")?,
+        modified: string(&obj, "M")?,
+        created: string(&obj, "CreationDate")?,
+        name: string(&obj, "NM")?,
+        default_appearance: string(&obj, "DA")?,
+        ..Default::default()
+    };
+    if let Some(opacity) = obj.get_dict("CA")? {
+        json.opacity = Some(widen(opacity.as_float()?));
+    }
+    if let Some(icon) = obj.get_dict("Name")? {
+        json.icon = Some(icon.as_name()?.to_string());
+    }
+    if let Some(bs) = obj.get_dict("BS")? {
+        if let Some(width) = bs.get_dict("W")? {
+            json.border_width = Some(widen(width.as_float()?));
+        }
+    }
+    if let Some(endings) = obj.get_dict("LE")? {
+        let mut names = Vec::new();
+        for i in 0..endings.len()? {
+            if let Some(ending) = endings.get_array(i as i32)? {
+                names.push(ending.as_name()?.to_string());
+            }
+        }
+        json.line_endings = Some(names);
+    }
+    if let Some(ink) = obj.get_dict("InkList")? {
+        let mut strokes = Vec::new();
+        for i in 0..ink.len()? {
+            if let Some(stroke) = ink.get_array(i as i32)? {
+                strokes.push(widen_all(floats(&stroke)?));
+            }
+        }
+        json.ink_list = Some(strokes);
+    }
+    Ok(Some(json))
+}
+
+pub(crate) fn export(doc: &PdfDocument) -> Result<Value, Error> {
+    let mut annots = Vec::new();
+    for page_no in 0..doc.page_count()? {
+        let page = PdfPage::from(doc.load_page(page_no)?);
+        for annot in page.annotations() {
+            if let Some(json) = annotation_to_json(page_no, &annot)? {
+                annots.push(json);
+            }
+        }
+    }
+    serde_json::to_value(annots).map_err(|e| Error::InvalidArgument(e.to_string()))
+}
+
+fn annotation_from_json(doc: &PdfDocument, json: &JsonAnnotation) -> Result<bool, Error> {
+    let subtype = match ANNOTATION_TYPES
+        .iter()
+        .find(|(name, _)| *name == json.subtype)
+    {
+        Some((_, subtype)) => *subtype,
+        None => return Ok(false),
+    };
+    let mut page = PdfPage::from(doc.load_page(json.page)?);
+    let mut annot = page.create_annotation(subtype as i32)?;
+    let mut obj = annot.object();
+
+    let arrays = [
+        ("Rect", &json.rect),
+        ("C", &json.color),
+        ("IC", &json.interior_color),
+        ("QuadPoints", &json.quad_points),
+        ("Vertices", &json.vertices),
+        ("L", &json.line),
+    ];
+    for (key, values) in arrays.iter() {
+        if let Some(values) = values {
+            obj.dict_put(*key, real_array(doc, &narrow_all(values))?)?;
+        }
+    }
+    for (key, int) in [("F", json.flags), ("Q", json.justification)].iter() {
+        if let Some(int) = int {
+            obj.dict_put(*key, PdfObject::new_int(*int)?)?;
+        }
+    }
+    let strings = [
+        ("T", &json.author),
+        ("Contents", &json.contents),
+        ("M", &json.modified),
+        ("CreationDate", &json.created),
+        ("NM", &json.name),
+        ("DA", &json.default_appearance),
+    ];
+    for (key, string) in strings.iter() {
+        if let Some(string) = string {
+            obj.dict_put(*key, PdfObject::new_string(string)?)?;
+        }
+    }
+    if let Some(opacity) = json.opacity {
+        obj.dict_put("CA", PdfObject::new_real(opacity as f32)?)?;
+    }
+    if let Some(icon) = &json.icon {
+        obj.dict_put("Name", PdfObject::new_name(icon)?)?;
+    }
+    if let Some(width) = json.border_width {
+        let mut bs = doc.new_dict()?;
+        bs.dict_put("W", PdfObject::new_real(width as f32)?)?;
+        obj.dict_put("BS", bs)?;
+    }
+    if let Some(endings) = &json.line_endings {
+        let mut array = doc.new_array()?;
+        for ending in endings {
+            array.array_push(PdfObject::new_name(ending)?)?;
+        }
+        obj.dict_put("LE", array)?;
+    }
+    if let Some(strokes) = &json.ink_list {
+        let mut ink = doc.new_array()?;
+        for stroke in strokes {
+            ink.array_push(real_array(doc, &narrow_all(stroke))?)?;
+        }
+        obj.dict_put("InkList", ink)?;
+    }
+    annot.dirty();
+    annot.update()?;
+    Ok(true)
+}
+
+pub(crate) fn import(doc: &mut PdfDocument, value: &Value) -> Result<usize, Error> {
+    let mut count = 0;
+    for element in value.as_array().into_iter().flatten() {
+        if !element.is_object() {
+            continue;
+        }
+        let json: JsonAnnotation = serde_json::from_value(element.clone())
+            .map_err(|e| Error::InvalidArgument(e.to_string()))?;
+        if annotation_from_json(doc, &json)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
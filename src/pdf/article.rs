@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use crate::pdf::destination::page_obj_transform;
+use crate::{Error, PdfDocument, PdfObject, Rect};
+
+/// A piece of an article on one page, in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfArticleBead {
+    /// 0-based index of the page the bead is on
+    pub page: i32,
+    /// Area of the bead in page space
+    pub rect: Rect,
+}
+
+/// An article thread, linking the parts of an article spread over columns and pages
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfArticleThread {
+    pub title: String,
+    pub beads: Vec<PdfArticleBead>,
+}
+
+fn rect_of(array: &PdfObject) -> Result<Rect, Error> {
+    let mut coords = [0.0; 4];
+    for (i, coord) in coords.iter_mut().enumerate() {
+        if let Some(n) = array.get_array(i as i32)? {
+            *coord = n.as_float()?;
+        }
+    }
+    let [x0, y0, x1, y1] = coords;
+    Ok(Rect::new(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)))
+}
+
+fn load_bead(doc: &PdfDocument, bead: &PdfObject) -> Result<Option<PdfArticleBead>, Error> {
+    let page_obj = match bead.get_dict("P")? {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let page = doc.page_number_of(&page_obj)?;
+    if page < 0 {
+        return Ok(None);
+    }
+    let rect = match bead.get_dict("R")? {
+        Some(r) => rect_of(&r)?,
+        None => return Ok(None),
+    };
+    let (_, ctm) = page_obj_transform(&page_obj)?;
+    Ok(Some(PdfArticleBead {
+        page,
+        rect: rect.transform(&ctm),
+    }))
+}
+
+/// Follow the circular list of beads starting at the thread's `/F` entry
+fn load_thread(doc: &PdfDocument, thread: &PdfObject) -> Result<PdfArticleThread, Error> {
+    let title = match thread.get_dict("I")? {
+        Some(info) => match info.get_dict("Title")? {
+            Some(t) => t.as_string()?.to_string(),
+            None => String::new(),
+        },
+        None => String::new(),
+    };
+    let mut beads = Vec::new();
+    let first = match thread.get_dict("F")? {
+        Some(f) => f,
+        None => return Ok(PdfArticleThread { title, beads }),
+    };
+    // Stop at the first bead seen twice, damaged files may loop back past `/F`
+    let mut seen = HashSet::new();
+    seen.insert(first.as_indirect()?);
+    let mut bead = first;
+    loop {
+        if let Some(b) = load_bead(doc, &bead)? {
+            beads.push(b);
+        }
+        let next = match bead.get_dict("N")? {
+            Some(n) => n,
+            None => break,
+        };
+        let num = next.as_indirect()?;
+        if num == 0 || !seen.insert(num) {
+            break;
+        }
+        bead = next;
+    }
+    Ok(PdfArticleThread { title, beads })
+}
+
+pub(crate) fn load_threads(doc: &PdfDocument) -> Result<Vec<PdfArticleThread>, Error> {
+    let mut threads = Vec::new();
+    if let Some(list) = doc.catalog()?.get_dict("Threads")? {
+        for i in 0..list.len()? as i32 {
+            if let Some(thread) = list.get_array(i)? {
+                threads.push(load_thread(doc, &thread)?);
+            }
+        }
+    }
+    Ok(threads)
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+
+use bitflags::bitflags;
+
+use crate::pdf::PdfObject;
+
+/// One operator of a page content stream with its operands, such as `/F1 12 Tf` or `1 0 0 RG`
+///
+/// Inline images have the `BI` operator with the image dictionary and a string of the raw image
+/// data as operands.
+#[derive(Debug)]
+pub struct PdfContentOperation {
+    pub operator: String,
+    pub operands: Vec<PdfObject>,
+}
+
+impl PdfContentOperation {
+    pub fn new(operator: &str, operands: Vec<PdfObject>) -> Self {
+        Self {
+            operator: operator.to_string(),
+            operands,
+        }
+    }
+
+    /// What keeps the operation from being written to a content stream, if anything
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let op = self.operator.as_str();
+        let regular = |b: &u8| !b"\0\t\n\x0c\r ()<>[]{}/%".contains(b);
+        if op.is_empty() || !op.bytes().all(|b| regular(&b)) {
+            return Err(format!("{:?} is not a content stream operator", op));
+        }
+        match op {
+            "ID" | "EI" => Err(format!("{} is only valid inside BI", op)),
+            "BI" => match self.operands.as_slice() {
+                [dict, data]
+                    if dict.is_dict().unwrap_or(false) && data.is_string().unwrap_or(false) =>
+                {
+                    Ok(())
+                }
+                _ => Err("BI takes an image dictionary and a data string".to_string()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+bitflags! {
+    /// Kinds of page content for [`PdfPage::remove_contents`](crate::pdf::PdfPage::remove_contents)
+    pub struct PdfContentKinds: u32 {
+        /// Text showing operators, the text state and graphics state set inside text objects
+        /// are kept as later content may depend on them
+        const TEXT = 1;
+        /// Image XObjects and inline images
+        const IMAGES = 2;
+        /// Filled and stroked paths and shadings, clipping paths are kept
+        const VECTOR = 4;
+    }
+}
+
+/// Filter state removing content of some kinds from a content stream
+#[derive(Debug)]
+pub(crate) struct ContentRemoval {
+    kinds: PdfContentKinds,
+    /// Resource names of the image XObjects
+    images: HashSet<String>,
+}
+
+impl ContentRemoval {
+    pub(crate) fn new(kinds: PdfContentKinds, images: HashSet<String>) -> Self {
+        Self { kinds, images }
+    }
+
+    /// Whether to keep `op`, painting operators of removed paths become `n`
+    pub(crate) fn keep(&mut self, op: &mut PdfContentOperation) -> bool {
+        if self.kinds.contains(PdfContentKinds::TEXT) {
+            if let "Tj" | "TJ" | "'" | "\"" = op.operator.as_str() {
+                return false;
+            }
+        }
+        if self.kinds.contains(PdfContentKinds::IMAGES) {
+            match op.operator.as_str() {
+                "BI" => return false,
+                "Do" => {
+                    let name = op.operands.first().and_then(|name| name.as_name().ok());
+                    if name.map_or(false, |name| self.images.contains(name)) {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.kinds.contains(PdfContentKinds::VECTOR) {
+            match op.operator.as_str() {
+                "sh" => return false,
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" => {
+                    op.operator = "n".to_string();
+                    op.operands.clear();
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{ContentRemoval, PdfContentKinds, PdfContentOperation};
+    use crate::PdfObject;
+
+    fn ops(ops: &[(&str, Option<&str>)]) -> Vec<PdfContentOperation> {
+        ops.iter()
+            .map(|(op, name)| {
+                let operands = name
+                    .map(|name| vec![PdfObject::new_name(name).unwrap()])
+                    .unwrap_or_default();
+                PdfContentOperation::new(op, operands)
+            })
+            .collect()
+    }
+
+    fn remove(kinds: PdfContentKinds) -> Vec<String> {
+        let mut images = HashSet::new();
+        images.insert("Im0".to_string());
+        let mut removal = ContentRemoval::new(kinds, images);
+        ops(&[
+            ("BT", None),
+            ("Tf", Some("F1")),
+            ("rg", None),
+            ("Tj", None),
+            ("'", None),
+            ("ET", None),
+            ("Do", Some("Im0")),
+            ("Do", Some("Fm0")),
+            ("re", None),
+            ("W", None),
+            ("f", None),
+        ])
+        .into_iter()
+        .filter_map(|mut op| {
+            if removal.keep(&mut op) {
+                Some(op.operator)
+            } else {
+                None
+            }
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_content_operation_validate() {
+        assert!(PdfContentOperation::new("T*", vec![]).validate().is_ok());
+        assert!(PdfContentOperation::new("'", vec![]).validate().is_ok());
+        assert!(PdfContentOperation::new("", vec![]).validate().is_err());
+        assert!(PdfContentOperation::new("Q q", vec![]).validate().is_err());
+        assert!(PdfContentOperation::new("f\n(x)", vec![])
+            .validate()
+            .is_err());
+        assert!(PdfContentOperation::new("EI", vec![]).validate().is_err());
+        assert!(PdfContentOperation::new("BI", vec![]).validate().is_err());
+    }
+
+    #[test]
+    fn test_content_removal() {
+        assert_eq!(
+            remove(PdfContentKinds::TEXT),
+            vec!["BT", "Tf", "rg", "ET", "Do", "Do", "re", "W", "f"]
+        );
+        assert_eq!(
+            remove(PdfContentKinds::IMAGES | PdfContentKinds::VECTOR),
+            vec!["BT", "Tf", "rg", "Tj", "'", "ET", "Do", "re", "W", "n"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use mupdf_sys::*;
+
+use crate::{context, Error, Matrix, PdfDocument, PdfObject, Rect};
+
+/// A named destination, resolved to its target page
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfDestination {
+    pub name: String,
+    /// 0-based index of the target page
+    pub page: i32,
+    /// Area of the target page to show, a single point for `/XYZ` destinations
+    pub rect: Rect,
+}
+
+/// Collect the named destinations of the catalog, both from the `/Names` `/Dests`
+/// name tree and from the PDF 1.1 style `/Dests` dictionary
+pub(crate) fn collect_named_dests(
+    catalog: &PdfObject,
+    out: &mut Vec<(String, PdfObject)>,
+) -> Result<(), Error> {
+    if let Some(dests) = catalog.get_dict("Dests")? {
+        for i in 0..dests.dict_len()? as i32 {
+            if let (Some(key), Some(val)) = (dests.get_dict_key(i)?, dests.get_dict_val(i)?) {
+                out.push((key.as_name()?.to_string(), val));
+            }
+        }
+    }
+    if let Some(names) = catalog.get_dict("Names")? {
+        if let Some(tree) = names.get_dict("Dests")? {
+            collect_name_tree(&tree, out, &mut HashSet::new())?;
+        }
+    }
+    Ok(())
+}
+
+/// Walk a name tree, skipping the nodes in `seen` so that a cycle of `/Kids` references ends
+fn collect_name_tree(
+    node: &PdfObject,
+    out: &mut Vec<(String, PdfObject)>,
+    seen: &mut HashSet<i32>,
+) -> Result<(), Error> {
+    if node.is_indirect()? && !seen.insert(node.as_indirect()?) {
+        return Ok(());
+    }
+    if let Some(names) = node.get_dict("Names")? {
+        let len = names.len()? as i32;
+        let mut i = 0;
+        while i + 1 < len {
+            if let (Some(key), Some(val)) = (names.get_array(i)?, names.get_array(i + 1)?) {
+                let name = key.as_string()?.to_string();
+                out.push((name, val));
+            }
+            i += 2;
+        }
+    }
+    if let Some(kids) = node.get_dict("Kids")? {
+        for i in 0..kids.len()? as i32 {
+            if let Some(kid) = kids.get_array(i)? {
+                collect_name_tree(&kid, out, seen)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Media box of a page object in PDF space, and the matrix mapping it to page space
+pub(crate) fn page_obj_transform(page: &PdfObject) -> Result<(Rect, Matrix), Error> {
+    let mut mediabox = fz_rect {
+        x0: 0.0,
+        y0: 0.0,
+        x1: 0.0,
+        y1: 0.0,
+    };
+    let ctm = unsafe {
+        ffi_try!(mupdf_pdf_page_obj_transform(
+            context(),
+            page.inner,
+            &mut mediabox
+        ))
+    };
+    Ok((mediabox.into(), ctm.into()))
+}
+
+fn number_at(array: &PdfObject, index: i32) -> Result<Option<f32>, Error> {
+    match array.get_array(index)? {
+        Some(n) if n.is_number()? => Ok(Some(n.as_float()?)),
+        _ => Ok(None),
+    }
+}
+
+/// Resolve a destination array, or a dictionary with a `/D` entry, to its page and target area
+pub(crate) fn resolve_dest(
+    doc: &PdfDocument,
+    name: String,
+    dest: &PdfObject,
+) -> Result<Option<PdfDestination>, Error> {
+    let dest = if dest.is_dict()? {
+        match dest.get_dict("D")? {
+            Some(d) => d,
+            None => return Ok(None),
+        }
+    } else {
+        match dest.resolve()? {
+            Some(d) => d,
+            None => return Ok(None),
+        }
+    };
+    if !dest.is_array()? {
+        return Ok(None);
+    }
+    let page_obj = match dest.get_array(0)? {
+        Some(p) if p.is_dict()? => p,
+        _ => return Ok(None),
+    };
+    let page = doc.page_number_of(&page_obj)?;
+    if page < 0 {
+        return Ok(None);
+    }
+
+    let (mb, ctm) = page_obj_transform(&page_obj)?;
+    let kind = match dest.get_array(1)? {
+        Some(k) if k.is_name()? => k.as_name()?.to_string(),
+        _ => String::new(),
+    };
+    // Coordinates left out or null keep the page edges
+    let rect = match kind.as_str() {
+        "XYZ" => {
+            let x = number_at(&dest, 2)?.unwrap_or(mb.x0);
+            let y = number_at(&dest, 3)?.unwrap_or(mb.y1);
+            Rect::new(x, y, x, y)
+        }
+        "FitH" | "FitBH" => {
+            let y = number_at(&dest, 2)?.unwrap_or(mb.y1);
+            Rect::new(mb.x0, y, mb.x1, y)
+        }
+        "FitV" | "FitBV" => {
+            let x = number_at(&dest, 2)?.unwrap_or(mb.x0);
+            Rect::new(x, mb.y0, x, mb.y1)
+        }
+        "FitR" => Rect::new(
+            number_at(&dest, 2)?.unwrap_or(mb.x0),
+            number_at(&dest, 3)?.unwrap_or(mb.y0),
+            number_at(&dest, 4)?.unwrap_or(mb.x1),
+            number_at(&dest, 5)?.unwrap_or(mb.y1),
+        ),
+        _ => mb,
+    };
+    Ok(Some(PdfDestination {
+        name,
+        page,
+        rect: rect.transform(&ctm),
+    }))
+}
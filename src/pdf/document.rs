@@ -1,15 +1,24 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::ffi::{CStr, CString};
+use std::fmt;
+use std::fs::File;
 use std::io::{self, Write};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Range};
 use std::ptr;
 
 use bitflags::bitflags;
 use mupdf_sys::*;
+use num_enum::TryFromPrimitive;
 
+use crate::pdf::{
+    article, destination, field, imposition, output_intent, page_label, pagination, watermark, xfdf,
+};
 use crate::{
-    context, Buffer, CjkFontOrdering, Document, Error, Font, Image, PdfGraftMap, PdfObject,
-    PdfPage, SimpleFontEncoding, Size, WriteMode,
+    context, Buffer, CjkFontOrdering, Colorspace, Document, Error, Font, Image, Outline,
+    PageLabelRange, Pagination, PdfArticleThread, PdfDestination, PdfDrawing, PdfField,
+    PdfGraftMap, PdfObject, PdfOutputIntent, PdfPage, PdfPageBuilder, Rect, SimpleFontEncoding,
+    Size, Watermark, WatermarkPosition, WriteMode,
 };
 
 bitflags! {
@@ -25,9 +34,67 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// How [`PdfDocument::flatten_form`] treats the form widgets
+    pub struct FlattenFormOptions: u32 {
+        /// Drop widgets that are not printed instead of drawing them
+        const PRINT_ONLY = 1;
+        /// Leave signature fields interactive, the AcroForm is kept for them
+        const KEEP_SIGNATURES = 1 << 1;
+    }
+}
+
+/// Encryption applied when writing a PDF file
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+pub enum EncryptionMethod {
+    /// Keep the encryption of the original document
+    Keep = PDF_ENCRYPT_KEEP as i32,
+    /// Write an unencrypted document
+    None = PDF_ENCRYPT_NONE as i32,
+    Rc4_40 = PDF_ENCRYPT_RC4_40 as i32,
+    Rc4_128 = PDF_ENCRYPT_RC4_128 as i32,
+    Aes128 = PDF_ENCRYPT_AES_128 as i32,
+    Aes256 = PDF_ENCRYPT_AES_256 as i32,
+}
+
+impl Default for EncryptionMethod {
+    fn default() -> Self {
+        Self::Keep
+    }
+}
+
+/// How thoroughly unused and duplicate objects are removed when writing a PDF file,
+/// each level includes the previous ones
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, TryFromPrimitive)]
+#[repr(i32)]
+pub enum GarbageLevel {
+    /// Write every object
+    None = 0,
+    /// Drop objects no longer referenced
+    Unused = 1,
+    /// Renumber objects to compact the cross-reference table
+    Compact = 2,
+    /// Merge identical objects
+    Deduplicate = 3,
+    /// Also merge objects whose streams are identical
+    DeduplicateStreams = 4,
+}
+
+impl Default for GarbageLevel {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Options controlling how [`PdfDocument::save_with_options`] and
+/// [`PdfDocument::write_to_with_options`] write a PDF file
+///
+/// Setters return `&mut Self` so they can be chained.
 #[derive(Clone, Copy)]
 pub struct PdfWriteOptions {
     inner: pdf_write_options,
+    deterministic: bool,
 }
 
 impl Default for PdfWriteOptions {
@@ -35,12 +102,51 @@ impl Default for PdfWriteOptions {
         unsafe {
             Self {
                 inner: pdf_default_write_options,
+                deterministic: false,
             }
         }
     }
 }
 
+impl fmt::Debug for PdfWriteOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PdfWriteOptions")
+            .field("incremental", &self.incremental())
+            .field("pretty", &self.pretty())
+            .field("ascii", &self.ascii())
+            .field("compress", &self.compress())
+            .field("compress_images", &self.compress_images())
+            .field("compress_fonts", &self.compress_fonts())
+            .field("decompress", &self.decompress())
+            .field("garbage", &self.garbage_level())
+            .field("linear", &self.linear())
+            .field("clean", &self.clean())
+            .field("sanitize", &self.sanitize())
+            .field("appearance", &self.appearance())
+            .field("encryption", &self.encryption())
+            .field("permissions", &self.permissions())
+            .field("deterministic", &self.deterministic())
+            .finish()
+    }
+}
+
+/// Copy `pwd` into a fixed size C buffer, truncated on a character boundary if it is too long
+fn copy_password(pwd: &str, dst: &mut [std::os::raw::c_char]) {
+    let mut len = pwd.len().min(dst.len() - 1);
+    while !pwd.is_char_boundary(len) {
+        len -= 1;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(pwd.as_ptr() as *const _, dst.as_mut_ptr(), len);
+    }
+    dst[len] = 0;
+}
+
 impl PdfWriteOptions {
+    /// Append changes to the end of the original file instead of rewriting it
+    ///
+    /// The output file must be the file the document was opened from, see
+    /// [`PdfDocument::save_incremental`].
     pub fn incremental(&self) -> bool {
         self.inner.do_incremental != 0
     }
@@ -50,6 +156,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Pretty-print objects with indentation
     pub fn pretty(&self) -> bool {
         self.inner.do_pretty != 0
     }
@@ -59,6 +166,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// ASCII hex encode binary streams
     pub fn ascii(&self) -> bool {
         self.inner.do_ascii != 0
     }
@@ -68,6 +176,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Compress all uncompressed streams
     pub fn compress(&self) -> bool {
         self.inner.do_compress != 0
     }
@@ -77,6 +186,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Compress uncompressed image streams
     pub fn compress_images(&self) -> bool {
         self.inner.do_compress_images != 0
     }
@@ -86,6 +196,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Compress uncompressed font streams
     pub fn compress_fonts(&self) -> bool {
         self.inner.do_compress_fonts != 0
     }
@@ -95,6 +206,10 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Decompress all streams, except images and fonts when compressing those
+    ///
+    /// Combined with [`set_pretty`](Self::set_pretty) this gives a human readable file, handy
+    /// for debugging content streams.
     pub fn decompress(&self) -> bool {
         self.inner.do_decompress != 0
     }
@@ -104,6 +219,8 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Remove unused objects before writing, see [`set_garbage_level`](Self::set_garbage_level)
+    /// for more thorough clean ups
     pub fn garbage(&self) -> bool {
         self.inner.do_garbage != 0
     }
@@ -113,7 +230,21 @@ impl PdfWriteOptions {
         self
     }
 
-    pub fn linear(self) -> bool {
+    pub fn garbage_level(&self) -> GarbageLevel {
+        GarbageLevel::try_from(self.inner.do_garbage.min(4)).unwrap_or_default()
+    }
+
+    pub fn set_garbage_level(&mut self, level: GarbageLevel) -> &mut Self {
+        self.inner.do_garbage = level as i32;
+        self
+    }
+
+    /// Write a linearized (web optimized) file
+    ///
+    /// Objects needed by the first page are written first, with hint tables after them, so
+    /// viewers can display it before the rest of the file is downloaded. Cannot be combined
+    /// with incremental saving.
+    pub fn linear(&self) -> bool {
         self.inner.do_linear != 0
     }
 
@@ -122,6 +253,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Rewrite page content streams in a normalized form, see [`PdfPage::clean_contents`]
     pub fn clean(&self) -> bool {
         self.inner.do_clean != 0
     }
@@ -131,6 +263,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Also drop invalid and invisible operators while cleaning content streams
     pub fn sanitize(&self) -> bool {
         self.inner.do_sanitize != 0
     }
@@ -140,6 +273,7 @@ impl PdfWriteOptions {
         self
     }
 
+    /// Create appearance streams for annotations that lack them
     pub fn appearance(&self) -> bool {
         self.inner.do_appearance != 0
     }
@@ -149,17 +283,19 @@ impl PdfWriteOptions {
         self
     }
 
-    pub fn encrypt(&self) -> bool {
-        self.inner.do_encrypt != 0
+    /// Encryption method, the passwords and permissions are only used when encrypting
+    pub fn encryption(&self) -> EncryptionMethod {
+        EncryptionMethod::try_from(self.inner.do_encrypt).unwrap_or_default()
     }
 
-    pub fn set_encrypt(&mut self, value: bool) -> &mut Self {
-        self.inner.do_encrypt = if value { 1 } else { 0 };
+    pub fn set_encryption(&mut self, method: EncryptionMethod) -> &mut Self {
+        self.inner.do_encrypt = method as i32;
         self
     }
 
+    /// Permissions granted to users opening an encrypted document with the user password
     pub fn permissions(&self) -> Permission {
-        Permission::from_bits(self.inner.permissions as u32).unwrap()
+        Permission::from_bits_truncate(self.inner.permissions as u32)
     }
 
     pub fn set_permissions(&mut self, value: Permission) -> &mut Self {
@@ -172,12 +308,9 @@ impl PdfWriteOptions {
         c_pwd.to_str().unwrap()
     }
 
+    /// Set the owner password, truncated to 127 bytes
     pub fn set_owner_password(&mut self, pwd: &str) -> &mut Self {
-        let len = pwd.len() + 1;
-        let c_pwd = CString::new(pwd).unwrap();
-        unsafe {
-            ptr::copy_nonoverlapping(c_pwd.as_ptr(), self.inner.opwd_utf8.as_mut_ptr(), len);
-        }
+        copy_password(pwd, &mut self.inner.opwd_utf8);
         self
     }
 
@@ -186,19 +319,33 @@ impl PdfWriteOptions {
         c_pwd.to_str().unwrap()
     }
 
+    /// Set the user password, truncated to 127 bytes
     pub fn set_user_password(&mut self, pwd: &str) -> &mut Self {
-        let len = pwd.len() + 1;
-        let c_pwd = CString::new(pwd).unwrap();
-        unsafe {
-            ptr::copy_nonoverlapping(c_pwd.as_ptr(), self.inner.upwd_utf8.as_mut_ptr(), len);
-        }
+        copy_password(pwd, &mut self.inner.upwd_utf8);
+        self
+    }
+
+    /// Derive the document `/ID` from the written content, so the same document always gives
+    /// the same bytes
+    ///
+    /// The changing `/ID` string of the output is the MD5 digest of the output, the permanent
+    /// one is kept from the document or also taken from the digest. The document itself, its
+    /// trailer included, is not modified. Dates in the `/Info` dictionary are written as they
+    /// are. AES encryption, 128 or 256 bit, starts every string and stream with a random IV
+    /// and is never reproducible. Deterministic writes cannot be incremental.
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn set_deterministic(&mut self, value: bool) -> &mut Self {
+        self.deterministic = value;
         self
     }
 }
 
 #[derive(Debug)]
 pub struct PdfDocument {
-    inner: *mut pdf_document,
+    pub(crate) inner: *mut pdf_document,
     doc: Document,
 }
 
@@ -251,6 +398,10 @@ impl PdfDocument {
         PdfObject::new_string(s)
     }
 
+    pub fn new_byte_string(&self, bytes: &[u8]) -> Result<PdfObject, Error> {
+        PdfObject::new_byte_string(bytes)
+    }
+
     pub fn new_name(&self, name: &str) -> Result<PdfObject, Error> {
         PdfObject::new_name(name)
     }
@@ -276,10 +427,11 @@ impl PdfDocument {
         }
     }
 
+    /// A graft map for copying objects and pages from other documents into this one
     pub fn new_graft_map(&self) -> Result<PdfGraftMap, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_new_graft_map(context(), self.inner));
-            Ok(PdfGraftMap::from_raw(inner))
+            Ok(PdfGraftMap::from_raw(inner, self.inner))
         }
     }
 
@@ -302,6 +454,169 @@ impl PdfDocument {
         }
     }
 
+    /// Copy page `page_from` of `src` into this document at `page_to`, `-1` appends it
+    ///
+    /// Use a [`PdfGraftMap`] instead when copying several pages from the same document.
+    pub fn graft_page(
+        &mut self,
+        page_to: i32,
+        src: &PdfDocument,
+        page_from: i32,
+    ) -> Result<(), Error> {
+        let mut map = self.new_graft_map()?;
+        map.graft_page(page_to, src, page_from)
+    }
+
+    /// Append the pages in the 0-based index range `pages` of `src` to this document
+    ///
+    /// Resources shared by those pages, such as fonts and images, are copied only once.
+    /// A range outside the pages of `src` fails with [`Error::InvalidArgument`] before anything
+    /// is copied, but a page of a damaged `src` failing to copy leaves the pages before it
+    /// appended.
+    pub fn append(&mut self, src: &PdfDocument, pages: Range<i32>) -> Result<(), Error> {
+        let page_count = src.page_count()?;
+        if pages.start < 0 || pages.end > page_count {
+            return Err(Error::InvalidArgument(format!(
+                "page range {:?} is outside the {} pages of the source document",
+                pages, page_count
+            )));
+        }
+        let mut map = self.new_graft_map()?;
+        for page_no in pages {
+            map.graft_page(-1, src, page_no)?;
+        }
+        Ok(())
+    }
+
+    /// A new document holding the pages in the 0-based index `ranges`, in order
+    ///
+    /// Only the objects those pages depend on are copied, each of them once.
+    pub fn extract_pages(&self, ranges: &[Range<i32>]) -> Result<PdfDocument, Error> {
+        let mut extracted = Self::new();
+        let mut map = extracted.new_graft_map()?;
+        for range in ranges {
+            for page_no in range.clone() {
+                map.graft_page(-1, self, page_no)?;
+            }
+        }
+        drop(map);
+        Ok(extracted)
+    }
+
+    /// A form XObject of `size` drawing `drawing`, to show on many pages with
+    /// [`PdfPage::show_xobject`] while storing it once
+    ///
+    /// Drawing coordinates start at the top left corner of the form, with y going down.
+    /// Fails with [`Error::InvalidArgument`] if `drawing` was given an invalid color.
+    pub fn new_form_xobject<T: Into<Size>>(
+        &mut self,
+        size: T,
+        drawing: &PdfDrawing,
+    ) -> Result<PdfObject, Error> {
+        if let Some(msg) = &drawing.error {
+            return Err(Error::InvalidArgument(msg.clone()));
+        }
+        let size = size.into();
+        let bbox = Rect::new(0.0, 0.0, size.width, size.height);
+        let content = format!("1 0 0 -1 0 {} cm\n{}", size.height, drawing.content);
+        let c_content = CString::new(content)?;
+        let mut xobj = unsafe {
+            let inner = ffi_try!(mupdf_pdf_new_form_xobject(
+                context(),
+                self.inner,
+                bbox.into(),
+                c_content.as_ptr()
+            ));
+            PdfObject::from_raw(inner, true)
+        };
+        for (name, fill, stroke) in &drawing.alphas {
+            xobj.add_alpha_gstate(name, *fill, *stroke)?;
+        }
+        Ok(xobj)
+    }
+
+    /// A form XObject drawing page `page_no` of `src`, see
+    /// [`PdfGraftMap::graft_page_as_xobject`]
+    pub fn new_form_xobject_from_page(
+        &mut self,
+        src: &PdfDocument,
+        page_no: i32,
+    ) -> Result<PdfObject, Error> {
+        let mut map = self.new_graft_map()?;
+        let (xobj, _) = map.graft_page_as_xobject(src, page_no)?;
+        Ok(xobj)
+    }
+
+    /// Write the headers and footers of `pagination` on every page
+    pub fn paginate(&mut self, pagination: &Pagination) -> Result<(), Error> {
+        pagination::paginate(self, pagination)
+    }
+
+    /// Stamp `watermark` on every page, rotated by `rotation` degrees counterclockwise
+    ///
+    /// The watermark is added once as a form XObject shared by all pages and drawn with
+    /// `opacity`, from 0 for transparent to 1 for opaque, on top of the page content or
+    /// below it when `underlay` is set. Corner positions keep half an inch from the page edges.
+    pub fn stamp_watermark(
+        &mut self,
+        watermark: &Watermark,
+        position: WatermarkPosition,
+        opacity: f32,
+        rotation: f32,
+        underlay: bool,
+    ) -> Result<(), Error> {
+        watermark::stamp(self, watermark, position, opacity, rotation, underlay)
+    }
+
+    /// A new document laid out as a saddle-stitched booklet, for duplex printing, folding and
+    /// stapling in the middle
+    ///
+    /// Each output page is a sheet side holding two pages side by side, sized twice as wide
+    /// as the first page. Pages are padded with blanks to a multiple of four.
+    pub fn booklet(&self) -> Result<PdfDocument, Error> {
+        imposition::booklet(self)
+    }
+
+    /// A new document splitting each page into a grid of `tile` sized pages, for printing
+    /// large pages on a small printer
+    ///
+    /// Neighbouring tiles share `overlap` points along their edges to leave room for gluing,
+    /// it is clamped to half the tile size. Tiles are ordered row by row from the top left
+    /// corner, pages that already fit are copied as a single tile. A tile without a positive
+    /// width and height is an [`Error::InvalidArgument`].
+    pub fn poster<T: Into<Size>>(&self, tile: T, overlap: f32) -> Result<PdfDocument, Error> {
+        imposition::poster(self, tile.into(), overlap)
+    }
+
+    /// Annotations and form field values as an XFDF document, for exchange with other PDF
+    /// review tools
+    ///
+    /// Link, widget and file attachment annotations are left out, popups are written inside
+    /// the annotation they belong to and replies name their parent in `inreplyto`. Form
+    /// fields are written under their fully qualified names. Coordinates are in PDF space as
+    /// XFDF requires.
+    pub fn export_xfdf(&self) -> Result<String, Error> {
+        xfdf::export(self)
+    }
+
+    /// Add the annotations of an XFDF document and set the form field values it holds
+    ///
+    /// Fields are matched by their fully qualified name, unknown ones are ignored. Pages are
+    /// numbered from 0.
+    pub fn import_xfdf(&mut self, xfdf: &str) -> Result<(), Error> {
+        xfdf::import(self, xfdf)
+    }
+
+    /// A new document holding all pages of `docs`, one after the other
+    pub fn merge(docs: &[&PdfDocument]) -> Result<Self, Error> {
+        let mut merged = Self::new();
+        for doc in docs {
+            merged.append(doc, 0..doc.page_count()?)?;
+        }
+        Ok(merged)
+    }
+
+    /// Store `obj` as a new indirect object and return a reference to it
     pub fn add_object(&mut self, obj: &PdfObject) -> Result<PdfObject, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_add_object(context(), self.inner, obj.inner));
@@ -309,6 +624,7 @@ impl PdfDocument {
         }
     }
 
+    /// Allocate a new, empty indirect object and return a reference to it
     pub fn create_object(&mut self) -> Result<PdfObject, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_create_object(context(), self.inner));
@@ -316,6 +632,20 @@ impl PdfDocument {
         }
     }
 
+    /// Replace indirect object `num` with `obj`, keeping every reference to it valid
+    pub fn update_object(&mut self, num: i32, obj: &PdfObject) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_update_object(
+                context(),
+                self.inner,
+                num,
+                obj.inner
+            ));
+        }
+        Ok(())
+    }
+
+    /// Free indirect object `num`, references to it resolve to null afterwards
     pub fn delete_object(&mut self, num: i32) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_delete_object(context(), self.inner, num));
@@ -386,6 +716,16 @@ impl PdfDocument {
     }
 
     pub fn save_with_options(&self, filename: &str, options: PdfWriteOptions) -> Result<(), Error> {
+        if options.deterministic {
+            if options.incremental() {
+                return Err(Error::InvalidArgument(
+                    "deterministic writes cannot be incremental".to_string(),
+                ));
+            }
+            let mut buf = self.write_with_options(options)?;
+            io::copy(&mut buf, &mut File::create(filename)?)?;
+            return Ok(());
+        }
         let c_name = CString::new(filename)?;
         unsafe {
             ffi_try!(mupdf_pdf_save_document(
@@ -424,6 +764,7 @@ impl PdfDocument {
         Ok(())
     }
 
+    /// The trailer dictionary, holding `/Root`, `/Info`, `/ID` and the encryption dictionary.
     pub fn trailer(&self) -> Result<PdfObject, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_trailer(context(), self.inner));
@@ -431,6 +772,8 @@ impl PdfDocument {
         }
     }
 
+    /// The document catalog (`/Root`), the way into everything not modelled by this crate,
+    /// such as `/ViewerPreferences`, `/AcroForm` or the `/Names` trees.
     pub fn catalog(&self) -> Result<PdfObject, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_catalog(context(), self.inner));
@@ -438,6 +781,16 @@ impl PdfDocument {
         }
     }
 
+    /// Iterate over the objects in use, as `(object number, indirect reference)` pairs.
+    pub fn objects(&self) -> Result<PdfObjectIter<'_>, Error> {
+        Ok(PdfObjectIter {
+            num: 1,
+            len: self.count_objects()? as i32,
+            doc: self,
+        })
+    }
+
+    /// Number of entries in the cross-reference table, including free ones.
     pub fn count_objects(&self) -> Result<u32, Error> {
         let count = unsafe { ffi_try!(mupdf_pdf_count_objects(context(), self.inner)) };
         Ok(count as u32)
@@ -455,6 +808,46 @@ impl PdfDocument {
         Ok(false)
     }
 
+    /// Set the fields of the form back to their default values like a `ResetForm` action:
+    /// all of them, those named in `names` and their descendants, or with `exclude` all
+    /// but those
+    ///
+    /// Returns the number of terminal fields reset.
+    pub fn reset_form(&mut self, names: Option<&[&str]>, exclude: bool) -> Result<usize, Error> {
+        let mut count = 0;
+        for mut field in self.form_fields()? {
+            if let Some(names) = names {
+                let name = field.name()?;
+                let named = names.iter().any(|n| {
+                    name == *n || (name.starts_with(*n) && name[n.len()..].starts_with('.'))
+                });
+                if named == exclude {
+                    continue;
+                }
+            }
+            field.reset()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Draw the appearances of the visible form widgets into the page contents and remove
+    /// the widgets and the AcroForm, leaving a document that can't be filled in any more
+    ///
+    /// Appearances needing it are regenerated first. Returns the number of widgets drawn, pages
+    /// loaded before keep their old widgets until loaded again.
+    pub fn flatten_form(&mut self, options: FlattenFormOptions) -> Result<usize, Error> {
+        let count = unsafe {
+            ffi_try!(mupdf_pdf_flatten_form(
+                context(),
+                self.inner,
+                options.contains(FlattenFormOptions::PRINT_ONLY),
+                options.contains(FlattenFormOptions::KEEP_SIGNATURES)
+            ))
+        };
+        Ok(count as usize)
+    }
+
     pub fn has_xfa_form(&self) -> Result<bool, Error> {
         let trailer = self.trailer()?;
         if let Some(root) = trailer.get_dict("Root")? {
@@ -467,6 +860,17 @@ impl PdfDocument {
         Ok(false)
     }
 
+    /// The terminal fields of the interactive form in field tree order, empty without one
+    pub fn form_fields(&self) -> Result<Vec<PdfField>, Error> {
+        let mut fields = Vec::new();
+        if let Some(form) = self.catalog()?.get_dict("AcroForm")? {
+            if let Some(roots) = form.get_dict("Fields")? {
+                field::collect_fields(&roots, &mut fields)?;
+            }
+        }
+        Ok(fields)
+    }
+
     pub fn has_xref_streams(&self) -> bool {
         unsafe { (*self.inner).has_xref_streams > 0 }
     }
@@ -484,13 +888,31 @@ impl PdfDocument {
         self.save_with_options(filename, PdfWriteOptions::default())
     }
 
+    /// Append the changes made since opening to `filename` as an incremental update
+    ///
+    /// `filename` must be the file the document was opened from. Its original bytes, and any
+    /// signatures covering them, are left untouched and only an update section is appended.
+    pub fn save_incremental(&self, filename: &str) -> Result<(), Error> {
+        let mut options = PdfWriteOptions::default();
+        options.set_incremental(true);
+        self.save_with_options(filename, options)
+    }
+
     fn write_with_options(&self, options: PdfWriteOptions) -> Result<Buffer, Error> {
         unsafe {
-            let buf = ffi_try!(mupdf_pdf_write_document(
-                context(),
-                self.inner,
-                options.inner
-            ));
+            let buf = if options.deterministic {
+                ffi_try!(mupdf_pdf_write_document_deterministic(
+                    context(),
+                    self.inner,
+                    options.inner
+                ))
+            } else {
+                ffi_try!(mupdf_pdf_write_document(
+                    context(),
+                    self.inner,
+                    options.inner
+                ))
+            };
             Ok(Buffer::from_raw(buf))
         }
     }
@@ -508,6 +930,7 @@ impl PdfDocument {
         self.write_to_with_options(w, PdfWriteOptions::default())
     }
 
+    /// Page object of the page at 0-based index `page_no`.
     pub fn find_page(&self, page_no: i32) -> Result<PdfObject, Error> {
         unsafe {
             let inner = ffi_try!(mupdf_pdf_lookup_page_obj(context(), self.inner, page_no));
@@ -515,6 +938,243 @@ impl PdfDocument {
         }
     }
 
+    /// 0-based index of the page object `page`, the inverse of [`find_page`](Self::find_page)
+    ///
+    /// Fails when `page` is not a page object.
+    pub fn page_number_of(&self, page: &PdfObject) -> Result<i32, Error> {
+        let page_no = unsafe {
+            ffi_try!(mupdf_pdf_lookup_page_number(
+                context(),
+                self.inner,
+                page.inner
+            ))
+        };
+        Ok(page_no)
+    }
+
+    /// Named destinations of the document, with their target pages and areas in page space.
+    ///
+    /// Destinations pointing outside of the page tree are skipped.
+    pub fn named_destinations(&self) -> Result<Vec<PdfDestination>, Error> {
+        let mut entries = Vec::new();
+        destination::collect_named_dests(&self.catalog()?, &mut entries)?;
+        let mut dests = Vec::with_capacity(entries.len());
+        for (name, dest) in entries {
+            if let Some(dest) = destination::resolve_dest(self, name, &dest)? {
+                dests.push(dest);
+            }
+        }
+        Ok(dests)
+    }
+
+    /// Look up a named destination, e.g. the target of a `#nameddest=` link.
+    pub fn find_named_destination(&self, name: &str) -> Result<Option<PdfDestination>, Error> {
+        Ok(self
+            .named_destinations()?
+            .into_iter()
+            .find(|dest| dest.name == name))
+    }
+
+    /// The catalog `/OpenAction`, a destination array or an action dictionary.
+    pub fn open_action(&self) -> Result<Option<PdfObject>, Error> {
+        self.catalog()?.get_dict("OpenAction")
+    }
+
+    pub fn set_open_action(&mut self, action: PdfObject) -> Result<(), Error> {
+        self.catalog()?.dict_put("OpenAction", action)
+    }
+
+    /// Destination the document should be shown at when opened, for `/OpenAction`
+    /// entries that are destinations or `/GoTo` actions.
+    pub fn open_action_destination(&self) -> Result<Option<PdfDestination>, Error> {
+        let action = match self.open_action()? {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+        if action.is_dict()? {
+            match action.get_dict("S")? {
+                Some(s) if s.is_name()? && s.as_name()? == "GoTo" => {}
+                _ => return Ok(None),
+            }
+            if let Some(dest) = action.get_dict("D")? {
+                if dest.is_name()? {
+                    return self.find_named_destination(dest.as_name()?);
+                }
+                if dest.is_string()? {
+                    return self.find_named_destination(dest.as_string()?);
+                }
+            }
+        }
+        destination::resolve_dest(self, String::new(), &action)
+    }
+
+    /// The catalog `/AA` additional-actions dictionary, triggered on document events
+    /// such as closing, saving or printing.
+    pub fn additional_actions(&self) -> Result<Option<PdfObject>, Error> {
+        self.catalog()?.get_dict("AA")
+    }
+
+    /// Remove the actions that run without user interaction: the catalog `/OpenAction`,
+    /// the document level scripts in `/Names/JavaScript` and the `/AA` entries of the
+    /// catalog, of every page and of every annotation and widget.
+    ///
+    /// Returns the number of entries removed.
+    pub fn remove_automatic_actions(&mut self) -> Result<usize, Error> {
+        let mut removed = 0;
+        let mut catalog = self.catalog()?;
+        for key in &["OpenAction", "AA"] {
+            if catalog.get_dict(key)?.is_some() {
+                catalog.dict_delete(*key)?;
+                removed += 1;
+            }
+        }
+        if let Some(mut names) = catalog.get_dict("Names")? {
+            if names.get_dict("JavaScript")?.is_some() {
+                names.dict_delete("JavaScript")?;
+                removed += 1;
+            }
+        }
+        for page_no in 0..self.page_count()? {
+            let mut page = self.find_page(page_no)?;
+            if page.get_dict("AA")?.is_some() {
+                page.dict_delete("AA")?;
+                removed += 1;
+            }
+            let annots = match page.get_dict("Annots")? {
+                Some(annots) if annots.is_array()? => annots,
+                _ => continue,
+            };
+            for i in 0..annots.len()? {
+                if let Some(mut annot) = annots.get_array(i as i32)? {
+                    if annot.is_dict()? && annot.get_dict("AA")?.is_some() {
+                        annot.dict_delete("AA")?;
+                        removed += 1;
+                    }
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Article threads of the document, with their beads in reading order.
+    pub fn article_threads(&self) -> Result<Vec<PdfArticleThread>, Error> {
+        article::load_threads(self)
+    }
+
+    /// Output intents of the document, with their embedded ICC profiles.
+    pub fn output_intents(&self) -> Result<Vec<PdfOutputIntent>, Error> {
+        output_intent::load_output_intents(&self.catalog()?)
+    }
+
+    /// Page label ranges of the document, sorted by start page.
+    pub fn page_labels(&self) -> Result<Vec<PageLabelRange>, Error> {
+        page_label::load_page_labels(&self.catalog()?)
+    }
+
+    /// Replace the page labels of the document, an empty slice removes them.
+    pub fn set_page_labels(&mut self, ranges: &[PageLabelRange]) -> Result<(), Error> {
+        let mut catalog = self.catalog()?;
+        if ranges.is_empty() {
+            if catalog.get_dict("PageLabels")?.is_some() {
+                catalog.dict_delete("PageLabels")?;
+            }
+            return Ok(());
+        }
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by_key(|r| r.start_page);
+        let mut nums = self.new_array()?;
+        for range in &ranges {
+            nums.array_push(self.new_int(range.start_page)?)?;
+            nums.array_push(range.to_dict(self)?)?;
+        }
+        let mut labels = self.new_dict()?;
+        labels.dict_put("Nums", nums)?;
+        catalog.dict_put("PageLabels", labels)?;
+        Ok(())
+    }
+
+    /// Replace the table of contents with `toc`, an empty slice removes it.
+    ///
+    /// Items with a page index link to that page at the point `(x, y)`, other items
+    /// with a `uri` link to it as an external URL. MuPDF 1.16 has no API to edit single
+    /// outline items, so the whole tree is rewritten and the objects of the old one deleted.
+    pub fn set_outlines(&mut self, toc: &[Outline]) -> Result<(), Error> {
+        self.delete_outlines()?;
+        if toc.is_empty() {
+            return Ok(());
+        }
+        let mut outlines = self.new_dict()?;
+        outlines.dict_put("Type", self.new_name("Outlines")?)?;
+        let outlines = self.add_object(&outlines)?;
+        self.write_outline_items(&outlines, toc)?;
+        self.catalog()?.dict_put("Outlines", outlines)?;
+        Ok(())
+    }
+
+    /// Remove the table of contents and delete the objects of its items
+    pub fn delete_outlines(&mut self) -> Result<(), Error> {
+        let mut catalog = self.catalog()?;
+        let outlines = match catalog.get_dict("Outlines")? {
+            Some(outlines) => outlines,
+            None => return Ok(()),
+        };
+        let mut nums = HashSet::new();
+        collect_outline_items(&outlines, &mut nums)?;
+        catalog.dict_delete("Outlines")?;
+        for num in nums {
+            self.delete_object(num)?;
+        }
+        Ok(())
+    }
+
+    /// Write `items` as the children of the outline node `parent`, returning the number
+    /// of visible descendants.
+    fn write_outline_items(&mut self, parent: &PdfObject, items: &[Outline]) -> Result<i32, Error> {
+        let mut nums = Vec::with_capacity(items.len());
+        for _ in items {
+            let dict = self.new_dict()?;
+            nums.push(self.add_object(&dict)?.as_indirect()?);
+        }
+        let parent_num = parent.as_indirect()?;
+        let mut count = items.len() as i32;
+        for (i, item) in items.iter().enumerate() {
+            let mut node = self.new_indirect(nums[i], 0)?;
+            node.dict_put("Title", self.new_string(&item.title)?)?;
+            node.dict_put("Parent", self.new_indirect(parent_num, 0)?)?;
+            if i > 0 {
+                node.dict_put("Prev", self.new_indirect(nums[i - 1], 0)?)?;
+            }
+            if i + 1 < nums.len() {
+                node.dict_put("Next", self.new_indirect(nums[i + 1], 0)?)?;
+            }
+            if item.page >= 0 {
+                node.dict_put("Dest", self.new_dest(item.page, item.x, item.y)?)?;
+            } else if !item.uri.is_empty() {
+                let mut action = self.new_dict()?;
+                action.dict_put("S", self.new_name("URI")?)?;
+                action.dict_put("URI", self.new_string(&item.uri)?)?;
+                node.dict_put("A", action)?;
+            }
+            if !item.down.is_empty() {
+                count += self.write_outline_items(&node, &item.down)?;
+            }
+        }
+        let mut parent = self.new_indirect(parent_num, 0)?;
+        parent.dict_put("First", self.new_indirect(nums[0], 0)?)?;
+        parent.dict_put("Last", self.new_indirect(nums[nums.len() - 1], 0)?)?;
+        parent.dict_put("Count", self.new_int(count)?)?;
+        Ok(count)
+    }
+
+    /// Destination array for the 0-based page `page_no` at the page space point `(x, y)`.
+    fn new_dest(&self, page_no: i32, x: f32, y: f32) -> Result<PdfObject, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_pdf_new_dest(context(), self.inner, page_no, x, y));
+            Ok(PdfObject::from_raw(inner, true))
+        }
+    }
+
+    /// Create a new page and insert it before the 0-based index `page_no`, `-1` appends.
     pub fn new_page_at<T: Into<Size>>(&mut self, page_no: i32, size: T) -> Result<PdfPage, Error> {
         let size = size.into();
         unsafe {
@@ -533,6 +1193,25 @@ impl PdfDocument {
         self.new_page_at(-1, size)
     }
 
+    /// Start drawing a new page to insert before the 0-based index `page_no`, `-1` appends.
+    ///
+    /// The page is added when [`PdfPageBuilder::finish`] is called.
+    pub fn begin_page_at<T: Into<Size>>(
+        &mut self,
+        page_no: i32,
+        size: T,
+    ) -> Result<PdfPageBuilder<'_>, Error> {
+        let size = size.into();
+        let media_box = Rect::new(0.0, 0.0, size.width, size.height);
+        PdfPageBuilder::new(self, page_no, media_box)
+    }
+
+    /// Start drawing a new page to append, see [`begin_page_at`](Self::begin_page_at).
+    pub fn begin_page<T: Into<Size>>(&mut self, size: T) -> Result<PdfPageBuilder<'_>, Error> {
+        self.begin_page_at(-1, size)
+    }
+
+    /// Insert the page object `page` before the page at 0-based index `page_no`, `-1` appends.
     pub fn insert_page(&mut self, page_no: i32, page: &PdfObject) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_insert_page(
@@ -545,12 +1224,208 @@ impl PdfDocument {
         Ok(())
     }
 
+    /// Delete the page at 0-based index `page_no`.
     pub fn delete_page(&mut self, page_no: i32) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_delete_page(context(), self.inner, page_no));
         }
         Ok(())
     }
+
+    /// Delete the pages in the 0-based index range `pages`.
+    pub fn delete_pages(&mut self, pages: Range<i32>) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_delete_page_range(
+                context(),
+                self.inner,
+                pages.start,
+                pages.end
+            ));
+        }
+        Ok(())
+    }
+
+    /// Keep only the pages at the 0-based indices in `order`, in that order.
+    ///
+    /// A page listed several times is copied, the copies share its contents and resources
+    /// but get their own copies of its annotations, without popups. Links and outline
+    /// entries pointing at dropped pages are left dangling. `order` must not be empty.
+    pub fn rearrange_pages(&mut self, order: &[i32]) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_rearrange_pages(
+                context(),
+                self.inner,
+                order.as_ptr(),
+                order.len() as i32
+            ));
+        }
+        Ok(())
+    }
+
+    /// Convert the colors of every page to `cs`, which must be a gray, RGB or CMYK colorspace.
+    ///
+    /// Device colors set in the page content streams are rewritten, and page images are
+    /// re-encoded in `cs`. Patterns, shadings, spot colors and form XObjects are left as they are.
+    pub fn recolor(&mut self, cs: &Colorspace) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_recolor(context(), self.inner, cs.inner));
+        }
+        Ok(())
+    }
+
+    /// Downsample images drawn at more than `max_dpi`, returning how many were rewritten.
+    ///
+    /// Resolutions are measured where images are placed in page and form XObject content
+    /// streams, an image used several times is judged by its largest placement. Images are
+    /// subsampled by powers of two so they stay at or above `max_dpi`, and stored Flate compressed
+    /// in place. Stencil masks, color key masked images and JPX images carrying their own alpha
+    /// are left as they are, images with a separate `/SMask` are downsampled but keep their mask
+    /// untouched. JPEG recompression is not available. Save with a [`GarbageLevel`] to drop data
+    /// no longer used, such as the palettes of indexed images.
+    pub fn downsample_images(&mut self, max_dpi: f32) -> Result<usize, Error> {
+        let count =
+            unsafe { ffi_try!(mupdf_pdf_downsample_images(context(), self.inner, max_dpi)) };
+        Ok(count as usize)
+    }
+}
+
+#[derive(Debug)]
+pub struct PdfObjectIter<'a> {
+    num: i32,
+    len: i32,
+    doc: &'a PdfDocument,
+}
+
+impl PdfObjectIter<'_> {
+    /// Generation of the object `num`, `None` for free entries
+    fn entry_in_use(&self, num: i32) -> Result<Option<i32>, Error> {
+        let mut gen = 0;
+        let t = unsafe {
+            ffi_try!(mupdf_pdf_xref_entry_type(
+                context(),
+                self.doc.inner,
+                num,
+                &mut gen
+            ))
+        };
+        if t as u8 == b'n' || t as u8 == b'o' {
+            Ok(Some(gen))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Iterator for PdfObjectIter<'_> {
+    type Item = Result<(i32, PdfObject), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.num < self.len {
+            let num = self.num;
+            self.num += 1;
+            match self.entry_in_use(num) {
+                Ok(Some(gen)) => {
+                    return Some(self.doc.new_indirect(num, gen).map(|obj| (num, obj)))
+                }
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl PdfDocument {
+    /// Build an object from a [`serde_json::Value`], the inverse of [`PdfObject::to_json`]
+    ///
+    /// Objects whose only member is a `"name"` string become names, those whose only member is
+    /// a `"ref"` string of the form `"N G R"` become indirect references. Strings are always
+    /// PDF text strings.
+    pub fn object_from_json(&self, value: &serde_json::Value) -> Result<PdfObject, Error> {
+        use serde_json::Value;
+
+        match value {
+            Value::Null => Ok(self.new_null()),
+            Value::Bool(b) => Ok(self.new_bool(*b)),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) if i >= i32::MIN as i64 && i <= i32::MAX as i64 => self.new_int(i as i32),
+                _ => self.new_real(n.as_f64().unwrap_or_default() as f32),
+            },
+            Value::String(s) => self.new_string(s),
+            Value::Array(items) => {
+                let mut array = self.new_array()?;
+                for item in items {
+                    array.array_push(self.object_from_json(item)?)?;
+                }
+                Ok(array)
+            }
+            Value::Object(map) => {
+                if map.len() == 1 {
+                    if let Some(name) = map.get("name").and_then(Value::as_str) {
+                        return self.new_name(name);
+                    }
+                    let reference = map.get("ref").and_then(Value::as_str);
+                    if let Some((num, gen)) = reference.and_then(parse_reference) {
+                        return self.new_indirect(num, gen);
+                    }
+                }
+                let mut dict = self.new_dict()?;
+                for (key, val) in map {
+                    dict.dict_put(key.as_str(), self.object_from_json(val)?)?;
+                }
+                Ok(dict)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl PdfDocument {
+    /// Annotations of all pages as a JSON array, for syncing markup with web front-ends
+    ///
+    /// Each element is a serialized [`JsonAnnotation`](crate::JsonAnnotation). Link, widget,
+    /// popup and file attachment annotations are left out.
+    pub fn annotations_to_json(&self) -> Result<serde_json::Value, Error> {
+        crate::pdf::annotation_json::export(self)
+    }
+
+    /// Add the annotations of a JSON array in the format of
+    /// [`annotations_to_json`](Self::annotations_to_json), returns how many were added
+    ///
+    /// Elements that are not objects or have an unknown `type` are skipped, objects that do
+    /// not match [`JsonAnnotation`](crate::JsonAnnotation) are an
+    /// [`Error::InvalidArgument`].
+    pub fn import_annotations_json(&mut self, value: &serde_json::Value) -> Result<usize, Error> {
+        crate::pdf::annotation_json::import(self, value)
+    }
+}
+
+/// Object numbers of the outline node `node` and of all its descendants
+fn collect_outline_items(node: &PdfObject, nums: &mut HashSet<i32>) -> Result<(), Error> {
+    if node.is_indirect()? && !nums.insert(node.as_indirect()?) {
+        return Ok(());
+    }
+    let mut child = node.get_dict("First")?;
+    while let Some(item) = child {
+        if item.is_indirect()? && nums.contains(&item.as_indirect()?) {
+            break;
+        }
+        collect_outline_items(&item, nums)?;
+        child = item.get_dict("Next")?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde_json")]
+fn parse_reference(s: &str) -> Option<(i32, i32)> {
+    let mut parts = s.split(' ');
+    let num = parts.next()?.parse().ok()?;
+    let gen = parts.next()?.parse().ok()?;
+    if parts.next() != Some("R") || parts.next().is_some() {
+        return None;
+    }
+    Some((num, gen))
 }
 
 impl Deref for PdfDocument {
@@ -590,7 +1465,8 @@ impl<'a> IntoIterator for &'a PdfDocument {
 
 #[cfg(test)]
 mod test {
-    use super::{PdfDocument, PdfWriteOptions, Permission};
+    use super::{EncryptionMethod, GarbageLevel, PdfDocument, PdfWriteOptions, Permission};
+    use crate::PdfFieldType;
 
     #[test]
     fn test_pdf_write_options_passwords() {
@@ -605,6 +1481,191 @@ mod test {
         let user_pwd = options.user_password();
         assert_eq!(owner_pwd, "abc");
         assert_eq!(user_pwd, "def");
+
+        let long = "\u{e9}".repeat(100);
+        options.set_owner_password(&long);
+        assert_eq!(options.owner_password(), &long[..126]);
+    }
+
+    #[test]
+    fn test_pdf_write_options() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut options = PdfWriteOptions::default();
+        options
+            .set_garbage(true)
+            .set_compress(true)
+            .set_clean(true)
+            .set_sanitize(true)
+            .set_pretty(true);
+        assert!(options.garbage() && options.compress() && options.pretty());
+        assert!(!options.linear());
+
+        let mut output = Vec::new();
+        doc.write_to_with_options(&mut output, options).unwrap();
+        let written = PdfDocument::from_bytes(&output).unwrap();
+        assert_eq!(written.page_count().unwrap(), doc.page_count().unwrap());
+    }
+
+    #[test]
+    fn test_pdf_write_options_decompress() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut options = PdfWriteOptions::default();
+        options.set_decompress(true).set_pretty(true);
+        assert!(options.decompress());
+
+        let mut output = Vec::new();
+        doc.write_to_with_options(&mut output, options).unwrap();
+        let text = String::from_utf8_lossy(&output);
+        assert!(!text.contains("/FlateDecode"));
+        assert!(text.contains("BT"));
+    }
+
+    #[test]
+    fn test_pdf_write_options_garbage() {
+        let mut doc = PdfDocument::new();
+        let mut twins = doc.new_array().unwrap();
+        for _ in 0..2 {
+            let mut dict = doc.new_dict().unwrap();
+            dict.dict_put("Twin", doc.new_int(1).unwrap()).unwrap();
+            twins.array_push(doc.add_object(&dict).unwrap()).unwrap();
+        }
+        doc.catalog().unwrap().dict_put("Twins", twins).unwrap();
+        let unused = doc.new_dict().unwrap();
+        doc.add_object(&unused).unwrap();
+
+        let objects_after = |level| {
+            let mut options = PdfWriteOptions::default();
+            options.set_garbage_level(level);
+            assert_eq!(options.garbage_level(), level);
+            let mut output = Vec::new();
+            doc.write_to_with_options(&mut output, options).unwrap();
+            let written = PdfDocument::from_bytes(&output).unwrap();
+            written.objects().unwrap().count()
+        };
+        let all = objects_after(GarbageLevel::None);
+        let used = objects_after(GarbageLevel::Compact);
+        let unique = objects_after(GarbageLevel::Deduplicate);
+        assert_eq!(used, all - 1);
+        assert_eq!(unique, used - 1);
+    }
+
+    #[test]
+    fn test_pdf_write_options_deterministic() {
+        let mut options = PdfWriteOptions::default();
+        options.set_deterministic(true);
+        assert!(options.deterministic());
+
+        let id = |doc: &PdfDocument| -> Vec<Vec<u8>> {
+            match doc.trailer().unwrap().get_dict("ID").unwrap() {
+                Some(id) => (0..2)
+                    .map(|i| id.get_array(i).unwrap().unwrap().as_bytes().unwrap())
+                    .collect(),
+                None => Vec::new(),
+            }
+        };
+        let write = |doc: &PdfDocument| {
+            let before = id(doc);
+            let mut output = Vec::new();
+            doc.write_to_with_options(&mut output, options).unwrap();
+            assert_eq!(id(doc), before);
+            assert!(!doc.has_unsaved_changes());
+            output
+        };
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let output = write(&doc);
+        assert_eq!(
+            output,
+            write(&PdfDocument::open("tests/files/dummy.pdf").unwrap())
+        );
+        let written = id(&PdfDocument::from_bytes(&output).unwrap());
+        assert_eq!(written[0], id(&doc)[0]);
+        assert_ne!(written[1], id(&doc)[1]);
+
+        let new = || {
+            let mut doc = PdfDocument::new();
+            doc.new_page(crate::Size::A4).unwrap();
+            let mut output = Vec::new();
+            doc.write_to_with_options(&mut output, options).unwrap();
+            output
+        };
+        let output = new();
+        assert_eq!(output, new());
+        let written = id(&PdfDocument::from_bytes(&output).unwrap());
+        assert_eq!(written[0].len(), 16);
+        assert_eq!(written[0], written[1]);
+
+        options.set_incremental(true);
+        let path =
+            std::env::temp_dir().join(format!("mupdf-rs-deterministic-{}.pdf", std::process::id()));
+        assert!(doc
+            .save_with_options(path.to_str().unwrap(), options)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pdf_write_options_linear() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut options = PdfWriteOptions::default();
+        options.set_linear(true);
+        assert!(options.linear());
+
+        let mut output = Vec::new();
+        doc.write_to_with_options(&mut output, options).unwrap();
+        let head = String::from_utf8_lossy(&output[..output.len().min(1024)]);
+        assert!(head.contains("/Linearized"));
+        let linear = PdfDocument::from_bytes(&output).unwrap();
+        assert_eq!(linear.page_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_pdf_write_options_encryption() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut options = PdfWriteOptions::default();
+        assert_eq!(options.encryption(), EncryptionMethod::Keep);
+        options
+            .set_encryption(EncryptionMethod::Aes256)
+            .set_owner_password("owner")
+            .set_user_password("user")
+            .set_permissions(Permission::PRINT | Permission::COPY);
+        assert_eq!(options.encryption(), EncryptionMethod::Aes256);
+
+        let mut output = Vec::new();
+        doc.write_to_with_options(&mut output, options).unwrap();
+        let mut encrypted = PdfDocument::from_bytes(&output).unwrap();
+        assert!(encrypted.needs_password().unwrap());
+        assert!(!encrypted.authenticate("wrong").unwrap());
+        assert!(encrypted.authenticate("user").unwrap());
+        let perm = encrypted.permissions();
+        assert!(perm.contains(Permission::PRINT | Permission::COPY));
+        assert!(!perm.contains(Permission::MODIFY));
+    }
+
+    #[test]
+    fn test_pdf_document_save_incremental() {
+        use std::fs;
+
+        let path = std::env::temp_dir().join(format!(
+            "mupdf-rs-save-incremental-{}.pdf",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        fs::copy("tests/files/dummy.pdf", path).unwrap();
+        let original = fs::read(path).unwrap();
+
+        let mut doc = PdfDocument::open(path).unwrap();
+        assert!(doc.can_be_saved_incrementally());
+        let obj = doc.new_name("Incremental").unwrap();
+        doc.add_object(&obj).unwrap();
+        doc.save_incremental(path).unwrap();
+        drop(doc);
+
+        let updated = fs::read(path).unwrap();
+        assert!(updated.len() > original.len());
+        assert_eq!(&updated[..original.len()], &original[..]);
+        let doc = PdfDocument::open(path).unwrap();
+        assert_eq!(doc.page_count().unwrap(), 1);
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -687,6 +1748,168 @@ mod test {
         assert!(obj.is_dict().unwrap());
     }
 
+    #[test]
+    fn test_pdf_document_add_object() {
+        let mut pdf = PdfDocument::new();
+        let obj = pdf.new_byte_string(&[0, 159, 255]).unwrap();
+        assert!(obj.is_string().unwrap());
+        assert_eq!(obj.as_bytes().unwrap(), [0, 159, 255]);
+
+        let mut meta = pdf.new_dict().unwrap();
+        meta.dict_put("Version", pdf.new_int(2).unwrap()).unwrap();
+        meta.dict_put("Tool", pdf.new_string("mupdf-rs").unwrap())
+            .unwrap();
+        let count = pdf.count_objects().unwrap();
+        let meta_ref = pdf.add_object(&meta).unwrap();
+        assert!(meta_ref.is_indirect().unwrap());
+        assert_eq!(pdf.count_objects().unwrap(), count + 1);
+        pdf.catalog()
+            .unwrap()
+            .dict_put("AppData", meta_ref)
+            .unwrap();
+
+        let meta = pdf.catalog().unwrap().get_dict("AppData").unwrap().unwrap();
+        let meta = meta.resolve().unwrap().unwrap();
+        assert_eq!(
+            meta.get_dict("Version").unwrap().unwrap().as_int().unwrap(),
+            2
+        );
+
+        let empty = pdf.create_object().unwrap();
+        assert!(empty.is_indirect().unwrap());
+        assert!(empty
+            .resolve()
+            .unwrap()
+            .map_or(true, |o| o.is_null().unwrap()));
+    }
+
+    #[test]
+    fn test_pdf_object_update_stream() {
+        let mut pdf = PdfDocument::new();
+        let mut dict = pdf.new_dict().unwrap();
+        assert!(matches!(
+            dict.update_stream(b"direct", false),
+            Err(crate::Error::InvalidArgument(_))
+        ));
+        let mut stream = pdf.add_object(&dict).unwrap();
+        let data = b"BT /F1 12 Tf (Hello Hello Hello Hello) Tj ET".repeat(4);
+
+        stream.update_stream(&data, true).unwrap();
+        assert!(stream.is_stream().unwrap());
+        assert_eq!(
+            stream
+                .get_dict("Filter")
+                .unwrap()
+                .unwrap()
+                .as_name()
+                .unwrap(),
+            "FlateDecode"
+        );
+        assert_eq!(stream.read_stream().unwrap(), data);
+        assert!(stream.read_raw_stream().unwrap().len() < data.len());
+
+        stream.update_stream(b"raw", false).unwrap();
+        assert!(stream.get_dict("Filter").unwrap().is_none());
+        assert_eq!(stream.read_raw_stream().unwrap(), b"raw");
+    }
+
+    #[test]
+    fn test_pdf_document_update_delete_object() {
+        let mut pdf = PdfDocument::new();
+        let dict = pdf.new_object_from_str("<< /Kind /Old >>").unwrap();
+        let obj = pdf.add_object(&dict).unwrap();
+        let num = obj.as_indirect().unwrap();
+
+        let replacement = pdf.new_object_from_str("<< /Kind /New >>").unwrap();
+        pdf.update_object(num, &replacement).unwrap();
+        let kind = obj.get_dict("Kind").unwrap().unwrap();
+        assert_eq!(kind.as_name().unwrap(), "New");
+
+        pdf.delete_object(num).unwrap();
+        let resolved = pdf.new_indirect(num, 0).unwrap().resolve().unwrap();
+        assert!(resolved.map_or(true, |o| o.is_null().unwrap()));
+        assert!(pdf.objects().unwrap().all(|entry| entry.unwrap().0 != num));
+    }
+
+    #[test]
+    fn test_pdf_document_trailer_catalog() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let trailer = doc.trailer().unwrap();
+        assert!(trailer.get_dict("Size").unwrap().unwrap().as_int().unwrap() > 0);
+        let catalog = doc.catalog().unwrap();
+        let pages = catalog.get_dict("Pages").unwrap().unwrap();
+        assert_eq!(
+            pages.get_dict("Count").unwrap().unwrap().as_int().unwrap(),
+            1
+        );
+
+        let mut prefs = doc.new_dict().unwrap();
+        prefs.dict_put("HideToolbar", true.into()).unwrap();
+        doc.catalog()
+            .unwrap()
+            .dict_put("ViewerPreferences", prefs)
+            .unwrap();
+        let prefs = doc
+            .catalog()
+            .unwrap()
+            .get_dict("ViewerPreferences")
+            .unwrap()
+            .unwrap();
+        assert!(prefs
+            .get_dict("HideToolbar")
+            .unwrap()
+            .unwrap()
+            .as_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn test_pdf_document_objects() {
+        use crate::PdfObjectType;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.count_objects().unwrap() > 1);
+        let objects: Vec<_> = doc.objects().unwrap().collect::<Result<_, _>>().unwrap();
+        assert!(!objects.is_empty());
+        assert!(objects.iter().all(|(num, _)| *num > 0));
+        let catalog_num = doc.trailer().unwrap().get_dict("Root").unwrap().unwrap();
+        let catalog_num = catalog_num.as_indirect().unwrap();
+        let (_, catalog) = objects.iter().find(|(num, _)| *num == catalog_num).unwrap();
+        assert_eq!(catalog.object_type().unwrap(), PdfObjectType::Dict);
+        assert!(objects
+            .iter()
+            .any(|(_, obj)| obj.object_type().unwrap() == PdfObjectType::Stream));
+    }
+
+    #[test]
+    fn test_pdf_document_graft_page() {
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut pdf = PdfDocument::new();
+        let mut map = pdf.new_graft_map().unwrap();
+        map.graft_page(-1, &src, 0).unwrap();
+        map.graft_page(-1, &src, 0).unwrap();
+        drop(map);
+        pdf.graft_page(0, &src, 0).unwrap();
+        assert_eq!(pdf.page_count().unwrap(), 3);
+
+        let resources = src.find_page(0).unwrap().get_dict("Resources").unwrap();
+        let mut map = pdf.new_graft_map().unwrap();
+        let grafted = map.graft_object(&resources.unwrap()).unwrap();
+        assert!(grafted.is_dict().unwrap());
+    }
+
+    #[test]
+    fn test_pdf_document_page_lookup() {
+        let pdf = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let page = pdf.find_page(0).unwrap();
+        assert!(page.is_indirect().unwrap());
+        assert_eq!(pdf.page_number_of(&page).unwrap(), 0);
+        assert!(pdf.find_page(pdf.page_count().unwrap()).is_err());
+
+        let catalog = pdf.catalog().unwrap();
+        assert!(pdf.page_number_of(&catalog).is_err());
+    }
+
     #[test]
     fn test_pdf_object_array() {
         let pdf = PdfDocument::new();
@@ -720,7 +1943,13 @@ mod test {
         assert!(val0.as_bool().unwrap());
         let val1 = obj.get_dict("test").unwrap().unwrap();
         assert_eq!(val1.as_string().unwrap(), "test");
+        assert_eq!(obj.dict_len().unwrap(), 2);
+        let key = obj.get_dict_key(1).unwrap().unwrap();
+        assert_eq!(key.as_name().unwrap(), "test");
+        let val = obj.get_dict_val(1).unwrap().unwrap();
+        assert_eq!(val.as_string().unwrap(), "test");
         obj.dict_delete("test").unwrap();
+        assert_eq!(obj.dict_len().unwrap(), 1);
     }
 
     #[test]
@@ -738,4 +1967,1216 @@ mod test {
         assert_eq!(bounds.x1, 595.0);
         assert_eq!(bounds.y1, 842.0);
     }
+
+    #[test]
+    fn test_pdf_document_begin_page() {
+        use crate::{ColorParams, Colorspace, Matrix, Path, Rect, Size};
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        let builder = pdf.begin_page_at(0, (200.0, 100.0)).unwrap();
+        let mut path = Path::new().unwrap();
+        path.rect(10, 20, 60, 50).unwrap();
+        builder
+            .device()
+            .fill_path(
+                &path,
+                false,
+                &Matrix::IDENTITY,
+                &Colorspace::device_rgb(),
+                &[1.0, 0.0, 0.0],
+                1.0,
+                ColorParams::default(),
+            )
+            .unwrap();
+        let page = builder.finish().unwrap();
+        assert_eq!(pdf.page_count().unwrap(), 2);
+        let bounds = page.bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (200.0, 100.0));
+        let content = page.content_bbox().unwrap();
+        assert_eq!(content, Rect::new(10.0, 20.0, 60.0, 50.0));
+    }
+
+    #[test]
+    fn test_pdf_document_merge() {
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut merged = PdfDocument::merge(&[&src, &src]).unwrap();
+        assert_eq!(merged.page_count().unwrap(), 2);
+
+        merged.append(&src, 0..1).unwrap();
+        merged.append(&src, 0..0).unwrap();
+        assert_eq!(merged.page_count().unwrap(), 3);
+        assert!(matches!(
+            merged.append(&src, 0..2),
+            Err(crate::Error::InvalidArgument(_))
+        ));
+        assert_eq!(merged.page_count().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_pdf_document_extract_pages() {
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let extracted = src.extract_pages(&[0..1, 0..0, 0..1]).unwrap();
+        assert_eq!(extracted.page_count().unwrap(), 2);
+        let contents = |page_no| {
+            let page = extracted.find_page(page_no).unwrap();
+            page.get_dict("Contents")
+                .unwrap()
+                .unwrap()
+                .as_indirect()
+                .unwrap()
+        };
+        assert_eq!(contents(0), contents(1));
+        assert_eq!(src.page_count().unwrap(), 1);
+        assert!(src.extract_pages(&[1..2]).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_new_form_xobject() {
+        use crate::{PdfDrawing, Rect, Size};
+
+        let mut pdf = PdfDocument::new();
+        let mut drawing = PdfDrawing::new();
+        drawing
+            .set_fill_color(&[1.0, 0.0, 0.0])
+            .set_alpha(0.5, 0.5)
+            .rect(Rect::new(0.0, 0.0, 50.0, 10.0))
+            .fill(false);
+        let stamp = pdf.new_form_xobject((100.0, 20.0), &drawing).unwrap();
+        for _ in 0..2 {
+            let mut page = pdf.new_page(Size::A4).unwrap();
+            page.show_xobject(&stamp, Rect::new(100.0, 100.0, 300.0, 140.0))
+                .unwrap();
+            let content = page.content_bbox().unwrap();
+            assert!((content.x0 - 100.0).abs() < 0.5 && (content.y0 - 100.0).abs() < 0.5);
+            assert!((content.x1 - 200.0).abs() < 0.5 && (content.y1 - 120.0).abs() < 0.5);
+        }
+
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let letterhead = pdf.new_form_xobject_from_page(&src, 0).unwrap();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        page.show_xobject(&letterhead, Rect::new(0.0, 0.0, 595.0, 842.0))
+            .unwrap();
+        assert!(!page.content_bbox().unwrap().is_empty());
+
+        let flat = pdf.new_form_xobject((100.0, 0.0), &drawing).unwrap();
+        let result = page.show_xobject(&flat, Rect::new(0.0, 0.0, 100.0, 100.0));
+        assert!(matches!(result, Err(crate::Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_pdf_document_paginate() {
+        use crate::{BatesNumbering, Font, Pagination, PaginationPosition, TextPageOptions};
+
+        let mut pdf = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let font = Font::new("Helvetica").unwrap();
+        let mut pagination = Pagination::new(&font, 9.0);
+        pagination
+            .bates(BatesNumbering::new("CASE", 120, 5))
+            .line(PaginationPosition::TopRight, "Confidential")
+            .line(PaginationPosition::BottomRight, "{bates}")
+            .line(PaginationPosition::BottomCenter, "{page} / {pages}");
+        pdf.paginate(&pagination).unwrap();
+
+        let text_page = pdf
+            .load_page(0)
+            .unwrap()
+            .to_text_page(TextPageOptions::empty())
+            .unwrap();
+        let mut text = String::new();
+        for block in text_page.blocks() {
+            for line in block.lines() {
+                text.extend(line.chars().filter_map(|ch| ch.char()));
+                text.push('\n');
+            }
+        }
+        assert!(text.contains("Confidential"));
+        assert!(text.contains("CASE00120"));
+        assert!(text.contains("1 / 1"));
+    }
+
+    #[test]
+    fn test_pdf_document_stamp_watermark() {
+        use crate::{Colorspace, Font, Size, Watermark, WatermarkPosition};
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        pdf.new_page(Size::LETTER).unwrap();
+        let font = Font::new("Helvetica").unwrap();
+        let watermark = Watermark::Text {
+            text: "DRAFT",
+            font: &font,
+            size: 72.0,
+            cs: &Colorspace::device_gray(),
+            color: &[0.5],
+        };
+        pdf.stamp_watermark(&watermark, WatermarkPosition::Center, 0.3, 45.0, false)
+            .unwrap();
+
+        let mut xobjects = Vec::new();
+        for page_no in 0..2 {
+            let page = pdf.load_page(page_no).unwrap();
+            let content = page.content_bbox().unwrap();
+            let bounds = page.bounds().unwrap();
+            assert!(!content.is_empty());
+            let center = (
+                (content.x0 + content.x1) / 2.0,
+                (content.y0 + content.y1) / 2.0,
+            );
+            assert!((center.0 - bounds.width() / 2.0).abs() < 1.0);
+            assert!((center.1 - bounds.height() / 2.0).abs() < 1.0);
+            let xobj = pdf
+                .find_page(page_no)
+                .unwrap()
+                .get_dict("Resources")
+                .unwrap()
+                .unwrap()
+                .get_dict("XObject")
+                .unwrap()
+                .unwrap()
+                .get_dict("Fm0")
+                .unwrap()
+                .unwrap();
+            xobjects.push(xobj.as_indirect().unwrap());
+        }
+        assert_eq!(xobjects[0], xobjects[1]);
+    }
+
+    #[test]
+    fn test_pdf_document_booklet() {
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let booklet = src.booklet().unwrap();
+        assert_eq!(booklet.page_count().unwrap(), 2);
+        let bounds = booklet.load_page(0).unwrap().bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (1190.0, 842.0));
+        let content = booklet.load_page(0).unwrap().content_bbox().unwrap();
+        assert!(content.x0 >= 595.0);
+        let content = booklet.load_page(1).unwrap().content_bbox().unwrap();
+        assert!(content.is_empty());
+    }
+
+    #[test]
+    fn test_pdf_document_poster() {
+        use crate::Size;
+
+        let src = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let poster = src.poster(Size::new(300.0, 500.0), 10.0).unwrap();
+        assert_eq!(poster.page_count().unwrap(), 6);
+        let bounds = poster.load_page(5).unwrap().bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (300.0, 500.0));
+
+        let single = src.poster(Size::new(600.0, 900.0), 10.0).unwrap();
+        assert_eq!(single.page_count().unwrap(), 1);
+
+        assert!(src.poster(Size::new(0.0, 500.0), 10.0).is_err());
+        assert!(src.poster(Size::new(300.0, -1.0), 10.0).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_rearrange_pages() {
+        use crate::Size;
+
+        let mut pdf = PdfDocument::new();
+        for width in 1..=4 {
+            pdf.new_page(Size::new(width as f32 * 100.0, 100.0))
+                .unwrap();
+        }
+        let width = |pdf: &PdfDocument, page_no| {
+            let media_box = pdf.find_page(page_no).unwrap().get_dict("MediaBox");
+            let media_box = media_box.unwrap().unwrap();
+            media_box.get_array(2).unwrap().unwrap().as_int().unwrap()
+        };
+
+        let page = pdf.find_page(0).unwrap();
+        pdf.delete_page(0).unwrap();
+        pdf.insert_page(-1, &page).unwrap();
+        assert_eq!(width(&pdf, 3), 100);
+
+        let mut annot = pdf.new_dict().unwrap();
+        annot
+            .dict_put("Subtype", pdf.new_name("Text").unwrap())
+            .unwrap();
+        annot.dict_put("P", pdf.find_page(3).unwrap()).unwrap();
+        let annot = pdf.add_object(&annot).unwrap();
+        let mut annots = pdf.new_array().unwrap();
+        annots.array_push(annot).unwrap();
+        pdf.find_page(3)
+            .unwrap()
+            .dict_put("Annots", annots)
+            .unwrap();
+        let annot_parent = |pdf: &PdfDocument, page_no| {
+            let annots = pdf.find_page(page_no).unwrap().get_dict("Annots");
+            let annot = annots.unwrap().unwrap().get_array(0).unwrap().unwrap();
+            let parent = annot.get_dict("P").unwrap().unwrap();
+            (annot.as_indirect().unwrap(), parent.as_indirect().unwrap())
+        };
+
+        pdf.rearrange_pages(&[3, 1, 3]).unwrap();
+        assert_eq!(pdf.page_count().unwrap(), 3);
+        assert_eq!(width(&pdf, 0), 100);
+        assert_eq!(width(&pdf, 1), 300);
+        assert_eq!(width(&pdf, 2), 100);
+        assert_ne!(
+            pdf.find_page(0).unwrap().as_indirect().unwrap(),
+            pdf.find_page(2).unwrap().as_indirect().unwrap()
+        );
+        let (first, first_parent) = annot_parent(&pdf, 0);
+        let (copy, copy_parent) = annot_parent(&pdf, 2);
+        assert_ne!(first, copy);
+        assert_eq!(
+            first_parent,
+            pdf.find_page(0).unwrap().as_indirect().unwrap()
+        );
+        assert_eq!(
+            copy_parent,
+            pdf.find_page(2).unwrap().as_indirect().unwrap()
+        );
+        assert!(pdf.rearrange_pages(&[3]).is_err());
+        assert!(pdf.rearrange_pages(&[]).is_err());
+
+        pdf.delete_pages(0..2).unwrap();
+        assert_eq!(pdf.page_count().unwrap(), 1);
+        assert!(pdf.delete_pages(0..2).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_named_destinations() {
+        use crate::{Rect, Size};
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.named_destinations().unwrap().is_empty());
+
+        let mut pdf = PdfDocument::new();
+        let page = pdf.new_page(Size::A4).unwrap();
+        let mut dest = pdf.new_array().unwrap();
+        dest.array_push(page.object()).unwrap();
+        dest.array_push(pdf.new_name("XYZ").unwrap()).unwrap();
+        dest.array_push(pdf.new_int(100).unwrap()).unwrap();
+        dest.array_push(pdf.new_int(700).unwrap()).unwrap();
+        dest.array_push(pdf.new_null()).unwrap();
+        let mut names = pdf.new_array().unwrap();
+        names.array_push(pdf.new_string("intro").unwrap()).unwrap();
+        names.array_push(dest).unwrap();
+        let mut tree = pdf.new_dict().unwrap();
+        tree.dict_put("Names", names).unwrap();
+        // a name tree node listing itself in its /Kids
+        let mut tree = pdf.add_object(&tree).unwrap();
+        let mut kids = pdf.new_array().unwrap();
+        kids.array_push(pdf.new_indirect(tree.as_indirect().unwrap(), 0).unwrap())
+            .unwrap();
+        tree.dict_put("Kids", kids).unwrap();
+        let mut name_dict = pdf.new_dict().unwrap();
+        name_dict.dict_put("Dests", tree).unwrap();
+        pdf.catalog().unwrap().dict_put("Names", name_dict).unwrap();
+
+        let dests = pdf.named_destinations().unwrap();
+        assert_eq!(dests.len(), 1);
+        let intro = pdf.find_named_destination("intro").unwrap().unwrap();
+        assert_eq!(intro.page, 0);
+        assert_eq!(intro.rect, Rect::new(100.0, 142.0, 100.0, 142.0));
+        assert!(pdf.find_named_destination("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pdf_document_set_outlines() {
+        use crate::{Outline, Size};
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        pdf.new_page(Size::A4).unwrap();
+        let toc = vec![
+            Outline {
+                title: "Chapter 1".to_string(),
+                uri: String::new(),
+                page: 0,
+                down: vec![Outline {
+                    title: "Section 1.1".to_string(),
+                    uri: String::new(),
+                    page: 1,
+                    down: Vec::new(),
+                    x: 0.0,
+                    y: 100.0,
+                }],
+                x: 0.0,
+                y: 0.0,
+            },
+            Outline {
+                title: "Website".to_string(),
+                uri: "https://mupdf.com".to_string(),
+                page: -1,
+                down: Vec::new(),
+                x: 0.0,
+                y: 0.0,
+            },
+        ];
+        pdf.set_outlines(&toc).unwrap();
+
+        let outlines = pdf.outlines().unwrap();
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(outlines[0].title, "Chapter 1");
+        assert_eq!(outlines[0].page, 0);
+        assert_eq!(outlines[0].down.len(), 1);
+        assert_eq!(outlines[0].down[0].title, "Section 1.1");
+        assert_eq!(outlines[0].down[0].page, 1);
+        assert_eq!(outlines[1].uri, "https://mupdf.com");
+
+        let root = pdf
+            .catalog()
+            .unwrap()
+            .get_dict("Outlines")
+            .unwrap()
+            .unwrap();
+        let first = root.get_dict("First").unwrap().unwrap();
+        let nums = [root.as_indirect().unwrap(), first.as_indirect().unwrap()];
+        pdf.set_outlines(&[]).unwrap();
+        assert!(pdf.outlines().unwrap().is_empty());
+        for num in nums.iter() {
+            let resolved = pdf.new_indirect(*num, 0).unwrap().resolve().unwrap();
+            assert!(resolved.map_or(true, |o| o.is_null().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_pdf_document_set_page_labels() {
+        use crate::{PageLabelRange, PageLabelStyle, Size};
+
+        let mut pdf = PdfDocument::new();
+        for _ in 0..4 {
+            pdf.new_page(Size::A4).unwrap();
+        }
+        let ranges = vec![
+            PageLabelRange {
+                start_page: 2,
+                style: PageLabelStyle::Decimal,
+                prefix: "A-".to_string(),
+                first: 1,
+            },
+            PageLabelRange {
+                start_page: 0,
+                style: PageLabelStyle::LowerRoman,
+                prefix: String::new(),
+                first: 1,
+            },
+        ];
+        pdf.set_page_labels(&ranges).unwrap();
+
+        let labels = pdf.page_labels().unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].style, PageLabelStyle::LowerRoman);
+        assert_eq!(pdf.page_label(1).unwrap(), "ii");
+        assert_eq!(pdf.page_label(3).unwrap(), "A-2");
+        assert_eq!(pdf.index_for_label("A-1").unwrap(), Some(2));
+
+        // a number tree node listing itself in its /Kids
+        let mut catalog = pdf.catalog().unwrap();
+        let labels = catalog.get_dict("PageLabels").unwrap().unwrap();
+        let mut labels = pdf.add_object(&labels).unwrap();
+        let mut kids = pdf.new_array().unwrap();
+        kids.array_push(pdf.new_indirect(labels.as_indirect().unwrap(), 0).unwrap())
+            .unwrap();
+        labels.dict_put("Kids", kids).unwrap();
+        catalog.dict_put("PageLabels", labels).unwrap();
+        assert_eq!(pdf.page_labels().unwrap().len(), 2);
+
+        pdf.set_page_labels(&[]).unwrap();
+        assert!(pdf.page_labels().unwrap().is_empty());
+        assert_eq!(pdf.page_label(3).unwrap(), "4");
+    }
+
+    #[test]
+    fn test_pdf_document_open_action() {
+        use crate::Size;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.open_action().unwrap().is_none());
+        assert!(doc.open_action_destination().unwrap().is_none());
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        let page = pdf.new_page(Size::A4).unwrap();
+        let mut dest = pdf.new_array().unwrap();
+        dest.array_push(page.object()).unwrap();
+        dest.array_push(pdf.new_name("Fit").unwrap()).unwrap();
+        let mut action = pdf.new_dict().unwrap();
+        action.dict_put("S", pdf.new_name("GoTo").unwrap()).unwrap();
+        action.dict_put("D", dest).unwrap();
+        pdf.set_open_action(action).unwrap();
+
+        let dest = pdf.open_action_destination().unwrap().unwrap();
+        assert_eq!(dest.page, 1);
+
+        let mut js = pdf.new_dict().unwrap();
+        js.dict_put("S", pdf.new_name("JavaScript").unwrap())
+            .unwrap();
+        let mut aa = pdf.new_dict().unwrap();
+        aa.dict_put("WC", js).unwrap();
+        pdf.catalog().unwrap().dict_put("AA", aa).unwrap();
+        assert!(pdf.additional_actions().unwrap().is_some());
+
+        let mut names = pdf.new_dict().unwrap();
+        names
+            .dict_put("JavaScript", pdf.new_dict().unwrap())
+            .unwrap();
+        pdf.catalog().unwrap().dict_put("Names", names).unwrap();
+
+        let mut annot = pdf.new_dict().unwrap();
+        annot
+            .dict_put("Subtype", pdf.new_name("Widget").unwrap())
+            .unwrap();
+        annot.dict_put("AA", pdf.new_dict().unwrap()).unwrap();
+        let annot = pdf.add_object(&annot).unwrap();
+        let mut annots = pdf.new_array().unwrap();
+        annots.array_push(annot).unwrap();
+        pdf.find_page(1)
+            .unwrap()
+            .dict_put("Annots", annots)
+            .unwrap();
+
+        assert_eq!(pdf.remove_automatic_actions().unwrap(), 4);
+        assert!(pdf.open_action().unwrap().is_none());
+        assert!(pdf.additional_actions().unwrap().is_none());
+        let names = pdf.catalog().unwrap().get_dict("Names").unwrap().unwrap();
+        assert!(names.get_dict("JavaScript").unwrap().is_none());
+        let annots = pdf
+            .find_page(1)
+            .unwrap()
+            .get_dict("Annots")
+            .unwrap()
+            .unwrap();
+        let annot = annots.get_array(0).unwrap().unwrap();
+        assert!(annot.get_dict("AA").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pdf_document_recolor() {
+        use crate::{Colorspace, Size};
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        let dict = pdf.new_dict().unwrap();
+        let mut contents = pdf.add_object(&dict).unwrap();
+        contents
+            .write_stream_string("1 0 0 rg 0 0 10 10 re f 0 0 1 0 K 0 0 m 10 10 l S")
+            .unwrap();
+        pdf.find_page(0)
+            .unwrap()
+            .dict_put("Contents", contents)
+            .unwrap();
+
+        pdf.recolor(&Colorspace::device_gray()).unwrap();
+        let contents = pdf.find_page(0).unwrap().get_dict("Contents").unwrap();
+        let stream = String::from_utf8(contents.unwrap().read_stream().unwrap()).unwrap();
+        assert!(!stream.contains("rg"));
+        assert!(!stream.contains(" K"));
+        assert!(stream.contains(" g"));
+        assert!(stream.contains(" G"));
+        assert!(stream.contains("re"));
+
+        assert!(pdf.recolor(&Colorspace::device_lab()).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_downsample_images() {
+        use crate::{Colorspace, Image, Pixmap, Size};
+
+        let mut pdf = PdfDocument::new();
+        pdf.new_page(Size::A4).unwrap();
+        let mut pixmap = Pixmap::new_with_w_h(&Colorspace::device_rgb(), 200, 100, false).unwrap();
+        pixmap.clear_with(128).unwrap();
+        let image = pdf
+            .add_image(&Image::from_pixmap(&pixmap).unwrap())
+            .unwrap();
+        let mut xobjects = pdf.new_dict().unwrap();
+        xobjects.dict_put("Im0", image).unwrap();
+        let mut resources = pdf.new_dict().unwrap();
+        resources.dict_put("XObject", xobjects).unwrap();
+        let dict = pdf.new_dict().unwrap();
+        let mut contents = pdf.add_object(&dict).unwrap();
+        // 200 pixels over 10 points, 1440 dpi
+        contents
+            .write_stream_string("q 10 0 0 5 0 0 cm /Im0 Do Q")
+            .unwrap();
+        let mut page = pdf.find_page(0).unwrap();
+        page.dict_put("Resources", resources).unwrap();
+        page.dict_put("Contents", contents).unwrap();
+
+        assert_eq!(pdf.downsample_images(2000.0).unwrap(), 0);
+        assert_eq!(pdf.downsample_images(300.0).unwrap(), 1);
+        let image = page
+            .get_dict("Resources")
+            .unwrap()
+            .unwrap()
+            .get_dict("XObject")
+            .unwrap()
+            .unwrap()
+            .get_dict("Im0")
+            .unwrap()
+            .unwrap();
+        let width = image.get_dict("Width").unwrap().unwrap().as_int().unwrap();
+        let height = image.get_dict("Height").unwrap().unwrap().as_int().unwrap();
+        assert_eq!((width, height), (50, 25));
+        assert!(pdf.downsample_images(0.0).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_output_intents() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.output_intents().unwrap().is_empty());
+
+        let pdf = PdfDocument::new();
+        let intent = pdf
+            .new_object_from_str(
+                "<< /Type /OutputIntent /S /GTS_PDFX /OutputConditionIdentifier (FOGRA39) \
+                 /RegistryName (http://www.color.org) >>",
+            )
+            .unwrap();
+        let mut intents = pdf.new_array().unwrap();
+        intents.array_push(intent).unwrap();
+        pdf.catalog()
+            .unwrap()
+            .dict_put("OutputIntents", intents)
+            .unwrap();
+
+        let intents = pdf.output_intents().unwrap();
+        assert_eq!(intents.len(), 1);
+        assert_eq!(intents[0].subtype, "GTS_PDFX");
+        assert_eq!(intents[0].identifier, "FOGRA39");
+        assert_eq!(
+            intents[0].registry_name.as_deref(),
+            Some("http://www.color.org")
+        );
+        assert_eq!(intents[0].condition, None);
+        assert!(intents[0].profile.is_none());
+        assert!(intents[0].colorspace().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pdf_document_article_threads() {
+        use crate::{Rect, Size};
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        assert!(doc.article_threads().unwrap().is_empty());
+
+        let mut pdf = PdfDocument::new();
+        let page = pdf.new_page(Size::A4).unwrap();
+        let thread_dict = pdf.new_dict().unwrap();
+        let thread = pdf.add_object(&thread_dict).unwrap();
+        let bead_dict = pdf.new_dict().unwrap();
+        let mut bead = pdf.add_object(&bead_dict).unwrap();
+        let bead_num = bead.as_indirect().unwrap();
+        bead.dict_put(
+            "T",
+            pdf.new_indirect(thread.as_indirect().unwrap(), 0).unwrap(),
+        )
+        .unwrap();
+        // The second bead links back to itself instead of to the first one
+        let second_dict = pdf.new_dict().unwrap();
+        let mut second = pdf.add_object(&second_dict).unwrap();
+        let second_num = second.as_indirect().unwrap();
+        second
+            .dict_put("N", pdf.new_indirect(second_num, 0).unwrap())
+            .unwrap();
+        second.dict_put("P", page.object()).unwrap();
+        second
+            .dict_put("R", pdf.new_object_from_str("[300 442 595 842]").unwrap())
+            .unwrap();
+        bead.dict_put("N", pdf.new_indirect(second_num, 0).unwrap())
+            .unwrap();
+        bead.dict_put("V", pdf.new_indirect(second_num, 0).unwrap())
+            .unwrap();
+        bead.dict_put("P", page.object()).unwrap();
+        bead.dict_put("R", pdf.new_object_from_str("[0 442 300 842]").unwrap())
+            .unwrap();
+        let mut info = pdf.new_dict().unwrap();
+        info.dict_put("Title", pdf.new_string("Lead story 日本").unwrap())
+            .unwrap();
+        let mut thread_obj = pdf.new_indirect(thread.as_indirect().unwrap(), 0).unwrap();
+        thread_obj.dict_put("I", info).unwrap();
+        thread_obj.dict_put("F", bead).unwrap();
+        let mut threads = pdf.new_array().unwrap();
+        threads.array_push(thread).unwrap();
+        pdf.catalog().unwrap().dict_put("Threads", threads).unwrap();
+
+        let threads = pdf.article_threads().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].title, "Lead story 日本");
+        assert_eq!(threads[0].beads.len(), 2);
+        assert_eq!(threads[0].beads[0].page, 0);
+        assert_eq!(threads[0].beads[0].rect, Rect::new(0.0, 0.0, 300.0, 400.0));
+    }
+
+    #[test]
+    fn test_pdf_document_xfdf_round_trip() {
+        use crate::{PdfAnnotationType, PdfPage, Quad, Rect, ReviewState, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let quad = Quad::new(50.0, 100.0, 200.0, 100.0, 50.0, 115.0, 200.0, 115.0);
+        let mut highlight = page0
+            .create_highlight(&[quad.clone()], &[1.0, 1.0, 0.0], 0.5)
+            .unwrap();
+        highlight.set_contents("Check <this> & that").unwrap();
+        highlight.set_author("Alice").unwrap();
+        highlight
+            .set_popup(Rect::new(300.0, 100.0, 450.0, 200.0))
+            .unwrap();
+        page0.create_reply(&highlight, "Agreed", "Bob").unwrap();
+        page0
+            .set_review_state(&highlight, ReviewState::Accepted, "Bob")
+            .unwrap();
+
+        let mut catalog = doc.catalog().unwrap();
+        let mut field = doc.new_dict().unwrap();
+        field.dict_put("FT", doc.new_name("Tx").unwrap()).unwrap();
+        field
+            .dict_put("T", doc.new_string("name").unwrap())
+            .unwrap();
+        field
+            .dict_put("V", doc.new_string("Alice").unwrap())
+            .unwrap();
+        let mut fields = doc.new_array().unwrap();
+        fields.array_push(doc.add_object(&field).unwrap()).unwrap();
+        // a parent field listing itself next to its kid
+        let mut parent = doc.new_dict().unwrap();
+        parent
+            .dict_put("T", doc.new_string("person").unwrap())
+            .unwrap();
+        let mut parent = doc.add_object(&parent).unwrap();
+        let parent_num = parent.as_indirect().unwrap();
+        let mut kid = doc.new_dict().unwrap();
+        kid.dict_put("FT", doc.new_name("Tx").unwrap()).unwrap();
+        kid.dict_put("T", doc.new_string("first").unwrap()).unwrap();
+        kid.dict_put("V", doc.new_string("Bob").unwrap()).unwrap();
+        kid.dict_put("Parent", doc.new_indirect(parent_num, 0).unwrap())
+            .unwrap();
+        let mut kids = doc.new_array().unwrap();
+        kids.array_push(doc.add_object(&kid).unwrap()).unwrap();
+        kids.array_push(doc.new_indirect(parent_num, 0).unwrap())
+            .unwrap();
+        parent.dict_put("Kids", kids).unwrap();
+        fields.array_push(parent).unwrap();
+        let mut form = doc.new_dict().unwrap();
+        form.dict_put("Fields", fields).unwrap();
+        catalog.dict_put("AcroForm", form).unwrap();
+
+        let xfdf = doc.export_xfdf().unwrap();
+        assert!(xfdf.contains("<highlight page=\"0\""));
+        assert!(xfdf.contains("color=\"#FFFF00\""));
+        assert!(xfdf.contains("<contents>Check &lt;this&gt; &amp; that</contents>"));
+        assert!(xfdf.contains("<field name=\"name\">\n<value>Alice</value>"));
+        assert!(xfdf.contains("<field name=\"person.first\">\n<value>Bob</value>"));
+        assert!(xfdf.contains("inreplyto=\""));
+        assert!(xfdf.contains("state=\"Accepted\""));
+        assert!(xfdf.contains("statemodel=\"Review\""));
+        assert!(xfdf.contains("<popup rect=\""));
+
+        let mut copy = PdfDocument::new();
+        copy.new_page(Size::A4).unwrap();
+        copy.import_xfdf(&xfdf).unwrap();
+        let page = PdfPage::from(copy.load_page(0).unwrap());
+        let annots: Vec<_> = page
+            .annotations()
+            .filter(|annot| annot.r#type().unwrap() == PdfAnnotationType::Highlight)
+            .collect();
+        assert_eq!(annots.len(), 1);
+        assert_eq!(annots[0].contents().unwrap(), "Check <this> & that");
+        assert_eq!(annots[0].author().unwrap().as_deref(), Some("Alice"));
+        assert_eq!(annots[0].opacity().unwrap(), 0.5);
+        assert_eq!(annots[0].quad_points().unwrap(), vec![quad]);
+        assert!(annots[0].popup().unwrap().is_some());
+        let replies = page.replies(&annots[0]).unwrap();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].contents().unwrap(), "Agreed");
+        assert_eq!(
+            page.review_state(&annots[0], Some("Bob")).unwrap(),
+            Some(ReviewState::Accepted)
+        );
+
+        assert!(copy.import_xfdf("<fdf/>").is_err());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_pdf_document_annotations_json() {
+        use crate::{PdfAnnotationType, PdfPage, Point, Size};
+        use serde_json::json;
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let strokes = vec![vec![Point::new(10.0, 10.0), Point::new(20.0, 30.0)]];
+        let mut ink = page0.create_ink(&strokes, 2.0, &[1.0, 0.0, 0.0]).unwrap();
+        ink.set_contents("scribble").unwrap();
+        ink.set_opacity(0.1).unwrap();
+
+        let value = doc.annotations_to_json().unwrap();
+        let annots = value.as_array().unwrap();
+        assert_eq!(annots.len(), 1);
+        assert_eq!(annots[0]["type"], json!("ink"));
+        assert_eq!(annots[0]["page"], json!(0));
+        assert_eq!(annots[0]["contents"], json!("scribble"));
+        assert_eq!(annots[0]["color"], json!([1.0, 0.0, 0.0]));
+        assert_eq!(annots[0]["opacity"], json!(0.1));
+        assert_eq!(annots[0]["inkList"][0].as_array().unwrap().len(), 4);
+
+        let mut copy = PdfDocument::new();
+        copy.new_page(Size::A4).unwrap();
+        let mut input = value.clone();
+        input
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "type": "unknown" }));
+        assert_eq!(copy.import_annotations_json(&input).unwrap(), 1);
+        assert!(copy
+            .import_annotations_json(&json!([{ "type": "ink", "color": "red" }]))
+            .is_err());
+        let page = PdfPage::from(copy.load_page(0).unwrap());
+        let annot = page.annotations().next().unwrap();
+        assert_eq!(annot.r#type().unwrap(), PdfAnnotationType::Ink);
+        assert_eq!(annot.contents().unwrap(), "scribble");
+        assert_eq!(annot.color().unwrap(), vec![1.0, 0.0, 0.0]);
+    }
+
+    /// A one page document with a text field and a single widget combo box
+    fn new_form_document() -> PdfDocument {
+        use crate::{PdfObject, Size};
+
+        let mut doc = PdfDocument::new();
+        doc.new_page(Size::A4).unwrap();
+        let copy = |doc: &PdfDocument, obj: &PdfObject| {
+            doc.new_indirect(obj.as_indirect().unwrap(), 0).unwrap()
+        };
+
+        let text = doc
+            .new_object_from_str(concat!(
+                "<</Type/Annot/Subtype/Widget/FT/Tx/T(name)/V(Alice)",
+                "/DA(/Helv 12 Tf 0 g)/Rect[100 700 300 720]>>"
+            ))
+            .unwrap();
+        let text = doc.add_object(&text).unwrap();
+
+        let country = doc
+            .new_object_from_str("<</FT/Ch/Ff 131072/T(country)/V(fr)/Opt[[(fr)(France)](Spain)]>>")
+            .unwrap();
+        let mut country = doc.add_object(&country).unwrap();
+        let mut widget = doc
+            .new_object_from_str("<</Type/Annot/Subtype/Widget/Rect[100 600 300 620]>>")
+            .unwrap();
+        widget.dict_put("Parent", copy(&doc, &country)).unwrap();
+        let widget = doc.add_object(&widget).unwrap();
+        let mut kids = doc.new_array().unwrap();
+        kids.array_push(copy(&doc, &widget)).unwrap();
+        country.dict_put("Kids", kids).unwrap();
+
+        let mut annots = doc.new_array().unwrap();
+        annots.array_push(copy(&doc, &text)).unwrap();
+        annots.array_push(widget).unwrap();
+        doc.find_page(0)
+            .unwrap()
+            .dict_put("Annots", annots)
+            .unwrap();
+        let mut fields = doc.new_array().unwrap();
+        fields.array_push(text).unwrap();
+        fields.array_push(country).unwrap();
+        let mut form = doc.new_dict().unwrap();
+        form.dict_put("Fields", fields).unwrap();
+        doc.catalog().unwrap().dict_put("AcroForm", form).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_pdf_document_form_fields() {
+        use crate::{PdfPage, Rect};
+
+        assert!(PdfDocument::new().form_fields().unwrap().is_empty());
+
+        let doc = new_form_document();
+        let fields = doc.form_fields().unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].name().unwrap(), "name");
+        assert_eq!(fields[0].field_type().unwrap(), PdfFieldType::Text);
+        assert_eq!(fields[0].value().unwrap(), "Alice");
+        assert_eq!(
+            fields[0].rect().unwrap(),
+            Some(Rect::new(100.0, 700.0, 300.0, 720.0))
+        );
+        assert_eq!(fields[1].name().unwrap(), "country");
+        assert_eq!(fields[1].field_type().unwrap(), PdfFieldType::ComboBox);
+        assert_eq!(fields[1].options().unwrap(), vec!["fr", "Spain"]);
+
+        let page = PdfPage::from(doc.load_page(0).unwrap());
+        let widgets = page.widgets().unwrap();
+        assert_eq!(widgets.len(), 2);
+        assert_eq!(widgets[1].type_code(), PdfFieldType::ComboBox as i32);
+        assert_eq!(widgets[1].name().unwrap(), "country");
+        assert_eq!(widgets[1].value().unwrap(), "fr");
+        assert_eq!(widgets[1].field().unwrap().options().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_pdf_document_form_field_cycles() {
+        let mut doc = PdfDocument::new();
+        let group = doc.new_object_from_str("<</T(loop)>>").unwrap();
+        let mut group = doc.add_object(&group).unwrap();
+        let leaf = doc.new_object_from_str("<</FT/Tx/T(leaf)>>").unwrap();
+        let mut leaf = doc.add_object(&leaf).unwrap();
+        let group_ref = |doc: &PdfDocument| doc.new_indirect(group.as_indirect().unwrap(), 0);
+        leaf.dict_put("Parent", group_ref(&doc).unwrap()).unwrap();
+        let mut kids = doc.new_array().unwrap();
+        kids.array_push(group_ref(&doc).unwrap()).unwrap();
+        kids.array_push(leaf).unwrap();
+        let parent = group_ref(&doc).unwrap();
+        group.dict_put("Parent", parent).unwrap();
+        group.dict_put("Kids", kids).unwrap();
+        let mut fields = doc.new_array().unwrap();
+        fields.array_push(group).unwrap();
+        let mut form = doc.new_dict().unwrap();
+        form.dict_put("Fields", fields).unwrap();
+        doc.catalog().unwrap().dict_put("AcroForm", form).unwrap();
+
+        let fields = doc.form_fields().unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name().unwrap(), "loop.leaf");
+    }
+
+    #[test]
+    fn test_pdf_document_fill_text_field() {
+        use crate::PdfPage;
+
+        let doc = new_form_document();
+        let mut fields = doc.form_fields().unwrap();
+        assert!(fields[0].set_text_value("Bob").unwrap());
+        assert_eq!(fields[0].value().unwrap(), "Bob");
+        assert!(fields[1].set_text_value("Bob").is_err());
+
+        let page = PdfPage::from(doc.load_page(0).unwrap());
+        let mut widgets = page.widgets().unwrap();
+        assert_eq!(widgets[0].value().unwrap(), "Bob");
+        assert!(widgets[0].set_text_value("Carol").unwrap());
+        assert_eq!(doc.form_fields().unwrap()[0].value().unwrap(), "Carol");
+        assert!(widgets[0]
+            .annotation()
+            .object()
+            .get_dict("AP")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_pdf_document_fill_text_field_widgets() {
+        use crate::Size;
+
+        let mut doc = new_form_document();
+        doc.new_page(Size::A4).unwrap();
+        let city = doc
+            .new_object_from_str("<</FT/Tx/T(city)/DA(/Helv 12 Tf 0 g)>>")
+            .unwrap();
+        let mut city = doc.add_object(&city).unwrap();
+        let mut kids = doc.new_array().unwrap();
+        for page_no in 0..2 {
+            let mut widget = doc
+                .new_object_from_str("<</Type/Annot/Subtype/Widget/Rect[100 400 300 420]>>")
+                .unwrap();
+            widget
+                .dict_put(
+                    "Parent",
+                    doc.new_indirect(city.as_indirect().unwrap(), 0).unwrap(),
+                )
+                .unwrap();
+            // Only the first widget names its page
+            if page_no == 0 {
+                widget.dict_put("P", doc.find_page(0).unwrap()).unwrap();
+            }
+            let widget = doc.add_object(&widget).unwrap();
+            let mut page = doc.find_page(page_no).unwrap();
+            let mut annots = match page.get_dict("Annots").unwrap() {
+                Some(annots) => annots,
+                None => {
+                    page.dict_put("Annots", doc.new_array().unwrap()).unwrap();
+                    page.get_dict("Annots").unwrap().unwrap()
+                }
+            };
+            annots
+                .array_push(doc.new_indirect(widget.as_indirect().unwrap(), 0).unwrap())
+                .unwrap();
+            kids.array_push(widget).unwrap();
+        }
+        city.dict_put("Kids", kids).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(city).unwrap();
+
+        let mut city = doc.form_fields().unwrap().remove(2);
+        assert!(city.set_text_value("Lyon").unwrap());
+        let kids = city.object().get_dict("Kids").unwrap().unwrap();
+        for i in 0..2 {
+            let widget = kids.get_array(i).unwrap().unwrap();
+            assert!(widget.get_dict("AP").unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_pdf_document_button_states() {
+        let mut doc = new_form_document();
+        let check = doc
+            .new_object_from_str(concat!(
+                "<</Type/Annot/Subtype/Widget/FT/Btn/T(agree)/V/Off/AS/Off",
+                "/AP<</N<</Yes<<>>/Off<<>>>>>>/Rect[100 500 120 520]>>"
+            ))
+            .unwrap();
+        let check = doc.add_object(&check).unwrap();
+        let radio = doc
+            .new_object_from_str(concat!(
+                "<</FT/Btn/Ff 49152/T(size)/Kids[",
+                "<</Subtype/Widget/AS/Off/AP<</N<</S<<>>/Off<<>>>>>>>>",
+                "<</Subtype/Widget/AS/Off/AP<</N<</L<<>>/Off<<>>>>>>>>]>>"
+            ))
+            .unwrap();
+        let radio = doc.add_object(&radio).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(check).unwrap();
+        fields.array_push(radio).unwrap();
+
+        let mut form = doc.form_fields().unwrap();
+        let check = &mut form[2];
+        assert_eq!(check.field_type().unwrap(), PdfFieldType::CheckBox);
+        assert_eq!(check.on_states().unwrap(), vec!["Yes"]);
+        assert!(!check.is_checked().unwrap());
+        check.set_checked(true).unwrap();
+        assert!(check.is_checked().unwrap());
+        assert_eq!(check.value().unwrap(), "Yes");
+        check.set_checked(false).unwrap();
+        assert_eq!(check.value().unwrap(), "Off");
+
+        let radio = &mut form[3];
+        assert_eq!(radio.field_type().unwrap(), PdfFieldType::RadioButton);
+        assert_eq!(radio.on_states().unwrap(), vec!["S", "L"]);
+        radio.set_state("L").unwrap();
+        assert_eq!(radio.value().unwrap(), "L");
+        let kids = radio.object().get_dict("Kids").unwrap().unwrap();
+        let first = kids.get_array(0).unwrap().unwrap();
+        let second = kids.get_array(1).unwrap().unwrap();
+        assert_eq!(
+            first.get_dict("AS").unwrap().unwrap().as_name().unwrap(),
+            "Off"
+        );
+        assert_eq!(
+            second.get_dict("AS").unwrap().unwrap().as_name().unwrap(),
+            "L"
+        );
+        assert!(radio.set_state("XL").is_err());
+        assert!(form[0].set_state("Off").is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_choice_fields() {
+        let mut doc = new_form_document();
+        let list = doc
+            .new_object_from_str("<</FT/Ch/Ff 2097152/T(toppings)/Opt[(ham)(olives)(basil)]>>")
+            .unwrap();
+        let list = doc.add_object(&list).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(list).unwrap();
+
+        let mut form = doc.form_fields().unwrap();
+        let combo = &mut form[1];
+        assert_eq!(
+            combo.choice_options().unwrap(),
+            vec![
+                ("fr".to_string(), "France".to_string()),
+                ("Spain".to_string(), "Spain".to_string())
+            ]
+        );
+        assert_eq!(combo.selected_values().unwrap(), vec!["fr"]);
+        combo.select(&["Spain"]).unwrap();
+        assert_eq!(combo.value().unwrap(), "Spain");
+        assert!(combo.select(&["Italy"]).is_err());
+        assert!(combo.select(&["fr", "Spain"]).is_err());
+        combo.add_option("it", Some("Italy")).unwrap();
+        combo.select(&["it"]).unwrap();
+        assert_eq!(combo.choice_options().unwrap().len(), 3);
+
+        let list = &mut form[2];
+        assert_eq!(list.field_type().unwrap(), PdfFieldType::ListBox);
+        list.select(&["ham", "basil"]).unwrap();
+        assert_eq!(list.selected_values().unwrap(), vec!["ham", "basil"]);
+        let indices = list.object().get_dict("I").unwrap().unwrap();
+        assert_eq!(indices.get_array(1).unwrap().unwrap().as_int().unwrap(), 2);
+        list.select(&[]).unwrap();
+        assert!(list.selected_values().unwrap().is_empty());
+        assert!(form[0].select(&["Bob"]).is_err());
+    }
+
+    #[test]
+    fn test_pdf_document_field_flags() {
+        use crate::PdfFieldFlags;
+
+        let doc = new_form_document();
+        let mut form = doc.form_fields().unwrap();
+        assert_eq!(form[1].flags().unwrap(), PdfFieldFlags::COMBO);
+
+        let text = &mut form[0];
+        assert!(text.flags().unwrap().is_empty());
+        assert_eq!(text.max_len().unwrap(), None);
+        text.set_flags(PdfFieldFlags::REQUIRED | PdfFieldFlags::MULTILINE)
+            .unwrap();
+        text.set_comb(Some(8)).unwrap();
+        assert_eq!(text.max_len().unwrap(), Some(8));
+        assert_eq!(
+            text.flags().unwrap(),
+            PdfFieldFlags::REQUIRED | PdfFieldFlags::COMB
+        );
+        assert_eq!(text.field_type().unwrap(), PdfFieldType::Text);
+        text.set_comb(None).unwrap();
+        text.set_max_len(None).unwrap();
+        assert_eq!(text.flags().unwrap(), PdfFieldFlags::REQUIRED);
+        assert_eq!(text.max_len().unwrap(), None);
+
+        let form_dict = doc
+            .catalog()
+            .unwrap()
+            .get_dict("AcroForm")
+            .unwrap()
+            .unwrap();
+        let need = form_dict.get_dict("NeedAppearances").unwrap().unwrap();
+        assert!(need.as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_pdf_document_flatten_form() {
+        use super::FlattenFormOptions;
+        use crate::PdfPage;
+
+        let mut doc = new_form_document();
+        // A signature field under a non-terminal parent
+        let group = doc.new_object_from_str("<</T(signatures)>>").unwrap();
+        let mut group = doc.add_object(&group).unwrap();
+        let mut sig = doc
+            .new_object_from_str(
+                "<</Type/Annot/Subtype/Widget/FT/Sig/T(sign)/F 4/Rect[100 100 300 150]>>",
+            )
+            .unwrap();
+        sig.dict_put(
+            "Parent",
+            doc.new_indirect(group.as_indirect().unwrap(), 0).unwrap(),
+        )
+        .unwrap();
+        let sig = doc.add_object(&sig).unwrap();
+        let mut kids = doc.new_array().unwrap();
+        kids.array_push(doc.new_indirect(sig.as_indirect().unwrap(), 0).unwrap())
+            .unwrap();
+        group.dict_put("Kids", kids).unwrap();
+        let mut page_obj = doc.find_page(0).unwrap();
+        let mut annots = page_obj.get_dict("Annots").unwrap().unwrap();
+        annots.array_push(sig).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(group).unwrap();
+        {
+            let page = PdfPage::from(doc.load_page(0).unwrap());
+            page.widgets().unwrap()[0].set_text_value("Bob").unwrap();
+        }
+        // A second page sharing the resources of the first
+        doc.new_page(crate::Size::A4).unwrap();
+        let resources = page_obj.get_dict("Resources").unwrap().unwrap();
+        let resources = doc.add_object(&resources).unwrap();
+        page_obj
+            .dict_put(
+                "Resources",
+                doc.new_indirect(resources.as_indirect().unwrap(), 0)
+                    .unwrap(),
+            )
+            .unwrap();
+        doc.find_page(1)
+            .unwrap()
+            .dict_put("Resources", resources)
+            .unwrap();
+
+        let flattened = doc
+            .flatten_form(FlattenFormOptions::KEEP_SIGNATURES)
+            .unwrap();
+        assert_eq!(flattened, 2);
+        let fields = doc.form_fields().unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name().unwrap(), "signatures.sign");
+        assert_eq!(fields[0].field_type().unwrap(), PdfFieldType::Signature);
+        {
+            let page = PdfPage::from(doc.load_page(0).unwrap());
+            assert_eq!(page.widgets().unwrap().len(), 1);
+            let resources = page.object().get_dict("Resources").unwrap().unwrap();
+            let xobjs = resources.get_dict("XObject").unwrap().unwrap();
+            assert!(xobjs.get_dict("Widget0").unwrap().is_some());
+            assert!(xobjs.get_dict("Widget1").unwrap().is_some());
+        }
+        let resources = doc.find_page(1).unwrap().get_dict("Resources").unwrap();
+        let xobjs = resources.unwrap().get_dict("XObject").unwrap();
+        assert!(xobjs.map_or(true, |xobjs| xobjs.get_dict("Widget0").unwrap().is_none()));
+
+        doc.flatten_form(FlattenFormOptions::empty()).unwrap();
+        assert!(!doc.has_acro_form().unwrap());
+        assert!(doc.form_fields().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pdf_document_reset_form() {
+        let mut doc = new_form_document();
+        let check = doc
+            .new_object_from_str(concat!(
+                "<</Type/Annot/Subtype/Widget/FT/Btn/T(agree)/V/Yes/AS/Yes/DV/Off",
+                "/AP<</N<</Yes<<>>/Off<<>>>>>>/Rect[100 500 120 520]>>"
+            ))
+            .unwrap();
+        let check = doc.add_object(&check).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(check).unwrap();
+
+        let mut text = fields.get_array(0).unwrap().unwrap();
+        text.dict_put("DV", doc.new_string("Nobody").unwrap())
+            .unwrap();
+
+        let mut form = doc.form_fields().unwrap();
+        assert_eq!(form[0].default_value().unwrap(), "Nobody");
+        assert_eq!(form[1].default_value().unwrap(), "");
+        form[1].select(&["Spain"]).unwrap();
+
+        assert_eq!(doc.reset_form(Some(&["agree"][..]), false).unwrap(), 1);
+        let fields = doc.form_fields().unwrap();
+        assert_eq!(fields[0].value().unwrap(), "Alice");
+        assert_eq!(fields[1].value().unwrap(), "Spain");
+        assert!(!fields[2].is_checked().unwrap());
+        let state = fields[2].object().get_dict("AS").unwrap().unwrap();
+        assert_eq!(state.as_name().unwrap(), "Off");
+
+        assert_eq!(doc.reset_form(Some(&["agree"][..]), true).unwrap(), 2);
+        let fields = doc.form_fields().unwrap();
+        assert_eq!(fields[0].value().unwrap(), "Nobody");
+        assert!(fields[1].selected_values().unwrap().is_empty());
+
+        // A value inherited from a non-terminal parent
+        let parent = doc
+            .new_object_from_str("<</T(address)/FT/Tx/V(Old)/DV(Home)>>")
+            .unwrap();
+        let mut parent = doc.add_object(&parent).unwrap();
+        let mut street = doc.new_object_from_str("<</T(street)>>").unwrap();
+        street
+            .dict_put(
+                "Parent",
+                doc.new_indirect(parent.as_indirect().unwrap(), 0).unwrap(),
+            )
+            .unwrap();
+        let mut kids = doc.new_array().unwrap();
+        kids.array_push(doc.add_object(&street).unwrap()).unwrap();
+        parent.dict_put("Kids", kids).unwrap();
+        let form = doc.catalog().unwrap().get_dict("AcroForm").unwrap();
+        let mut fields = form.unwrap().get_dict("Fields").unwrap().unwrap();
+        fields.array_push(parent).unwrap();
+
+        let mut street = doc.form_fields().unwrap().remove(3);
+        assert_eq!(street.name().unwrap(), "address.street");
+        assert_eq!(street.value().unwrap(), "Old");
+        street.reset().unwrap();
+        assert_eq!(street.value().unwrap(), "Home");
+    }
 }
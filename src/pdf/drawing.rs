@@ -0,0 +1,190 @@
+use std::fmt::Write;
+
+use crate::Rect;
+
+/// Resource name of the ExtGState with the fill and stroke alpha
+///
+/// Named after the values so drawing twice on a page shares the same state.
+pub(crate) fn alpha_gstate_name(fill: f32, stroke: f32) -> String {
+    format!(
+        "Alpha{}_{}",
+        (fill * 1000.0).round() as i32,
+        (stroke * 1000.0).round() as i32
+    )
+}
+
+/// Vector graphics to draw on a page with [`PdfPage::draw`](crate::pdf::PdfPage::draw)
+///
+/// Coordinates are the same as [`Page::bounds`](crate::Page::bounds), with y going down.
+/// Colors are gray, RGB or CMYK depending on the number of components, other lengths and
+/// non-finite components make [`PdfPage::draw`](crate::pdf::PdfPage::draw) fail.
+#[derive(Debug, Clone, Default)]
+pub struct PdfDrawing {
+    pub(crate) content: String,
+    /// Names and fill and stroke alpha of the graphics states used
+    pub(crate) alphas: Vec<(String, f32, f32)>,
+    /// First invalid argument given, reported when drawing
+    pub(crate) error: Option<String>,
+}
+
+impl PdfDrawing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn op(&mut self, args: &[f32], op: &str) -> &mut Self {
+        for arg in args {
+            let _ = write!(self.content, "{} ", arg);
+        }
+        self.content.push_str(op);
+        self.content.push('\n');
+        self
+    }
+
+    /// Save the graphics state, until the matching [`restore`](Self::restore)
+    pub fn save(&mut self) -> &mut Self {
+        self.op(&[], "q")
+    }
+
+    pub fn restore(&mut self) -> &mut Self {
+        self.op(&[], "Q")
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.op(&[x, y], "m")
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.op(&[x, y], "l")
+    }
+
+    /// Cubic Bézier curve to `(ex, ey)` with the control points `(cx1, cy1)` and `(cx2, cy2)`
+    pub fn curve_to(
+        &mut self,
+        cx1: f32,
+        cy1: f32,
+        cx2: f32,
+        cy2: f32,
+        ex: f32,
+        ey: f32,
+    ) -> &mut Self {
+        self.op(&[cx1, cy1, cx2, cy2, ex, ey], "c")
+    }
+
+    pub fn rect(&mut self, rect: Rect) -> &mut Self {
+        self.op(&[rect.x0, rect.y0, rect.width(), rect.height()], "re")
+    }
+
+    pub fn close_path(&mut self) -> &mut Self {
+        self.op(&[], "h")
+    }
+
+    pub fn set_line_width(&mut self, width: f32) -> &mut Self {
+        self.op(&[width], "w")
+    }
+
+    /// Dash pattern of alternating dash and gap lengths, an empty pattern draws solid lines
+    pub fn set_dash(&mut self, dashes: &[f32], phase: f32) -> &mut Self {
+        self.content.push('[');
+        for (i, dash) in dashes.iter().enumerate() {
+            if i > 0 {
+                self.content.push(' ');
+            }
+            let _ = write!(self.content, "{}", dash);
+        }
+        self.content.push_str("] ");
+        self.op(&[phase], "d")
+    }
+
+    fn color_op(&mut self, color: &[f32], ops: [&str; 3]) -> &mut Self {
+        let op = match color.len() {
+            1 => ops[0],
+            3 => ops[1],
+            4 => ops[2],
+            n => return self.invalid(format!("color with {} components", n)),
+        };
+        if color.iter().any(|c| !c.is_finite()) {
+            return self.invalid(format!("color {:?} is not finite", color));
+        }
+        self.op(color, op)
+    }
+
+    fn invalid(&mut self, msg: String) -> &mut Self {
+        if self.error.is_none() {
+            self.error = Some(msg);
+        }
+        self
+    }
+
+    pub fn set_fill_color(&mut self, color: &[f32]) -> &mut Self {
+        self.color_op(color, ["g", "rg", "k"])
+    }
+
+    pub fn set_stroke_color(&mut self, color: &[f32]) -> &mut Self {
+        self.color_op(color, ["G", "RG", "K"])
+    }
+
+    /// Opacity of fills and strokes drawn after this, from 0 for transparent to 1 for opaque
+    pub fn set_alpha(&mut self, fill: f32, stroke: f32) -> &mut Self {
+        let fill = fill.max(0.0).min(1.0);
+        let stroke = stroke.max(0.0).min(1.0);
+        let name = alpha_gstate_name(fill, stroke);
+        let _ = writeln!(self.content, "/{} gs", name);
+        if !self.alphas.iter().any(|(n, _, _)| *n == name) {
+            self.alphas.push((name, fill, stroke));
+        }
+        self
+    }
+
+    pub fn fill(&mut self, even_odd: bool) -> &mut Self {
+        self.op(&[], if even_odd { "f*" } else { "f" })
+    }
+
+    pub fn stroke(&mut self) -> &mut Self {
+        self.op(&[], "S")
+    }
+
+    pub fn fill_stroke(&mut self, even_odd: bool) -> &mut Self {
+        self.op(&[], if even_odd { "B*" } else { "B" })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PdfDrawing;
+    use crate::Rect;
+
+    #[test]
+    fn test_pdf_drawing_content() {
+        let mut drawing = PdfDrawing::new();
+        drawing
+            .set_stroke_color(&[1.0, 0.0, 0.0])
+            .set_dash(&[3.0, 1.5], 0.0)
+            .set_alpha(0.5, 1.0)
+            .rect(Rect::new(10.0, 20.0, 30.0, 60.0))
+            .stroke()
+            .set_alpha(0.5, 1.0);
+        assert_eq!(
+            drawing.content,
+            "1 0 0 RG\n[3 1.5] 0 d\n/Alpha500_1000 gs\n10 20 20 40 re\nS\n/Alpha500_1000 gs\n"
+        );
+        assert_eq!(
+            drawing.alphas,
+            vec![("Alpha500_1000".to_string(), 0.5, 1.0)]
+        );
+        assert!(drawing.error.is_none());
+    }
+
+    #[test]
+    fn test_pdf_drawing_invalid_color() {
+        let mut drawing = PdfDrawing::new();
+        drawing.set_fill_color(&[1.0, 0.0]).set_fill_color(&[0.5]);
+        assert_eq!(drawing.content, "0.5 g\n");
+        assert!(drawing.error.is_some());
+
+        let mut drawing = PdfDrawing::new();
+        drawing.set_stroke_color(&[f32::NAN, 0.0, 0.0]);
+        assert!(drawing.content.is_empty());
+        assert!(drawing.error.is_some());
+    }
+}
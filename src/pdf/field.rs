@@ -0,0 +1,456 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use bitflags::bitflags;
+use mupdf_sys::*;
+use num_enum::TryFromPrimitive;
+
+use crate::pdf::xfdf::floats;
+use crate::{context, Error, PdfObject, Rect};
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+pub enum PdfFieldType {
+    Unknown = 0,
+    Button = 1,
+    CheckBox = 2,
+    ComboBox = 3,
+    ListBox = 4,
+    RadioButton = 5,
+    Signature = 6,
+    Text = 7,
+}
+
+bitflags! {
+    /// Field flags (`/Ff`), some bits mean different things for buttons, text and choice
+    /// fields
+    pub struct PdfFieldFlags: i32 {
+        const READ_ONLY = 1;
+        const REQUIRED = 1 << 1;
+        const NO_EXPORT = 1 << 2;
+        /// Text fields
+        const MULTILINE = 1 << 12;
+        const PASSWORD = 1 << 13;
+        const FILE_SELECT = 1 << 20;
+        const DO_NOT_SPELL_CHECK = 1 << 22;
+        const DO_NOT_SCROLL = 1 << 23;
+        /// Split the field into [`max_len`](PdfField::max_len) evenly spaced cells
+        const COMB = 1 << 24;
+        const RICH_TEXT = 1 << 25;
+        /// Buttons
+        const NO_TOGGLE_TO_OFF = 1 << 14;
+        const RADIO = 1 << 15;
+        const PUSH_BUTTON = 1 << 16;
+        const RADIOS_IN_UNISON = 1 << 25;
+        /// Choice fields
+        const COMBO = 1 << 17;
+        const EDIT = 1 << 18;
+        const SORT = 1 << 19;
+        const MULTI_SELECT = 1 << 21;
+        const COMMIT_ON_SELECTION_CHANGE = 1 << 26;
+    }
+}
+
+/// A terminal field of the interactive form (AcroForm), the holder of a value shown by one
+/// or more widgets
+#[derive(Debug)]
+pub struct PdfField {
+    obj: PdfObject,
+}
+
+impl PdfField {
+    pub(crate) fn new(obj: PdfObject) -> Self {
+        Self { obj }
+    }
+
+    /// The field dictionary
+    pub fn object(&self) -> &PdfObject {
+        &self.obj
+    }
+
+    /// Fully qualified name, the partial names (`/T`) of the field and its ancestors joined
+    /// by periods
+    pub fn name(&self) -> Result<String, Error> {
+        let mut names = Vec::new();
+        if let Some(name) = self.obj.get_dict("T")? {
+            names.push(name.as_string()?.to_string());
+        }
+        let mut seen = HashSet::new();
+        if self.obj.is_indirect()? {
+            seen.insert(self.obj.as_indirect()?);
+        }
+        let mut parent = self.obj.get_dict("Parent")?;
+        while let Some(field) = parent {
+            // Stop at a cycle of /Parent references
+            if field.is_indirect()? && !seen.insert(field.as_indirect()?) {
+                break;
+            }
+            if let Some(name) = field.get_dict("T")? {
+                names.push(name.as_string()?.to_string());
+            }
+            parent = field.get_dict("Parent")?;
+        }
+        names.reverse();
+        Ok(names.join("."))
+    }
+
+    pub fn field_type(&self) -> Result<PdfFieldType, Error> {
+        let code = unsafe { ffi_try!(mupdf_pdf_field_type(context(), self.obj.inner)) };
+        Ok(PdfFieldType::try_from(code).unwrap_or(PdfFieldType::Unknown))
+    }
+
+    /// Current value (`/V`), the state name for check boxes and radio buttons, empty if unset
+    /// or if several options of a list box are selected
+    pub fn value(&self) -> Result<String, Error> {
+        self.inheritable_text("V")
+    }
+
+    /// Set the value of a text field and regenerate the appearances of all its widgets,
+    /// returns `false` if the field's own checks reject it
+    pub fn set_text_value(&mut self, value: &str) -> Result<bool, Error> {
+        let c_value = CString::new(value)?;
+        let accepted = unsafe {
+            ffi_try!(mupdf_pdf_set_text_field_value(
+                context(),
+                self.obj.inner,
+                c_value.as_ptr()
+            ))
+        };
+        Ok(accepted)
+    }
+
+    /// Default value (`/DV`) the field takes back on a form reset, empty if unset
+    pub fn default_value(&self) -> Result<String, Error> {
+        self.inheritable_text("DV")
+    }
+
+    /// Set the field back to its [`default_value`](Self::default_value), the way a
+    /// `ResetForm` action does
+    ///
+    /// A value inherited from a parent field is reset on the parent, which also resets the
+    /// other fields inheriting it.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_reset_field(context(), self.obj.inner));
+        }
+        self.need_appearances()
+    }
+
+    /// Names of the appearance states of a check box or radio button other than `Off`, the
+    /// export values its widgets can be turned on with
+    pub fn on_states(&self) -> Result<Vec<String>, Error> {
+        let mut states = Vec::new();
+        match self.obj.get_dict("Kids")? {
+            Some(kids) => {
+                for i in 0..kids.len()? {
+                    if let Some(widget) = kids.get_array(i as i32)? {
+                        push_on_states(&widget, &mut states)?;
+                    }
+                }
+            }
+            None => push_on_states(&self.obj, &mut states)?,
+        }
+        Ok(states)
+    }
+
+    pub fn is_checked(&self) -> Result<bool, Error> {
+        let value = self.value()?;
+        Ok(!value.is_empty() && value != "Off")
+    }
+
+    /// Check or clear a check box
+    pub fn set_checked(&mut self, checked: bool) -> Result<(), Error> {
+        if !checked {
+            return self.set_state("Off");
+        }
+        let on = self.on_states()?.into_iter().next();
+        self.set_state(on.as_deref().unwrap_or("Yes"))
+    }
+
+    /// Turn a check box or radio button to the state `state`, one of the
+    /// [`on_states`](Self::on_states) or `Off`
+    ///
+    /// Selecting the export value of a radio group turns the other buttons of the group off.
+    pub fn set_state(&mut self, state: &str) -> Result<(), Error> {
+        let c_state = CString::new(state)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_set_button_field_state(
+                context(),
+                self.obj.inner,
+                c_state.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Values of a choice field's options (`/Opt`), the export value when an option has one,
+    /// see [`choice_options`](Self::choice_options) for the display texts
+    pub fn options(&self) -> Result<Vec<String>, Error> {
+        let key = PdfObject::new_name("Opt")?;
+        let opts = match self.obj.get_dict_inheritable(&key)? {
+            Some(opts) => opts,
+            None => return Ok(Vec::new()),
+        };
+        let mut options = Vec::with_capacity(opts.len()?);
+        for i in 0..opts.len()? {
+            let opt = match opts.get_array(i as i32)? {
+                Some(opt) if opt.is_array()? => opt.get_array(0)?,
+                opt => opt,
+            };
+            if let Some(opt) = opt {
+                options.push(opt.as_string()?.to_string());
+            }
+        }
+        Ok(options)
+    }
+
+    /// Options of a choice field as `(export value, display text)` pairs, the two are the
+    /// same for options without an export value
+    pub fn choice_options(&self) -> Result<Vec<(String, String)>, Error> {
+        let key = PdfObject::new_name("Opt")?;
+        let opts = match self.obj.get_dict_inheritable(&key)? {
+            Some(opts) => opts,
+            None => return Ok(Vec::new()),
+        };
+        let mut options = Vec::with_capacity(opts.len()?);
+        for i in 0..opts.len()? {
+            let opt = match opts.get_array(i as i32)? {
+                Some(opt) => opt,
+                None => continue,
+            };
+            if opt.is_array()? {
+                let export = opt.get_array(0)?.ok_or(Error::InvalidPdfDocument)?;
+                let display = opt.get_array(1)?.ok_or(Error::InvalidPdfDocument)?;
+                options.push((
+                    export.as_string()?.to_string(),
+                    display.as_string()?.to_string(),
+                ));
+            } else {
+                let value = opt.as_string()?.to_string();
+                options.push((value.clone(), value));
+            }
+        }
+        Ok(options)
+    }
+
+    /// Export values of the selected options of a choice field, custom text included
+    pub fn selected_values(&self) -> Result<Vec<String>, Error> {
+        let key = PdfObject::new_name("V")?;
+        let value = match self.obj.get_dict_inheritable(&key)? {
+            Some(value) => value,
+            None => return Ok(Vec::new()),
+        };
+        if !value.is_array()? {
+            return Ok(vec![value.as_string()?.to_string()]);
+        }
+        let mut values = Vec::with_capacity(value.len()?);
+        for i in 0..value.len()? {
+            if let Some(v) = value.get_array(i as i32)? {
+                values.push(v.as_string()?.to_string());
+            }
+        }
+        Ok(values)
+    }
+
+    /// Select the options of a choice field with the export values `values`, clearing the
+    /// selection when empty
+    ///
+    /// Selecting more than one option needs a multiple selection list box, values outside
+    /// the options are only accepted by editable combo boxes.
+    pub fn select(&mut self, values: &[&str]) -> Result<(), Error> {
+        let c_values = values
+            .iter()
+            .map(|v| CString::new(*v))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ptrs: Vec<*const c_char> = c_values.iter().map(|v| v.as_ptr()).collect();
+        unsafe {
+            ffi_try!(mupdf_pdf_set_choice_field_value(
+                context(),
+                self.obj.inner,
+                ptrs.as_ptr(),
+                ptrs.len() as i32
+            ));
+        }
+        Ok(())
+    }
+
+    /// Append an option to a choice field, showing `display` instead of the export value
+    /// when given
+    pub fn add_option(&mut self, export: &str, display: Option<&str>) -> Result<(), Error> {
+        let c_export = CString::new(export)?;
+        let c_display = display.map(CString::new).transpose()?;
+        unsafe {
+            ffi_try!(mupdf_pdf_add_choice_field_option(
+                context(),
+                self.obj.inner,
+                c_export.as_ptr(),
+                c_display.as_ref().map_or(std::ptr::null(), |d| d.as_ptr())
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn flags(&self) -> Result<PdfFieldFlags, Error> {
+        let key = PdfObject::new_name("Ff")?;
+        match self.obj.get_dict_inheritable(&key)? {
+            Some(flags) => Ok(PdfFieldFlags::from_bits_truncate(flags.as_int()?)),
+            None => Ok(PdfFieldFlags::empty()),
+        }
+    }
+
+    /// Replace the field flags, the `RADIO`, `PUSH_BUTTON` and `COMBO` bits change the
+    /// [`field_type`](Self::field_type)
+    pub fn set_flags(&mut self, flags: PdfFieldFlags) -> Result<(), Error> {
+        self.obj.dict_put("Ff", PdfObject::new_int(flags.bits())?)?;
+        self.need_appearances()
+    }
+
+    /// Maximum length of the text of a text field (`/MaxLen`)
+    pub fn max_len(&self) -> Result<Option<u32>, Error> {
+        let key = PdfObject::new_name("MaxLen")?;
+        match self.obj.get_dict_inheritable(&key)? {
+            Some(max_len) => Ok(Some(max_len.as_int()?.max(0) as u32)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_max_len(&mut self, max_len: Option<u32>) -> Result<(), Error> {
+        match max_len {
+            Some(max_len) => self
+                .obj
+                .dict_put("MaxLen", PdfObject::new_int(max_len as i32)?)?,
+            None => self.obj.dict_delete("MaxLen")?,
+        }
+        self.need_appearances()
+    }
+
+    /// Lay the text of a text field out in `cells` evenly spaced cells, one character each,
+    /// or turn comb layout off with `None`
+    ///
+    /// Comb fields can't be multiline, password or file select fields, those flags are
+    /// cleared.
+    pub fn set_comb(&mut self, cells: Option<u32>) -> Result<(), Error> {
+        let flags = self.flags()?;
+        match cells {
+            Some(cells) => {
+                self.set_max_len(Some(cells))?;
+                let exclusive =
+                    PdfFieldFlags::MULTILINE | PdfFieldFlags::PASSWORD | PdfFieldFlags::FILE_SELECT;
+                self.set_flags((flags - exclusive) | PdfFieldFlags::COMB)
+            }
+            None => self.set_flags(flags - PdfFieldFlags::COMB),
+        }
+    }
+
+    fn need_appearances(&self) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_field_need_appearances(context(), self.obj.inner));
+        }
+        Ok(())
+    }
+
+    /// Rect of the first widget of the field in PDF user space, `None` if it has none
+    pub fn rect(&self) -> Result<Option<Rect>, Error> {
+        let rect = match self.obj.get_dict("Kids")? {
+            Some(kids) => match kids.get_array(0)? {
+                Some(widget) => widget.get_dict("Rect")?,
+                None => None,
+            },
+            None => self.obj.get_dict("Rect")?,
+        };
+        let rect = match rect {
+            Some(rect) => floats(&rect)?,
+            None => return Ok(None),
+        };
+        if rect.len() != 4 {
+            return Ok(None);
+        }
+        Ok(Some(Rect::new(rect[0], rect[1], rect[2], rect[3])))
+    }
+
+    fn inheritable_text(&self, key: &str) -> Result<String, Error> {
+        let key = PdfObject::new_name(key)?;
+        match self.obj.get_dict_inheritable(&key)? {
+            Some(value) if value.is_name()? => Ok(value.as_name()?.to_string()),
+            Some(value) if value.is_string()? => Ok(value.as_string()?.to_string()),
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+fn push_on_states(widget: &PdfObject, states: &mut Vec<String>) -> Result<(), Error> {
+    let normal = match widget.get_dict("AP")? {
+        Some(ap) => ap.get_dict("N")?,
+        None => None,
+    };
+    let normal = match normal {
+        Some(normal) if normal.is_dict()? => normal,
+        _ => return Ok(()),
+    };
+    for i in 0..normal.dict_len()? {
+        if let Some(key) = normal.get_dict_key(i as i32)? {
+            let name = key.as_name()?;
+            if name != "Off" && !states.iter().any(|s| s == name) {
+                states.push(name.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `obj` is a terminal field, one whose kids, if any, are only its widgets
+fn is_terminal(obj: &PdfObject) -> Result<bool, Error> {
+    let kids = match obj.get_dict("Kids")? {
+        Some(kids) => kids,
+        None => return Ok(true),
+    };
+    for i in 0..kids.len()? {
+        if let Some(kid) = kids.get_array(i as i32)? {
+            if kid.get_dict("T")?.is_some() {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+pub(crate) fn collect_fields(fields: &PdfObject, out: &mut Vec<PdfField>) -> Result<(), Error> {
+    collect_fields_once(fields, out, &mut HashSet::new())
+}
+
+/// Like [`collect_fields`], skipping the fields in `seen` so that a cycle of `/Kids`
+/// references ends
+fn collect_fields_once(
+    fields: &PdfObject,
+    out: &mut Vec<PdfField>,
+    seen: &mut HashSet<i32>,
+) -> Result<(), Error> {
+    for i in 0..fields.len()? {
+        let field = match fields.get_array(i as i32)? {
+            Some(field) => field,
+            None => continue,
+        };
+        if field.is_indirect()? && !seen.insert(field.as_indirect()?) {
+            continue;
+        }
+        if is_terminal(&field)? {
+            out.push(PdfField::new(field));
+        } else if let Some(kids) = field.get_dict("Kids")? {
+            collect_fields_once(&kids, out, seen)?;
+        }
+    }
+    Ok(())
+}
+
+/// The terminal field a widget annotation belongs to, widgets without a partial name are
+/// kids of their field
+pub(crate) fn field_of_widget(widget: PdfObject) -> Result<PdfField, Error> {
+    if widget.get_dict("T")?.is_none() {
+        if let Some(parent) = widget.get_dict("Parent")? {
+            return Ok(PdfField::new(parent));
+        }
+    }
+    Ok(PdfField::new(widget))
+}
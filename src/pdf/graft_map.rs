@@ -1,15 +1,84 @@
 use mupdf_sys::*;
 
-use crate::context;
+use crate::pdf::{PdfDocument, PdfObject};
+use crate::{context, Error, Rect};
 
+/// Remembers which objects have already been copied into a destination document
+///
+/// Grafting several objects or pages through the same map copies resources they share,
+/// such as fonts and images, only once.
 #[derive(Debug)]
 pub struct PdfGraftMap {
     pub(crate) inner: *mut pdf_graft_map,
+    dst: *mut pdf_document,
 }
 
 impl PdfGraftMap {
-    pub(crate) unsafe fn from_raw(ptr: *mut pdf_graft_map) -> Self {
-        Self { inner: ptr }
+    pub(crate) unsafe fn from_raw(ptr: *mut pdf_graft_map, dst: *mut pdf_document) -> Self {
+        Self { inner: ptr, dst }
+    }
+
+    /// Deep copy `obj` from its document into the destination document
+    pub fn graft_object(&mut self, obj: &PdfObject) -> Result<PdfObject, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_pdf_graft_mapped_object(
+                context(),
+                self.inner,
+                obj.inner
+            ));
+            Ok(PdfObject::from_raw(inner, true))
+        }
+    }
+
+    /// Copy page `page_from` of `src` into the destination document at `page_to`
+    ///
+    /// Use `-1` as `page_to` to append the page at the end.
+    pub fn graft_page(
+        &mut self,
+        page_to: i32,
+        src: &PdfDocument,
+        page_from: i32,
+    ) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_graft_mapped_page(
+                context(),
+                self.inner,
+                self.dst,
+                page_to,
+                src.inner,
+                page_from
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copy page `page_from` of `src` as a form XObject drawing it, returning the XObject and
+    /// the box it draws in
+    ///
+    /// The page rotation and crop box are applied, the box starts at the origin and matches
+    /// the page bounds. Annotations are not included.
+    pub fn graft_page_as_xobject(
+        &mut self,
+        src: &PdfDocument,
+        page_from: i32,
+    ) -> Result<(PdfObject, Rect), Error> {
+        let mut bounds = fz_rect {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 0.0,
+            y1: 0.0,
+        };
+        unsafe {
+            let inner = ffi_try!(mupdf_pdf_graft_page_as_xobject(
+                context(),
+                self.inner,
+                self.dst,
+                src.inner,
+                page_from,
+                &mut bounds
+            ));
+            Ok((PdfObject::from_raw(inner, true), bounds.into()))
+        }
     }
 }
 
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use crate::pdf::PdfDocument;
+use crate::{Error, Matrix, Rect, Size};
+
+/// A source page drawn on an output sheet
+#[derive(Debug, Clone)]
+pub(crate) struct Placement {
+    pub page: i32,
+    /// Maps the source page box, starting at the origin, into sheet PDF space
+    pub matrix: Matrix,
+    /// Area of the sheet the page is clipped to, in sheet PDF space
+    pub clip: Option<Rect>,
+}
+
+/// Matrix scaling `bounds` to fit `area`, keeping its aspect ratio and centering it
+pub(crate) fn fit(bounds: Rect, area: Rect) -> Matrix {
+    let scale = (area.width() / bounds.width()).min(area.height() / bounds.height());
+    let x = area.x0 + (area.width() - bounds.width() * scale) / 2.0;
+    let y = area.y0 + (area.height() - bounds.height() * scale) / 2.0;
+    Matrix::new(scale, 0.0, 0.0, scale, x, y)
+}
+
+/// A new document with one page of the given size per sheet, drawing the placed pages of `src`
+pub(crate) fn impose(
+    src: &PdfDocument,
+    sheets: &[(Size, Vec<Placement>)],
+) -> Result<PdfDocument, Error> {
+    let mut doc = PdfDocument::new();
+    let mut map = doc.new_graft_map()?;
+    // Source page number to XObject number, each page is copied once
+    let mut forms = HashMap::new();
+    for (size, placements) in sheets {
+        let mut xobjects = doc.new_dict()?;
+        let mut contents = String::new();
+        for placement in placements {
+            let num = match forms.get(&placement.page) {
+                Some(&num) => num,
+                None => {
+                    let (xobj, _) = map.graft_page_as_xobject(src, placement.page)?;
+                    let num = xobj.as_indirect()?;
+                    forms.insert(placement.page, num);
+                    num
+                }
+            };
+            let name = format!("P{}", placement.page);
+            xobjects.dict_put(name.as_str(), doc.new_indirect(num, 0)?)?;
+
+            contents.push_str("q\n");
+            if let Some(clip) = placement.clip {
+                contents.push_str(&format!(
+                    "{} {} {} {} re W n\n",
+                    clip.x0,
+                    clip.y0,
+                    clip.width(),
+                    clip.height()
+                ));
+            }
+            let m = &placement.matrix;
+            contents.push_str(&format!(
+                "{} {} {} {} {} {} cm /{} Do\nQ\n",
+                m.a, m.b, m.c, m.d, m.e, m.f, name
+            ));
+        }
+
+        let page = doc.new_page(*size)?;
+        let mut page_obj = page.object();
+        let mut resources = doc.new_dict()?;
+        resources.dict_put("XObject", xobjects)?;
+        page_obj.dict_put("Resources", resources)?;
+        let dict = doc.new_dict()?;
+        let mut stream = doc.add_object(&dict)?;
+        stream.write_stream_string(&contents)?;
+        page_obj.dict_put("Contents", stream)?;
+    }
+    Ok(doc)
+}
+
+/// Source page pairs for each sheet side of a saddle-stitched booklet of `page_count` pages
+///
+/// Sides alternate between front and back, `None` marks blank padding at the end.
+pub(crate) fn booklet_order(page_count: i32) -> Vec<[Option<i32>; 2]> {
+    let padded = (page_count.max(0) + 3) / 4 * 4;
+    let page = |n: i32| if n < page_count { Some(n) } else { None };
+    let mut sides = Vec::with_capacity(padded as usize / 2);
+    for sheet in 0..padded / 4 {
+        let outer = 2 * sheet;
+        sides.push([page(padded - 1 - outer), page(outer)]);
+        sides.push([page(outer + 1), page(padded - 2 - outer)]);
+    }
+    sides
+}
+
+pub(crate) fn booklet(src: &PdfDocument) -> Result<PdfDocument, Error> {
+    let count = src.page_count()?;
+    if count == 0 {
+        return Ok(PdfDocument::new());
+    }
+    let cell = src.load_page(0)?.bounds()?;
+    let (w, h) = (cell.width(), cell.height());
+    let mut bounds = HashMap::new();
+    for page_no in 0..count {
+        bounds.insert(page_no, src.load_page(page_no)?.bounds()?);
+    }
+
+    let sheets: Vec<_> = booklet_order(count)
+        .iter()
+        .map(|side| {
+            let placements = side
+                .iter()
+                .enumerate()
+                .filter_map(|(i, page)| page.map(|page| (i, page)))
+                .map(|(i, page)| {
+                    let area = Rect::new(i as f32 * w, 0.0, (i + 1) as f32 * w, h);
+                    let b = bounds[&page];
+                    Placement {
+                        page,
+                        matrix: fit(Rect::new(0.0, 0.0, b.width(), b.height()), area),
+                        clip: None,
+                    }
+                })
+                .collect();
+            (Size::new(2.0 * w, h), placements)
+        })
+        .collect();
+    impose(src, &sheets)
+}
+
+/// Number of tiles of length `tile`, overlapping by `overlap`, needed to cover `length`
+pub(crate) fn tile_count(length: f32, tile: f32, overlap: f32) -> i32 {
+    if length <= tile {
+        return 1;
+    }
+    ((length - overlap) / (tile - overlap)).ceil() as i32
+}
+
+pub(crate) fn poster(src: &PdfDocument, tile: Size, overlap: f32) -> Result<PdfDocument, Error> {
+    if !(tile.width > 0.0 && tile.height > 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "tile size {}x{} is not positive",
+            tile.width, tile.height
+        )));
+    }
+    let overlap = overlap.max(0.0).min(tile.width.min(tile.height) / 2.0);
+    let mut sheets = Vec::new();
+    for page_no in 0..src.page_count()? {
+        let bounds = src.load_page(page_no)?.bounds()?;
+        let (w, h) = (bounds.width(), bounds.height());
+        let columns = tile_count(w, tile.width, overlap);
+        let rows = tile_count(h, tile.height, overlap);
+        // Row by row from the top left corner, the same reading order as the page
+        for row in 0..rows {
+            let top = h - row as f32 * (tile.height - overlap);
+            for column in 0..columns {
+                let left = column as f32 * (tile.width - overlap);
+                let placement = Placement {
+                    page: page_no,
+                    matrix: Matrix::new_translate(-left, tile.height - top),
+                    clip: None,
+                };
+                sheets.push((tile, vec![placement]));
+            }
+        }
+    }
+    impose(src, &sheets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{booklet_order, fit, tile_count};
+    use crate::{Matrix, Rect};
+
+    #[test]
+    fn test_booklet_order() {
+        assert_eq!(
+            booklet_order(4),
+            vec![[Some(3), Some(0)], [Some(1), Some(2)]]
+        );
+        assert_eq!(
+            booklet_order(6),
+            vec![
+                [None, Some(0)],
+                [Some(1), None],
+                [Some(5), Some(2)],
+                [Some(3), Some(4)]
+            ]
+        );
+        assert!(booklet_order(0).is_empty());
+    }
+
+    #[test]
+    fn test_fit() {
+        let m = fit(
+            Rect::new(0.0, 0.0, 100.0, 200.0),
+            Rect::new(0.0, 0.0, 400.0, 200.0),
+        );
+        assert_eq!(m, Matrix::new(1.0, 0.0, 0.0, 1.0, 150.0, 0.0));
+    }
+
+    #[test]
+    fn test_tile_count() {
+        assert_eq!(tile_count(500.0, 595.0, 20.0), 1);
+        assert_eq!(tile_count(595.0, 595.0, 20.0), 1);
+        assert_eq!(tile_count(1190.0, 595.0, 20.0), 3);
+        assert_eq!(tile_count(1170.0, 595.0, 20.0), 2);
+    }
+}
@@ -1,13 +1,45 @@
 mod annotation;
+#[cfg(feature = "serde_json")]
+mod annotation_json;
+mod article;
+pub(crate) mod content;
+pub(crate) mod destination;
 mod document;
+mod drawing;
+pub(crate) mod field;
 mod graft_map;
+mod imposition;
 mod object;
+mod output_intent;
 mod page;
+mod page_builder;
+pub(crate) mod page_label;
+mod pagination;
+mod watermark;
 mod widget;
+mod xfdf;
 
-pub use annotation::{LineEndingStyle, PdfAnnotation, PdfAnnotationType};
-pub use document::{PdfDocument, PdfWriteOptions, Permission};
+pub use annotation::{
+    BorderStyle, FreeTextFont, FreeTextStyle, LineEndingStyle, PdfAnnotation, PdfAnnotationFlags,
+    PdfAnnotationIter, PdfAnnotationType, PdfEmbeddedFile, ReviewState, TextAlignment,
+};
+#[cfg(feature = "serde_json")]
+pub use annotation_json::JsonAnnotation;
+pub use article::{PdfArticleBead, PdfArticleThread};
+pub use content::{PdfContentKinds, PdfContentOperation};
+pub use destination::PdfDestination;
+pub use document::{
+    EncryptionMethod, FlattenFormOptions, GarbageLevel, PdfDocument, PdfObjectIter,
+    PdfWriteOptions, Permission,
+};
+pub use drawing::PdfDrawing;
+pub use field::{PdfField, PdfFieldFlags, PdfFieldType};
 pub use graft_map::PdfGraftMap;
-pub use object::PdfObject;
-pub use page::PdfPage;
+pub use object::{PdfObject, PdfObjectType};
+pub use output_intent::PdfOutputIntent;
+pub use page::{ImageInsertOptions, PdfPage};
+pub use page_builder::PdfPageBuilder;
+pub use page_label::{PageLabelRange, PageLabelStyle};
+pub use pagination::{BatesNumbering, Pagination, PaginationPosition};
+pub use watermark::{Watermark, WatermarkPosition};
 pub use widget::{PdfWidget, PdfWidgetInner};
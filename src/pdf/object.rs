@@ -29,6 +29,20 @@ impl IntoPdfDictKey for PdfObject {
     }
 }
 
+/// Kind of value a [`PdfObject`] holds, with indirect references resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PdfObjectType {
+    Null,
+    Bool,
+    Int,
+    Real,
+    String,
+    Name,
+    Array,
+    Dict,
+    Stream,
+}
+
 #[derive(Debug)]
 pub struct PdfObject {
     pub(crate) inner: *mut pdf_obj,
@@ -76,6 +90,18 @@ impl PdfObject {
         }
     }
 
+    /// String object holding `bytes` as is, for binary data or already encoded text
+    pub fn new_byte_string(bytes: &[u8]) -> Result<PdfObject, Error> {
+        unsafe {
+            let inner = ffi_try!(mupdf_pdf_new_byte_string(
+                context(),
+                bytes.as_ptr(),
+                bytes.len()
+            ));
+            Ok(PdfObject::from_raw(inner, true))
+        }
+    }
+
     pub fn new_name(name: &str) -> Result<PdfObject, Error> {
         let c_name = CString::new(name)?;
         unsafe {
@@ -139,6 +165,29 @@ impl PdfObject {
         Ok(ret)
     }
 
+    pub fn object_type(&self) -> Result<PdfObjectType, Error> {
+        let t = if self.is_stream()? {
+            PdfObjectType::Stream
+        } else if self.is_dict()? {
+            PdfObjectType::Dict
+        } else if self.is_array()? {
+            PdfObjectType::Array
+        } else if self.is_name()? {
+            PdfObjectType::Name
+        } else if self.is_string()? {
+            PdfObjectType::String
+        } else if self.is_int()? {
+            PdfObjectType::Int
+        } else if self.is_real()? {
+            PdfObjectType::Real
+        } else if self.is_bool()? {
+            PdfObjectType::Bool
+        } else {
+            PdfObjectType::Null
+        };
+        Ok(t)
+    }
+
     pub fn as_bool(&self) -> Result<bool, Error> {
         let ret = unsafe { ffi_try!(mupdf_pdf_to_bool(context(), self.inner)) };
         Ok(ret)
@@ -159,6 +208,12 @@ impl PdfObject {
         Ok(ret)
     }
 
+    /// Generation number of an indirect reference
+    pub fn generation(&self) -> Result<i32, Error> {
+        let gen = unsafe { ffi_try!(mupdf_pdf_to_gen(context(), self.inner)) };
+        Ok(gen)
+    }
+
     pub fn as_name(&self) -> Result<&str, Error> {
         unsafe {
             let name_ptr = ffi_try!(mupdf_pdf_to_name(context(), self.inner));
@@ -168,6 +223,7 @@ impl PdfObject {
         }
     }
 
+    /// Text of a string object, decoded from PDFDocEncoding or UTF-16
     pub fn as_string(&self) -> Result<&str, Error> {
         unsafe {
             let str_ptr = ffi_try!(mupdf_pdf_to_string(context(), self.inner));
@@ -177,6 +233,7 @@ impl PdfObject {
         }
     }
 
+    /// Raw bytes of a string object
     pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
         let mut len = 0;
         unsafe {
@@ -186,6 +243,7 @@ impl PdfObject {
         }
     }
 
+    /// Follow an indirect reference to the object it points to
     pub fn resolve(&self) -> Result<Option<Self>, Error> {
         let inner = unsafe { ffi_try!(mupdf_pdf_resolve_indirect(context(), self.inner)) };
         if inner.is_null() {
@@ -194,6 +252,7 @@ impl PdfObject {
         Ok(Some(Self { inner, owned: true }))
     }
 
+    /// Data of a stream object, with its filters decoded
     pub fn read_stream(&self) -> Result<Vec<u8>, Error> {
         let inner = unsafe { ffi_try!(mupdf_pdf_read_stream(context(), self.inner)) };
         let buf = unsafe { Buffer::from_raw(inner) };
@@ -204,6 +263,7 @@ impl PdfObject {
         Ok(output)
     }
 
+    /// Data of a stream object as stored in the file
     pub fn read_raw_stream(&self) -> Result<Vec<u8>, Error> {
         let inner = unsafe { ffi_try!(mupdf_pdf_read_raw_stream(context(), self.inner)) };
         let buf = unsafe { Buffer::from_raw(inner) };
@@ -214,6 +274,30 @@ impl PdfObject {
         Ok(output)
     }
 
+    /// Replace the data of a stream object.
+    ///
+    /// With `compress` the data is stored Flate compressed, otherwise any filter is removed.
+    /// Streams live in the cross-reference table, so this fails with
+    /// [`Error::InvalidArgument`] on direct objects, add them with
+    /// [`PdfDocument::add_object`](crate::PdfDocument::add_object) first.
+    pub fn update_stream(&mut self, data: &[u8], compress: bool) -> Result<(), Error> {
+        if !self.is_indirect()? {
+            return Err(Error::InvalidArgument(
+                "only indirect objects can hold a stream".to_string(),
+            ));
+        }
+        unsafe {
+            ffi_try!(mupdf_pdf_update_stream(
+                context(),
+                self.inner,
+                data.as_ptr(),
+                data.len(),
+                compress
+            ));
+        }
+        Ok(())
+    }
+
     pub fn write_object(&mut self, obj: &PdfObject) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_write_object(context(), self.inner, obj.inner));
@@ -255,6 +339,7 @@ impl PdfObject {
         self.write_raw_stream_buffer(&buf)
     }
 
+    /// Element `index` of an array, `None` if out of bounds
     pub fn get_array(&self, index: i32) -> Result<Option<Self>, Error> {
         let inner = unsafe { ffi_try!(mupdf_pdf_array_get(context(), self.inner, index)) };
         if inner.is_null() {
@@ -263,6 +348,7 @@ impl PdfObject {
         Ok(Some(Self { inner, owned: true }))
     }
 
+    /// Value of `key` in a dictionary, `None` if missing
     pub fn get_dict(&self, key: &str) -> Result<Option<Self>, Error> {
         let c_key = CString::new(key)?;
         let inner = unsafe { ffi_try!(mupdf_pdf_dict_gets(context(), self.inner, c_key.as_ptr())) };
@@ -291,6 +377,29 @@ impl PdfObject {
         Ok(size as usize)
     }
 
+    pub fn dict_len(&self) -> Result<usize, Error> {
+        let size = unsafe { ffi_try!(mupdf_pdf_dict_len(context(), self.inner)) };
+        Ok(size as usize)
+    }
+
+    /// Key of the `index`-th entry of a dictionary.
+    pub fn get_dict_key(&self, index: i32) -> Result<Option<Self>, Error> {
+        let inner = unsafe { ffi_try!(mupdf_pdf_dict_get_key(context(), self.inner, index)) };
+        if inner.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(Self { inner, owned: true }))
+    }
+
+    /// Value of the `index`-th entry of a dictionary.
+    pub fn get_dict_val(&self, index: i32) -> Result<Option<Self>, Error> {
+        let inner = unsafe { ffi_try!(mupdf_pdf_dict_get_val(context(), self.inner, index)) };
+        if inner.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(Self { inner, owned: true }))
+    }
+
     pub fn array_put(&mut self, index: i32, value: Self) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_array_put(
@@ -337,6 +446,46 @@ impl PdfObject {
         }
         Ok(())
     }
+
+    /// Add an ExtGState `name` with the fill and stroke alpha to the resources of this page or
+    /// form XObject, unless there is one already
+    pub(crate) fn add_alpha_gstate(
+        &mut self,
+        name: &str,
+        fill: f32,
+        stroke: f32,
+    ) -> Result<(), Error> {
+        let c_name = CString::new(name)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_add_alpha_gstate(
+                context(),
+                self.inner,
+                c_name.as_ptr(),
+                fill,
+                stroke
+            ));
+        }
+        Ok(())
+    }
+
+    /// PDF syntax of the object, for inspecting it while debugging
+    ///
+    /// `pretty` spreads dictionaries and arrays over indented lines, `ascii` escapes
+    /// non-ASCII bytes in strings. Indirect references are printed as `N 0 R`, not followed.
+    pub fn to_string(&self, pretty: bool, ascii: bool) -> Result<String, Error> {
+        let mut buf = unsafe {
+            let inner = ffi_try!(mupdf_pdf_obj_to_buffer(
+                context(),
+                self.inner,
+                !pretty,
+                ascii
+            ));
+            Buffer::from_raw(inner)
+        };
+        let mut text = String::new();
+        buf.read_to_string(&mut text)?;
+        Ok(text)
+    }
 }
 
 impl Write for PdfObject {
@@ -401,3 +550,154 @@ impl TryFrom<String> for PdfObject {
         PdfObject::new_string(&s)
     }
 }
+
+#[cfg(feature = "serde_json")]
+impl PdfObject {
+    /// Convert the object tree to a [`serde_json::Value`]
+    ///
+    /// An indirect receiver is resolved first. Names become `{"name": "Type"}` objects and the
+    /// indirect references inside the tree become `{"ref": "N G R"}` objects, not followed, so
+    /// `/Parent` cycles are safe. Streams only contribute their dictionary.
+    pub fn to_json(&self) -> Result<serde_json::Value, Error> {
+        if self.is_indirect()? {
+            return match self.resolve()? {
+                Some(obj) => obj.json_value(),
+                None => Ok(serde_json::Value::Null),
+            };
+        }
+        self.json_value()
+    }
+
+    fn json_value(&self) -> Result<serde_json::Value, Error> {
+        use serde_json::{json, Map, Number, Value};
+
+        if self.is_indirect()? {
+            let reference = format!("{} {} R", self.as_indirect()?, self.generation()?);
+            return Ok(json!({ "ref": reference }));
+        }
+        let value = match self.object_type()? {
+            PdfObjectType::Null => Value::Null,
+            PdfObjectType::Bool => Value::Bool(self.as_bool()?),
+            PdfObjectType::Int => Value::Number(self.as_int()?.into()),
+            PdfObjectType::Real => {
+                Number::from_f64(self.as_float()? as f64).map_or(Value::Null, Value::Number)
+            }
+            PdfObjectType::String => Value::String(self.as_string()?.to_owned()),
+            PdfObjectType::Name => json!({ "name": self.as_name()? }),
+            PdfObjectType::Array => {
+                let mut items = Vec::with_capacity(self.len()?);
+                for i in 0..self.len()? as i32 {
+                    items.push(match self.get_array(i)? {
+                        Some(item) => item.json_value()?,
+                        None => Value::Null,
+                    });
+                }
+                Value::Array(items)
+            }
+            PdfObjectType::Dict | PdfObjectType::Stream => {
+                let mut map = Map::new();
+                for i in 0..self.dict_len()? as i32 {
+                    if let (Some(key), Some(val)) = (self.get_dict_key(i)?, self.get_dict_val(i)?) {
+                        map.insert(key.as_name()?.to_owned(), val.json_value()?);
+                    }
+                }
+                Value::Object(map)
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PdfDocument;
+
+    #[test]
+    fn test_pdf_object_read() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let trailer = doc.trailer().unwrap();
+        let root = trailer.get_dict("Root").unwrap().unwrap();
+        assert!(root.is_indirect().unwrap());
+        let catalog = root.resolve().unwrap().unwrap();
+        assert!(catalog.is_dict().unwrap());
+        assert_eq!(
+            catalog
+                .get_dict("Type")
+                .unwrap()
+                .unwrap()
+                .as_name()
+                .unwrap(),
+            "Catalog"
+        );
+        assert!(catalog.get_dict("Missing").unwrap().is_none());
+
+        let obj = doc
+            .new_object_from_str("<< /N 3 /R 1.5 /B true /S (caf\\351) /A [1 /x] >>")
+            .unwrap();
+        assert_eq!(obj.get_dict("N").unwrap().unwrap().as_int().unwrap(), 3);
+        assert_eq!(obj.get_dict("R").unwrap().unwrap().as_float().unwrap(), 1.5);
+        assert!(obj.get_dict("B").unwrap().unwrap().as_bool().unwrap());
+        let s = obj.get_dict("S").unwrap().unwrap();
+        assert_eq!(s.as_string().unwrap(), "caf\u{e9}");
+        assert_eq!(s.as_bytes().unwrap(), b"caf\xe9");
+        let array = obj.get_dict("A").unwrap().unwrap();
+        assert_eq!(array.len().unwrap(), 2);
+        assert!(array.get_array(1).unwrap().unwrap().is_name().unwrap());
+        assert!(array.get_array(2).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pdf_object_to_string() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let obj = doc.new_object_from_str("<< /A [1 2] /B 3 0 R >>").unwrap();
+        assert_eq!(obj.to_string(false, false).unwrap(), "<</A[1 2]/B 3 0 R>>");
+        let pretty = obj.to_string(true, false).unwrap();
+        assert!(pretty.contains('\n'));
+        assert!(pretty.contains("3 0 R"));
+
+        let s = doc.new_object_from_str("(caf\\351)").unwrap();
+        assert!(s.to_string(false, true).unwrap().is_ascii());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_pdf_object_json_roundtrip() {
+        use serde_json::json;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let obj = doc
+            .new_object_from_str("<< /Type /Page /N 3 /R 1.5 /S (hi) /A [true null] /P 3 0 R >>")
+            .unwrap();
+        let value = obj.to_json().unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "Type": { "name": "Page" },
+                "N": 3,
+                "R": 1.5,
+                "S": "hi",
+                "A": [true, null],
+                "P": { "ref": "3 0 R" },
+            })
+        );
+
+        let back = doc.object_from_json(&value).unwrap();
+        assert!(back.get_dict("P").unwrap().unwrap().is_indirect().unwrap());
+        assert!(back.get_dict("Type").unwrap().unwrap().is_name().unwrap());
+        assert_eq!(back.to_json().unwrap(), value);
+
+        // Strings that look like names or references stay strings
+        let tricky = json!({ "S": "/Page", "T": "3 0 R" });
+        let back = doc.object_from_json(&tricky).unwrap();
+        assert!(back.get_dict("S").unwrap().unwrap().is_string().unwrap());
+        assert!(back.get_dict("T").unwrap().unwrap().is_string().unwrap());
+        assert_eq!(back.to_json().unwrap(), tricky);
+
+        let catalog = doc.catalog().unwrap();
+        assert!(catalog.is_indirect().unwrap());
+        assert_eq!(
+            catalog.to_json().unwrap()["Type"],
+            json!({ "name": "Catalog" })
+        );
+    }
+}
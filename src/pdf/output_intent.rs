@@ -0,0 +1,63 @@
+use crate::{Colorspace, Error, PdfObject};
+
+/// An entry of the catalog's `/OutputIntents`, describing the intended print condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfOutputIntent {
+    /// Subtype, e.g. `GTS_PDFX` or `GTS_PDFA1`
+    pub subtype: String,
+    /// Name of the print condition, e.g. `FOGRA39`
+    pub identifier: String,
+    pub condition: Option<String>,
+    pub registry_name: Option<String>,
+    pub info: Option<String>,
+    /// Embedded ICC profile from `/DestOutputProfile`
+    pub profile: Option<Vec<u8>>,
+}
+
+impl PdfOutputIntent {
+    /// Colorspace of the embedded ICC profile, if any
+    pub fn colorspace(&self) -> Result<Option<Colorspace>, Error> {
+        match &self.profile {
+            Some(profile) => Ok(Some(Colorspace::from_icc(profile)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn text_of(dict: &PdfObject, key: &str) -> Result<Option<String>, Error> {
+    match dict.get_dict(key)? {
+        Some(s) if s.is_string()? => Ok(Some(s.as_string()?.to_string())),
+        _ => Ok(None),
+    }
+}
+
+pub(crate) fn load_output_intents(catalog: &PdfObject) -> Result<Vec<PdfOutputIntent>, Error> {
+    let mut intents = Vec::new();
+    let array = match catalog.get_dict("OutputIntents")? {
+        Some(a) if a.is_array()? => a,
+        _ => return Ok(intents),
+    };
+    for i in 0..array.len()? as i32 {
+        let dict = match array.get_array(i)? {
+            Some(d) if d.is_dict()? => d,
+            _ => continue,
+        };
+        let subtype = match dict.get_dict("S")? {
+            Some(s) if s.is_name()? => s.as_name()?.to_string(),
+            _ => String::new(),
+        };
+        let profile = match dict.get_dict("DestOutputProfile")? {
+            Some(p) if p.is_stream()? => Some(p.read_stream()?),
+            _ => None,
+        };
+        intents.push(PdfOutputIntent {
+            subtype,
+            identifier: text_of(&dict, "OutputConditionIdentifier")?.unwrap_or_default(),
+            condition: text_of(&dict, "OutputCondition")?,
+            registry_name: text_of(&dict, "RegistryName")?,
+            info: text_of(&dict, "Info")?,
+            profile,
+        });
+    }
+    Ok(intents)
+}
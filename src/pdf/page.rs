@@ -1,8 +1,29 @@
+use std::collections::HashSet;
+use std::ffi::CString;
 use std::ops::{Deref, DerefMut};
+use std::os::raw::c_char;
+
+use bitflags::bitflags;
 
 use mupdf_sys::*;
 
-use crate::{context, Error, Matrix, Page, PdfAnnotation, PdfObject, Rect};
+use crate::pdf::content::ContentRemoval;
+use crate::{
+    context, Colorspace, DefaultColorspaces, Error, Font, FreeTextStyle, Image, Link,
+    LinkDestination, LinkDestinationKind, Matrix, Page, PdfAnnotation, PdfAnnotationFlags,
+    PdfAnnotationIter, PdfAnnotationType, PdfContentKinds, PdfContentOperation, PdfDrawing,
+    PdfEmbeddedFile, PdfObject, PdfWidget, Point, Quad, Rect, ReviewState,
+};
+
+bitflags! {
+    /// How [`PdfPage::insert_image`] places the image
+    pub struct ImageInsertOptions: u32 {
+        /// Scale the image to fit the rect, centered, instead of stretching it
+        const KEEP_PROPORTION = 1;
+        /// Draw the image below the existing page content instead of on top of it
+        const UNDERLAY = 2;
+    }
+}
 
 #[derive(Debug)]
 pub struct PdfPage {
@@ -14,7 +35,7 @@ impl PdfPage {
     pub(crate) unsafe fn from_raw(ptr: *mut pdf_page) -> Self {
         Self {
             inner: ptr,
-            page: Page::from_raw(ptr as *mut fz_page),
+            page: Page::from_raw(ptr as *mut fz_page, &mut (*(*ptr).doc).super_),
         }
     }
 
@@ -25,6 +46,320 @@ impl PdfPage {
         }
     }
 
+    /// Highlight the text covered by `quads`, in the same coordinates as [`bounds`](Page::bounds)
+    ///
+    /// `color` has 1 (gray), 3 (RGB) or 4 (CMYK) components, `opacity` ranges from 0 to 1.
+    pub fn create_highlight(
+        &mut self,
+        quads: &[Quad],
+        color: &[f32],
+        opacity: f32,
+    ) -> Result<PdfAnnotation, Error> {
+        self.create_markup(PdfAnnotationType::Highlight, quads, color, opacity)
+    }
+
+    /// Underline the text covered by `quads`, see [`create_highlight`](Self::create_highlight)
+    pub fn create_underline(
+        &mut self,
+        quads: &[Quad],
+        color: &[f32],
+        opacity: f32,
+    ) -> Result<PdfAnnotation, Error> {
+        self.create_markup(PdfAnnotationType::Underline, quads, color, opacity)
+    }
+
+    /// Strike out the text covered by `quads`, see [`create_highlight`](Self::create_highlight)
+    pub fn create_strikeout(
+        &mut self,
+        quads: &[Quad],
+        color: &[f32],
+        opacity: f32,
+    ) -> Result<PdfAnnotation, Error> {
+        self.create_markup(PdfAnnotationType::StrikeOut, quads, color, opacity)
+    }
+
+    /// Squiggly underline below the text covered by `quads`, see
+    /// [`create_highlight`](Self::create_highlight)
+    pub fn create_squiggly(
+        &mut self,
+        quads: &[Quad],
+        color: &[f32],
+        opacity: f32,
+    ) -> Result<PdfAnnotation, Error> {
+        self.create_markup(PdfAnnotationType::Squiggly, quads, color, opacity)
+    }
+
+    /// Caret marking a text insertion point, drawn in `rect`
+    pub fn create_caret(&mut self, rect: Rect, color: &[f32]) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::Caret as i32)?;
+        annot.set_rect(rect)?;
+        annot.set_color(color)?;
+        Ok(annot)
+    }
+
+    fn create_markup(
+        &mut self,
+        subtype: PdfAnnotationType,
+        quads: &[Quad],
+        color: &[f32],
+        opacity: f32,
+    ) -> Result<PdfAnnotation, Error> {
+        let quads: Vec<fz_quad> = quads.iter().cloned().map(Into::into).collect();
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_markup_annot(
+                context(),
+                self.inner,
+                subtype as i32,
+                quads.as_ptr(),
+                quads.len() as i32,
+                color.as_ptr(),
+                color.len() as i32,
+                opacity
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Freehand drawing made of `strokes`, each a list of points in the same coordinates as
+    /// [`bounds`](Page::bounds)
+    ///
+    /// Lines are `width` points wide, `color` has 1 (gray), 3 (RGB) or 4 (CMYK) components.
+    pub fn create_ink(
+        &mut self,
+        strokes: &[Vec<Point>],
+        width: f32,
+        color: &[f32],
+    ) -> Result<PdfAnnotation, Error> {
+        let count: Vec<i32> = strokes.iter().map(|stroke| stroke.len() as i32).collect();
+        let points: Vec<fz_point> = strokes
+            .iter()
+            .flatten()
+            .map(|&point| point.into())
+            .collect();
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_ink_annot(
+                context(),
+                self.inner,
+                count.len() as i32,
+                count.as_ptr(),
+                points.as_ptr(),
+                width,
+                color.as_ptr(),
+                color.len() as i32
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Typed comment showing `text` inside `rect`, in the same coordinates as
+    /// [`bounds`](Page::bounds)
+    pub fn create_free_text(
+        &mut self,
+        rect: Rect,
+        text: &str,
+        style: &FreeTextStyle,
+    ) -> Result<PdfAnnotation, Error> {
+        let c_text = CString::new(text)?;
+        let c_font = CString::new(style.font.resource_name())?;
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_free_text_annot(
+                context(),
+                self.inner,
+                rect.into(),
+                c_text.as_ptr(),
+                c_font.as_ptr(),
+                style.size,
+                style.color.as_ptr(),
+                style.border_width,
+                style.alignment as i32
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Stamp annotation showing `image` stretched over `rect`, in the same coordinates as
+    /// [`bounds`](Page::bounds), e.g. a scanned signature
+    pub fn create_image_stamp(
+        &mut self,
+        rect: Rect,
+        image: &Image,
+    ) -> Result<PdfAnnotation, Error> {
+        let mut bbox = fz_rect {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 0.0,
+            y1: 0.0,
+        };
+        let xobj = unsafe {
+            let doc = (*self.inner).doc;
+            let inner = ffi_try!(mupdf_pdf_new_image_xobject(
+                context(),
+                doc,
+                image.inner,
+                &mut bbox
+            ));
+            PdfObject::from_raw(inner, true)
+        };
+        self.create_stamp(rect, &xobj)
+    }
+
+    /// Stamp annotation whose appearance is the form XObject `xobj`, e.g. from
+    /// [`PdfDocument::new_form_xobject`](crate::PdfDocument::new_form_xobject), scaled to `rect`
+    pub fn create_stamp(&mut self, rect: Rect, xobj: &PdfObject) -> Result<PdfAnnotation, Error> {
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_stamp_annot(
+                context(),
+                self.inner,
+                rect.into(),
+                xobj.inner
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Rectangle annotation covering `rect`, in the same coordinates as [`bounds`](Page::bounds)
+    pub fn create_square(&mut self, rect: Rect) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::Square as i32)?;
+        annot.set_rect(rect)?;
+        Ok(annot)
+    }
+
+    /// Ellipse annotation inscribed in `rect`
+    pub fn create_circle(&mut self, rect: Rect) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::Circle as i32)?;
+        annot.set_rect(rect)?;
+        Ok(annot)
+    }
+
+    /// Line annotation from `a` to `b`
+    pub fn create_line(&mut self, a: Point, b: Point) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::Line as i32)?;
+        annot.set_line(a, b)?;
+        Ok(annot)
+    }
+
+    /// Closed polygon annotation through `vertices`
+    pub fn create_polygon(&mut self, vertices: &[Point]) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::Polygon as i32)?;
+        annot.set_vertices(vertices)?;
+        Ok(annot)
+    }
+
+    /// Open polyline annotation through `vertices`
+    pub fn create_polyline(&mut self, vertices: &[Point]) -> Result<PdfAnnotation, Error> {
+        let mut annot = self.create_annotation(PdfAnnotationType::PloyLine as i32)?;
+        annot.set_vertices(vertices)?;
+        Ok(annot)
+    }
+
+    /// Paperclip icon in `rect`, in the same coordinates as [`bounds`](Page::bounds), with
+    /// `file` embedded in the document
+    pub fn create_file_attachment(
+        &mut self,
+        rect: Rect,
+        file: &PdfEmbeddedFile,
+    ) -> Result<PdfAnnotation, Error> {
+        let c_filename = CString::new(file.filename.as_str())?;
+        let c_mime_type = file.mime_type.as_deref().map(CString::new).transpose()?;
+        let c_desc = file.description.as_deref().map(CString::new).transpose()?;
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_file_attachment_annot(
+                context(),
+                self.inner,
+                rect.into(),
+                c_filename.as_ptr(),
+                file.data.as_ptr(),
+                file.data.len(),
+                c_mime_type
+                    .as_ref()
+                    .map_or(std::ptr::null(), |s| s.as_ptr()),
+                c_desc.as_ref().map_or(std::ptr::null(), |s| s.as_ptr())
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Reply to `parent` in its comment thread, shown as a note at its top left corner
+    pub fn create_reply(
+        &mut self,
+        parent: &PdfAnnotation,
+        text: &str,
+        author: &str,
+    ) -> Result<PdfAnnotation, Error> {
+        let c_text = CString::new(text)?;
+        let c_author = CString::new(author)?;
+        unsafe {
+            let annot = ffi_try!(mupdf_pdf_create_reply_annot(
+                context(),
+                self.inner,
+                parent.inner,
+                c_text.as_ptr(),
+                c_author.as_ptr()
+            ));
+            Ok(PdfAnnotation::from_raw(annot))
+        }
+    }
+
+    /// Replies to `annot`, in page order, state annotations included
+    pub fn replies(&self, annot: &PdfAnnotation) -> Result<Vec<PdfAnnotation>, Error> {
+        let mut replies = Vec::new();
+        for other in self.annotations() {
+            if let Some(parent) = other.in_reply_to()? {
+                if parent.inner == annot.inner {
+                    replies.push(other);
+                }
+            }
+        }
+        Ok(replies)
+    }
+
+    /// Record the decision `state` of `author` on `annot` as a hidden state annotation
+    /// replying to it
+    pub fn set_review_state(
+        &mut self,
+        annot: &PdfAnnotation,
+        state: ReviewState,
+        author: &str,
+    ) -> Result<PdfAnnotation, Error> {
+        let text = format!("{} set by {}", state.name(), author);
+        let mut reply = self.create_reply(annot, &text, author)?;
+        let mut obj = reply.object();
+        obj.dict_put("StateModel", PdfObject::new_string(state.model())?)?;
+        obj.dict_put("State", PdfObject::new_string(state.name())?)?;
+        reply.set_flags(
+            PdfAnnotationFlags::HIDDEN
+                | PdfAnnotationFlags::PRINT
+                | PdfAnnotationFlags::NO_ZOOM
+                | PdfAnnotationFlags::NO_ROTATE,
+        )?;
+        Ok(reply)
+    }
+
+    /// The latest `Review` model state set on `annot`, by `author` if given
+    pub fn review_state(
+        &self,
+        annot: &PdfAnnotation,
+        author: Option<&str>,
+    ) -> Result<Option<ReviewState>, Error> {
+        let mut current = None;
+        for reply in self.replies(annot)? {
+            let state = match reply.review_state()? {
+                Some(state) if state.model() == "Review" => state,
+                _ => continue,
+            };
+            if author.is_none() || reply.author()?.as_deref() == author {
+                current = Some(state);
+            }
+        }
+        Ok(current)
+    }
+
+    /// Iterate over the annotations of the page, form widgets are kept apart by MuPDF and
+    /// listed by [`widgets`](Self::widgets)
+    pub fn annotations(&self) -> PdfAnnotationIter {
+        PdfAnnotationIter::new(self)
+    }
+
     pub fn delete_annotation(&mut self, annot: &PdfAnnotation) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_delete_annot(context(), self.inner, annot.inner));
@@ -32,6 +367,115 @@ impl PdfPage {
         Ok(())
     }
 
+    /// The topmost visible annotation whose rect contains `point`, in the same coordinates as
+    /// [`bounds`](Page::bounds)
+    ///
+    /// Popups and hidden annotations are skipped, form widgets are found by
+    /// [`widget_at`](Self::widget_at).
+    pub fn annotation_at(&self, point: Point) -> Result<Option<PdfAnnotation>, Error> {
+        let mut hit = None;
+        // Later annotations are drawn on top of earlier ones
+        for annot in self.annotations() {
+            if annot.r#type()? == PdfAnnotationType::Popup
+                || annot
+                    .flags()?
+                    .intersects(PdfAnnotationFlags::HIDDEN | PdfAnnotationFlags::NO_VIEW)
+            {
+                continue;
+            }
+            if annot.rect()?.contains(point.x, point.y) {
+                hit = Some(annot);
+            }
+        }
+        Ok(hit)
+    }
+
+    /// The form widgets of the page, in page order
+    pub fn widgets(&self) -> Result<Vec<PdfWidget>, Error> {
+        PdfAnnotationIter::new_widgets(self)
+            .map(PdfWidget::from_annotation)
+            .collect()
+    }
+
+    /// The topmost form widget whose rect contains `point`
+    pub fn widget_at(&self, point: Point) -> Result<Option<PdfWidget>, Error> {
+        let mut hit = None;
+        for widget in PdfAnnotationIter::new_widgets(self) {
+            if widget.flags()?.contains(PdfAnnotationFlags::HIDDEN) {
+                continue;
+            }
+            if widget.rect()?.contains(point.x, point.y) {
+                hit = Some(widget);
+            }
+        }
+        hit.map(PdfWidget::from_annotation).transpose()
+    }
+
+    /// Delete every annotation of type `subtype`, or all of them when `None`, form widgets
+    /// are left in place
+    ///
+    /// Returns the number of annotations deleted.
+    pub fn delete_all_annotations(
+        &mut self,
+        subtype: Option<PdfAnnotationType>,
+    ) -> Result<usize, Error> {
+        let mut doomed = Vec::new();
+        for annot in self.annotations() {
+            if subtype.is_none() || subtype == Some(annot.r#type()?) {
+                doomed.push(annot);
+            }
+        }
+        for annot in &doomed {
+            self.delete_annotation(annot)?;
+        }
+        Ok(doomed.len())
+    }
+
+    /// Add a link to the external `uri` covering `rect`, in page space.
+    pub fn create_link(&mut self, rect: Rect, uri: &str) -> Result<(), Error> {
+        let c_uri = CString::new(uri)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_create_link_uri(
+                context(),
+                self.inner,
+                rect.into(),
+                c_uri.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Add a link to a page of this document covering `rect`, in page space.
+    pub fn create_link_to(&mut self, rect: Rect, dest: &LinkDestination) -> Result<(), Error> {
+        let (x, y) = match dest.kind {
+            LinkDestinationKind::XYZ => (dest.x, dest.y),
+            LinkDestinationKind::Fit => (f32::NAN, f32::NAN),
+        };
+        unsafe {
+            ffi_try!(mupdf_pdf_create_link_dest(
+                context(),
+                self.inner,
+                rect.into(),
+                dest.page,
+                x,
+                y
+            ));
+        }
+        Ok(())
+    }
+
+    /// Delete the link annotation covering the bounds of `link`.
+    pub fn delete_link(&mut self, link: &Link) -> Result<bool, Error> {
+        let deleted = unsafe {
+            ffi_try!(mupdf_pdf_delete_link(
+                context(),
+                self.inner,
+                link.bounds.into()
+            ))
+        };
+        Ok(deleted)
+    }
+
     pub fn update(&mut self) -> Result<bool, Error> {
         let ret = unsafe { ffi_try!(mupdf_pdf_update_page(context(), self.inner)) };
         Ok(ret)
@@ -42,20 +486,62 @@ impl PdfPage {
         Ok(ret)
     }
 
+    /// Rewrite the page content streams, and those of its forms and patterns, in a normalized form
+    ///
+    /// With `sanitize`, operators that are invalid or draw nothing are dropped too, like
+    /// `mutool clean -s`. Useful for repairing files from buggy generators.
+    pub fn clean_contents(&mut self, sanitize: bool) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_clean_page_contents(
+                context(),
+                self.inner,
+                sanitize
+            ));
+        }
+        Ok(())
+    }
+
+    /// Default colorspaces of the page, taken from its resources and the document output intent.
+    ///
+    /// Running the page installs these on the device, overriding any set before.
+    pub fn default_colorspaces(&self) -> Result<DefaultColorspaces, Error> {
+        unsafe {
+            let dcs = ffi_try!(mupdf_pdf_load_default_colorspaces(context(), self.inner));
+            Ok(DefaultColorspaces::from_raw(dcs))
+        }
+    }
+
     pub fn object(&self) -> PdfObject {
         unsafe { PdfObject::from_raw((*self.inner).obj, false) }
     }
 
+    /// The `/AA` additional-actions dictionary, triggered when the page is opened or closed.
+    pub fn additional_actions(&self) -> Result<Option<PdfObject>, Error> {
+        self.object().get_dict("AA")
+    }
+
+    pub fn remove_additional_actions(&mut self) -> Result<(), Error> {
+        let mut page = self.object();
+        if page.get_dict("AA")?.is_some() {
+            page.dict_delete("AA")?;
+        }
+        Ok(())
+    }
+
+    /// Clockwise rotation of the page when displayed, one of 0, 90, 180 or 270 degrees
     pub fn rotation(&self) -> Result<i32, Error> {
         if let Some(rotate) = self
             .object()
             .get_dict_inheritable(&PdfObject::new_name("Rotate")?)?
         {
-            return rotate.as_int();
+            return Ok(rotate.as_int()?.rem_euclid(360));
         }
         Ok(0)
     }
 
+    /// Store a clockwise display rotation in the page `/Rotate`, saved with the document
+    ///
+    /// `rotate` must be a multiple of 90, negative values and full turns are normalized.
     pub fn set_rotation(&mut self, rotate: i32) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_pdf_page_set_rotation(context(), self.inner, rotate));
@@ -63,11 +549,13 @@ impl PdfPage {
         Ok(())
     }
 
+    /// The `/MediaBox` in PDF user space, with y going up
     pub fn media_box(&self) -> Result<Rect, Error> {
         let rect = unsafe { mupdf_pdf_page_media_box(context(), self.inner) };
         Ok(rect.into())
     }
 
+    /// The visible area of the page, with y going down from the top of the media box
     pub fn crop_box(&self) -> Result<Rect, Error> {
         let bounds = self.bounds()?;
         let pos = unsafe { mupdf_pdf_page_crop_box_position(context(), self.inner) };
@@ -90,12 +578,406 @@ impl PdfPage {
         Ok(())
     }
 
+    /// Crop the page to [`Page::content_bbox`] grown by `margin` on each side, within the current
+    /// crop box
+    ///
+    /// Blank pages are left as they are, returns whether the page was cropped.
+    pub fn crop_to_content(&mut self, margin: f32) -> Result<bool, Error> {
+        let cropped = unsafe {
+            ffi_try!(mupdf_pdf_page_crop_to_content(
+                context(),
+                self.inner,
+                margin
+            ))
+        };
+        Ok(cropped)
+    }
+
+    /// Replace the `/MediaBox`, given in PDF user space with y going up
+    pub fn set_media_box(&mut self, media_box: Rect) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_page_set_media_box(
+                context(),
+                self.inner,
+                media_box.into()
+            ));
+        }
+        Ok(())
+    }
+
+    fn page_box(&self, name: &str) -> Result<Rect, Error> {
+        let c_name = CString::new(name)?;
+        let rect = unsafe { ffi_try!(mupdf_pdf_page_box(context(), self.inner, c_name.as_ptr())) };
+        Ok(rect.into())
+    }
+
+    fn set_page_box(&mut self, name: &str, rect: Rect) -> Result<(), Error> {
+        let c_name = CString::new(name)?;
+        unsafe {
+            ffi_try!(mupdf_pdf_page_set_box(
+                context(),
+                self.inner,
+                c_name.as_ptr(),
+                rect.into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `/BleedBox`, defaulting to the crop box, in the same coordinates as [`crop_box`](Self::crop_box)
+    pub fn bleed_box(&self) -> Result<Rect, Error> {
+        self.page_box("BleedBox")
+    }
+
+    pub fn set_bleed_box(&mut self, bleed_box: Rect) -> Result<(), Error> {
+        self.set_page_box("BleedBox", bleed_box)
+    }
+
+    /// The `/TrimBox`, defaulting to the crop box, in the same coordinates as [`crop_box`](Self::crop_box)
+    pub fn trim_box(&self) -> Result<Rect, Error> {
+        self.page_box("TrimBox")
+    }
+
+    pub fn set_trim_box(&mut self, trim_box: Rect) -> Result<(), Error> {
+        self.set_page_box("TrimBox", trim_box)
+    }
+
+    /// The `/ArtBox`, defaulting to the crop box, in the same coordinates as [`crop_box`](Self::crop_box)
+    pub fn art_box(&self) -> Result<Rect, Error> {
+        self.page_box("ArtBox")
+    }
+
+    pub fn set_art_box(&mut self, art_box: Rect) -> Result<(), Error> {
+        self.set_page_box("ArtBox", art_box)
+    }
+
+    /// Draw the existing page content through `matrix`, given in PDF user space with y going up
+    ///
+    /// The crop, bleed, trim and art boxes move along with the content and are clipped to the
+    /// media box, which is left as is. Use it with [`set_media_box`](Self::set_media_box) to
+    /// scale pages to another paper size, or with a translation to shift margins for binding.
+    /// Annotations are not moved.
+    pub fn transform_content(&mut self, matrix: &Matrix) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_page_transform_content(
+                context(),
+                self.inner,
+                matrix.into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Draw `image` into `rect`, in the same coordinates as [`bounds`](Page::bounds)
+    ///
+    /// The image is added to the document and the page resources as a new image XObject.
+    pub fn insert_image(
+        &mut self,
+        rect: Rect,
+        image: &Image,
+        options: ImageInsertOptions,
+    ) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_page_insert_image(
+                context(),
+                self.inner,
+                rect.into(),
+                image.inner,
+                options.contains(ImageInsertOptions::KEEP_PROPORTION),
+                !options.contains(ImageInsertOptions::UNDERLAY)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write `text` on one line on top of the page content, starting at the baseline point `pos`
+    /// in the same coordinates as [`bounds`](Page::bounds)
+    ///
+    /// `font` is embedded in the document as a whole, MuPDF does not subset fonts.
+    pub fn insert_text(
+        &mut self,
+        pos: Point,
+        text: &str,
+        font: &Font,
+        size: f32,
+        cs: &Colorspace,
+        color: &[f32],
+    ) -> Result<(), Error> {
+        self.insert_text_lines(&[(pos, text)], font, size, cs, color)
+    }
+
+    /// [`insert_text`](Self::insert_text) for several lines at once, added to the page as a
+    /// single content stream
+    pub(crate) fn insert_text_lines(
+        &mut self,
+        lines: &[(Point, &str)],
+        font: &Font,
+        size: f32,
+        cs: &Colorspace,
+        color: &[f32],
+    ) -> Result<(), Error> {
+        let points: Vec<fz_point> = lines.iter().map(|(pos, _)| (*pos).into()).collect();
+        let c_lines = lines
+            .iter()
+            .map(|(_, text)| CString::new(*text))
+            .collect::<Result<Vec<_>, _>>()?;
+        let c_ptrs: Vec<*const c_char> = c_lines.iter().map(|s| s.as_ptr()).collect();
+        unsafe {
+            ffi_try!(mupdf_pdf_page_insert_text(
+                context(),
+                self.inner,
+                lines.len() as i32,
+                points.as_ptr(),
+                c_ptrs.as_ptr(),
+                font.inner,
+                size,
+                cs.inner,
+                color.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Draw `drawing` on top of the page content
+    ///
+    /// Fails with [`Error::InvalidArgument`] if `drawing` was given an invalid color.
+    pub fn draw(&mut self, drawing: &PdfDrawing) -> Result<(), Error> {
+        if let Some(msg) = &drawing.error {
+            return Err(Error::InvalidArgument(msg.clone()));
+        }
+        for (name, fill, stroke) in &drawing.alphas {
+            self.object().add_alpha_gstate(name, *fill, *stroke)?;
+        }
+        let c_content = CString::new(drawing.content.as_str())?;
+        unsafe {
+            ffi_try!(mupdf_pdf_page_append_content(
+                context(),
+                self.inner,
+                c_content.as_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Draw the form XObject `xobj` on top of the page content, stretched to fill `rect` in the
+    /// same coordinates as [`bounds`](Page::bounds)
+    ///
+    /// Fails with [`Error::InvalidArgument`] if `xobj` has no `/BBox` or draws nothing.
+    pub fn show_xobject(&mut self, xobj: &PdfObject, rect: Rect) -> Result<(), Error> {
+        let bbox = match xobj.get_dict("BBox")? {
+            Some(bbox) => rect_from_array(&bbox)?,
+            None => {
+                return Err(Error::InvalidArgument(
+                    "form XObject has no BBox".to_string(),
+                ))
+            }
+        };
+        let form_matrix = match xobj.get_dict("Matrix")? {
+            Some(m) => {
+                let mut v = [0.0; 6];
+                for (i, x) in v.iter_mut().enumerate() {
+                    *x = m.get_array(i as i32)?.map_or(Ok(0.0), |x| x.as_float())?;
+                }
+                Matrix::new(v[0], v[1], v[2], v[3], v[4], v[5])
+            }
+            None => Matrix::IDENTITY,
+        };
+        // Map the drawn box, with y going up, onto `rect` with y going down
+        let drawn = bbox.transform(&form_matrix);
+        if !(drawn.width() > 0.0 && drawn.height() > 0.0) {
+            return Err(Error::InvalidArgument(format!(
+                "form XObject draws into the empty box {:?}",
+                drawn
+            )));
+        }
+        let mut m = Matrix::new_translate(-drawn.x0, -drawn.y1);
+        m.concat(Matrix::new_scale(
+            rect.width() / drawn.width(),
+            -rect.height() / drawn.height(),
+        ));
+        m.concat(Matrix::new_translate(rect.x0, rect.y0));
+        self.insert_xobject(xobj, &m, None, true)
+    }
+
+    /// Enclose the page contents in `q` and `Q`, adding more of them when the contents leave
+    /// the graphics state unbalanced, so content added after them starts from the default state
+    pub fn wrap_contents(&mut self) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_page_wrap_contents(context(), self.inner));
+        }
+        Ok(())
+    }
+
+    /// Append content stream operators in PDF user space on top of the page content
+    ///
+    /// The page contents are wrapped as by [`wrap_contents`](Self::wrap_contents) and `content`
+    /// is balanced the same way, so neither changes the graphics state of the other.
+    pub fn append_content(&mut self, content: &[u8]) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_pdf_page_append_raw_content(
+                context(),
+                self.inner,
+                content.as_ptr(),
+                content.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The operators of the page content streams, in order
+    ///
+    /// Form XObjects drawn with `Do` are not entered.
+    pub fn content_operations(&self) -> Result<Vec<PdfContentOperation>, Error> {
+        let ops = unsafe {
+            let inner = ffi_try!(mupdf_pdf_page_content_operations(context(), self.inner));
+            PdfObject::from_raw(inner, true)
+        };
+        let len = ops.len()?;
+        let mut operations = Vec::with_capacity(len);
+        for i in 0..len {
+            let op = ops.get_array(i as i32)?.ok_or(Error::InvalidPdfDocument)?;
+            let n = op.len()? as i32;
+            let operator = match op.get_array(n - 1)? {
+                Some(name) => name.as_name()?.to_string(),
+                None => return Err(Error::InvalidPdfDocument),
+            };
+            let mut operands = Vec::with_capacity(n as usize - 1);
+            for j in 0..n - 1 {
+                operands.extend(op.get_array(j)?);
+            }
+            operations.push(PdfContentOperation { operator, operands });
+        }
+        Ok(operations)
+    }
+
+    /// Replace the page contents by a single stream of `operations`
+    ///
+    /// The page resources are left as they are. Operators that are not a single PDF token,
+    /// and `BI` without an image dictionary and data string, are an [`Error::InvalidArgument`].
+    pub fn set_content_operations(
+        &mut self,
+        operations: Vec<PdfContentOperation>,
+    ) -> Result<(), Error> {
+        for operation in &operations {
+            operation.validate().map_err(Error::InvalidArgument)?;
+        }
+        unsafe {
+            let doc = (*self.inner).doc;
+            let mut ops = PdfObject::from_raw(
+                ffi_try!(mupdf_pdf_new_array(context(), doc, operations.len() as i32)),
+                true,
+            );
+            for operation in operations {
+                let mut op = PdfObject::from_raw(
+                    ffi_try!(mupdf_pdf_new_array(
+                        context(),
+                        doc,
+                        operation.operands.len() as i32 + 1
+                    )),
+                    true,
+                );
+                for operand in operation.operands {
+                    op.array_push(operand)?;
+                }
+                op.array_push(PdfObject::new_name(&operation.operator)?)?;
+                ops.array_push(op)?;
+            }
+            ffi_try!(mupdf_pdf_page_set_content_operations(
+                context(),
+                self.inner,
+                ops.inner
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rewrite the page contents by passing each operator to `filter`, which can change it
+    /// in place and returns whether to keep it
+    pub fn filter_contents<F>(&mut self, mut filter: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut PdfContentOperation) -> bool,
+    {
+        let mut operations = Vec::new();
+        for mut operation in self.content_operations()? {
+            if filter(&mut operation) {
+                operations.push(operation);
+            }
+        }
+        self.set_content_operations(operations)
+    }
+
+    /// Remove all content of `kinds` from the page content streams, for text only or image only
+    /// copies of a page
+    ///
+    /// Content inside form XObjects is not removed, annotations are left as they are.
+    pub fn remove_contents(&mut self, kinds: PdfContentKinds) -> Result<(), Error> {
+        let mut images = HashSet::new();
+        let xobjects = match self
+            .object()
+            .get_dict_inheritable(&PdfObject::new_name("Resources")?)?
+        {
+            Some(resources) => resources.get_dict("XObject")?,
+            None => None,
+        };
+        if let Some(xobjects) = xobjects {
+            for i in 0..xobjects.dict_len()? as i32 {
+                let key = xobjects.get_dict_key(i)?;
+                let val = xobjects.get_dict_val(i)?;
+                if let (Some(key), Some(val)) = (key, val) {
+                    let subtype = val.get_dict("Subtype")?;
+                    if subtype.map_or(Ok(false), |s| s.as_name().map(|s| s == "Image"))? {
+                        images.insert(key.as_name()?.to_string());
+                    }
+                }
+            }
+        }
+        let mut removal = ContentRemoval::new(kinds, images);
+        self.filter_contents(|op| removal.keep(op))
+    }
+
+    /// Draw the form XObject `xobj` through `matrix`, in page coordinates
+    pub(crate) fn insert_xobject(
+        &mut self,
+        xobj: &PdfObject,
+        matrix: &Matrix,
+        gstate: Option<&str>,
+        overlay: bool,
+    ) -> Result<(), Error> {
+        let c_gstate = gstate.map(CString::new).transpose()?;
+        unsafe {
+            ffi_try!(mupdf_pdf_page_insert_xobject(
+                context(),
+                self.inner,
+                xobj.inner,
+                matrix.into(),
+                c_gstate.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+                overlay
+            ));
+        }
+        Ok(())
+    }
+
     pub fn ctm(&self) -> Result<Matrix, Error> {
         let m = unsafe { ffi_try!(mupdf_pdf_page_transform(context(), self.inner)) };
         Ok(m.into())
     }
 }
 
+fn rect_from_array(array: &PdfObject) -> Result<Rect, Error> {
+    let mut v = [0.0; 4];
+    for (i, x) in v.iter_mut().enumerate() {
+        *x = array
+            .get_array(i as i32)?
+            .map_or(Ok(0.0), |x| x.as_float())?;
+    }
+    Ok(Rect::new(
+        v[0].min(v[2]),
+        v[1].min(v[3]),
+        v[0].max(v[2]),
+        v[1].max(v[3]),
+    ))
+}
+
 impl Deref for PdfPage {
     type Target = Page;
 
@@ -155,4 +1037,851 @@ mod test {
         let crop_box = page0.crop_box().unwrap();
         assert_eq!(crop_box, Rect::new(100.0, 100.0, 400.0, 400.0));
     }
+
+    #[test]
+    fn test_page_boxes() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let full = Rect::new(0.0, 0.0, 595.0, 842.0);
+        assert_eq!(page0.trim_box().unwrap(), full);
+        assert_eq!(page0.bleed_box().unwrap(), full);
+        assert_eq!(page0.art_box().unwrap(), full);
+
+        let crop = Rect::new(10.0, 20.0, 500.0, 800.0);
+        page0.set_crop_box(crop).unwrap();
+        assert_eq!(page0.trim_box().unwrap(), crop);
+        let trim = Rect::new(20.0, 30.0, 490.0, 790.0);
+        page0.set_trim_box(trim).unwrap();
+        assert_eq!(page0.trim_box().unwrap(), trim);
+        let trim_obj = page0.object().get_dict("TrimBox").unwrap().unwrap();
+        assert_eq!(
+            trim_obj.get_array(1).unwrap().unwrap().as_float().unwrap(),
+            52.0
+        );
+        page0.set_bleed_box(full).unwrap();
+        page0.set_art_box(trim).unwrap();
+        assert_eq!(page0.bleed_box().unwrap(), full);
+        assert_eq!(page0.art_box().unwrap(), trim);
+
+        let media = Rect::new(0.0, 0.0, 300.0, 400.0);
+        page0.set_media_box(media).unwrap();
+        assert_eq!(page0.media_box().unwrap(), media);
+        assert_eq!(
+            page0.bleed_box().unwrap(),
+            Rect::new(0.0, 0.0, 300.0, 400.0)
+        );
+    }
+
+    #[test]
+    fn test_page_crop_to_content() {
+        use crate::Size;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let content = page0.content_bbox().unwrap();
+        assert!(!content.is_empty());
+        assert!(page0.crop_to_content(5.0).unwrap());
+        let bounds = page0.bounds().unwrap();
+        assert!((bounds.width() - (content.width() + 10.0)).abs() < 0.01);
+        assert!((bounds.height() - (content.height() + 10.0)).abs() < 0.01);
+
+        let mut pdf = PdfDocument::new();
+        let mut blank = pdf.new_page(Size::A4).unwrap();
+        assert!(blank.content_bbox().unwrap().is_empty());
+        assert!(!blank.crop_to_content(5.0).unwrap());
+    }
+
+    #[test]
+    fn test_page_transform_content() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let before = page0.content_bbox().unwrap();
+        page0
+            .transform_content(&Matrix::new_scale(0.5, 0.5))
+            .unwrap();
+        let after = page0.content_bbox().unwrap();
+        assert!((after.width() - before.width() / 2.0).abs() < 0.5);
+        assert!((after.x0 - before.x0 / 2.0).abs() < 0.5);
+        let bounds = page0.bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (595.0, 842.0));
+    }
+
+    #[test]
+    fn test_page_insert_image() {
+        use super::ImageInsertOptions;
+        use crate::{Colorspace, Image, Pixmap, Size};
+
+        let mut pixmap = Pixmap::new_with_w_h(&Colorspace::device_rgb(), 20, 10, false).unwrap();
+        pixmap.clear().unwrap();
+        let image = Image::from_pixmap(&pixmap).unwrap();
+
+        let mut pdf = PdfDocument::new();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        page.insert_image(
+            Rect::new(100.0, 100.0, 300.0, 300.0),
+            &image,
+            ImageInsertOptions::KEEP_PROPORTION,
+        )
+        .unwrap();
+        let content = page.content_bbox().unwrap();
+        assert_eq!(content, Rect::new(100.0, 150.0, 300.0, 250.0));
+
+        page.insert_image(
+            Rect::new(0.0, 0.0, 50.0, 50.0),
+            &image,
+            ImageInsertOptions::UNDERLAY,
+        )
+        .unwrap();
+        let xobjects = page
+            .object()
+            .get_dict("Resources")
+            .unwrap()
+            .unwrap()
+            .get_dict("XObject")
+            .unwrap()
+            .unwrap();
+        assert_eq!(xobjects.dict_len().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_page_insert_text() {
+        use crate::{Colorspace, Font, Point, TextPageOptions};
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let font = Font::new("Helvetica").unwrap();
+        page0
+            .insert_text(
+                Point::new(50.0, 800.0),
+                "Inserted label",
+                &font,
+                12.0,
+                &Colorspace::device_rgb(),
+                &[0.0, 0.0, 1.0],
+            )
+            .unwrap();
+
+        let mut output = Vec::new();
+        doc.write_to(&mut output).unwrap();
+        let saved = PdfDocument::from_bytes(&output).unwrap();
+        let text_page = saved
+            .load_page(0)
+            .unwrap()
+            .to_text_page(TextPageOptions::empty())
+            .unwrap();
+        let mut text = String::new();
+        for block in text_page.blocks() {
+            for line in block.lines() {
+                text.extend(line.chars().filter_map(|ch| ch.char()));
+            }
+        }
+        assert!(text.contains("Inserted label"));
+    }
+
+    #[test]
+    fn test_page_draw() {
+        use crate::{PdfDrawing, Size};
+
+        let mut pdf = PdfDocument::new();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        let mut drawing = PdfDrawing::new();
+        drawing
+            .set_fill_color(&[0.0, 0.0, 1.0])
+            .set_alpha(0.5, 0.5)
+            .move_to(100.0, 100.0)
+            .line_to(200.0, 100.0)
+            .curve_to(250.0, 100.0, 250.0, 200.0, 200.0, 200.0)
+            .close_path()
+            .fill(false);
+        page.draw(&drawing).unwrap();
+
+        let content = page.content_bbox().unwrap();
+        assert!((content.x0 - 100.0).abs() < 1.0 && (content.y0 - 100.0).abs() < 1.0);
+        assert!((content.y1 - 200.0).abs() < 1.0);
+        let gstates = page
+            .object()
+            .get_dict("Resources")
+            .unwrap()
+            .unwrap()
+            .get_dict("ExtGState")
+            .unwrap()
+            .unwrap();
+        assert_eq!(gstates.dict_len().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_page_content_operations() {
+        use crate::{PdfDrawing, PdfObject, Size};
+
+        let mut pdf = PdfDocument::new();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        let mut drawing = PdfDrawing::new();
+        drawing
+            .set_stroke_color(&[1.0, 0.0, 0.0])
+            .rect(Rect::new(10.0, 10.0, 20.0, 20.0))
+            .stroke();
+        page.draw(&drawing).unwrap();
+
+        let ops = page.content_operations().unwrap();
+        let stroke = ops.iter().find(|op| op.operator == "RG").unwrap();
+        assert_eq!(stroke.operands.len(), 3);
+        assert_eq!(stroke.operands[0].as_float().unwrap(), 1.0);
+        assert!(ops.iter().any(|op| op.operator == "re"));
+
+        // Recolor the stroke to blue and drop the rectangle path
+        page.filter_contents(|op| {
+            if op.operator == "RG" {
+                op.operands = vec![
+                    PdfObject::new_int(0).unwrap(),
+                    PdfObject::new_int(0).unwrap(),
+                    PdfObject::new_int(1).unwrap(),
+                ];
+            }
+            op.operator != "re"
+        })
+        .unwrap();
+        let ops = page.content_operations().unwrap();
+        let stroke = ops.iter().find(|op| op.operator == "RG").unwrap();
+        assert_eq!(stroke.operands[2].as_int().unwrap(), 1);
+        assert!(!ops.iter().any(|op| op.operator == "re"));
+
+        // CRLF after ID is not part of the image data
+        page.append_content(b"BI /W 1 /H 1 /BPC 8 /CS /G ID\r\n\x7f EI\n")
+            .unwrap();
+        let ops = page.content_operations().unwrap();
+        let image = ops.iter().find(|op| op.operator == "BI").unwrap();
+        assert_eq!(image.operands[1].as_bytes().unwrap(), b"\x7f");
+
+        let result = page.filter_contents(|op| {
+            if op.operator == "S" {
+                op.operator = "S 0 0 m".to_string();
+            }
+            true
+        });
+        assert!(matches!(result, Err(crate::Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_page_remove_contents() {
+        use crate::PdfContentKinds;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        assert!(!page0.content_bbox().unwrap().is_empty());
+        page0.remove_contents(PdfContentKinds::TEXT).unwrap();
+        assert!(!page0
+            .content_operations()
+            .unwrap()
+            .iter()
+            .any(|op| op.operator == "Tj" || op.operator == "TJ"));
+    }
+
+    #[test]
+    fn test_page_append_content() {
+        use crate::Size;
+
+        let mut pdf = PdfDocument::new();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        // Leaves a saved state and a scaled matrix behind
+        page.append_content(b"q 2 0 0 2 0 0 cm Q Q q 0.5 0 0 0.5 0 0 cm")
+            .unwrap();
+        page.append_content(b"0 0 100 100 re f").unwrap();
+        page.wrap_contents().unwrap();
+
+        let mut depth = 0;
+        for op in page.content_operations().unwrap() {
+            match op.operator.as_str() {
+                "q" => depth += 1,
+                "Q" => {
+                    depth -= 1;
+                    assert!(depth >= 0);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(depth, 0);
+        let content = page.content_bbox().unwrap();
+        assert_eq!(content, Rect::new(0.0, 742.0, 100.0, 842.0));
+    }
+
+    #[test]
+    fn test_page_wrap_unbalanced_contents() {
+        use crate::Size;
+
+        let mut pdf = PdfDocument::new();
+        let mut page = pdf.new_page(Size::A4).unwrap();
+        // Two restores without a save, then two saves without a restore
+        page.append_content(b"Q Q 0 0 10 10 re f q q 1 0 0 1 5 5 cm")
+            .unwrap();
+        page.wrap_contents().unwrap();
+        page.wrap_contents().unwrap();
+
+        let mut depth = 0;
+        for op in page.content_operations().unwrap() {
+            match op.operator.as_str() {
+                "q" => depth += 1,
+                "Q" => {
+                    depth -= 1;
+                    assert!(depth >= 0);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn test_page_set_rotation() {
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        page0.set_rotation(-90).unwrap();
+        assert_eq!(page0.rotation().unwrap(), 270);
+        page0.set_rotation(450).unwrap();
+        assert_eq!(page0.rotation().unwrap(), 90);
+        assert!(page0.set_rotation(45).is_err());
+        let bounds = page0.bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (842.0, 595.0));
+
+        let mut output = Vec::new();
+        doc.write_to(&mut output).unwrap();
+        let saved = PdfDocument::from_bytes(&output).unwrap();
+        let page0 = PdfPage::from(saved.load_page(0).unwrap());
+        assert_eq!(page0.rotation().unwrap(), 90);
+    }
+
+    #[test]
+    fn test_page_clean_contents() {
+        use crate::Colorspace;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let mut page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let cs = Colorspace::device_rgb();
+        let render = |page: &PdfPage| {
+            page.to_pixmap(&Matrix::IDENTITY, &cs, 0.0, false)
+                .unwrap()
+                .digest()
+        };
+        let before = render(&page0);
+        page0.clean_contents(true).unwrap();
+
+        let contents = page0.object().get_dict("Contents").unwrap().unwrap();
+        assert!(contents.is_stream().unwrap());
+        let page0 = PdfPage::from(doc.load_page(0).unwrap());
+        assert_eq!(render(&page0), before);
+    }
+
+    #[test]
+    fn test_page_default_colorspaces() {
+        use crate::Colorspace;
+
+        let doc = PdfDocument::open("tests/files/dummy.pdf").unwrap();
+        let page0 = PdfPage::from(doc.load_page(0).unwrap());
+        let dcs = page0.default_colorspaces().unwrap();
+        assert_eq!(dcs.rgb(), Colorspace::device_rgb());
+        assert!(dcs.output_intent().is_none());
+    }
+
+    #[test]
+    fn test_page_create_links() {
+        use crate::{LinkDestination, LinkDestinationKind, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        doc.new_page(Size::A4).unwrap();
+
+        let rect = Rect::new(10.0, 10.0, 110.0, 30.0);
+        page0.create_link(rect, "https://mupdf.com").unwrap();
+        let dest = LinkDestination {
+            page: 1,
+            x: 50.0,
+            y: 100.0,
+            kind: LinkDestinationKind::XYZ,
+        };
+        page0
+            .create_link_to(Rect::new(10.0, 50.0, 110.0, 70.0), &dest)
+            .unwrap();
+
+        let links: Vec<_> = page0.links().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].uri, "https://mupdf.com");
+        assert!(links[0].is_external());
+        assert_eq!(links[1].page, Some(1));
+
+        assert!(page0.delete_link(&links[0]).unwrap());
+        let links: Vec<_> = page0.links().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].page, Some(1));
+    }
+
+    #[test]
+    fn test_page_annotations() {
+        use crate::{PdfAnnotationType, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        assert_eq!(page0.annotations().count(), 0);
+
+        page0
+            .create_annotation(PdfAnnotationType::Text as i32)
+            .unwrap();
+        page0
+            .create_annotation(PdfAnnotationType::Square as i32)
+            .unwrap();
+
+        let annots: Vec<_> = page0.annotations().collect();
+        assert_eq!(annots.len(), 2);
+        assert_eq!(annots[0].r#type().unwrap(), PdfAnnotationType::Text);
+        assert_eq!(annots[1].r#type().unwrap(), PdfAnnotationType::Square);
+        assert!(!annots[0].rect().unwrap().is_empty());
+        assert_eq!(annots[1].contents().unwrap(), "");
+    }
+
+    #[test]
+    fn test_page_create_markup_annotations() {
+        use crate::{PdfAnnotationType, Quad, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let quad = Quad::new(50.0, 100.0, 200.0, 100.0, 50.0, 115.0, 200.0, 115.0);
+
+        let highlight = page0
+            .create_highlight(&[quad.clone()], &[1.0, 1.0, 0.0], 0.5)
+            .unwrap();
+        assert_eq!(highlight.r#type().unwrap(), PdfAnnotationType::Highlight);
+        assert_eq!(highlight.color().unwrap(), vec![1.0, 1.0, 0.0]);
+        let rect = highlight.rect().unwrap();
+        assert!(rect.x0 <= 50.0 && rect.x1 >= 200.0);
+
+        page0
+            .create_underline(&[quad.clone()], &[0.0, 0.0, 1.0], 1.0)
+            .unwrap();
+        page0
+            .create_strikeout(&[quad], &[1.0, 0.0, 0.0], 1.0)
+            .unwrap();
+        let types: Vec<_> = page0
+            .annotations()
+            .map(|annot| annot.r#type().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                PdfAnnotationType::Highlight,
+                PdfAnnotationType::Underline,
+                PdfAnnotationType::StrikeOut
+            ]
+        );
+    }
+
+    #[test]
+    fn test_page_create_ink() {
+        use crate::{PdfAnnotationType, Point, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let strokes = vec![
+            vec![Point::new(100.0, 100.0), Point::new(150.0, 120.0)],
+            vec![
+                Point::new(100.0, 200.0),
+                Point::new(120.0, 180.0),
+                Point::new(140.0, 220.0),
+            ],
+        ];
+        let ink = page0.create_ink(&strokes, 2.0, &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(ink.r#type().unwrap(), PdfAnnotationType::Ink);
+        assert_eq!(ink.color().unwrap(), vec![0.0, 0.0, 1.0]);
+        let rect = ink.rect().unwrap();
+        assert!(rect.x0 <= 100.0 && rect.y0 <= 100.0);
+        assert!(rect.x1 >= 150.0 && rect.y1 >= 220.0);
+    }
+
+    #[test]
+    fn test_page_create_free_text() {
+        use crate::{FreeTextFont, FreeTextStyle, PdfAnnotationType, Size, TextAlignment};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let style = FreeTextStyle {
+            font: FreeTextFont::Courier,
+            size: 14.0,
+            color: [1.0, 0.0, 0.0],
+            alignment: TextAlignment::Center,
+            ..FreeTextStyle::default()
+        };
+        let rect = Rect::new(100.0, 100.0, 300.0, 150.0);
+        let annot = page0
+            .create_free_text(rect, "Please check", &style)
+            .unwrap();
+        assert_eq!(annot.r#type().unwrap(), PdfAnnotationType::FreeText);
+        assert_eq!(annot.contents().unwrap(), "Please check");
+        assert_eq!(annot.rect().unwrap(), rect);
+    }
+
+    #[test]
+    fn test_page_create_stamp() {
+        use crate::{Colorspace, Image, PdfAnnotationType, PdfDrawing, Pixmap, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut drawing = PdfDrawing::new();
+        drawing
+            .set_fill_color(&[1.0, 0.0, 0.0])
+            .rect(Rect::new(0.0, 0.0, 100.0, 40.0))
+            .fill(false);
+        let xobj = doc
+            .new_form_xobject(Size::new(100.0, 40.0), &drawing)
+            .unwrap();
+
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let rect = Rect::new(300.0, 50.0, 500.0, 130.0);
+        let stamp = page0.create_stamp(rect, &xobj).unwrap();
+        assert_eq!(stamp.r#type().unwrap(), PdfAnnotationType::Stamp);
+        assert_eq!(stamp.rect().unwrap(), rect);
+
+        let mut pixmap = Pixmap::new_with_w_h(&Colorspace::device_rgb(), 8, 8, false).unwrap();
+        pixmap.clear().unwrap();
+        let image = Image::from_pixmap(&pixmap).unwrap();
+        page0
+            .create_image_stamp(Rect::new(50.0, 50.0, 150.0, 150.0), &image)
+            .unwrap();
+        assert_eq!(page0.annotations().count(), 2);
+    }
+
+    #[test]
+    fn test_page_create_geometric_annotations() {
+        use crate::{LineEndingStyle, PdfAnnotationType, Point, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+
+        let rect = Rect::new(100.0, 100.0, 200.0, 150.0);
+        let mut square = page0.create_square(rect).unwrap();
+        square.set_interior_color(&[0.0, 1.0, 0.0]).unwrap();
+        square.set_border_width(3.0).unwrap();
+        assert_eq!(square.r#type().unwrap(), PdfAnnotationType::Square);
+        assert_eq!(square.interior_color().unwrap(), vec![0.0, 1.0, 0.0]);
+        assert_eq!(square.border_width().unwrap(), 3.0);
+
+        let circle = page0.create_circle(rect).unwrap();
+        assert_eq!(circle.r#type().unwrap(), PdfAnnotationType::Circle);
+
+        let (a, b) = (Point::new(50.0, 300.0), Point::new(250.0, 300.0));
+        let mut line = page0.create_line(a, b).unwrap();
+        assert_eq!(line.line().unwrap(), (a, b));
+        line.set_line_ending_styles(LineEndingStyle::None, LineEndingStyle::ClosedArrow)
+            .unwrap();
+        assert_eq!(
+            line.line_ending_styles().unwrap(),
+            (LineEndingStyle::None, LineEndingStyle::ClosedArrow)
+        );
+
+        let vertices = vec![
+            Point::new(300.0, 300.0),
+            Point::new(400.0, 300.0),
+            Point::new(350.0, 400.0),
+        ];
+        let polygon = page0.create_polygon(&vertices).unwrap();
+        assert_eq!(polygon.vertices().unwrap(), vertices);
+        let mut polyline = page0.create_polyline(&vertices[..2]).unwrap();
+        polyline.set_vertices(&vertices).unwrap();
+        assert_eq!(polyline.vertices().unwrap().len(), 3);
+        assert_eq!(page0.annotations().count(), 5);
+    }
+
+    #[test]
+    fn test_page_edit_annotation() {
+        use crate::{PdfAnnotationFlags, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let mut annot = page0
+            .create_square(Rect::new(10.0, 10.0, 50.0, 50.0))
+            .unwrap();
+
+        annot.set_contents("Needs review").unwrap();
+        annot.set_author("Reviewer").unwrap();
+        annot.set_modification_date(0).unwrap();
+        annot.set_opacity(0.25).unwrap();
+        annot.set_color(&[0.5]).unwrap();
+        annot
+            .set_flags(PdfAnnotationFlags::PRINT | PdfAnnotationFlags::LOCKED)
+            .unwrap();
+        let rect = Rect::new(20.0, 20.0, 80.0, 60.0);
+        annot.set_rect(rect).unwrap();
+
+        let annot = page0.annotations().next().unwrap();
+        assert_eq!(annot.contents().unwrap(), "Needs review");
+        assert_eq!(annot.author().unwrap().as_deref(), Some("Reviewer"));
+        assert!(annot
+            .modification_date()
+            .unwrap()
+            .unwrap()
+            .starts_with("D:1970"));
+        assert_eq!(annot.opacity().unwrap(), 0.25);
+        assert_eq!(annot.color().unwrap(), vec![0.5]);
+        assert_eq!(
+            annot.flags().unwrap(),
+            PdfAnnotationFlags::PRINT | PdfAnnotationFlags::LOCKED
+        );
+        assert_eq!(annot.rect().unwrap(), rect);
+    }
+
+    #[test]
+    fn test_page_delete_annotations() {
+        use crate::{PdfAnnotationType, Point, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let rect = Rect::new(10.0, 10.0, 50.0, 50.0);
+        let square = page0.create_square(rect).unwrap();
+        page0.create_circle(rect).unwrap();
+        page0.create_square(rect).unwrap();
+        page0
+            .create_line(Point::new(0.0, 0.0), Point::new(10.0, 10.0))
+            .unwrap();
+
+        page0.delete_annotation(&square).unwrap();
+        assert_eq!(page0.annotations().count(), 3);
+        assert_eq!(
+            page0
+                .delete_all_annotations(Some(PdfAnnotationType::Square))
+                .unwrap(),
+            1
+        );
+        assert_eq!(page0.annotations().count(), 2);
+        assert_eq!(page0.delete_all_annotations(None).unwrap(), 2);
+        assert_eq!(page0.annotations().count(), 0);
+    }
+
+    #[test]
+    fn test_page_annotation_border_style() {
+        use crate::{BorderStyle, PdfAnnotationFlags, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let mut annot = page0
+            .create_square(Rect::new(10.0, 10.0, 50.0, 50.0))
+            .unwrap();
+        assert_eq!(annot.border_style().unwrap(), BorderStyle::Solid);
+        assert!(annot.border_dash().unwrap().is_empty());
+
+        annot.set_border_style(BorderStyle::Dashed).unwrap();
+        annot.set_border_dash(&[3.0, 2.0]).unwrap();
+        assert_eq!(annot.border_style().unwrap(), BorderStyle::Dashed);
+        assert_eq!(annot.border_dash().unwrap(), vec![3.0, 2.0]);
+        annot.set_border_dash(&[]).unwrap();
+        assert!(annot.border_dash().unwrap().is_empty());
+
+        let flags = annot.flags().unwrap();
+        annot.set_flags(flags | PdfAnnotationFlags::HIDDEN).unwrap();
+        assert!(annot.flags().unwrap().contains(PdfAnnotationFlags::HIDDEN));
+    }
+
+    #[test]
+    fn test_page_annotation_appearance() {
+        use crate::{ColorParams, Colorspace, Device, DisplayList, Path, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let mut annot = page0
+            .create_square(Rect::new(0.0, 0.0, 20.0, 20.0))
+            .unwrap();
+        annot.set_interior_color(&[1.0, 0.0, 0.0]).unwrap();
+        annot.update().unwrap();
+
+        annot
+            .set_appearance_content(b"0 0 1 rg 0 0 20 20 re f", None)
+            .unwrap();
+        let pixmap = page0
+            .to_pixmap(&Matrix::IDENTITY, &Colorspace::device_rgb(), 0.0, true)
+            .unwrap();
+        let i = 10 * pixmap.stride() as usize + 10 * pixmap.n() as usize;
+        assert_eq!(&pixmap.samples()[i..i + 3], &[0, 0, 255]);
+
+        let list = DisplayList::new(Rect::new(0.0, 0.0, 20.0, 20.0)).unwrap();
+        {
+            let device = Device::from_display_list(&list).unwrap();
+            let mut path = Path::new().unwrap();
+            path.rect(0, 0, 20, 20).unwrap();
+            device
+                .fill_path(
+                    &path,
+                    false,
+                    &Matrix::IDENTITY,
+                    &Colorspace::device_rgb(),
+                    &[0.0, 1.0, 0.0],
+                    1.0,
+                    ColorParams::mupdf_default(),
+                )
+                .unwrap();
+        }
+        annot.set_appearance_from_display_list(&list).unwrap();
+        let pixmap = page0
+            .to_pixmap(&Matrix::IDENTITY, &Colorspace::device_rgb(), 0.0, true)
+            .unwrap();
+        assert_eq!(&pixmap.samples()[i..i + 3], &[0, 255, 0]);
+    }
+
+    #[test]
+    fn test_page_annotation_replies() {
+        use crate::{PdfAnnotationType, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let mut note = page0
+            .create_square(Rect::new(100.0, 100.0, 200.0, 200.0))
+            .unwrap();
+        assert_eq!(note.popup().unwrap(), None);
+        let popup = Rect::new(220.0, 100.0, 400.0, 200.0);
+        note.set_popup(popup).unwrap();
+        assert_eq!(note.popup().unwrap(), Some(popup));
+
+        let reply = page0.create_reply(&note, "Agreed", "Bob").unwrap();
+        assert_eq!(reply.r#type().unwrap(), PdfAnnotationType::Text);
+        assert_eq!(reply.contents().unwrap(), "Agreed");
+        assert!(note.in_reply_to().unwrap().is_none());
+        let parent = reply.in_reply_to().unwrap().unwrap();
+        assert_eq!(parent.inner, note.inner);
+
+        let replies = page0.replies(&note).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].author().unwrap().as_deref(), Some("Bob"));
+    }
+
+    #[test]
+    fn test_page_file_attachment() {
+        use crate::{PdfAnnotationType, PdfEmbeddedFile, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let file = PdfEmbeddedFile {
+            filename: "notes.txt".to_string(),
+            mime_type: Some("text/plain".to_string()),
+            description: Some("Reviewer notes".to_string()),
+            data: b"hello".to_vec(),
+        };
+        let annot = page0
+            .create_file_attachment(Rect::new(10.0, 10.0, 30.0, 30.0), &file)
+            .unwrap();
+        assert_eq!(annot.r#type().unwrap(), PdfAnnotationType::FileAttachment);
+        assert_eq!(annot.attachment().unwrap(), Some(file));
+
+        let square = page0
+            .create_square(Rect::new(50.0, 50.0, 60.0, 60.0))
+            .unwrap();
+        assert_eq!(square.attachment().unwrap(), None);
+    }
+
+    #[test]
+    fn test_page_create_caret_and_squiggly() {
+        use crate::{PdfAnnotationType, Quad, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let quad = Quad::new(50.0, 100.0, 200.0, 100.0, 50.0, 115.0, 200.0, 115.0);
+        let squiggly = page0
+            .create_squiggly(&[quad], &[1.0, 0.0, 0.0], 1.0)
+            .unwrap();
+        assert_eq!(squiggly.r#type().unwrap(), PdfAnnotationType::Squiggly);
+
+        let rect = Rect::new(120.0, 105.0, 130.0, 115.0);
+        let caret = page0.create_caret(rect, &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(caret.r#type().unwrap(), PdfAnnotationType::Caret);
+        assert_eq!(caret.rect().unwrap(), rect);
+        assert_eq!(caret.color().unwrap(), vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_page_annotation_geometry() {
+        use crate::{Point, Quad, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let quad = Quad::new(50.0, 100.0, 200.0, 100.0, 50.0, 115.0, 200.0, 115.0);
+        let mut highlight = page0
+            .create_highlight(&[quad.clone()], &[1.0, 1.0, 0.0], 1.0)
+            .unwrap();
+        assert_eq!(highlight.quad_points().unwrap(), vec![quad]);
+        let moved = Quad::new(50.0, 200.0, 200.0, 200.0, 50.0, 215.0, 200.0, 215.0);
+        highlight
+            .set_quad_points(&[moved.clone(), moved.clone()])
+            .unwrap();
+        assert_eq!(highlight.quad_points().unwrap(), vec![moved.clone(), moved]);
+
+        let mut line = page0
+            .create_line(Point::new(0.0, 0.0), Point::new(10.0, 10.0))
+            .unwrap();
+        let (a, b) = (Point::new(20.0, 30.0), Point::new(40.0, 50.0));
+        line.set_line(a, b).unwrap();
+        assert_eq!(line.line().unwrap(), (a, b));
+    }
+
+    #[test]
+    fn test_page_annotation_at() {
+        use crate::{PdfAnnotationFlags, PdfAnnotationType, Point, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        page0
+            .create_square(Rect::new(100.0, 100.0, 200.0, 200.0))
+            .unwrap();
+        page0
+            .create_circle(Rect::new(150.0, 150.0, 250.0, 250.0))
+            .unwrap();
+
+        let hit = page0
+            .annotation_at(Point::new(120.0, 120.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.r#type().unwrap(), PdfAnnotationType::Square);
+        let mut hit = page0
+            .annotation_at(Point::new(175.0, 175.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.r#type().unwrap(), PdfAnnotationType::Circle);
+        assert!(page0
+            .annotation_at(Point::new(50.0, 50.0))
+            .unwrap()
+            .is_none());
+
+        hit.set_flags(PdfAnnotationFlags::HIDDEN).unwrap();
+        let hit = page0
+            .annotation_at(Point::new(175.0, 175.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.r#type().unwrap(), PdfAnnotationType::Square);
+        assert!(page0.widget_at(Point::new(175.0, 175.0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_page_review_states() {
+        use crate::{ReviewState, Size};
+
+        let mut doc = PdfDocument::new();
+        let mut page0 = doc.new_page(Size::A4).unwrap();
+        let note = page0
+            .create_square(Rect::new(100.0, 100.0, 200.0, 200.0))
+            .unwrap();
+        assert_eq!(page0.review_state(&note, None).unwrap(), None);
+
+        page0
+            .set_review_state(&note, ReviewState::Rejected, "Alice")
+            .unwrap();
+        let state = page0
+            .set_review_state(&note, ReviewState::Accepted, "Bob")
+            .unwrap();
+        page0
+            .set_review_state(&note, ReviewState::Marked, "Alice")
+            .unwrap();
+        assert_eq!(state.review_state().unwrap(), Some(ReviewState::Accepted));
+        assert_eq!(state.contents().unwrap(), "Accepted set by Bob");
+
+        assert_eq!(
+            page0.review_state(&note, None).unwrap(),
+            Some(ReviewState::Accepted)
+        );
+        assert_eq!(
+            page0.review_state(&note, Some("Alice")).unwrap(),
+            Some(ReviewState::Rejected)
+        );
+        assert_eq!(page0.replies(&note).unwrap().len(), 3);
+    }
 }
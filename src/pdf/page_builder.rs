@@ -0,0 +1,84 @@
+use std::marker::PhantomData;
+use std::ptr;
+
+use mupdf_sys::*;
+
+use crate::pdf::{PdfDocument, PdfObject, PdfPage};
+use crate::{context, Buffer, Device, Error, Rect};
+
+/// A page being drawn through a [`Device`], added to its document by [`finish`](Self::finish)
+///
+/// Created by [`PdfDocument::begin_page`](crate::pdf::PdfDocument::begin_page). Device
+/// coordinates have the origin at the top left corner of the page and y going down, fonts and
+/// images used by the drawing calls are added to the page resources. The document stays
+/// borrowed until then.
+#[derive(Debug)]
+pub struct PdfPageBuilder<'a> {
+    doc: *mut pdf_document,
+    page_no: i32,
+    media_box: Rect,
+    device: Device,
+    resources: PdfObject,
+    contents: Buffer,
+    _doc: PhantomData<&'a mut PdfDocument>,
+}
+
+impl<'a> PdfPageBuilder<'a> {
+    pub(crate) fn new(
+        pdf: &'a mut PdfDocument,
+        page_no: i32,
+        media_box: Rect,
+    ) -> Result<Self, Error> {
+        let doc = pdf.inner;
+        let mut resources = ptr::null_mut();
+        let mut contents = ptr::null_mut();
+        unsafe {
+            let dev = ffi_try!(mupdf_pdf_page_write(
+                context(),
+                doc,
+                media_box.into(),
+                &mut resources,
+                &mut contents
+            ));
+            Ok(Self {
+                doc,
+                page_no,
+                media_box,
+                device: Device::from_raw(dev, ptr::null_mut()),
+                resources: PdfObject::from_raw(resources, true),
+                contents: Buffer::from_raw(contents),
+                _doc: PhantomData,
+            })
+        }
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Close the device and insert the page into the document
+    pub fn finish(self) -> Result<PdfPage, Error> {
+        let Self {
+            doc,
+            page_no,
+            media_box,
+            device,
+            resources,
+            contents,
+            ..
+        } = self;
+        // Closing the device flushes pending content into the buffer
+        drop(device);
+        unsafe {
+            let inner = ffi_try!(mupdf_pdf_add_page(
+                context(),
+                doc,
+                page_no,
+                media_box.into(),
+                resources.inner,
+                contents.inner
+            ));
+            Ok(PdfPage::from_raw(inner))
+        }
+    }
+}
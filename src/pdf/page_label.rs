@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+
+use crate::{Error, PdfDocument, PdfObject};
+
+/// Numbering style of a page label range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageLabelStyle {
+    /// Prefix only, without a number
+    None,
+    Decimal,
+    UpperRoman,
+    LowerRoman,
+    UpperAlpha,
+    LowerAlpha,
+}
+
+impl PageLabelStyle {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "D" => Self::Decimal,
+            "R" => Self::UpperRoman,
+            "r" => Self::LowerRoman,
+            "A" => Self::UpperAlpha,
+            "a" => Self::LowerAlpha,
+            _ => Self::None,
+        }
+    }
+
+    /// Value of the `/S` entry, `None` for label ranges without numbers
+    fn to_name(&self) -> Option<&'static str> {
+        match *self {
+            Self::None => None,
+            Self::Decimal => Some("D"),
+            Self::UpperRoman => Some("R"),
+            Self::LowerRoman => Some("r"),
+            Self::UpperAlpha => Some("A"),
+            Self::LowerAlpha => Some("a"),
+        }
+    }
+
+    fn format(&self, n: i32) -> String {
+        match *self {
+            Self::None => String::new(),
+            Self::Decimal => n.to_string(),
+            Self::UpperRoman => to_roman(n).to_uppercase(),
+            Self::LowerRoman => to_roman(n),
+            Self::UpperAlpha => to_alpha(n).to_uppercase(),
+            Self::LowerAlpha => to_alpha(n),
+        }
+    }
+}
+
+fn to_roman(mut n: i32) -> String {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut out = String::new();
+    for &(value, numeral) in NUMERALS.iter() {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// Letters run a to z, then aa to zz, aaa to zzz and so on
+fn to_alpha(n: i32) -> String {
+    if n < 1 {
+        return String::new();
+    }
+    let letter = (b'a' + ((n - 1) % 26) as u8) as char;
+    let count = ((n - 1) / 26 + 1) as usize;
+    std::iter::repeat(letter).take(count).collect()
+}
+
+/// A `/PageLabels` number tree entry, labelling pages from `start_page` onwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLabelRange {
+    /// 0-based index of the first page of the range
+    pub start_page: i32,
+    pub style: PageLabelStyle,
+    pub prefix: String,
+    /// Number of the first page of the range
+    pub first: i32,
+}
+
+impl PageLabelRange {
+    fn from_dict(start_page: i32, dict: &PdfObject) -> Result<Self, Error> {
+        let style = match dict.get_dict("S")? {
+            Some(s) => PageLabelStyle::from_name(s.as_name()?),
+            None => PageLabelStyle::None,
+        };
+        let prefix = match dict.get_dict("P")? {
+            Some(p) => p.as_string()?.to_string(),
+            None => String::new(),
+        };
+        let first = match dict.get_dict("St")? {
+            Some(st) => st.as_int()?,
+            None => 1,
+        };
+        Ok(Self {
+            start_page,
+            style,
+            prefix,
+            first,
+        })
+    }
+
+    /// Build the `/PageLabels` entry dictionary of the range
+    pub(crate) fn to_dict(&self, doc: &PdfDocument) -> Result<PdfObject, Error> {
+        let mut dict = doc.new_dict()?;
+        if let Some(name) = self.style.to_name() {
+            dict.dict_put("S", doc.new_name(name)?)?;
+        }
+        if !self.prefix.is_empty() {
+            dict.dict_put("P", doc.new_string(&self.prefix)?)?;
+        }
+        if self.first != 1 {
+            dict.dict_put("St", doc.new_int(self.first)?)?;
+        }
+        Ok(dict)
+    }
+
+    /// Label of the 0-based page `page_no`, which must be part of the range
+    pub fn label(&self, page_no: i32) -> String {
+        let mut label = self.prefix.clone();
+        label.push_str(&self.style.format(self.first + page_no - self.start_page));
+        label
+    }
+}
+
+/// Walk a number tree, skipping the nodes in `seen` so that a cycle of `/Kids` references
+/// ends
+fn collect_ranges(
+    node: &PdfObject,
+    ranges: &mut Vec<PageLabelRange>,
+    seen: &mut HashSet<i32>,
+) -> Result<(), Error> {
+    if node.is_indirect()? && !seen.insert(node.as_indirect()?) {
+        return Ok(());
+    }
+    if let Some(nums) = node.get_dict("Nums")? {
+        let len = nums.len()? as i32;
+        let mut i = 0;
+        while i + 1 < len {
+            if let (Some(start), Some(dict)) = (nums.get_array(i)?, nums.get_array(i + 1)?) {
+                ranges.push(PageLabelRange::from_dict(start.as_int()?, &dict)?);
+            }
+            i += 2;
+        }
+    }
+    if let Some(kids) = node.get_dict("Kids")? {
+        for i in 0..kids.len()? as i32 {
+            if let Some(kid) = kids.get_array(i)? {
+                collect_ranges(&kid, ranges, seen)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the page label ranges of the document catalog, sorted by start page
+pub(crate) fn load_page_labels(catalog: &PdfObject) -> Result<Vec<PageLabelRange>, Error> {
+    let mut ranges = Vec::new();
+    if let Some(labels) = catalog.get_dict("PageLabels")? {
+        collect_ranges(&labels, &mut ranges, &mut HashSet::new())?;
+    }
+    ranges.sort_by_key(|r| r.start_page);
+    Ok(ranges)
+}
+
+/// Label of the 0-based page `page_no`, pages without labels are numbered from 1
+pub(crate) fn page_label(ranges: &[PageLabelRange], page_no: i32) -> String {
+    if ranges.is_empty() {
+        return (page_no + 1).to_string();
+    }
+    match ranges.iter().rev().find(|r| r.start_page <= page_no) {
+        Some(range) => range.label(page_no),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{page_label, PageLabelRange, PageLabelStyle};
+
+    #[test]
+    fn test_page_label_styles() {
+        let ranges = vec![
+            PageLabelRange {
+                start_page: 0,
+                style: PageLabelStyle::LowerRoman,
+                prefix: String::new(),
+                first: 1,
+            },
+            PageLabelRange {
+                start_page: 4,
+                style: PageLabelStyle::Decimal,
+                prefix: String::new(),
+                first: 1,
+            },
+            PageLabelRange {
+                start_page: 10,
+                style: PageLabelStyle::Decimal,
+                prefix: "A-".to_string(),
+                first: 3,
+            },
+            PageLabelRange {
+                start_page: 12,
+                style: PageLabelStyle::UpperAlpha,
+                prefix: String::new(),
+                first: 26,
+            },
+        ];
+        assert_eq!(page_label(&ranges, 3), "iv");
+        assert_eq!(page_label(&ranges, 4), "1");
+        assert_eq!(page_label(&ranges, 10), "A-3");
+        assert_eq!(page_label(&ranges, 12), "Z");
+        assert_eq!(page_label(&ranges, 13), "AA");
+        assert_eq!(page_label(&[], 0), "1");
+    }
+}
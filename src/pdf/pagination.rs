@@ -0,0 +1,201 @@
+use crate::pdf::{PdfDocument, PdfPage};
+use crate::{Colorspace, Error, Font, Point};
+
+/// Where a header or footer line of a [`Pagination`] is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaginationPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+/// Bates numbers: a prefix followed by a zero padded number incrementing on every page
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatesNumbering {
+    pub prefix: String,
+    /// Number of the first page
+    pub start: u64,
+    /// Minimum number of digits, padded with zeros
+    pub digits: usize,
+}
+
+impl BatesNumbering {
+    pub fn new(prefix: &str, start: u64, digits: usize) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            start,
+            digits,
+        }
+    }
+
+    /// The Bates number of the 0-based page `page_no`
+    pub fn format(&self, page_no: u64) -> String {
+        format!(
+            "{}{:0width$}",
+            self.prefix,
+            self.start + page_no,
+            width = self.digits
+        )
+    }
+}
+
+/// Headers and footers for [`PdfDocument::paginate`]
+///
+/// Each line is a template where `{page}` is replaced by the page number, `{pages}` by the
+/// page count and `{bates}` by the Bates number. Colors are gray, RGB or CMYK depending on the
+/// number of components.
+#[derive(Debug)]
+pub struct Pagination<'a> {
+    font: &'a Font,
+    size: f32,
+    color: Vec<f32>,
+    margin: f32,
+    first_number: i64,
+    bates: Option<BatesNumbering>,
+    lines: Vec<(PaginationPosition, String)>,
+}
+
+impl<'a> Pagination<'a> {
+    /// Black text in `font` at `size` points, half an inch from the page edges
+    pub fn new(font: &'a Font, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: vec![0.0],
+            margin: 36.0,
+            first_number: 1,
+            bates: None,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn color(&mut self, color: &[f32]) -> &mut Self {
+        self.color = color.to_vec();
+        self
+    }
+
+    /// Distance in points between the lines and the page edges
+    pub fn margin(&mut self, margin: f32) -> &mut Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Number `{page}` starts counting from on the first page, 1 by default
+    pub fn first_number(&mut self, number: i64) -> &mut Self {
+        self.first_number = number;
+        self
+    }
+
+    pub fn bates(&mut self, bates: BatesNumbering) -> &mut Self {
+        self.bates = Some(bates);
+        self
+    }
+
+    /// Write `template` at `position` on every page
+    pub fn line(&mut self, position: PaginationPosition, template: &str) -> &mut Self {
+        self.lines.push((position, template.to_string()));
+        self
+    }
+
+    /// `template` with the placeholders filled in for the 0-based page `page_no`
+    pub(crate) fn expand(&self, template: &str, page_no: i32, page_count: i32) -> String {
+        let mut text = template
+            .replace("{page}", &(self.first_number + page_no as i64).to_string())
+            .replace("{pages}", &page_count.to_string());
+        if let Some(bates) = &self.bates {
+            text = text.replace("{bates}", &bates.format(page_no as u64));
+        }
+        text
+    }
+
+    fn text_width(&self, text: &str) -> Result<f32, Error> {
+        let mut width = 0.0;
+        for c in text.chars() {
+            let glyph = self.font.encode_character(c as i32)?;
+            width += self.font.advance_glyph(glyph)?;
+        }
+        Ok(width * self.size)
+    }
+}
+
+pub(crate) fn paginate(doc: &mut PdfDocument, pagination: &Pagination) -> Result<(), Error> {
+    let cs = match pagination.color.len() {
+        1 => Colorspace::device_gray(),
+        4 => Colorspace::device_cmyk(),
+        _ => Colorspace::device_rgb(),
+    };
+    if pagination.lines.is_empty() {
+        return Ok(());
+    }
+    let page_count = doc.page_count()?;
+    let margin = pagination.margin;
+    for page_no in 0..page_count {
+        let mut page = PdfPage::from(doc.load_page(page_no)?);
+        let bounds = page.bounds()?;
+        let mut lines = Vec::with_capacity(pagination.lines.len());
+        for (position, template) in &pagination.lines {
+            let text = pagination.expand(template, page_no, page_count);
+            let width = pagination.text_width(&text)?;
+            let x = match position {
+                PaginationPosition::TopLeft | PaginationPosition::BottomLeft => bounds.x0 + margin,
+                PaginationPosition::TopCenter | PaginationPosition::BottomCenter => {
+                    (bounds.x0 + bounds.x1 - width) / 2.0
+                }
+                PaginationPosition::TopRight | PaginationPosition::BottomRight => {
+                    bounds.x1 - margin - width
+                }
+            };
+            // Baselines leave room for the line height below the top margin
+            let y = match position {
+                PaginationPosition::TopLeft
+                | PaginationPosition::TopCenter
+                | PaginationPosition::TopRight => bounds.y0 + margin + pagination.size,
+                _ => bounds.y1 - margin,
+            };
+            lines.push((Point::new(x, y), text));
+        }
+        let lines: Vec<(Point, &str)> = lines
+            .iter()
+            .map(|(pos, text)| (*pos, text.as_str()))
+            .collect();
+        page.insert_text_lines(
+            &lines,
+            pagination.font,
+            pagination.size,
+            &cs,
+            &pagination.color,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BatesNumbering, Pagination, PaginationPosition};
+    use crate::Font;
+
+    #[test]
+    fn test_bates_numbering_format() {
+        let bates = BatesNumbering::new("ABC", 42, 6);
+        assert_eq!(bates.format(0), "ABC000042");
+        assert_eq!(bates.format(1000), "ABC001042");
+    }
+
+    #[test]
+    fn test_pagination_expand() {
+        let font = Font::new("Helvetica").unwrap();
+        let mut pagination = Pagination::new(&font, 10.0);
+        pagination
+            .first_number(3)
+            .bates(BatesNumbering::new("DOC-", 1, 4))
+            .line(PaginationPosition::BottomCenter, "Page {page} of {pages}");
+        assert_eq!(
+            pagination.expand("Page {page} of {pages}", 1, 9),
+            "Page 4 of 9"
+        );
+        assert_eq!(pagination.expand("{bates}", 1, 9), "DOC-0002");
+    }
+}
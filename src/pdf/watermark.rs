@@ -0,0 +1,156 @@
+use std::ffi::CString;
+
+use mupdf_sys::*;
+
+use crate::pdf::drawing::alpha_gstate_name;
+use crate::pdf::{PdfDocument, PdfObject, PdfPage};
+use crate::{context, Colorspace, Error, Font, Image, Matrix, Rect};
+
+/// Distance in points between a watermark placed at a corner and the page edges
+const MARGIN: f32 = 36.0;
+
+/// What [`PdfDocument::stamp_watermark`] draws
+#[derive(Debug)]
+pub enum Watermark<'a> {
+    /// A single line of text
+    Text {
+        text: &'a str,
+        font: &'a Font,
+        size: f32,
+        cs: &'a Colorspace,
+        color: &'a [f32],
+    },
+    /// An image at its own resolution
+    Image(&'a Image),
+}
+
+/// Where [`PdfDocument::stamp_watermark`] places the watermark on each page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatermarkPosition {
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Default for WatermarkPosition {
+    fn default() -> Self {
+        Self::Center
+    }
+}
+
+fn new_xobject(doc: &PdfDocument, watermark: &Watermark) -> Result<(PdfObject, Rect), Error> {
+    let mut bbox = fz_rect {
+        x0: 0.0,
+        y0: 0.0,
+        x1: 0.0,
+        y1: 0.0,
+    };
+    let inner = match watermark {
+        Watermark::Text {
+            text,
+            font,
+            size,
+            cs,
+            color,
+        } => {
+            let c_text = CString::new(*text)?;
+            unsafe {
+                ffi_try!(mupdf_pdf_new_text_xobject(
+                    context(),
+                    doc.inner,
+                    c_text.as_ptr(),
+                    font.inner,
+                    *size,
+                    cs.inner,
+                    color.as_ptr(),
+                    &mut bbox
+                ))
+            }
+        }
+        Watermark::Image(image) => unsafe {
+            ffi_try!(mupdf_pdf_new_image_xobject(
+                context(),
+                doc.inner,
+                image.inner,
+                &mut bbox
+            ))
+        },
+    };
+    Ok((unsafe { PdfObject::from_raw(inner, true) }, bbox.into()))
+}
+
+/// Matrix drawing the form `bbox`, with y going up, rotated by `rotation` degrees counterclockwise
+/// at `position` on a page with `bounds`, with y going down
+pub(crate) fn placement(
+    bbox: Rect,
+    bounds: Rect,
+    position: WatermarkPosition,
+    rotation: f32,
+) -> Matrix {
+    let mut m = Matrix::new_translate(-(bbox.x0 + bbox.x1) / 2.0, -(bbox.y0 + bbox.y1) / 2.0);
+    m.concat(Matrix::new_scale(1.0, -1.0));
+    m.concat(Matrix::new_rotate(-rotation));
+    let extent = bbox.transform(&m);
+    let (x, y) = match position {
+        WatermarkPosition::Center => ((bounds.x0 + bounds.x1) / 2.0, (bounds.y0 + bounds.y1) / 2.0),
+        WatermarkPosition::TopLeft => (
+            bounds.x0 + MARGIN - extent.x0,
+            bounds.y0 + MARGIN - extent.y0,
+        ),
+        WatermarkPosition::TopRight => (
+            bounds.x1 - MARGIN - extent.x1,
+            bounds.y0 + MARGIN - extent.y0,
+        ),
+        WatermarkPosition::BottomLeft => (
+            bounds.x0 + MARGIN - extent.x0,
+            bounds.y1 - MARGIN - extent.y1,
+        ),
+        WatermarkPosition::BottomRight => (
+            bounds.x1 - MARGIN - extent.x1,
+            bounds.y1 - MARGIN - extent.y1,
+        ),
+    };
+    m.concat(Matrix::new_translate(x, y));
+    m
+}
+
+pub(crate) fn stamp(
+    doc: &mut PdfDocument,
+    watermark: &Watermark,
+    position: WatermarkPosition,
+    opacity: f32,
+    rotation: f32,
+    underlay: bool,
+) -> Result<(), Error> {
+    let (xobj, bbox) = new_xobject(doc, watermark)?;
+    let opacity = opacity.max(0.0).min(1.0);
+    let gstate = alpha_gstate_name(opacity, opacity);
+    for page_no in 0..doc.page_count()? {
+        let mut page = PdfPage::from(doc.load_page(page_no)?);
+        let matrix = placement(bbox, page.bounds()?, position, rotation);
+        page.object().add_alpha_gstate(&gstate, opacity, opacity)?;
+        page.insert_xobject(&xobj, &matrix, Some(&gstate), !underlay)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{placement, WatermarkPosition};
+    use crate::Rect;
+
+    #[test]
+    fn test_watermark_placement() {
+        let bbox = Rect::new(0.0, -2.0, 100.0, 8.0);
+        let bounds = Rect::new(0.0, 0.0, 595.0, 842.0);
+        let m = placement(bbox, bounds, WatermarkPosition::Center, 0.0);
+        assert_eq!(bbox.transform(&m), Rect::new(247.5, 416.0, 347.5, 426.0));
+
+        let m = placement(bbox, bounds, WatermarkPosition::BottomRight, 90.0);
+        let placed = bbox.transform(&m);
+        assert!((placed.x1 - 559.0).abs() < 0.01 && (placed.y1 - 806.0).abs() < 0.01);
+        assert!((placed.width() - 10.0).abs() < 0.01 && (placed.height() - 100.0).abs() < 0.01);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::PdfAnnotation;
+use mupdf_sys::*;
+
+use crate::pdf::field::field_of_widget;
+use crate::{context, Error, PdfAnnotation, PdfField, Rect};
 
 #[derive(Debug)]
 pub enum PdfWidget {
@@ -18,6 +21,68 @@ pub struct PdfWidgetInner {
 }
 
 impl PdfWidget {
+    pub(crate) fn from_annotation(annot: PdfAnnotation) -> Result<Self, Error> {
+        use PdfWidget::*;
+
+        let code = unsafe { ffi_try!(mupdf_pdf_widget_type(context(), annot.inner)) };
+        let inner = PdfWidgetInner { annot };
+        Ok(match code {
+            1 => Button { inner },
+            2 => CheckBox { inner },
+            3 => ComboBox { inner },
+            4 => ListBox { inner },
+            5 => RadioButton { inner },
+            6 => Signature { inner },
+            7 => Text { inner },
+            _ => Unknown { inner },
+        })
+    }
+
+    /// The widget annotation, for its rect, flags and appearance
+    pub fn annotation(&self) -> &PdfAnnotation {
+        use PdfWidget::*;
+
+        match self {
+            Button { inner }
+            | CheckBox { inner }
+            | ComboBox { inner }
+            | ListBox { inner }
+            | RadioButton { inner }
+            | Signature { inner }
+            | Text { inner }
+            | Unknown { inner } => &inner.annot,
+        }
+    }
+
+    /// The form field the widget shows, holding its name, value and options
+    pub fn field(&self) -> Result<PdfField, Error> {
+        field_of_widget(self.annotation().object())
+    }
+
+    /// Fully qualified name of the field
+    pub fn name(&self) -> Result<String, Error> {
+        self.field()?.name()
+    }
+
+    pub fn value(&self) -> Result<String, Error> {
+        self.field()?.value()
+    }
+
+    pub fn options(&self) -> Result<Vec<String>, Error> {
+        self.field()?.options()
+    }
+
+    /// Set the value of a text field and regenerate the appearances of its widgets, returns
+    /// `false` if the field's own checks reject it
+    pub fn set_text_value(&mut self, value: &str) -> Result<bool, Error> {
+        self.field()?.set_text_value(value)
+    }
+
+    /// Rect of the widget in page coordinates
+    pub fn rect(&self) -> Result<Rect, Error> {
+        self.annotation().rect()
+    }
+
     pub fn type_code(&self) -> i32 {
         use PdfWidget::*;
 
@@ -0,0 +1,609 @@
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+
+use mupdf_sys::*;
+
+use crate::pdf::{
+    field, PdfAnnotation, PdfAnnotationFlags, PdfAnnotationType, PdfDocument, PdfField, PdfObject,
+    PdfPage,
+};
+use crate::{context, Error};
+
+/// XFDF elements of the annotation subtypes exchanged, the lower case PDF subtype names
+pub(crate) const ANNOTATION_TYPES: &[(&str, PdfAnnotationType)] = &[
+    ("text", PdfAnnotationType::Text),
+    ("freetext", PdfAnnotationType::FreeText),
+    ("line", PdfAnnotationType::Line),
+    ("square", PdfAnnotationType::Square),
+    ("circle", PdfAnnotationType::Circle),
+    ("polygon", PdfAnnotationType::Polygon),
+    ("polyline", PdfAnnotationType::PloyLine),
+    ("highlight", PdfAnnotationType::Highlight),
+    ("underline", PdfAnnotationType::Underline),
+    ("squiggly", PdfAnnotationType::Squiggly),
+    ("strikeout", PdfAnnotationType::StrikeOut),
+    ("stamp", PdfAnnotationType::Stamp),
+    ("caret", PdfAnnotationType::Caret),
+    ("ink", PdfAnnotationType::Ink),
+];
+
+const FLAG_NAMES: &[(&str, PdfAnnotationFlags)] = &[
+    ("invisible", PdfAnnotationFlags::INVISIBLE),
+    ("hidden", PdfAnnotationFlags::HIDDEN),
+    ("print", PdfAnnotationFlags::PRINT),
+    ("nozoom", PdfAnnotationFlags::NO_ZOOM),
+    ("norotate", PdfAnnotationFlags::NO_ROTATE),
+    ("noview", PdfAnnotationFlags::NO_VIEW),
+    ("readonly", PdfAnnotationFlags::READ_ONLY),
+    ("locked", PdfAnnotationFlags::LOCKED),
+    ("togglenoview", PdfAnnotationFlags::TOGGLE_NO_VIEW),
+    ("lockedcontents", PdfAnnotationFlags::LOCKED_CONTENTS),
+];
+
+/// Annotation entries written as XFDF attributes of the same value
+const TEXT_ATTRIBUTES: &[(&str, &str)] = &[
+    ("title", "T"),
+    ("date", "M"),
+    ("creationdate", "CreationDate"),
+    ("name", "NM"),
+];
+
+struct XmlDoc {
+    inner: *mut fz_xml_doc,
+}
+
+impl XmlDoc {
+    fn parse(data: &[u8], root: &str) -> Result<Self, Error> {
+        let c_root = CString::new(root)?;
+        let inner = unsafe {
+            ffi_try!(mupdf_parse_xml(
+                context(),
+                data.as_ptr(),
+                data.len(),
+                c_root.as_ptr()
+            ))
+        };
+        Ok(Self { inner })
+    }
+
+    fn root(&self) -> XmlNode {
+        XmlNode {
+            inner: unsafe { fz_xml_root(self.inner) },
+            _doc: PhantomData,
+        }
+    }
+}
+
+impl Drop for XmlDoc {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_xml(context(), self.inner) };
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct XmlNode<'a> {
+    inner: *mut fz_xml,
+    _doc: PhantomData<&'a XmlDoc>,
+}
+
+impl<'a> XmlNode<'a> {
+    /// Element name, `None` for text nodes
+    fn tag(&self) -> Option<&'a str> {
+        unsafe {
+            let ptr = fz_xml_tag(self.inner);
+            if ptr.is_null() {
+                return None;
+            }
+            CStr::from_ptr(ptr).to_str().ok()
+        }
+    }
+
+    fn att(&self, name: &str) -> Option<String> {
+        let c_name = CString::new(name).ok()?;
+        unsafe {
+            let ptr = fz_xml_att(self.inner, c_name.as_ptr());
+            if ptr.is_null() {
+                return None;
+            }
+            Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Child elements, text nodes skipped
+    fn elements(&self) -> Vec<XmlNode<'a>> {
+        let mut elements = Vec::new();
+        let mut child = unsafe { fz_xml_down(self.inner) };
+        while !child.is_null() {
+            let node = XmlNode {
+                inner: child,
+                _doc: PhantomData,
+            };
+            if node.tag().is_some() {
+                elements.push(node);
+            }
+            child = unsafe { fz_xml_next(child) };
+        }
+        elements
+    }
+
+    /// Concatenated text of the child text nodes
+    fn text(&self) -> String {
+        let mut text = String::new();
+        let mut child = unsafe { fz_xml_down(self.inner) };
+        while !child.is_null() {
+            unsafe {
+                let ptr = fz_xml_text(child);
+                if !ptr.is_null() {
+                    text.push_str(&CStr::from_ptr(ptr).to_string_lossy());
+                }
+                child = fz_xml_next(child);
+            }
+        }
+        text
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn floats(array: &PdfObject) -> Result<Vec<f32>, Error> {
+    let mut values = Vec::new();
+    for i in 0..array.len()? {
+        if let Some(value) = array.get_array(i as i32)? {
+            values.push(value.as_float()?);
+        }
+    }
+    Ok(values)
+}
+
+fn join(values: &[f32], sep: &str) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+/// `x,y;x,y;...` as used by the vertices and ink gestures
+fn join_points(values: &[f32]) -> String {
+    values
+        .chunks(2)
+        .map(|p| join(p, ","))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn parse_floats(s: &str) -> Vec<f32> {
+    s.split(|c| c == ',' || c == ';' || char::is_whitespace(c))
+        .filter_map(|v| v.parse().ok())
+        .collect()
+}
+
+/// `#RRGGBB` of a gray or RGB color, `None` for other colors
+fn hex_color(color: &[f32]) -> Option<String> {
+    let rgb = match *color {
+        [gray] => [gray, gray, gray],
+        [r, g, b] => [r, g, b],
+        _ => return None,
+    };
+    let byte = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    Some(format!(
+        "#{:02X}{:02X}{:02X}",
+        byte(rgb[0]),
+        byte(rgb[1]),
+        byte(rgb[2])
+    ))
+}
+
+fn parse_hex_color(s: &str) -> Option<[f32; 3]> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let mut rgb = [0.0; 3];
+    for (i, c) in rgb.iter_mut().enumerate() {
+        *c = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()? as f32 / 255.0;
+    }
+    Some(rgb)
+}
+
+pub(crate) fn real_array(doc: &PdfDocument, values: &[f32]) -> Result<PdfObject, Error> {
+    let mut array = doc.new_array()?;
+    for &v in values {
+        array.array_push(PdfObject::new_real(v)?)?;
+    }
+    Ok(array)
+}
+
+fn write_annotation(xfdf: &mut String, page_no: i32, annot: &PdfAnnotation) -> Result<(), Error> {
+    let subtype = annot.r#type()?;
+    let element = match ANNOTATION_TYPES.iter().find(|(_, t)| *t == subtype) {
+        Some((element, _)) => *element,
+        None => return Ok(()),
+    };
+    let obj = annot.object();
+    let mut attrs = vec![("page", page_no.to_string())];
+    if let Some(rect) = obj.get_dict("Rect")? {
+        attrs.push(("rect", join(&floats(&rect)?, ",")));
+    }
+    for (attr, key) in &[("color", "C"), ("interior-color", "IC")] {
+        if let Some(color) = obj.get_dict(*key)? {
+            if let Some(hex) = hex_color(&floats(&color)?) {
+                attrs.push((*attr, hex));
+            }
+        }
+    }
+    if let Some(opacity) = obj.get_dict("CA")? {
+        attrs.push(("opacity", opacity.as_float()?.to_string()));
+    }
+    for (attr, key) in TEXT_ATTRIBUTES {
+        if let Some(value) = obj.get_dict(*key)? {
+            attrs.push((*attr, value.as_string()?.to_string()));
+        }
+    }
+    // Replies refer to their parent by name, so every annotation needs one
+    if obj.get_dict("NM")?.is_none() {
+        attrs.push(("name", annotation_name(&obj)?));
+    }
+    if let Some(parent) = obj.get_dict("IRT")? {
+        attrs.push(("inreplyto", annotation_name(&parent)?));
+        if let Some(rt) = obj.get_dict("RT")? {
+            let reply_type = if rt.as_name()? == "Group" {
+                "group"
+            } else {
+                "reply"
+            };
+            attrs.push(("replyType", reply_type.to_string()));
+        }
+    }
+    for (attr, key) in &[("state", "State"), ("statemodel", "StateModel")] {
+        if let Some(value) = obj.get_dict(*key)? {
+            attrs.push((*attr, value.as_string()?.to_string()));
+        }
+    }
+    let flags = annot.flags()?;
+    if !flags.is_empty() {
+        let names: Vec<_> = FLAG_NAMES
+            .iter()
+            .filter(|(_, flag)| flags.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect();
+        attrs.push(("flags", names.join(",")));
+    }
+    if let Some(width) = obj.get_dict("BS")?.map(|bs| bs.get_dict("W")).transpose()? {
+        if let Some(width) = width {
+            attrs.push(("width", width.as_float()?.to_string()));
+        }
+    }
+    if let Some(quads) = obj.get_dict("QuadPoints")? {
+        attrs.push(("coords", join(&floats(&quads)?, ",")));
+    }
+    if let Some(line) = obj.get_dict("L")? {
+        let line = floats(&line)?;
+        if line.len() == 4 {
+            attrs.push(("start", join(&line[..2], ",")));
+            attrs.push(("end", join(&line[2..], ",")));
+        }
+    }
+    if let Some(endings) = obj.get_dict("LE")? {
+        for (i, attr) in ["head", "tail"].iter().enumerate() {
+            if let Some(ending) = endings.get_array(i as i32)? {
+                attrs.push((*attr, ending.as_name()?.to_string()));
+            }
+        }
+    }
+    if let Some(icon) = obj.get_dict("Name")? {
+        attrs.push(("icon", icon.as_name()?.to_string()));
+    }
+    if let Some(q) = obj.get_dict("Q")? {
+        let justification = match q.as_int()? {
+            1 => "centered",
+            2 => "right",
+            _ => "left",
+        };
+        attrs.push(("justification", justification.to_string()));
+    }
+
+    xfdf.push('<');
+    xfdf.push_str(element);
+    for (attr, value) in &attrs {
+        xfdf.push_str(&format!(" {}=\"{}\"", attr, escape(value)));
+    }
+    xfdf.push_str(">\n");
+    if let Some(contents) = obj.get_dict("Contents")? {
+        let contents = escape(contents.as_string()?);
+        xfdf.push_str(&format!("<contents>{}</contents>\n", contents));
+    }
+    if let Some(da) = obj.get_dict("DA")? {
+        let da = escape(da.as_string()?);
+        xfdf.push_str(&format!("<defaultappearance>{}</defaultappearance>\n", da));
+    }
+    if let Some(vertices) = obj.get_dict("Vertices")? {
+        let vertices = join_points(&floats(&vertices)?);
+        xfdf.push_str(&format!("<vertices>{}</vertices>\n", vertices));
+    }
+    if let Some(ink) = obj.get_dict("InkList")? {
+        xfdf.push_str("<inklist>\n");
+        for i in 0..ink.len()? {
+            if let Some(stroke) = ink.get_array(i as i32)? {
+                let gesture = join_points(&floats(&stroke)?);
+                xfdf.push_str(&format!("<gesture>{}</gesture>\n", gesture));
+            }
+        }
+        xfdf.push_str("</inklist>\n");
+    }
+    if let Some(popup) = obj.get_dict("Popup")? {
+        xfdf.push_str("<popup");
+        if let Some(rect) = popup.get_dict("Rect")? {
+            xfdf.push_str(&format!(" rect=\"{}\"", join(&floats(&rect)?, ",")));
+        }
+        let open = match popup.get_dict("Open")? {
+            Some(open) => open.as_bool()?,
+            None => false,
+        };
+        xfdf.push_str(&format!(
+            " open=\"{}\"/>\n",
+            if open { "yes" } else { "no" }
+        ));
+    }
+    xfdf.push_str(&format!("</{}>\n", element));
+    Ok(())
+}
+
+/// `/NM` of the annotation, or a name made from its object number for those without one
+fn annotation_name(obj: &PdfObject) -> Result<String, Error> {
+    match obj.get_dict("NM")? {
+        Some(name) => Ok(name.as_string()?.to_string()),
+        None => Ok(format!("mupdf-{}", obj.as_indirect()?)),
+    }
+}
+
+/// Write a terminal field under its fully qualified name, which [`read_fields`] accepts
+/// as well as nested `<field>` elements
+fn write_field(xfdf: &mut String, field: &PdfField) -> Result<(), Error> {
+    let name = field.name()?;
+    if name.is_empty() {
+        return Ok(());
+    }
+    xfdf.push_str(&format!("<field name=\"{}\">\n", escape(&name)));
+    if let Some(value) = field.object().get_dict("V")? {
+        let value = if value.is_name()? {
+            Some(value.as_name()?.to_string())
+        } else if value.is_string()? {
+            Some(value.as_string()?.to_string())
+        } else {
+            None
+        };
+        if let Some(value) = value {
+            xfdf.push_str(&format!("<value>{}</value>\n", escape(&value)));
+        }
+    }
+    xfdf.push_str("</field>\n");
+    Ok(())
+}
+
+pub(crate) fn export(doc: &PdfDocument) -> Result<String, Error> {
+    let mut xfdf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xfdf.push_str("<xfdf xmlns=\"http://ns.adobe.com/xfdf/\" xml:space=\"preserve\">\n");
+    xfdf.push_str("<annots>\n");
+    for page_no in 0..doc.page_count()? {
+        let page = PdfPage::from(doc.load_page(page_no)?);
+        for annot in page.annotations() {
+            write_annotation(&mut xfdf, page_no, &annot)?;
+        }
+    }
+    xfdf.push_str("</annots>\n");
+    let form = doc.catalog()?.get_dict("AcroForm")?;
+    if let Some(fields) = form.map(|form| form.get_dict("Fields")).transpose()? {
+        if let Some(fields) = fields {
+            let mut terminals = Vec::new();
+            field::collect_fields(&fields, &mut terminals)?;
+            xfdf.push_str("<fields>\n");
+            for field in &terminals {
+                write_field(&mut xfdf, field)?;
+            }
+            xfdf.push_str("</fields>\n");
+        }
+    }
+    xfdf.push_str("</xfdf>\n");
+    Ok(xfdf)
+}
+
+/// An imported annotation, to link replies once all of them are created
+struct ReadAnnotation {
+    name: Option<String>,
+    num: i32,
+    in_reply_to: Option<String>,
+}
+
+fn read_annotation(
+    doc: &PdfDocument,
+    node: XmlNode,
+    subtype: PdfAnnotationType,
+) -> Result<ReadAnnotation, Error> {
+    let page_no = node.att("page").and_then(|p| p.parse().ok()).unwrap_or(0);
+    let mut page = PdfPage::from(doc.load_page(page_no)?);
+    let mut annot = page.create_annotation(subtype as i32)?;
+    let mut obj = annot.object();
+
+    if let Some(rect) = node.att("rect") {
+        obj.dict_put("Rect", real_array(doc, &parse_floats(&rect))?)?;
+    }
+    for (attr, key) in &[("color", "C"), ("interior-color", "IC")] {
+        if let Some(rgb) = node.att(attr).as_deref().and_then(parse_hex_color) {
+            obj.dict_put(*key, real_array(doc, &rgb)?)?;
+        }
+    }
+    if let Some(opacity) = node.att("opacity").and_then(|o| o.parse().ok()) {
+        obj.dict_put("CA", PdfObject::new_real(opacity)?)?;
+    }
+    for (attr, key) in TEXT_ATTRIBUTES {
+        if let Some(value) = node.att(attr) {
+            obj.dict_put(*key, PdfObject::new_string(&value)?)?;
+        }
+    }
+    if let Some(names) = node.att("flags") {
+        let flags = FLAG_NAMES
+            .iter()
+            .filter(|(name, _)| names.split(',').any(|n| n.trim() == *name))
+            .fold(PdfAnnotationFlags::empty(), |flags, (_, flag)| {
+                flags | *flag
+            });
+        obj.dict_put("F", PdfObject::new_int(flags.bits())?)?;
+    }
+    if let Some(width) = node.att("width").and_then(|w| w.parse().ok()) {
+        let mut bs = doc.new_dict()?;
+        bs.dict_put("W", PdfObject::new_real(width)?)?;
+        obj.dict_put("BS", bs)?;
+    }
+    if let Some(coords) = node.att("coords") {
+        obj.dict_put("QuadPoints", real_array(doc, &parse_floats(&coords))?)?;
+    }
+    if let (Some(start), Some(end)) = (node.att("start"), node.att("end")) {
+        let mut line = parse_floats(&start);
+        line.extend(parse_floats(&end));
+        obj.dict_put("L", real_array(doc, &line)?)?;
+    }
+    if let (Some(head), Some(tail)) = (node.att("head"), node.att("tail")) {
+        let mut endings = doc.new_array()?;
+        endings.array_push(PdfObject::new_name(&head)?)?;
+        endings.array_push(PdfObject::new_name(&tail)?)?;
+        obj.dict_put("LE", endings)?;
+    }
+    if let Some(icon) = node.att("icon") {
+        obj.dict_put("Name", PdfObject::new_name(&icon)?)?;
+    }
+    if let Some(justification) = node.att("justification") {
+        let q = match justification.as_str() {
+            "centered" => 1,
+            "right" => 2,
+            _ => 0,
+        };
+        obj.dict_put("Q", PdfObject::new_int(q)?)?;
+    }
+    if node.att("inreplyto").is_some() {
+        let rt = match node.att("replyType").as_deref() {
+            Some("group") => "Group",
+            _ => "R",
+        };
+        obj.dict_put("RT", PdfObject::new_name(rt)?)?;
+    }
+    for (attr, key) in &[("state", "State"), ("statemodel", "StateModel")] {
+        if let Some(value) = node.att(attr) {
+            obj.dict_put(*key, PdfObject::new_string(&value)?)?;
+        }
+    }
+    for child in node.elements() {
+        match child.tag() {
+            Some("contents") => obj.dict_put("Contents", PdfObject::new_string(&child.text())?)?,
+            Some("defaultappearance") => {
+                obj.dict_put("DA", PdfObject::new_string(&child.text())?)?
+            }
+            Some("vertices") => {
+                obj.dict_put("Vertices", real_array(doc, &parse_floats(&child.text()))?)?
+            }
+            Some("inklist") => {
+                let mut ink = doc.new_array()?;
+                for gesture in child.elements() {
+                    ink.array_push(real_array(doc, &parse_floats(&gesture.text()))?)?;
+                }
+                obj.dict_put("InkList", ink)?;
+            }
+            Some("popup") => {
+                let mut popup = page
+                    .create_annotation(PdfAnnotationType::Popup as i32)?
+                    .object();
+                if let Some(rect) = child.att("rect") {
+                    popup.dict_put("Rect", real_array(doc, &parse_floats(&rect))?)?;
+                }
+                let open = child.att("open").as_deref() == Some("yes");
+                popup.dict_put("Open", PdfObject::new_bool(open))?;
+                popup.dict_put("Parent", doc.new_indirect(obj.as_indirect()?, 0)?)?;
+                obj.dict_put("Popup", popup)?;
+            }
+            _ => {}
+        }
+    }
+    annot.dirty();
+    annot.update()?;
+    Ok(ReadAnnotation {
+        name: node.att("name"),
+        num: obj.as_indirect()?,
+        in_reply_to: node.att("inreplyto"),
+    })
+}
+
+fn read_fields(doc: &mut PdfDocument, node: XmlNode, prefix: &str) -> Result<(), Error> {
+    for field in node.elements() {
+        if field.tag() != Some("field") {
+            continue;
+        }
+        let name = match field.att("name") {
+            Some(name) if prefix.is_empty() => name,
+            Some(name) => format!("{}.{}", prefix, name),
+            None => continue,
+        };
+        for child in field.elements() {
+            if child.tag() == Some("value") {
+                let c_name = CString::new(name.as_str())?;
+                let c_value = CString::new(child.text())?;
+                unsafe {
+                    ffi_try!(mupdf_pdf_set_field_value_by_name(
+                        context(),
+                        doc.inner,
+                        c_name.as_ptr(),
+                        c_value.as_ptr()
+                    ));
+                }
+            }
+        }
+        read_fields(doc, field, &name)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn import(doc: &mut PdfDocument, xfdf: &str) -> Result<(), Error> {
+    let xml = XmlDoc::parse(xfdf.as_bytes(), "xfdf")?;
+    let mut annots = Vec::new();
+    for section in xml.root().elements() {
+        match section.tag() {
+            Some("annots") => {
+                for node in section.elements() {
+                    let tag = node.tag().unwrap_or_default();
+                    if let Some((_, subtype)) = ANNOTATION_TYPES.iter().find(|(t, _)| *t == tag) {
+                        annots.push(read_annotation(doc, node, *subtype)?);
+                    }
+                }
+            }
+            Some("fields") => read_fields(doc, section, "")?,
+            _ => {}
+        }
+    }
+    // Replies may come before the annotation they reply to
+    for reply in &annots {
+        let parent = reply.in_reply_to.as_ref().and_then(|name| {
+            annots
+                .iter()
+                .find(|annot| annot.name.as_ref() == Some(name))
+        });
+        if let Some(parent) = parent {
+            let mut obj = doc.new_indirect(reply.num, 0)?;
+            obj.dict_put("IRT", doc.new_indirect(parent.num, 0)?)?;
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,15 @@
 use std::ffi::CString;
 use std::io::{self, Write};
+use std::slice;
 
 use mupdf_sys::*;
 
-use crate::{context, Buffer, Colorspace, Error, IRect};
+use crate::{context, Buffer, ColorParams, Colorspace, Error, IRect};
 
+/// Output formats for pixmaps.
+///
+/// MuPDF 1.16 has no JPEG encoder, use PNG for lossless or PAM for raw output instead.
+/// There is no TIFF encoder either, CMYK pixmaps and their spot channels are best kept as PSD.
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
 pub enum ImageFormat {
@@ -137,6 +142,29 @@ impl Pixmap {
         }
     }
 
+    /// Pixel data, `height` rows of `stride` bytes each.
+    ///
+    /// Each pixel is stored as `n` bytes, the color components followed by alpha if present.
+    pub fn samples(&self) -> &[u8] {
+        unsafe {
+            if (*self.inner).samples.is_null() {
+                return &[];
+            }
+            let len = (*self.inner).h as usize * (*self.inner).stride as usize;
+            slice::from_raw_parts((*self.inner).samples, len)
+        }
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            if (*self.inner).samples.is_null() {
+                return &mut [];
+            }
+            let len = (*self.inner).h as usize * (*self.inner).stride as usize;
+            slice::from_raw_parts_mut((*self.inner).samples, len)
+        }
+    }
+
     /// Size of pixmap
     pub fn size(&self) -> usize {
         unsafe { fz_pixmap_size(context(), self.inner) }
@@ -146,6 +174,15 @@ impl Pixmap {
         unsafe { fz_pixmap_bbox(context(), self.inner).into() }
     }
 
+    /// MD5 digest of the pixel data, handy for golden-image comparisons.
+    pub fn digest(&self) -> [u8; 16] {
+        let mut digest = [0u8; 16];
+        unsafe {
+            fz_md5_pixmap(context(), self.inner, digest.as_mut_ptr());
+        }
+        digest
+    }
+
     /// Initialize the samples area with 0x00
     pub fn clear(&mut self) -> Result<(), Error> {
         unsafe {
@@ -166,6 +203,21 @@ impl Pixmap {
         Ok(())
     }
 
+    /// Initialize the part of the samples area inside `rect`, like [`Pixmap::clear_with`].
+    ///
+    /// Useful to patch only the dirty region of a page render.
+    pub fn clear_rect_with(&mut self, value: i32, rect: IRect) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_clear_pixmap_rect_with_value(
+                context(),
+                self.inner,
+                value,
+                rect.into()
+            ));
+        }
+        Ok(())
+    }
+
     pub fn save_as(&self, filename: &str, format: ImageFormat) -> Result<(), Error> {
         let c_filename = CString::new(filename)?;
         unsafe {
@@ -179,6 +231,19 @@ impl Pixmap {
         Ok(())
     }
 
+    /// Same as [`save_as`](Self::save_as)
+    pub fn write_to_file(&self, filename: &str, format: ImageFormat) -> Result<(), Error> {
+        self.save_as(filename, format)
+    }
+
+    /// Encode the pixmap in `format`.
+    pub fn to_bytes(&self, format: ImageFormat) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes, format)?;
+        Ok(bytes)
+    }
+
+    /// Invert all the pixels in a pixmap, except for the alpha channel
     pub fn invert(&mut self) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_invert_pixmap(context(), self.inner));
@@ -200,7 +265,14 @@ impl Pixmap {
         Ok(())
     }
 
-    /// Tint pixmap with color
+    /// Tint pixmap with color, mapping black to `black` and white to `white`.
+    ///
+    /// Only gray and RGB pixmaps can be tinted.
+    ///
+    /// ## Params
+    ///
+    /// * `black` - color to map black to, as `0xRRGGBB`
+    /// * `white` - color to map white to, as `0xRRGGBB`
     pub fn tint(&mut self, black: i32, white: i32) -> Result<(), Error> {
         unsafe {
             ffi_try!(mupdf_tint_pixmap(context(), self.inner, black, white));
@@ -275,6 +347,231 @@ impl Pixmap {
         };
         Ok(Self { inner })
     }
+
+    /// Convert the pixmap to the colorspace `cs`, e.g. an RGB render to grayscale or CMYK.
+    ///
+    /// Alpha is dropped unless `keep_alpha` is set.
+    pub fn convert(&self, cs: &Colorspace, keep_alpha: bool) -> Result<Self, Error> {
+        self.convert_with_params(cs, keep_alpha, ColorParams::mupdf_default())
+    }
+
+    /// Like [`Pixmap::convert`], with control over rendering intent and black point compensation.
+    pub fn convert_with_params(
+        &self,
+        cs: &Colorspace,
+        keep_alpha: bool,
+        cp: ColorParams,
+    ) -> Result<Self, Error> {
+        let inner = unsafe {
+            ffi_try!(mupdf_convert_pixmap(
+                context(),
+                self.inner,
+                cs.inner,
+                cp.into(),
+                keep_alpha
+            ))
+        };
+        Ok(Self { inner })
+    }
+
+    /// Copy of the pixmap with an opaque alpha plane added.
+    ///
+    /// Returns a plain copy if the pixmap already has alpha. Pixmaps with spot
+    /// channels are rejected with [`Error::InvalidArgument`].
+    pub fn add_alpha(&self) -> Result<Self, Error> {
+        self.with_alpha_plane(true)
+    }
+
+    /// Copy of the pixmap with the alpha plane dropped.
+    ///
+    /// Color components are kept as stored, i.e. premultiplied pixmaps end up composited over black.
+    /// Pixmaps with spot channels are rejected with [`Error::InvalidArgument`].
+    pub fn remove_alpha(&self) -> Result<Self, Error> {
+        self.with_alpha_plane(false)
+    }
+
+    fn with_alpha_plane(&self, alpha: bool) -> Result<Self, Error> {
+        let spots = unsafe { (*self.inner).s };
+        if spots > 0 {
+            return Err(Error::InvalidArgument(format!(
+                "pixmap has {} spot channels",
+                spots
+            )));
+        }
+        let (x, y) = self.origin();
+        let (w, h) = (self.width() as usize, self.height() as usize);
+        let inner = unsafe {
+            ffi_try!(mupdf_new_pixmap(
+                context(),
+                (*self.inner).colorspace,
+                x,
+                y,
+                w as i32,
+                h as i32,
+                alpha
+            ))
+        };
+        let mut dst = Self { inner };
+        let (x_res, y_res) = self.resolution();
+        dst.set_resolution(x_res, y_res);
+
+        let sn = self.n() as usize;
+        let dn = dst.n() as usize;
+        let colors = sn - self.alpha() as usize;
+        let (src_stride, dst_stride) = (self.stride() as usize, dst.stride() as usize);
+        let src = self.samples();
+        let samples = dst.samples_mut();
+        for row in 0..h {
+            let s = &src[row * src_stride..row * src_stride + w * sn];
+            let d = &mut samples[row * dst_stride..row * dst_stride + w * dn];
+            for (dp, sp) in d.chunks_mut(dn).zip(s.chunks(sn)) {
+                dp[..colors].copy_from_slice(&sp[..colors]);
+                if alpha {
+                    dp[dn - 1] = if self.alpha() { sp[sn - 1] } else { 255 };
+                }
+            }
+        }
+        Ok(dst)
+    }
+
+    /// Multiply the color components by alpha, the convention MuPDF renders with.
+    ///
+    /// Does nothing for pixmaps without alpha
+    pub fn premultiply(&mut self) {
+        self.map_colors(mul_alpha);
+    }
+
+    /// Divide the color components by alpha, giving straight (non-premultiplied) alpha.
+    ///
+    /// Does nothing for pixmaps without alpha
+    pub fn unpremultiply(&mut self) {
+        self.map_colors(div_alpha);
+    }
+
+    fn map_colors(&mut self, f: fn(u8, u8) -> u8) {
+        let n = self.n() as usize;
+        if !self.alpha() || n < 2 {
+            return;
+        }
+        let w = self.width() as usize;
+        let h = self.height() as usize;
+        let stride = self.stride() as usize;
+        let samples = self.samples_mut();
+        for row in 0..h {
+            for px in samples[row * stride..row * stride + w * n].chunks_mut(n) {
+                let a = px[n - 1];
+                for c in &mut px[..n - 1] {
+                    *c = f(*c, a);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl Pixmap {
+    /// Convert the pixmap to an [`image::DynamicImage`].
+    ///
+    /// Gray and RGB pixmaps are copied, other colorspaces are converted to RGB first.
+    /// MuPDF stores premultiplied alpha while `image` expects straight alpha, so color
+    /// components are unpremultiplied on the way.
+    pub fn to_image(&self) -> Result<image::DynamicImage, Error> {
+        use image::{DynamicImage, ImageBuffer};
+
+        let (is_gray, is_rgb) = match self.color_space() {
+            Some(cs) => (cs.is_gray(), cs.is_rgb()),
+            // Stencil masks only have an alpha plane, export it as gray
+            None => (true, false),
+        };
+        if !is_gray && !is_rgb {
+            return self
+                .convert(&Colorspace::device_rgb(), self.alpha())?
+                .to_image();
+        }
+
+        let w = self.width();
+        let h = self.height();
+        let n = self.n() as usize;
+        let stride = self.stride() as usize;
+        let premultiplied = self.alpha() && n > 1;
+        let samples = self.samples();
+        let mut data = Vec::with_capacity(w as usize * h as usize * n);
+        for y in 0..h as usize {
+            let row = &samples[y * stride..y * stride + w as usize * n];
+            if premultiplied {
+                for px in row.chunks(n) {
+                    let a = px[n - 1];
+                    data.extend(px[..n - 1].iter().map(|&c| div_alpha(c, a)));
+                    data.push(a);
+                }
+            } else {
+                data.extend_from_slice(row);
+            }
+        }
+        let image = match n {
+            1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(w, h, data).unwrap()),
+            2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(w, h, data).unwrap()),
+            3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(w, h, data).unwrap()),
+            _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(w, h, data).unwrap()),
+        };
+        Ok(image)
+    }
+
+    /// Create a pixmap from an [`image::DynamicImage`].
+    ///
+    /// 8-bit gray and RGB images keep their layout, anything else is converted to RGBA.
+    pub fn from_image(img: &image::DynamicImage) -> Result<Self, Error> {
+        use image::{DynamicImage, GenericImageView};
+        use std::borrow::Cow;
+
+        let (cs, alpha, raw): (_, _, Cow<[u8]>) = match img {
+            DynamicImage::ImageLuma8(buf) => {
+                (Colorspace::device_gray(), false, buf.as_raw().into())
+            }
+            DynamicImage::ImageLumaA8(buf) => {
+                (Colorspace::device_gray(), true, buf.as_raw().into())
+            }
+            DynamicImage::ImageRgb8(buf) => (Colorspace::device_rgb(), false, buf.as_raw().into()),
+            DynamicImage::ImageRgba8(buf) => (Colorspace::device_rgb(), true, buf.as_raw().into()),
+            _ => (
+                Colorspace::device_rgb(),
+                true,
+                img.to_rgba().into_raw().into(),
+            ),
+        };
+        let (w, h) = img.dimensions();
+        let mut pixmap = Self::new_with_w_h(&cs, w as i32, h as i32, alpha)?;
+        let n = pixmap.n() as usize;
+        let stride = pixmap.stride() as usize;
+        let samples = pixmap.samples_mut();
+        for (y, src) in raw.chunks(w as usize * n).take(h as usize).enumerate() {
+            let dst = &mut samples[y * stride..y * stride + src.len()];
+            if alpha {
+                for (d, s) in dst.chunks_mut(n).zip(src.chunks(n)) {
+                    let a = s[n - 1];
+                    for (dc, &sc) in d[..n - 1].iter_mut().zip(&s[..n - 1]) {
+                        *dc = mul_alpha(sc, a);
+                    }
+                    d[n - 1] = a;
+                }
+            } else {
+                dst.copy_from_slice(src);
+            }
+        }
+        Ok(pixmap)
+    }
+}
+
+fn mul_alpha(c: u8, a: u8) -> u8 {
+    ((c as u32 * a as u32 + 127) / 255) as u8
+}
+
+fn div_alpha(c: u8, a: u8) -> u8 {
+    if a == 0 {
+        0
+    } else {
+        ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8
+    }
 }
 
 impl Drop for Pixmap {
@@ -308,6 +605,51 @@ mod test {
         assert_eq!(pixmap.origin(), (0, 0));
     }
 
+    #[test]
+    fn test_pixmap_samples() {
+        let cs = Colorspace::device_rgb();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 10, 20, true).expect("Pixmap::new_with_w_h");
+        assert_eq!(pixmap.n(), 4);
+        assert_eq!(pixmap.stride(), 40);
+        pixmap.clear().unwrap();
+        assert_eq!(pixmap.samples().len(), 800);
+        assert!(pixmap.samples().iter().all(|&b| b == 0));
+
+        pixmap.samples_mut()[0] = 255;
+        assert_eq!(pixmap.samples()[0], 255);
+    }
+
+    #[test]
+    fn test_pixmap_to_bytes() {
+        use super::ImageFormat;
+
+        let cs = Colorspace::device_rgb();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 10, 10, false).expect("Pixmap::new_with_w_h");
+        pixmap.clear().unwrap();
+        let png = pixmap.to_bytes(ImageFormat::PNG).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let pnm = pixmap.to_bytes(ImageFormat::PNM).unwrap();
+        assert!(pnm.starts_with(b"P6"));
+        let pam = pixmap.to_bytes(ImageFormat::PAM).unwrap();
+        assert!(pam.starts_with(b"P7"));
+        let psd = pixmap.to_bytes(ImageFormat::PSD).unwrap();
+        assert!(psd.starts_with(b"8BPS"));
+    }
+
+    #[test]
+    fn test_pixmap_convert() {
+        let cs = Colorspace::device_rgb();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 10, 10, true).expect("Pixmap::new_with_w_h");
+        pixmap.clear_with(255).unwrap();
+        let gray = pixmap.convert(&Colorspace::device_gray(), false).unwrap();
+        assert_eq!(gray.n(), 1);
+        assert!(!gray.alpha());
+        assert!(gray.samples().iter().all(|&b| b == 255));
+        let cmyk = pixmap.convert(&Colorspace::device_cmyk(), true).unwrap();
+        assert_eq!(cmyk.n(), 5);
+        assert!(cmyk.alpha());
+    }
+
     #[test]
     fn test_pixmap_clear() {
         let cs = Colorspace::device_rgb();
@@ -316,12 +658,45 @@ mod test {
         pixmap.clear_with(1).unwrap();
     }
 
+    #[test]
+    fn test_pixmap_copy_rect() {
+        let cs = Colorspace::device_gray();
+        let mut src = Pixmap::new_with_w_h(&cs, 4, 4, false).expect("Pixmap::new_with_w_h");
+        src.clear_with(255).unwrap();
+        let mut dst = Pixmap::new_with_w_h(&cs, 4, 4, false).expect("Pixmap::new_with_w_h");
+        dst.clear().unwrap();
+
+        dst.copy_from(&src, IRect::new(0, 0, 2, 2)).unwrap();
+        assert_eq!(&dst.samples()[..4], &[255, 255, 0, 0]);
+        assert_eq!(&dst.samples()[8..12], &[0, 0, 0, 0]);
+
+        dst.clear_rect_with(0, IRect::new(0, 0, 1, 1)).unwrap();
+        assert_eq!(&dst.samples()[..4], &[0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_pixmap_digest() {
+        let cs = Colorspace::device_rgb();
+        let mut a = Pixmap::new_with_w_h(&cs, 10, 10, false).expect("Pixmap::new_with_w_h");
+        let mut b = Pixmap::new_with_w_h(&cs, 10, 10, false).expect("Pixmap::new_with_w_h");
+        a.clear().unwrap();
+        b.clear().unwrap();
+        assert_eq!(a.digest(), b.digest());
+        b.invert().unwrap();
+        assert_ne!(a.digest(), b.digest());
+    }
+
     #[test]
     fn test_pixmap_invert() {
         let cs = Colorspace::device_rgb();
         let mut pixmap = Pixmap::new_with_w_h(&cs, 100, 100, false).expect("Pixmap::new_with_w_h");
         pixmap.clear().unwrap();
         pixmap.invert().unwrap();
+
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 2, 2, true).expect("Pixmap::new_with_w_h");
+        pixmap.clear_with(0x40).unwrap();
+        pixmap.invert().unwrap();
+        assert_eq!(&pixmap.samples()[..4], &[0xBF, 0xBF, 0xBF, 0xFF]);
     }
 
     #[test]
@@ -338,6 +713,15 @@ mod test {
         let mut pixmap = Pixmap::new_with_w_h(&cs, 100, 100, false).expect("Pixmap::new_with_w_h");
         pixmap.clear().unwrap();
         pixmap.tint(0, 255).unwrap();
+
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 2, 2, false).expect("Pixmap::new_with_w_h");
+        pixmap.clear_with(0).unwrap();
+        pixmap.tint(0x102030, 0xFFFFFF).unwrap();
+        assert_eq!(&pixmap.samples()[..3], &[0x10, 0x20, 0x30]);
+
+        let cmyk = Colorspace::device_cmyk();
+        let mut pixmap = Pixmap::new_with_w_h(&cmyk, 2, 2, false).expect("Pixmap::new_with_w_h");
+        assert!(pixmap.tint(0x102030, 0xFFFFFF).is_err());
     }
 
     #[test]
@@ -347,4 +731,43 @@ mod test {
         pixmap.clear().unwrap();
         pixmap.scale(0.0, 0.0, 50.0, 50.0).unwrap();
     }
+
+    #[test]
+    fn test_pixmap_alpha() {
+        let cs = Colorspace::device_rgb();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 2, 2, false).expect("Pixmap::new_with_w_h");
+        pixmap.clear_with(100).unwrap();
+
+        let mut with_alpha = pixmap.add_alpha().unwrap();
+        assert!(with_alpha.alpha());
+        assert_eq!(&with_alpha.samples()[..4], &[100, 100, 100, 255]);
+
+        with_alpha.samples_mut()[3] = 128;
+        with_alpha.premultiply();
+        assert_eq!(&with_alpha.samples()[..4], &[50, 50, 50, 128]);
+        with_alpha.unpremultiply();
+        assert_eq!(&with_alpha.samples()[..4], &[100, 100, 100, 128]);
+
+        let without = with_alpha.remove_alpha().unwrap();
+        assert!(!without.alpha());
+        assert_eq!(without.n(), 3);
+        assert_eq!(without.samples(), pixmap.samples());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_pixmap_image_roundtrip() {
+        let cs = Colorspace::device_rgb();
+        let mut pixmap = Pixmap::new_with_w_h(&cs, 4, 3, true).expect("Pixmap::new_with_w_h");
+        pixmap.clear_with(200).unwrap();
+        let img = pixmap.to_image().unwrap();
+        assert_eq!(
+            img.as_rgba8().unwrap().get_pixel(0, 0).0,
+            [200, 200, 200, 255]
+        );
+
+        let back = Pixmap::from_image(&img).unwrap();
+        assert_eq!((back.width(), back.height(), back.n()), (4, 3, 4));
+        assert_eq!(back.samples(), pixmap.samples());
+    }
 }
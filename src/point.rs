@@ -18,6 +18,15 @@ impl From<fz_point> for Point {
     }
 }
 
+impl Into<fz_point> for Point {
+    fn into(self) -> fz_point {
+        fz_point {
+            x: self.x,
+            y: self.y,
+        }
+    }
+}
+
 impl From<(f32, f32)> for Point {
     fn from(p: (f32, f32)) -> Self {
         Self { x: p.0, y: p.1 }
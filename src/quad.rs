@@ -1,3 +1,5 @@
+use mupdf_sys::{fz_point, fz_quad};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Quad {
     pub ul_x: f32,
@@ -33,3 +35,42 @@ impl Quad {
         }
     }
 }
+
+impl From<fz_quad> for Quad {
+    fn from(q: fz_quad) -> Self {
+        let fz_quad { ul, ur, ll, lr } = q;
+        Self {
+            ul_x: ul.x,
+            ul_y: ul.y,
+            ur_x: ur.x,
+            ur_y: ur.y,
+            ll_x: ll.x,
+            ll_y: ll.y,
+            lr_x: lr.x,
+            lr_y: lr.y,
+        }
+    }
+}
+
+impl Into<fz_quad> for Quad {
+    fn into(self) -> fz_quad {
+        fz_quad {
+            ul: fz_point {
+                x: self.ul_x,
+                y: self.ul_y,
+            },
+            ur: fz_point {
+                x: self.ur_x,
+                y: self.ur_y,
+            },
+            ll: fz_point {
+                x: self.ll_x,
+                y: self.ll_y,
+            },
+            lr: fz_point {
+                x: self.lr_x,
+                y: self.lr_y,
+            },
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use mupdf_sys::*;
 
-use crate::{Point, Quad, Size};
+use crate::{Matrix, Point, Quad, Size};
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct IRect {
@@ -158,6 +158,12 @@ impl Rect {
         }
         self
     }
+
+    /// Bounding box of the rectangle transformed by `matrix`.
+    pub fn transform(&self, matrix: &Matrix) -> Self {
+        let rect = unsafe { fz_transform_rect((*self).into(), matrix.into()) };
+        rect.into()
+    }
 }
 
 impl From<IRect> for Rect {
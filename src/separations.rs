@@ -0,0 +1,178 @@
+use std::ffi::{CStr, CString};
+
+use mupdf_sys::*;
+
+use crate::{context, Colorspace, Error};
+
+/// How a separation is handled when rendering
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeparationBehavior {
+    /// Merged into the process colors of the pixmap
+    Composite,
+    /// Kept as a separate spot channel of the pixmap
+    Spot,
+    /// Not rendered at all
+    Disabled,
+}
+
+impl From<i32> for SeparationBehavior {
+    fn from(b: i32) -> Self {
+        match b {
+            1 => Self::Spot,
+            2 => Self::Disabled,
+            _ => Self::Composite,
+        }
+    }
+}
+
+impl Into<i32> for SeparationBehavior {
+    fn into(self) -> i32 {
+        match self {
+            Self::Composite => 0,
+            Self::Spot => 1,
+            Self::Disabled => 2,
+        }
+    }
+}
+
+/// The spot colors (separations) used by a page, with their rendering behavior
+#[derive(Debug)]
+pub struct Separations {
+    pub(crate) inner: *mut fz_separations,
+}
+
+impl Separations {
+    pub(crate) unsafe fn from_raw(inner: *mut fz_separations) -> Self {
+        Self { inner }
+    }
+
+    /// An empty set of separations, `controllable` ones can have their behavior changed
+    pub fn new(controllable: bool) -> Result<Self, Error> {
+        let inner = unsafe { ffi_try!(mupdf_new_separations(context(), controllable)) };
+        Ok(Self { inner })
+    }
+
+    /// Add a separation named `name`, equivalent to channel `colorant` of `cs`
+    pub fn add(&mut self, name: &str, cs: &Colorspace, colorant: i32) -> Result<(), Error> {
+        let c_name = CString::new(name)?;
+        unsafe {
+            ffi_try!(mupdf_add_separation(
+                context(),
+                self.inner,
+                c_name.as_ptr(),
+                cs.inner,
+                colorant
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { fz_count_separations(context(), self.inner) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of separations rendered as spot channels
+    pub fn active_count(&self) -> usize {
+        unsafe { fz_count_active_separations(context(), self.inner) as usize }
+    }
+
+    pub fn name(&self, index: usize) -> Option<String> {
+        if index >= self.len() {
+            return None;
+        }
+        unsafe {
+            let ptr = fz_separation_name(context(), self.inner, index as i32);
+            if ptr.is_null() {
+                return None;
+            }
+            Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+        }
+    }
+
+    pub fn behavior(&self, index: usize) -> Option<SeparationBehavior> {
+        if index >= self.len() {
+            return None;
+        }
+        let b = unsafe { fz_separation_current_behavior(context(), self.inner, index as i32) };
+        Some(SeparationBehavior::from(b as i32))
+    }
+
+    pub fn set_behavior(
+        &mut self,
+        index: usize,
+        behavior: SeparationBehavior,
+    ) -> Result<(), Error> {
+        unsafe {
+            ffi_try!(mupdf_set_separation_behavior(
+                context(),
+                self.inner,
+                index as i32,
+                behavior.into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Color of separation `index` expressed in `cs`
+    pub fn equivalent(&self, index: usize, cs: &Colorspace) -> Result<Vec<f32>, Error> {
+        if index >= self.len() {
+            return Err(Error::InvalidArgument(format!(
+                "separation index {} out of range",
+                index
+            )));
+        }
+        let mut color = [0.0; FZ_MAX_COLORS as usize];
+        unsafe {
+            ffi_try!(mupdf_separation_equivalent(
+                context(),
+                self.inner,
+                index as i32,
+                cs.inner,
+                color.as_mut_ptr()
+            ));
+        }
+        Ok(color[..cs.num_of_components() as usize].to_vec())
+    }
+}
+
+impl Drop for Separations {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unsafe { fz_drop_separations(context(), self.inner) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SeparationBehavior, Separations};
+    use crate::Colorspace;
+
+    #[test]
+    fn test_separations() {
+        let cmyk = Colorspace::device_cmyk();
+        let mut seps = Separations::new(true).unwrap();
+        assert!(seps.is_empty());
+        seps.add("Cyan", &cmyk, 0).unwrap();
+        assert_eq!(seps.len(), 1);
+        assert_eq!(seps.name(0).as_deref(), Some("Cyan"));
+        assert_eq!(seps.name(1), None);
+        assert_eq!(seps.behavior(1), None);
+
+        seps.set_behavior(0, SeparationBehavior::Spot).unwrap();
+        assert_eq!(seps.behavior(0), Some(SeparationBehavior::Spot));
+        assert_eq!(seps.active_count(), 1);
+        seps.set_behavior(0, SeparationBehavior::Disabled).unwrap();
+        assert_eq!(seps.behavior(0), Some(SeparationBehavior::Disabled));
+        assert_eq!(seps.active_count(), 0);
+
+        let color = seps.equivalent(0, &cmyk).unwrap();
+        assert_eq!(color.len(), 4);
+        assert!(color[0] > 0.99);
+        assert!(seps.equivalent(1, &cmyk).is_err());
+    }
+}
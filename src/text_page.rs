@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
 use mupdf_sys::*;
 
 use bitflags::bitflags;
 
-use crate::context;
+use crate::{context, Font, Point, Quad, Rect, WriteMode};
 
 bitflags! {
     pub struct TextPageOptions: u32 {
@@ -24,6 +28,172 @@ impl TextPage {
     pub(crate) unsafe fn from_raw(ptr: *mut fz_stext_page) -> Self {
         Self { inner: ptr }
     }
+
+    pub fn blocks(&self) -> TextBlockIter {
+        TextBlockIter {
+            next: unsafe { (*self.inner).first_block },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Export the text as HTML with one CSS class per font, size and color combination.
+    ///
+    /// Classes are numbered in order of first appearance on the page, so the same page
+    /// always produces the same class names.
+    pub fn to_html(&self, page_no: i32) -> TextHtml {
+        let mut styles = TextStyles::default();
+        let mut html = String::new();
+        html.push_str(&format!("<div id=\"page{}\" class=\"page\">\n", page_no));
+        for block in self.blocks() {
+            if block.r#type() != TextBlockType::Text {
+                continue;
+            }
+            html.push_str("<p>");
+            for (i, line) in block.lines().enumerate() {
+                if i > 0 {
+                    html.push_str("<br>\n");
+                }
+                let mut current = None;
+                for ch in line.chars() {
+                    let c = match ch.char() {
+                        Some(c) => c,
+                        None => continue,
+                    };
+                    let class = styles.class_of(&ch);
+                    if current != Some(class) {
+                        if current.is_some() {
+                            html.push_str("</span>");
+                        }
+                        html.push_str(&format!("<span class=\"s{}\">", class));
+                        current = Some(class);
+                    }
+                    push_escaped(&mut html, c);
+                }
+                if current.is_some() {
+                    html.push_str("</span>");
+                }
+            }
+            html.push_str("</p>\n");
+        }
+        html.push_str("</div>\n");
+        TextHtml {
+            html,
+            stylesheet: styles.stylesheet(),
+        }
+    }
+}
+
+/// HTML export of a [`TextPage`](struct.TextPage.html), with presentation kept in a separate stylesheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextHtml {
+    pub html: String,
+    pub stylesheet: String,
+}
+
+impl TextHtml {
+    /// A standalone HTML document embedding the stylesheet.
+    pub fn to_document(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            self.stylesheet, self.html
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TextStyle {
+    family: String,
+    generic: &'static str,
+    bold: bool,
+    italic: bool,
+    size: String,
+    color: u32,
+}
+
+#[derive(Debug, Default)]
+struct TextStyles {
+    styles: Vec<TextStyle>,
+    index: HashMap<String, usize>,
+}
+
+impl TextStyles {
+    fn class_of(&mut self, ch: &TextChar) -> usize {
+        let (family, generic, bold, italic) = match ch.font() {
+            Some(font) => {
+                // Font names come from the file and need not be UTF-8
+                let name = unsafe { CStr::from_ptr(fz_font_name(context(), font.inner)) };
+                let name = name.to_string_lossy();
+                // Drop the subset tag, e.g. "ABCDEF+Helvetica"
+                let family = match name.find('+') {
+                    Some(6) => &name[7..],
+                    _ => &name[..],
+                };
+                let generic = if font.is_monospaced() {
+                    "monospace"
+                } else if font.is_serif() {
+                    "serif"
+                } else {
+                    "sans-serif"
+                };
+                (
+                    family.to_string(),
+                    generic,
+                    font.is_bold(),
+                    font.is_italic(),
+                )
+            }
+            None => (String::new(), "serif", false, false),
+        };
+        let style = TextStyle {
+            family,
+            generic,
+            bold,
+            italic,
+            size: format!("{:.1}", ch.size()),
+            color: ch.color(),
+        };
+        let key = format!(
+            "{}|{}|{}|{}|{}|{:06x}",
+            style.family, style.generic, style.bold, style.italic, style.size, style.color
+        );
+        let styles = &mut self.styles;
+        *self.index.entry(key).or_insert_with(|| {
+            styles.push(style);
+            styles.len() - 1
+        })
+    }
+
+    fn stylesheet(&self) -> String {
+        let mut css = String::new();
+        for (i, style) in self.styles.iter().enumerate() {
+            css.push_str(&format!(".s{}{{font-family:", i));
+            if !style.family.is_empty() {
+                css.push_str(&format!("\"{}\",", style.family.replace('"', "")));
+            }
+            css.push_str(&format!(
+                "{};font-size:{}pt;color:#{:06x};",
+                style.generic, style.size, style.color
+            ));
+            if style.bold {
+                css.push_str("font-weight:bold;");
+            }
+            if style.italic {
+                css.push_str("font-style:italic;");
+            }
+            css.push_str("}\n");
+        }
+        css
+    }
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '&' => out.push_str("&amp;"),
+        '"' => out.push_str("&quot;"),
+        _ => out.push(c),
+    }
 }
 
 impl Drop for TextPage {
@@ -35,3 +205,271 @@ impl Drop for TextPage {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextBlockType {
+    Text,
+    Image,
+}
+
+#[derive(Debug)]
+pub struct TextBlock<'a> {
+    inner: &'a fz_stext_block,
+}
+
+impl TextBlock<'_> {
+    pub fn r#type(&self) -> TextBlockType {
+        if self.inner.type_ == FZ_STEXT_BLOCK_IMAGE as i32 {
+            TextBlockType::Image
+        } else {
+            TextBlockType::Text
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.inner.bbox.into()
+    }
+
+    pub fn lines(&self) -> TextLineIter {
+        let next = if self.r#type() == TextBlockType::Text {
+            unsafe { self.inner.u.t.first_line }
+        } else {
+            std::ptr::null_mut()
+        };
+        TextLineIter {
+            next,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextBlockIter<'a> {
+    next: *mut fz_stext_block,
+    _marker: PhantomData<TextBlock<'a>>,
+}
+
+impl<'a> Iterator for TextBlockIter<'a> {
+    type Item = TextBlock<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let node = unsafe { &*self.next };
+        self.next = node.next;
+        Some(TextBlock { inner: node })
+    }
+}
+
+/// Reading direction of a line of text, derived from its writing mode and baseline direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+    TopToBottom,
+    BottomToTop,
+}
+
+#[derive(Debug)]
+pub struct TextLine<'a> {
+    inner: &'a fz_stext_line,
+}
+
+impl TextLine<'_> {
+    pub fn bounds(&self) -> Rect {
+        self.inner.bbox.into()
+    }
+
+    /// Writing mode the text was laid out with, vertical for CJK vertical text.
+    pub fn wmode(&self) -> WriteMode {
+        if self.inner.wmode != 0 {
+            WriteMode::Vertical
+        } else {
+            WriteMode::Horizontal
+        }
+    }
+
+    /// Normalized direction vector of the baseline, in page space.
+    pub fn dir(&self) -> Point {
+        self.inner.dir.into()
+    }
+
+    /// Reading direction of the line.
+    ///
+    /// Vertical lines are classified by their baseline direction, horizontal ones
+    /// by the bidi class of their first strong character so that Arabic and Hebrew
+    /// runs are reported as right-to-left.
+    pub fn direction(&self) -> TextDirection {
+        let dir = self.dir();
+        if self.wmode() == WriteMode::Vertical || dir.x.abs() < dir.y.abs() {
+            return if dir.y < 0.0 {
+                TextDirection::BottomToTop
+            } else {
+                TextDirection::TopToBottom
+            };
+        }
+        for ch in self.chars() {
+            match ch.bidi_class() {
+                BidiClass::LeftToRight => return TextDirection::LeftToRight,
+                BidiClass::RightToLeft | BidiClass::ArabicLetter => {
+                    return TextDirection::RightToLeft
+                }
+                _ => {}
+            }
+        }
+        if dir.x < 0.0 {
+            TextDirection::RightToLeft
+        } else {
+            TextDirection::LeftToRight
+        }
+    }
+
+    pub fn chars(&self) -> TextCharIter {
+        TextCharIter {
+            next: self.inner.first_char,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextLineIter<'a> {
+    next: *mut fz_stext_line,
+    _marker: PhantomData<TextLine<'a>>,
+}
+
+impl<'a> Iterator for TextLineIter<'a> {
+    type Item = TextLine<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let node = unsafe { &*self.next };
+        self.next = node.next;
+        Some(TextLine { inner: node })
+    }
+}
+
+/// Unicode bidirectional character class, as defined by UAX #9.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BidiClass {
+    LeftToRight,
+    RightToLeft,
+    ArabicLetter,
+    EuropeanNumber,
+    ArabicNumber,
+    NonSpacingMark,
+    Whitespace,
+    Neutral,
+}
+
+impl From<i32> for BidiClass {
+    fn from(class: i32) -> Self {
+        match class as u32 {
+            UCDN_BIDI_CLASS_L | UCDN_BIDI_CLASS_LRE | UCDN_BIDI_CLASS_LRO => Self::LeftToRight,
+            UCDN_BIDI_CLASS_R | UCDN_BIDI_CLASS_RLE | UCDN_BIDI_CLASS_RLO => Self::RightToLeft,
+            UCDN_BIDI_CLASS_AL => Self::ArabicLetter,
+            UCDN_BIDI_CLASS_EN => Self::EuropeanNumber,
+            UCDN_BIDI_CLASS_AN => Self::ArabicNumber,
+            UCDN_BIDI_CLASS_NSM => Self::NonSpacingMark,
+            UCDN_BIDI_CLASS_WS => Self::Whitespace,
+            _ => Self::Neutral,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextChar<'a> {
+    inner: &'a fz_stext_char,
+}
+
+impl TextChar<'_> {
+    pub fn char(&self) -> Option<char> {
+        std::char::from_u32(self.inner.c as u32)
+    }
+
+    pub fn origin(&self) -> Point {
+        self.inner.origin.into()
+    }
+
+    pub fn size(&self) -> f32 {
+        self.inner.size
+    }
+
+    pub fn quad(&self) -> Quad {
+        self.inner.quad.into()
+    }
+
+    pub fn font(&self) -> Option<Font> {
+        if self.inner.font.is_null() {
+            return None;
+        }
+        unsafe { Some(Font::from_raw(fz_keep_font(context(), self.inner.font))) }
+    }
+
+    /// sRGB color of the glyph as `0xRRGGBB`.
+    pub fn color(&self) -> u32 {
+        self.inner.color as u32 & 0xff_ffff
+    }
+
+    pub fn bidi_class(&self) -> BidiClass {
+        let class = unsafe { ucdn_get_bidi_class(self.inner.c as u32) };
+        BidiClass::from(class)
+    }
+}
+
+#[derive(Debug)]
+pub struct TextCharIter<'a> {
+    next: *mut fz_stext_char,
+    _marker: PhantomData<TextChar<'a>>,
+}
+
+impl<'a> Iterator for TextCharIter<'a> {
+    type Item = TextChar<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let node = unsafe { &*self.next };
+        self.next = node.next;
+        Some(TextChar { inner: node })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Document, TextBlockType, TextDirection, TextPageOptions, WriteMode};
+
+    #[test]
+    fn test_text_page_lines() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let text_page = page0.to_text_page(TextPageOptions::empty()).unwrap();
+        let mut text = String::new();
+        for block in text_page.blocks() {
+            assert_eq!(block.r#type(), TextBlockType::Text);
+            for line in block.lines() {
+                assert_eq!(line.wmode(), WriteMode::Horizontal);
+                assert_eq!(line.direction(), TextDirection::LeftToRight);
+                text.extend(line.chars().filter_map(|ch| ch.char()));
+            }
+        }
+        assert!(text.starts_with("Dummy"));
+    }
+
+    #[test]
+    fn test_text_page_to_html() {
+        let doc = Document::open("tests/files/dummy.pdf").unwrap();
+        let page0 = doc.load_page(0).unwrap();
+        let text_page = page0.to_text_page(TextPageOptions::empty()).unwrap();
+        let html = text_page.to_html(0);
+        assert!(html.html.starts_with("<div id=\"page0\""));
+        assert!(html.html.contains("<span class=\"s0\">"));
+        assert!(html.stylesheet.starts_with(".s0{"));
+        assert!(!html.html.contains("style="));
+        assert_eq!(html, text_page.to_html(0));
+    }
+}
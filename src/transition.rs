@@ -0,0 +1,70 @@
+use mupdf_sys::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionType {
+    None,
+    Split,
+    Blinds,
+    Box,
+    Wipe,
+    Dissolve,
+    Glitter,
+    Fly,
+    Push,
+    Cover,
+    Uncover,
+    Fade,
+}
+
+impl From<i32> for TransitionType {
+    fn from(t: i32) -> Self {
+        match t as u32 {
+            FZ_TRANSITION_SPLIT => Self::Split,
+            FZ_TRANSITION_BLINDS => Self::Blinds,
+            FZ_TRANSITION_BOX => Self::Box,
+            FZ_TRANSITION_WIPE => Self::Wipe,
+            FZ_TRANSITION_DISSOLVE => Self::Dissolve,
+            FZ_TRANSITION_GLITTER => Self::Glitter,
+            FZ_TRANSITION_FLY => Self::Fly,
+            FZ_TRANSITION_PUSH => Self::Push,
+            FZ_TRANSITION_COVER => Self::Cover,
+            FZ_TRANSITION_UNCOVER => Self::Uncover,
+            FZ_TRANSITION_FADE => Self::Fade,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Effect used when a presentation advances to a page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub r#type: TransitionType,
+    /// Duration of the effect in seconds
+    pub duration: f32,
+    /// Split and blinds effects run vertically rather than horizontally
+    pub vertical: bool,
+    /// Split and box effects move outwards from the center
+    pub outwards: bool,
+    /// Direction of wipe, glitter, fly, push, cover and uncover effects in degrees
+    pub direction: i32,
+}
+
+impl From<fz_transition> for Transition {
+    fn from(t: fz_transition) -> Self {
+        Self {
+            r#type: TransitionType::from(t.type_),
+            duration: t.duration,
+            vertical: t.vertical != 0,
+            outwards: t.outwards != 0,
+            direction: t.direction,
+        }
+    }
+}
+
+/// Slideshow settings of a page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Presentation {
+    pub transition: Transition,
+    /// Seconds to show the page before advancing automatically, `0` waits for the user
+    pub duration: f32,
+}
@@ -0,0 +1,50 @@
+use std::ffi::CStr;
+
+use mupdf_sys::*;
+
+/// Version of the MuPDF library the crate was built against, e.g. `"1.16.1"`.
+pub fn version() -> &'static str {
+    let c_version = CStr::from_bytes_with_nul(FZ_VERSION).unwrap();
+    c_version.to_str().unwrap()
+}
+
+/// Optional features compiled into the native MuPDF build
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    /// JavaScript engine for PDF forms
+    pub js: bool,
+    /// ICC color management
+    pub icc: bool,
+    pub pdf: bool,
+    pub xps: bool,
+    pub svg: bool,
+    pub cbz: bool,
+    pub img: bool,
+    pub html: bool,
+    pub epub: bool,
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        js: FZ_ENABLE_JS != 0,
+        icc: FZ_ENABLE_ICC != 0,
+        pdf: FZ_ENABLE_PDF != 0,
+        xps: FZ_ENABLE_XPS != 0,
+        svg: FZ_ENABLE_SVG != 0,
+        cbz: FZ_ENABLE_CBZ != 0,
+        img: FZ_ENABLE_IMG != 0,
+        html: FZ_ENABLE_HTML != 0,
+        epub: FZ_ENABLE_EPUB != 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{capabilities, version};
+
+    #[test]
+    fn test_version() {
+        assert!(version().starts_with("1."));
+        assert!(capabilities().pdf);
+    }
+}